@@ -1,77 +1,484 @@
-use darling::{ast, util::SpannedValue, FromDeriveInput, FromField};
+use darling::{
+    ast,
+    util::{Flag, Override, SpannedValue},
+    FromDeriveInput, FromField, FromVariant,
+};
 use proc_macro2::TokenStream;
-use quote::{quote, quote_spanned};
+use proc_macro_error::abort;
+use quote::{format_ident, quote, quote_spanned};
 use syn::spanned::Spanned;
 
-use crate::util::{fields_to_constructor, fields_to_var_idents};
+use crate::introspect::args_introspect;
+use crate::util::{
+    cached_signature, field_path_name, fields_to_constructor, fields_to_path_constructor,
+    fields_to_var_idents, from_wire, rejected_read_error, roundtrip_test, to_wire, try_append,
+    wire_type, ArgType,
+};
 
 #[derive(Debug, FromField)]
-#[darling(attributes(dbus_args))]
+#[darling(attributes(dbus_args, dbus), forward_attrs(doc))]
 struct DbusArgsField {
     ident: Option<syn::Ident>,
     ty: syn::Type,
+    attrs: Vec<syn::Attribute>,
+    default: Flag,
+    skip: Flag,
+    arg_type: Option<syn::Type>,
+    sentinel: Option<syn::Expr>,
+    variant: Flag,
+}
+
+impl DbusArgsField {
+    fn conversion(&self) -> Option<ArgType> {
+        ArgType::from_attrs(&self.arg_type, &self.sentinel, &self.variant)
+    }
+}
+
+#[derive(Debug, FromVariant)]
+#[darling(attributes(dbus_args))]
+struct DbusArgsVariant {
+    ident: syn::Ident,
+    fields: ast::Fields<SpannedValue<DbusArgsField>>,
 }
 
 #[derive(Debug, FromDeriveInput)]
 #[darling(
     attributes(dbus_args),
-    supports(struct_named, struct_tuple, struct_newtype)
+    supports(
+        struct_named,
+        struct_tuple,
+        struct_newtype,
+        enum_named,
+        enum_tuple,
+        enum_newtype,
+        enum_unit
+    )
 )]
 pub struct DbusArgs {
     ident: syn::Ident,
+    vis: syn::Visibility,
     generics: syn::Generics,
-    data: ast::Data<darling::util::Ignored, SpannedValue<DbusArgsField>>,
+    data: ast::Data<DbusArgsVariant, SpannedValue<DbusArgsField>>,
+    validate: Option<syn::Path>,
+    test_roundtrip: Option<Override<syn::Path>>,
+    builder: Flag,
+    introspect: Flag,
 }
 
 pub fn derive_args(input: DbusArgs) -> TokenStream {
     let DbusArgs {
         ref ident,
+        ref vis,
         ref generics,
         data,
+        ref validate,
+        ref test_roundtrip,
+        builder,
+        introspect,
     } = input;
-    let data = data.take_struct().unwrap(/* using #[darling(supports(struct_named, struct_tuple, struct_newtype))], should fail on previous step if enum */);
 
+    // Reject decoded arguments if they don't pass user provided validation
+    let validate_value = validate.as_ref().map(|validate| {
+        let rejected = rejected_read_error(quote!(start));
+        quote_spanned!(validate.span() =>
+            if #validate(&value).is_err() {
+                return ::core::result::Result::Err(#rejected);
+            }
+        )
+    });
+
+    let roundtrip_test = test_roundtrip.as_ref().map(|value_fn| {
+        roundtrip_test(
+            "dbus_args",
+            ident,
+            generics,
+            value_fn,
+            quote!(::dbus::arg::AppendAll::append(&value, &mut ::dbus::arg::IterAppend::new(&mut m));),
+            quote!(m.read_all().unwrap()),
+        )
+    });
+
+    let builder = builder.is_present().then(|| match &data {
+        ast::Data::Struct(fields) if fields.style == ast::Style::Struct => {
+            args_builder(ident, vis, generics, fields, validate)
+        }
+        _ => abort!(
+            builder.span(),
+            "dbus_derive - builder is only supported for structs with named fields"
+        ),
+    });
+
+    let introspect_impl = introspect.is_present().then(|| match &data {
+        ast::Data::Struct(fields) => {
+            let fields: Vec<_> = fields
+                .iter()
+                .filter(|f| !f.skip.is_present())
+                .map(|f| {
+                    let wire_ty = wire_type(&f.ty, &f.conversion());
+                    (f.ident.clone(), wire_ty, f.attrs.clone())
+                })
+                .collect();
+            args_introspect(ident, generics, &fields)
+        }
+        ast::Data::Enum(_) => abort!(
+            introspect.span(),
+            "introspect is only supported for structs"
+        ),
+    });
+
+    let impls = match data {
+        ast::Data::Struct(fields) => derive_args_struct(ident, generics, fields, validate_value),
+        ast::Data::Enum(variants) => derive_args_enum(ident, generics, variants, validate_value),
+    };
+
+    quote! {
+        #roundtrip_test
+
+        #builder
+
+        #introspect_impl
+
+        #impls
+    }
+}
+
+fn derive_args_struct(
+    ident: &syn::Ident,
+    generics: &syn::Generics,
+    data: ast::Fields<SpannedValue<DbusArgsField>>,
+    validate_value: Option<TokenStream>,
+) -> TokenStream {
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
     let input_name = quote!(#ident #ty_generics);
 
-    let strs = core::iter::repeat(quote!(&'static str)).take(data.len());
-
     let field_idents: Vec<_> = data.iter().map(|f| f.ident.clone()).collect();
-    let field_types: Vec<_> = data.iter().map(|f| f.ty.clone()).collect();
+    // Types as they appear in a message, fields with arg_type are converted through DbusArg
+    let arg_types: Vec<_> = data.iter().map(|f| f.conversion()).collect();
     let var_idents = fields_to_var_idents(&ident.span(), &data.style, &field_idents);
     let struct_constructor = fields_to_constructor(&ident.span(), &data.style, &var_idents);
 
-    // Generating TokenStreams with calls to methods to attach correct field spans
-    let (mut iter_append_vars, mut iter_read_vars) = (vec![], vec![]);
-    for (f_id, f_ty) in var_idents.iter().zip(field_types.iter()) {
-        iter_append_vars.push(quote_spanned!(f_ty.span() => ia.append(#f_id);));
-        iter_read_vars.push(quote_spanned!(f_ty.span() => let #f_id = i.read()?;))
+    // Skipped fields are not part of a message
+    let (mut sent_idents, mut field_types) = (vec![], vec![]);
+    for ((f_id, f), arg_type) in var_idents.iter().zip(data.iter()).zip(&arg_types) {
+        if !f.skip.is_present() {
+            sent_idents.push(f_id);
+            field_types.push(wire_type(&f.ty, arg_type));
+        }
+    }
+    let strs = core::iter::repeat_n(quote!(&'static str), sent_idents.len());
+
+    // Generating TokenStreams with calls to methods to attach correct field spans. Converted
+    // fields are shadowed by owned values, which are appended by reference
+    let (mut convert_vars, mut iter_append_vars, mut iter_read_vars) = (vec![], vec![], vec![]);
+    for (idx, ((f_id, f), arg_type)) in var_idents
+        .iter()
+        .zip(data.iter())
+        .zip(&arg_types)
+        .enumerate()
+    {
+        if f.skip.is_present() {
+            iter_append_vars.push(quote_spanned!(f.span() => let _ = #f_id;));
+            iter_read_vars.push(quote_spanned!(f.span() =>
+                let #f_id = ::core::default::Default::default();
+            ));
+            continue;
+        }
+        let field = field_path_name(&f.ident, idx);
+        let append_var = match arg_type {
+            Some(arg_type) => {
+                convert_vars.push(to_wire(f_id, &field, &f.ty, arg_type));
+                quote!(&#f_id)
+            }
+            None => quote!(#f_id),
+        };
+        iter_append_vars.push(quote_spanned!(f.ty.span() => ia.append(#append_var);));
+        // Conversion errors cannot be stored in TypeMismatchError, so reading is rejected at
+        // the position of a field
+        iter_read_vars.push(
+            match from_wire(quote!(i.read()?), &f.ty, &field, arg_type) {
+                Some(converted) => {
+                    let rejected = rejected_read_error(quote!(field_start));
+                    quote_spanned!(f.ty.span() =>
+                        let field_start = *i;
+                        let #f_id = #converted.map_err(|_| #rejected)?;
+                    )
+                }
+                None => quote_spanned!(f.ty.span() => let #f_id = i.read()?;),
+            },
+        )
     }
 
+    let field_sigs = field_types
+        .iter()
+        .map(|ty| cached_signature(generics, quote!(<#ty as ::dbus::arg::Arg>::signature())));
+
+    let save_start = validate_value.as_ref().map(|_| quote!(let start = *i;));
+
+    let ident_name = ident.to_string();
+    let (try_append_impl, append_body) = try_append(
+        ident,
+        generics,
+        !convert_vars.is_empty(),
+        quote! {
+            let #struct_constructor = self;
+            #(#convert_vars)*
+            #(#iter_append_vars)*
+        },
+        quote!(#ident_name),
+    );
+
+    // ReadAll has no lifetime to borrow from, so it can only be implemented for owned structs
+    let read_all_impl = (generics.lifetimes().next().is_none()).then(|| {
+        quote! {
+            #[automatically_derived]
+            impl #impl_generics ::dbus::arg::ReadAll for #input_name #where_clause {
+                fn read(i: &mut ::dbus::arg::Iter) -> ::core::result::Result<Self, ::dbus::arg::TypeMismatchError> {
+                    #save_start
+                    #(#iter_read_vars)*
+                    let value = #struct_constructor;
+                    #validate_value
+                    ::core::result::Result::Ok(value)
+                }
+            }
+        }
+    });
+
     quote! {
+        #try_append_impl
+
         #[automatically_derived]
         impl #impl_generics ::dbus::arg::ArgAll for #input_name #where_clause {
             type strs = ( #(#strs),* );
 
             fn strs_sig<F: ::std::ops::FnMut(&'static str, ::dbus::Signature<'static>)>(strs: Self::strs, mut f: F) {
-                let (#(#var_idents),*) = strs;
-                #(f(#var_idents, <#field_types as ::dbus::arg::Arg>::signature());)*
+                let (#(#sent_idents),*) = strs;
+                #(f(#sent_idents, #field_sigs);)*
             }
         }
 
         #[automatically_derived]
         impl #impl_generics ::dbus::arg::AppendAll for #input_name #where_clause {
             fn append(&self, ia: &mut ::dbus::arg::IterAppend) {
-                let #struct_constructor = self;
-                #(#iter_append_vars)*
+                #append_body
             }
         }
 
-        #[automatically_derived]
-        impl #impl_generics ::dbus::arg::ReadAll for #input_name #where_clause {
-            fn read(i: &mut ::dbus::arg::Iter) -> ::core::result::Result<Self, ::dbus::arg::TypeMismatchError> {
+        #read_all_impl
+    }
+}
+
+/// Every variant represents an alternative shape of message arguments. [`ArgAll`] is not
+/// implemented, since there is no single signature.
+///
+/// [`ArgAll`]: dbus::arg::ArgAll
+fn derive_args_enum(
+    ident: &syn::Ident,
+    generics: &syn::Generics,
+    variants: Vec<DbusArgsVariant>,
+    validate_value: Option<TokenStream>,
+) -> TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let input_name = quote!(#ident #ty_generics);
+
+    let (mut append_arms, mut variant_names, mut read_attempts) = (vec![], vec![], vec![]);
+    let mut converted = false;
+    for variant in &variants {
+        let variant_ident = &variant.ident;
+        let fields = &variant.fields;
+        if let Some(f) = fields.iter().find(|f| f.skip.is_present()) {
+            abort!(f.span(), "skip is only supported for structs");
+        }
+        let field_idents: Vec<_> = fields.iter().map(|f| f.ident.clone()).collect();
+        let var_idents = fields_to_var_idents(&variant_ident.span(), &fields.style, &field_idents);
+        let constructor =
+            fields_to_path_constructor(&quote!(Self::#variant_ident), &fields.style, &var_idents);
+
+        // Generating TokenStreams with calls to methods to attach correct field spans
+        let (mut convert_vars, mut iter_append_vars, mut iter_read_vars) = (vec![], vec![], vec![]);
+        for (idx, (f_id, f)) in var_idents.iter().zip(fields.iter()).enumerate() {
+            let field = field_path_name(&f.ident, idx);
+            let arg_type = f.conversion();
+            let append_var = match &arg_type {
+                Some(arg_type) => {
+                    convert_vars.push(to_wire(f_id, &field, &f.ty, arg_type));
+                    quote!(&#f_id)
+                }
+                None => quote!(#f_id),
+            };
+            iter_append_vars.push(quote_spanned!(f.ty.span() => ia.append(#append_var);));
+            let f_ty = wire_type(&f.ty, &arg_type);
+            let read_var = match from_wire(quote!(i.read().ok()?), &f.ty, &field, &arg_type) {
+                Some(converted) => quote!(#converted.ok()?),
+                None => quote!(i.read().ok()?),
+            };
+            iter_read_vars.push(quote_spanned!(f.ty.span() =>
+                if i.arg_type() == ::dbus::arg::ArgType::Invalid
+                    || i.signature() != <#f_ty as ::dbus::arg::Arg>::signature()
+                {
+                    return ::core::option::Option::None;
+                }
+                let #f_id = #read_var;
+            ))
+        }
+
+        converted |= !convert_vars.is_empty();
+        append_arms.push(quote! {
+            #constructor => { #(#convert_vars)* #(#iter_append_vars)* }
+        });
+        let parent = format!("{ident}::{variant_ident}");
+        variant_names.push(quote!(Self::#variant_ident { .. } => #parent,));
+        // Comparing signatures, since empty arrays could be read as any array type.
+        // Every argument should be consumed, otherwise shorter variant would match longer message
+        read_attempts.push(quote! {
+            let attempt = || {
+                let mut i = start;
                 #(#iter_read_vars)*
-                ::core::result::Result::Ok(#struct_constructor)
+                if i.arg_type() != ::dbus::arg::ArgType::Invalid {
+                    return ::core::option::Option::None;
+                }
+                ::core::option::Option::Some((#constructor, i))
+            };
+            if let ::core::option::Option::Some((value, rest)) = attempt() {
+                #validate_value
+                *i = rest;
+                return ::core::result::Result::Ok(value);
+            }
+        });
+    }
+    let rejected = rejected_read_error(quote!(start));
+
+    let (try_append_impl, append_body) = try_append(
+        ident,
+        generics,
+        converted,
+        quote! {
+            match self {
+                #(#append_arms)*
+            }
+        },
+        quote!(match self { #(#variant_names)* }),
+    );
+
+    // ReadAll has no lifetime to borrow from, so it can only be implemented for owned enums
+    let read_all_impl = (generics.lifetimes().next().is_none()).then(|| {
+        quote! {
+            #[automatically_derived]
+            impl #impl_generics ::dbus::arg::ReadAll for #input_name #where_clause {
+                fn read(i: &mut ::dbus::arg::Iter) -> ::core::result::Result<Self, ::dbus::arg::TypeMismatchError> {
+                    let start = *i;
+                    #({ #read_attempts })*
+                    ::core::result::Result::Err(#rejected)
+                }
+            }
+        }
+    });
+
+    quote! {
+        #try_append_impl
+
+        #[automatically_derived]
+        impl #impl_generics ::dbus::arg::AppendAll for #input_name #where_clause {
+            fn append(&self, ia: &mut ::dbus::arg::IterAppend) {
+                #append_body
+            }
+        }
+
+        #read_all_impl
+    }
+}
+
+/// Builder takes ownership on every step, so field types don't have to implement [`Clone`].
+/// Fields without `default` attribute are required, `build` fails if any of them is missing.
+/// Skipped fields don't have setters and are always [`Default`].
+/// Built value is checked with `validate`, same as one read with [`ReadAll`].
+///
+/// [`ReadAll`]: dbus::arg::ReadAll
+fn args_builder(
+    ident: &syn::Ident,
+    vis: &syn::Visibility,
+    generics: &syn::Generics,
+    fields: &ast::Fields<SpannedValue<DbusArgsField>>,
+    validate: &Option<syn::Path>,
+) -> TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let builder_ident = format_ident!("{}Builder", ident);
+    let builder_name = quote!(#builder_ident #ty_generics);
+
+    let (skipped, fields): (Vec<_>, Vec<_>) = fields.iter().partition(|f| f.skip.is_present());
+    let skipped_idents = skipped.iter().map(|f| f.ident.clone().unwrap());
+    let field_idents: Vec<_> = fields.iter().map(|f| f.ident.clone().unwrap()).collect();
+    let field_types: Vec<_> = fields.iter().map(|f| f.ty.clone()).collect();
+
+    let setters = fields.iter().zip(&field_idents).map(|(f, f_id)| {
+        let (f_ty, docs) = (&f.ty, &f.attrs);
+        quote_spanned!(f.span() =>
+            #(#docs)*
+            #[must_use]
+            #vis fn #f_id(mut self, value: impl ::core::convert::Into<#f_ty>) -> Self {
+                self.#f_id = ::core::option::Option::Some(value.into());
+                self
+            }
+        )
+    });
+
+    let field_values = fields.iter().zip(&field_idents).map(|(f, f_id)| {
+        if f.default.is_present() {
+            quote_spanned!(f.span() => let #f_id = self.#f_id.unwrap_or_default();)
+        } else {
+            let err = format!("{ident}: required field `{f_id}` is not set");
+            quote_spanned!(f.span() =>
+                let #f_id = self.#f_id.ok_or(#err)?;
+            )
+        }
+    });
+
+    let validate_value = validate.as_ref().map(|validate| {
+        let rejected = format!("{ident} failed validation");
+        quote_spanned!(validate.span() =>
+            if #validate(&value).is_err() {
+                return ::core::result::Result::Err(#rejected);
+            }
+        )
+    });
+
+    let builder_doc = format!("Builder for [`{ident}`]. Setters mirror field names.");
+
+    quote! {
+        #[doc = #builder_doc]
+        #vis struct #builder_ident #impl_generics #where_clause {
+            #(#field_idents: ::core::option::Option<#field_types>,)*
+        }
+
+        #[automatically_derived]
+        impl #impl_generics ::core::default::Default for #builder_name #where_clause {
+            fn default() -> Self {
+                Self {
+                    #(#field_idents: ::core::option::Option::None,)*
+                }
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics #builder_name #where_clause {
+            #(#setters)*
+
+            /// Fails if a required field is not set or built value doesn't pass validation.
+            #vis fn build(self) -> ::core::result::Result<#ident #ty_generics, &'static str> {
+                #(#field_values)*
+                let value = #ident {
+                    #(#field_idents,)*
+                    #(#skipped_idents: ::core::default::Default::default(),)*
+                };
+                #validate_value
+                ::core::result::Result::Ok(value)
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics #ident #ty_generics #where_clause {
+            #[must_use]
+            #vis fn builder() -> #builder_name {
+                ::core::default::Default::default()
             }
         }
     }
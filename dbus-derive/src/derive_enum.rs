@@ -1,22 +1,35 @@
-use darling::FromDeriveInput;
+use darling::{ast, util::Flag, FromDeriveInput, FromVariant};
 use proc_macro2::{Span, TokenStream};
-use quote::{format_ident, quote, quote_spanned};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
 use syn::{GenericParam, Lifetime, LifetimeParam, Type};
 
+#[derive(Debug, FromVariant)]
+#[darling(attributes(dbus_enum))]
+struct DbusEnumVariant {
+    ident: syn::Ident,
+}
+
 #[derive(Debug, FromDeriveInput)]
 #[darling(attributes(dbus_enum), supports(enum_unit))]
 pub struct DbusEnum {
     ident: syn::Ident,
     generics: syn::Generics,
+    data: ast::Data<DbusEnumVariant, darling::util::Ignored>,
     as_type: Type,
+    discriminants: Flag,
+    zbus: Flag,
 }
 
 pub fn derive_enum(input: DbusEnum) -> TokenStream {
     let DbusEnum {
         ref ident,
         ref generics,
+        data,
         as_type,
+        discriminants,
+        zbus,
     } = input;
+    let variants = data.take_enum().unwrap(/* using #[darling(supports(enum_unit))], should fail on previous step if struct */);
 
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
     let input_name = quote!(#ident #ty_generics);
@@ -27,15 +40,52 @@ pub fn derive_enum(input: DbusEnum) -> TokenStream {
     generics_with_lt.params.push(GenericParam::Lifetime(ltp));
     let (impl_with_lt, _, _) = generics_with_lt.split_for_impl();
 
+    // Map every variant to its discriminant, which could be arbitrary, including negative values
+    let conversions = discriminants.is_present().then(|| {
+        let variant_idents: Vec<_> = variants.iter().map(|v| &v.ident).collect();
+        let out_of_bound = format!(
+            "{ident} {} representation out of bound",
+            as_type.to_token_stream()
+        );
+        quote! {
+            #[automatically_derived]
+            impl #impl_generics ::core::convert::From<#input_name> for #as_type #where_clause {
+                fn from(value: #input_name) -> Self {
+                    value as #as_type
+                }
+            }
+
+            #[automatically_derived]
+            impl #impl_generics ::core::convert::TryFrom<#as_type> for #input_name #where_clause {
+                type Error = &'static str;
+
+                fn try_from(value: #as_type) -> ::core::result::Result<Self, Self::Error> {
+                    #(if value == Self::#variant_idents as #as_type {
+                        return ::core::result::Result::Ok(Self::#variant_idents);
+                    })*
+                    ::core::result::Result::Err(#out_of_bound)
+                }
+            }
+        }
+    });
+
     // Generate a stub struct that will throw compile-time errors if trait bounds are not fullfiled
     let assert_struct_ident = format_ident!("_AssertDbusEnum{}", ident);
     let assert_struct = quote_spanned!(ident.span() =>
         struct #assert_struct_ident where #as_type: ::core::convert::From<#ident>, #ident: ::core::convert::TryFrom<#as_type>;
     );
 
+    let zbus_impls = zbus
+        .is_present()
+        .then(|| crate::zbus::enum_impls(ident, generics, &as_type));
+
     quote! {
+        #conversions
+
         #assert_struct
 
+        #zbus_impls
+
         #[automatically_derived]
         impl #impl_generics ::dbus::arg::Arg for #input_name #where_clause {
             const ARG_TYPE: ::dbus::arg::ArgType = <#as_type as ::dbus::arg::Arg>::ARG_TYPE;
@@ -48,9 +98,8 @@ pub fn derive_enum(input: DbusEnum) -> TokenStream {
         #[automatically_derived]
         impl #impl_with_lt ::dbus::arg::Get<#lt> for #input_name #where_clause {
             fn get(i: &mut ::dbus::arg::Iter<#lt>) -> ::core::option::Option<Self> {
-                let ::core::result::Result::Ok(val) = i.read::<#as_type>() else {
-                    return ::core::option::Option::None;
-                };
+                // Using get instead of read, since iterator is advanced by a caller
+                let val = i.get::<#as_type>()?;
                 ::core::convert::TryFrom::<#as_type>::try_from(val).ok()
             }
         }
@@ -1,16 +1,29 @@
-use darling::{ast, util::SpannedValue, FromDeriveInput, FromField};
+use std::collections::{HashMap, HashSet};
+
+use darling::{
+    ast,
+    util::{Flag, SpannedValue},
+    FromDeriveInput, FromField,
+};
 use proc_macro2::{Span, TokenStream};
+use proc_macro_error::emit_error;
 use quote::{format_ident, quote};
 use syn::{GenericParam, Lifetime, LifetimeParam};
 
-use crate::util::{fields_to_constructor, fields_to_var_idents, ty_generic_to_ty_contained};
+use crate::util::{
+    fields_to_constructor, fields_to_var_idents, from_wire, rename_case, to_wire, try_append,
+    ty_generic_to_ty_contained, wire_type, ArgType,
+};
 
 #[derive(Debug, FromField)]
-#[darling(attributes(dbus_propmap))]
+#[darling(attributes(dbus_propmap, dbus))]
 struct DbusPropmapField {
     ident: Option<syn::Ident>,
     ty: syn::Type,
-    rename: Option<String>,
+    arg_type: Option<syn::Type>,
+    rename: Option<SpannedValue<String>>,
+    #[darling(multiple)]
+    alias: Vec<SpannedValue<String>>,
 }
 
 #[derive(Debug, FromDeriveInput)]
@@ -19,6 +32,10 @@ pub struct DbusPropmap {
     ident: syn::Ident,
     generics: syn::Generics,
     data: ast::Data<darling::util::Ignored, SpannedValue<DbusPropmapField>>,
+    rename_all: Option<SpannedValue<String>>,
+    append_changed: Flag,
+    lenient: Flag,
+    zbus: Flag,
 }
 
 pub fn derive_propmap(input: DbusPropmap) -> TokenStream {
@@ -26,6 +43,10 @@ pub fn derive_propmap(input: DbusPropmap) -> TokenStream {
         ref ident,
         ref generics,
         data,
+        ref rename_all,
+        append_changed,
+        lenient,
+        zbus,
     } = input;
     let data = data.take_struct().unwrap(/* using #[darling(supports(struct_named, struct_tuple, struct_newtype))], should fail on previous step if enum */);
 
@@ -55,50 +76,237 @@ pub fn derive_propmap(input: DbusPropmap) -> TokenStream {
         return TokenStream::new();
     }
 
-    // Check if field has a rename attribute on it, if so - use provided name to access hashmap
-    let var_name_strs: Vec<_> = var_names
+    // Types stored in variants, fields with arg_type are converted through DbusArg. Missing keys
+    // already represent None, so sentinels and variant are not supported.
+    let arg_types: Vec<_> = data
+        .iter()
+        .map(|f| ArgType::from_attrs(&f.arg_type, &None, &Flag::default()))
+        .collect();
+    let wire_types: Vec<_> = field_types
+        .iter()
+        .zip(&arg_types)
+        .map(|(ty, arg_type)| wire_type(ty, arg_type))
+        .collect();
+    // Check if field has a rename attribute on it, if so - use provided name to access hashmap,
+    // otherwise apply container rename rule to a field name. Aliases are only used for reading.
+    let var_keys: Vec<Vec<_>> = var_names
         .iter()
         .zip(data.iter())
         .map(|(n, f)| {
-            if let Some(rename) = &f.rename {
-                rename.clone()
+            let key = if let Some(rename) = &f.rename {
+                (rename.as_ref().clone(), rename.span())
+            } else if let Some(rename_all) = rename_all {
+                (rename_case(&n.to_string(), rename_all), n.span())
             } else {
-                n.to_string()
-            }
+                (n.to_string(), n.span())
+            };
+            let aliases = f
+                .alias
+                .iter()
+                .map(|alias| (alias.as_ref().clone(), alias.span()));
+            std::iter::once(key).chain(aliases).collect()
+        })
+        .collect();
+
+    // Two fields mapped to the same key would silently overwrite each other, bail out. Notes
+    // with a span are only rendered on nightly, so the first use gets its own error
+    let mut used_keys = HashMap::new();
+    let mut reported_keys = HashSet::new();
+    let mut has_duplicates = false;
+    for (key, span) in var_keys.iter().flatten() {
+        let Some(&first_span) = used_keys.get(key) else {
+            used_keys.insert(key.clone(), *span);
+            continue;
+        };
+        has_duplicates = true;
+        emit_error!(
+            span,
+            "dbus_derive - Key \"{}\" is used by multiple fields",
+            key
+        );
+        if reported_keys.insert(key.clone()) {
+            emit_error!(
+                first_span,
+                "dbus_derive - Key \"{}\" is first used here",
+                key
+            );
+        }
+    }
+    if has_duplicates {
+        return TokenStream::new();
+    }
+    let var_keys: Vec<Vec<_>> = var_keys
+        .into_iter()
+        .map(|keys| keys.into_iter().map(|(key, _)| key).collect())
+        .collect();
+    let var_name_strs: Vec<_> = var_keys.iter().map(|keys| keys[0].clone()).collect();
+
+    // Conversion errors use canonical key as a field name
+    let value_ident = format_ident!("f");
+    let from_wire_values =
+        field_types
+            .iter()
+            .zip(&arg_types)
+            .zip(&var_name_strs)
+            .map(|((ty, arg_type), key)| {
+                match from_wire(quote!(#value_ident), ty, key, arg_type) {
+                    Some(converted) => quote!(.and_then(|#value_ident| #converted.ok())),
+                    None => TokenStream::new(),
+                }
+            });
+    // Statements shadowing a reference to a set value with an owned value to send
+    let to_wire_values: Vec<_> = field_types
+        .iter()
+        .zip(&arg_types)
+        .zip(&var_name_strs)
+        .map(|((ty, arg_type), key)| match arg_type {
+            Some(arg_type) => to_wire(&value_ident, key, ty, arg_type),
+            None => quote!(let #value_ident = ::core::clone::Clone::clone(#value_ident);),
         })
         .collect();
+    let ident_name = ident.to_string();
+    let (try_append_impl, append_body) = try_append(
+        ident,
+        generics,
+        arg_types.iter().any(Option::is_some),
+        quote! {
+            let mut #map_ident = ::dbus::arg::PropMap::new();
+            let (#self_constructor) = self;
+            #(if let ::core::option::Option::Some(#value_ident) = #var_names.as_ref() {
+                #to_wire_values
+                let #value_ident: #wire_types = #value_ident;
+                #map_ident.insert(#var_name_strs.to_string(), ::dbus::arg::Variant(::std::boxed::Box::new(#value_ident)));
+            })*
+            ::dbus::arg::Dict::new(#map_ident.iter()).append_by_ref(ia);
+        },
+        quote!(#ident_name),
+    );
+
+    // Try canonical key first, falling back to aliases
+    let map_lookups = var_keys.iter().map(|keys| {
+        let (key, aliases) = (&keys[0], &keys[1..]);
+        quote!(#map_ident.get(#key) #(.or_else(|| #map_ident.get(#aliases)))*)
+    });
+
+    // Values of unexpected type or failed conversions are dropped, lenient mode reports them
+    let get_values: Vec<_> = map_lookups
+        .zip(&wire_types)
+        .zip(field_types.iter().zip(&arg_types))
+        .zip(&var_name_strs)
+        .zip(from_wire_values)
+        .map(
+            |((((lookup, wire_ty), (ty, arg_type)), key), from_wire_value)| {
+                if !lenient.is_present() {
+                    return quote! {
+                        #lookup
+                            .and_then(|f| f.0.as_any().downcast_ref::<#wire_ty>())
+                            .cloned()
+                            #from_wire_value
+                    };
+                }
+                let converted = from_wire(quote!(#value_ident), ty, key, arg_type)
+                    .unwrap_or_else(|| quote!(::core::result::Result::Ok(#value_ident)));
+                quote! {
+                    #lookup.and_then(|v| {
+                        let value = match v.0.as_any().downcast_ref::<#wire_ty>() {
+                            ::core::option::Option::Some(#value_ident) => {
+                                let #value_ident = ::core::clone::Clone::clone(#value_ident);
+                                #converted
+                            }
+                            ::core::option::Option::None => ::core::result::Result::Err(
+                                ::dbus_traits::DbusArgError::new(::std::format!(
+                                    "expected {}, found {}",
+                                    <#wire_ty as ::dbus::arg::Arg>::signature(),
+                                    ::dbus::arg::RefArg::signature(&v.0),
+                                ))
+                                .in_field(#key),
+                            ),
+                        };
+                        value
+                            .map_err(::dbus_traits::lenient::warn)
+                            .ok()
+                    })
+                }
+            },
+        )
+        .collect();
+
+    let zbus_impls = zbus.is_present().then(|| {
+        crate::zbus::propmap_impls(
+            ident,
+            generics,
+            &self_constructor,
+            &var_names,
+            &var_keys,
+            &field_types,
+            &arg_types,
+        )
+    });
+
+    // Method is opt-in, since it requires PartialEq for every field
+    let append_changed_impl = append_changed.is_present().then(|| {
+        quote! {
+            #[automatically_derived]
+            impl #impl_generics #input_name #where_clause {
+                /// Returns a copy with only fields that are set and differ from `base`.
+                pub fn changed_since(&self, base: &Self) -> Self {
+                    let (#self_constructor) = self;
+                    #(let #var_names = #var_names
+                        .as_ref()
+                        .filter(|f| base.#var_names.as_ref() != ::core::option::Option::Some(*f))
+                        .cloned();
+                    )*
+                    #self_constructor
+                }
+
+                /// Appends only keys with values that differ from `base`, so a receiver which
+                /// applies every included key doesn't reset untouched properties.
+                pub fn append_changed(&self, base: &Self, ia: &mut ::dbus::arg::IterAppend) {
+                    ::dbus::arg::Append::append_by_ref(&self.changed_since(base), ia);
+                }
+            }
+        }
+    });
+
+    let get_body = quote! {
+        let #map_ident: ::dbus::arg::PropMap = ::dbus::arg::Dict::get(i).map(|d| d.collect())?;
+        #(let #var_names = #get_values;)*
+        ::core::option::Option::Some(#self_constructor)
+    };
+    // Only lenient mode reports warnings, which need a path to the value
+    let get_body = if lenient.is_present() {
+        quote!(::dbus_traits::lenient::reading_value(#ident_name, || { #get_body }))
+    } else {
+        get_body
+    };
 
     quote! {
+        #zbus_impls
+
+        #append_changed_impl
+
+        #try_append_impl
+
         #[automatically_derived]
         impl #impl_generics ::dbus::arg::Arg for #input_name #where_clause {
             const ARG_TYPE: ::dbus::arg::ArgType = ::dbus::arg::ArgType::Array;
 
             fn signature() -> ::dbus::Signature<'static> {
-                ::dbus::arg::PropMap::signature()
+                <::dbus::arg::PropMap as ::dbus::arg::Arg>::signature()
             }
         }
 
         #[automatically_derived]
         impl #impl_with_lt ::dbus::arg::Get<#lt> for #input_name #where_clause {
             fn get(i: &mut ::dbus::arg::Iter<#lt>) -> ::core::option::Option<Self> {
-                let #map_ident: ::dbus::arg::PropMap = ::dbus::arg::Dict::get(i).map(|d| d.collect())?;
-                #(let #var_names = #map_ident.get(#var_name_strs)
-                    .and_then(|f| f.0.as_any().downcast_ref::<#field_types>())
-                    .cloned();
-                )*
-                ::core::option::Option::Some(#self_constructor)
+                #get_body
             }
         }
 
         #[automatically_derived]
         impl #impl_generics ::dbus::arg::Append for #input_name #where_clause {
             fn append_by_ref(&self, ia: &mut ::dbus::arg::IterAppend) {
-                let mut #map_ident = ::dbus::arg::PropMap::new();
-                let (#self_constructor) = self;
-                #(if let ::core::option::Option::Some(f) = #var_names.as_ref().cloned() {
-                    #map_ident.insert(#var_name_strs.to_string(), ::dbus::arg::Variant(::std::boxed::Box::new(f)));
-                })*
-                ::dbus::arg::Dict::new(#map_ident.iter()).append_by_ref(ia);
+                #append_body
             }
         }
     }
@@ -1,88 +1,239 @@
-use darling::{ast, util::SpannedValue, FromDeriveInput, FromField};
+use darling::{
+    ast,
+    util::{Flag, Override, SpannedValue},
+    FromDeriveInput, FromField,
+};
 use proc_macro2::{Span, TokenStream};
 use quote::{quote, quote_spanned};
-use syn::{spanned::Spanned, GenericParam, Lifetime, LifetimeParam};
+use syn::{spanned::Spanned, Lifetime};
 
-use crate::util::{fields_to_constructor, fields_to_var_idents};
+use crate::introspect::struct_introspect;
+use crate::util::{
+    cached_signature, field_path_name, fields_to_constructor, fields_to_var_idents, from_wire,
+    generics_with_get_lifetime, lenient_or_default, reading_field, roundtrip_test, to_wire,
+    try_append, wire_type, ArgType,
+};
+use crate::zbus::StructField;
 
 #[derive(Debug, FromField)]
-#[darling(attributes(dbus_struct))]
+#[darling(attributes(dbus_struct, dbus), forward_attrs(doc))]
 struct DbusStructField {
     ident: Option<syn::Ident>,
     ty: syn::Type,
+    attrs: Vec<syn::Attribute>,
+    arg_type: Option<syn::Type>,
+    sentinel: Option<syn::Expr>,
+    variant: Flag,
+    lenient: Flag,
 }
 
 #[derive(Debug, FromDeriveInput)]
 #[darling(
     attributes(dbus_struct),
-    supports(struct_named, struct_tuple, struct_newtype)
+    supports(struct_named, struct_tuple, struct_newtype),
+    forward_attrs(doc)
 )]
 pub struct DbusStruct {
     ident: syn::Ident,
     generics: syn::Generics,
+    attrs: Vec<syn::Attribute>,
     data: ast::Data<darling::util::Ignored, SpannedValue<DbusStructField>>,
+    validate: Option<syn::Path>,
+    test_roundtrip: Option<Override<syn::Path>>,
+    introspect: Flag,
+    zbus: Flag,
 }
 
 pub fn derive_struct(input: DbusStruct) -> TokenStream {
     let DbusStruct {
         ref ident,
         ref generics,
+        ref attrs,
         data,
+        ref validate,
+        ref test_roundtrip,
+        introspect,
+        zbus,
     } = input;
     let data = data.take_struct().unwrap(/* using #[darling(supports(struct_named, struct_tuple, struct_newtype))], should fail on previous step if enum */);
 
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
     let input_name = quote!(#ident #ty_generics);
 
-    // Create modified generics parameter with additional lifetime for implementing Get trait
-    let mut generics_with_lt = generics.clone();
-    let lt = Lifetime::new("'derive_dbus_args", Span::call_site());
-    let ltp = LifetimeParam::new(lt.clone());
-    generics_with_lt.params.push(GenericParam::Lifetime(ltp));
-    let (impl_with_lt, _, _) = generics_with_lt.split_for_impl();
-
     // Create a format string for format!() macro in Arg trait implementation
     let sig_format = format!("({})", "{}".to_string().repeat(data.len()));
 
     let field_idents: Vec<_> = data.iter().map(|f| f.ident.clone()).collect();
-    let field_types: Vec<_> = data.iter().map(|f| f.ty.clone()).collect();
+    // Types as they appear in a message, fields with arg_type are converted through DbusArg
+    let arg_types: Vec<_> = data
+        .iter()
+        .map(|f| ArgType::from_attrs(&f.arg_type, &f.sentinel, &f.variant))
+        .collect();
+    let field_types: Vec<_> = data
+        .iter()
+        .zip(&arg_types)
+        .map(|(f, arg_type)| wire_type(&f.ty, arg_type))
+        .collect();
+
+    // Create modified generics parameter with additional lifetime for implementing Get trait
+    let lt = Lifetime::new("'derive_dbus_args", Span::call_site());
+    let generics_with_lt =
+        generics_with_get_lifetime(generics, &lt, &field_types, &quote!(::dbus::arg::Get));
+    let (impl_with_lt, _, where_with_lt) = generics_with_lt.split_for_impl();
     let var_idents = fields_to_var_idents(&ident.span(), &data.style, &field_idents);
     let field_iterator = data.iter().zip(var_idents.iter());
 
     let struct_constructor = fields_to_constructor(&ident.span(), &data.style, &var_idents);
 
+    // Only structs with lenient fields report warnings, which need a path to the value
+    let lenient = data.iter().any(|f| f.lenient.is_present());
+
     // Generating this here instead of inserting directly into quote! to attach correct span
     let get_vars = field_iterator
         .clone()
-        .map(|(f, var_id)| quote_spanned!(f.ty.span() => let #var_id = si.read().ok()?;));
+        .zip(&arg_types)
+        .zip(&field_types)
+        .enumerate()
+        .map(|(idx, (((f, var_id), arg_type), wire_ty))| {
+            let field = field_path_name(&f.ident, idx);
+            if f.lenient.is_present() {
+                let value = lenient_or_default(&field, &f.ty, wire_ty, arg_type);
+                return quote_spanned!(f.ty.span() => let #var_id = #value;);
+            }
+            let read = quote!(si.read::<#wire_ty>().ok());
+            let read = if lenient {
+                reading_field(&field, wire_ty, read)
+            } else {
+                read
+            };
+            match from_wire(quote!(#var_id), &f.ty, &field, arg_type) {
+                Some(converted) => quote_spanned!(f.ty.span() =>
+                    let #var_id = #read?;
+                    let #var_id = #converted.ok()?;
+                ),
+                None => quote_spanned!(f.ty.span() => let #var_id = #read?;),
+            }
+        });
+    // Converted fields are shadowed by owned values, which are appended by reference
+    let (mut convert_vars, mut append_vars) = (vec![], vec![]);
+    for (idx, ((f, var_id), arg_type)) in field_iterator.clone().zip(&arg_types).enumerate() {
+        match arg_type {
+            Some(arg_type) => {
+                let field = field_path_name(&f.ident, idx);
+                convert_vars.push(to_wire(var_id, &field, &f.ty, arg_type));
+                append_vars.push(quote!(&#var_id));
+            }
+            None => append_vars.push(quote!(#var_id)),
+        }
+    }
+    let ident_name = ident.to_string();
+    let (try_append_impl, append_body) = try_append(
+        ident,
+        generics,
+        !convert_vars.is_empty(),
+        quote! {
+            let #struct_constructor = self;
+            #(#convert_vars)*
+            ia.append_struct(|s| { #( <#field_types as ::dbus::arg::Append>::append_by_ref(#append_vars, s); )* });
+        },
+        quote!(#ident_name),
+    );
+
+    // Reject decoded value if it doesn't pass user provided validation
+    let validate_value = validate.as_ref().map(|validate| {
+        quote_spanned!(validate.span() =>
+            if #validate(&value).is_err() {
+                return ::core::option::Option::None;
+            }
+        )
+    });
+
+    let roundtrip_test = test_roundtrip.as_ref().map(|value_fn| {
+        roundtrip_test(
+            "dbus_struct",
+            ident,
+            generics,
+            value_fn,
+            quote!(::dbus::arg::IterAppend::new(&mut m).append(&value);),
+            quote!(::dbus::arg::Iter::new(&m).read().unwrap()),
+        )
+    });
+
+    let introspect_impl = introspect.is_present().then(|| {
+        let fields: Vec<_> = data
+            .iter()
+            .map(|f| (f.ident.clone(), f.attrs.clone()))
+            .collect();
+        struct_introspect(ident, generics, attrs, &fields)
+    });
+
+    let zbus_impls = zbus.is_present().then(|| {
+        let fields: Vec<_> = field_iterator
+            .clone()
+            .zip(&arg_types)
+            .enumerate()
+            .map(|(idx, ((f, var), arg_type))| StructField {
+                var,
+                ty: &f.ty,
+                name: field_path_name(&f.ident, idx),
+                arg_type,
+            })
+            .collect();
+        crate::zbus::struct_impls(ident, generics, &struct_constructor, &fields, validate)
+    });
+
+    let get_body = quote! {
+        let mut si = i.recurse(::dbus::arg::ArgType::Struct)?;
+        #(#get_vars)*
+        let value = #struct_constructor;
+        #validate_value
+        ::core::option::Option::Some(value)
+    };
+    let get_body = if lenient {
+        quote!(::dbus_traits::lenient::reading_value(#ident_name, || { #get_body }))
+    } else {
+        get_body
+    };
+
+    let signature = cached_signature(
+        generics,
+        quote! {
+            ::dbus::Signature::from(format!(
+                #sig_format,
+                #(<#field_types as ::dbus::arg::Arg>::signature()),*
+            ))
+        },
+    );
 
     quote! {
+        #roundtrip_test
+
+        #try_append_impl
+
+        #introspect_impl
+
+        #zbus_impls
+
         #[automatically_derived]
         impl #impl_generics ::dbus::arg::Arg for #input_name #where_clause {
             const ARG_TYPE: ::dbus::arg::ArgType = ::dbus::arg::ArgType::Struct;
 
             fn signature() -> ::dbus::Signature<'static> {
-                ::dbus::Signature::from(format!(
-                    #sig_format,
-                    #(<#field_types as ::dbus::arg::Arg>::signature()),*
-                ))
+                #signature
             }
         }
 
         #[automatically_derived]
         impl #impl_generics ::dbus::arg::Append for #input_name #where_clause {
             fn append_by_ref(&self, ia: &mut ::dbus::arg::IterAppend) {
-                let #struct_constructor = self;
-                ia.append_struct(|s| { #( <#field_types as ::dbus::arg::Append>::append_by_ref(#var_idents, s); )* });
+                #append_body
             }
         }
 
         #[automatically_derived]
-        impl #impl_with_lt ::dbus::arg::Get<#lt> for #input_name #where_clause {
+        impl #impl_with_lt ::dbus::arg::Get<#lt> for #input_name #where_with_lt {
             fn get(i: &mut ::dbus::arg::Iter<#lt>) -> ::core::option::Option<Self> {
-                let mut si = i.recurse(::dbus::arg::ArgType::Struct)?;
-                #(#get_vars)*
-                ::core::option::Option::Some(#struct_constructor)
+                #get_body
             }
         }
     }
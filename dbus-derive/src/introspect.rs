@@ -0,0 +1,132 @@
+//! Introspection XML generated from doc comments, for services that serve derived types.
+//!
+//! Docs are attached with `org.gtk.GDBus.DocString` annotations, same as gdbus-codegen expects.
+//!
+//! XML is returned by functions instead of being an associated const: signatures of field types
+//! come from `Arg::signature()`, which is not a const fn, and only literals could be joined with
+//! `concat!`. Docs are formatted at compile time, only names and signatures are filled in.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Attribute, Expr, ExprLit, Generics, Ident, Lit, Meta, Type};
+
+/// Joins `#[doc = "..."]` attributes into a single string, returns `None` if there are no docs.
+pub fn doc_string(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<_> = attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) if nv.path.is_ident("doc") => match &nv.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(s), ..
+                }) => Some(s.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    (!lines.is_empty()).then(|| lines.join("\n").trim().to_string())
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+}
+
+/// Format string for a single `<arg>` element with `{name}`, `{sig}` and `{direction}`
+/// arguments. Braces in docs are escaped, since result is used with `format!`.
+fn arg_format(doc: Option<&str>) -> String {
+    match doc {
+        Some(doc) => {
+            let doc = xml_escape(doc).replace('{', "{{").replace('}', "}}");
+            format!(
+                "<arg name=\"{{name}}\" type=\"{{sig}}\" direction=\"{{direction}}\">\
+                 <annotation name=\"org.gtk.GDBus.DocString\" value=\"{doc}\"/></arg>"
+            )
+        }
+        None => "<arg name=\"{name}\" type=\"{sig}\" direction=\"{direction}\"/>".to_string(),
+    }
+}
+
+/// Implements `introspect(direction)` returning `<arg>` element for every field of [`DbusArgs`]
+/// struct. Unnamed fields are named `arg{idx}`.
+///
+/// [`DbusArgs`]: crate::DbusArgs
+pub fn args_introspect(
+    ident: &Ident,
+    generics: &Generics,
+    fields: &[(Option<Ident>, Type, Vec<Attribute>)],
+) -> TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let input_name = quote!(#ident #ty_generics);
+    let args = fields.iter().enumerate().map(|(idx, (f_id, f_ty, attrs))| {
+        let name = match f_id {
+            Some(f_id) => f_id.to_string(),
+            None => format!("arg{idx}"),
+        };
+        let format = arg_format(doc_string(attrs).as_deref());
+        quote! {
+            ::std::format!(
+                #format,
+                name = #name,
+                sig = <#f_ty as ::dbus::arg::Arg>::signature(),
+                direction = direction,
+            )
+        }
+    });
+
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics #input_name #where_clause {
+            /// Returns introspection XML `<arg>` elements for every field, `direction` is either
+            /// `in` or `out`.
+            pub fn introspect(direction: &str) -> ::std::string::String {
+                [#(#args),*].join("\n")
+            }
+        }
+    }
+}
+
+/// Implements `introspect(name, direction)` returning `<arg>` element for [`DbusStruct`]. Docs
+/// of a struct and its fields are combined into a single annotation.
+///
+/// [`DbusStruct`]: crate::DbusStruct
+pub fn struct_introspect(
+    ident: &Ident,
+    generics: &Generics,
+    struct_attrs: &[Attribute],
+    fields: &[(Option<Ident>, Vec<Attribute>)],
+) -> TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let input_name = quote!(#ident #ty_generics);
+    let mut doc: Vec<_> = doc_string(struct_attrs).into_iter().collect();
+    for (idx, (f_id, attrs)) in fields.iter().enumerate() {
+        if let Some(f_doc) = doc_string(attrs) {
+            let name = match f_id {
+                Some(f_id) => f_id.to_string(),
+                None => idx.to_string(),
+            };
+            doc.push(format!("* {name}: {f_doc}"));
+        }
+    }
+    let doc = (!doc.is_empty()).then(|| doc.join("\n"));
+    let format = arg_format(doc.as_deref());
+
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics #input_name #where_clause {
+            /// Returns introspection XML `<arg>` element for this struct, `direction` is either
+            /// `in` or `out`.
+            pub fn introspect(name: &str, direction: &str) -> ::std::string::String {
+                ::std::format!(
+                    #format,
+                    name = name,
+                    sig = <Self as ::dbus::arg::Arg>::signature(),
+                    direction = direction,
+                )
+            }
+        }
+    }
+}
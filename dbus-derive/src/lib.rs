@@ -3,12 +3,53 @@
 //! Derive macros for [dbus] library
 //!
 //! Simplifies definition of complex dbus interfaces
+//!
+//! # Features
+//! * `nightly-diagnostics`: Richer errors for invalid attributes. Requires nightly toolchain.
+//!
+//! # Shared field attributes
+//! Accepted by [`DbusStruct`], [`DbusArgs`] and [`DbusPropMap`] under `dbus` namespace:
+//! * `#[dbus(arg_type = "T")]`: Field is sent as `T`, converting with `dbus_traits::DbusArg<T>`,
+//!   which field type has to implement. Requires `dbus-traits` as a dependency. Failed
+//!   conversion rejects a value when reading and **panics** when appending, since [`Append`]
+//!   and [`AppendAll`] cannot fail. Panic message includes the path to the failed value, like
+//!   `Could not convert Monitor: modes[1]: ...`. To handle the error, types with converted
+//!   fields get an inherent `try_append(&self, &mut IterAppend) -> Result<(), DbusArgError>`,
+//!   which appends nothing if a conversion fails. Nested values are still appended with
+//!   [`Append`]. For [`DbusPropMap`] conversion applies to a type contained in [`Option`].
+//! * `#[dbus(arg_type = "i32", sentinel = -1)]`: [`Option`] field, where [`None`] is sent as
+//!   reserved value, like "mode id or -1". Converts with `dbus_traits::option_to_dbus` and
+//!   `dbus_traits::option_from_dbus`. Not supported by [`DbusPropMap`], where a missing key
+//!   already means [`None`].
+//! * `#[dbus(arg_type = "u32", variant)]`: [`Option`] field, sent as a variant holding the
+//!   value, or an empty marker for [`None`], so no value has to be reserved. Converts with
+//!   `dbus_traits::option_to_variant` and `dbus_traits::option_from_variant`. Not supported by
+//!   [`DbusPropMap`] and with `zbus`.
+//!
+//! # Lenient warnings
+//! [`DbusStruct`] structs with lenient fields and lenient [`DbusPropMap`] structs report fields
+//! they read to `dbus_traits::lenient`, so a warning from a value nested in them has a full path
+//! from the outermost lenient struct, like `Crtc.rotation`. Other structs don't track a path
+//! while reading, failed conversions have the field in their `DbusArgError` instead.
+//!
+//! # zbus
+//! `#[dbus_struct(zbus)]`, `#[dbus_enum(zbus)]` and `#[dbus_propmap(zbus)]` additionally
+//! implement `zvariant::Type`, `serde::Serialize` and `serde::Deserialize`, so the same type could
+//! be used with zbus. Requires `zvariant` and `serde` as dependencies, and conflicts with serde's
+//! own derives on the same type. Generic type parameters are bound by the implemented traits.
+//! zbus flattens structures into message arguments, so derive [`DbusStruct`] to use a
+//! [`DbusArgs`] struct as a message body.
+//!
+//! [`Append`]: dbus::arg::Append
+//! [`AppendAll`]: dbus::arg::AppendAll
 
 mod derive_args;
 mod derive_enum;
 mod derive_propmap;
 mod derive_struct;
+mod introspect;
 mod util;
+mod zbus;
 
 use darling::FromDeriveInput;
 use proc_macro_error::proc_macro_error;
@@ -22,7 +63,27 @@ use crate::util::derive_input_style_span;
 
 /// Implements [`Arg`], [`Get`] and [`Append`] for an arbitrary struct.
 ///
-/// Expects every field type to implement [`Arg`], [`Get`] and [`Append`].
+/// Expects every field type to implement [`Arg`], [`Get`] and [`Append`]. Borrowed fields like
+/// `&'a str` are supported, [`Get`] is implemented only if every borrowed field implements it.
+///
+/// # Attributes
+/// * `#[dbus_struct(validate = "path::to::fn")]`: Calls `fn(&Self) -> Result<(), E>` after
+/// decoding, [`Get`] returns [`None`] if it fails.
+/// * `#[dbus_struct(test_roundtrip)]`: Generates a unit test that appends [`Default`] value to a
+/// message and expects to read the same value back. Requires [`PartialEq`] and [`Debug`].
+/// * `#[dbus_struct(test_roundtrip = "path::to::fn")]`: Same as above, but uses `fn() -> Self`
+/// to create a value.
+/// * `#[dbus_struct(introspect)]`: Generates `introspect(name, direction) -> String`, which
+///   returns introspection XML `<arg>` element. Doc comments of a struct and its fields are
+///   added as `org.gtk.GDBus.DocString` annotation. It is a function rather than a const, since
+///   [`Arg::signature`] of field types is not const.
+/// * `#[dbus_struct(zbus)]`: Implements zbus traits, see [crate level docs](crate#zbus).
+///   Deserialized value is checked with `validate` as well.
+///
+/// # Field attributes
+/// * `#[dbus_struct(lenient)]`: Value of unexpected type or one that fails `arg_type` conversion
+///   is replaced with [`Default`] instead of failing the whole struct, and is reported with
+///   `dbus_traits::lenient::warn`. Requires `dbus-traits` as a dependency.
 ///
 /// # Examples
 /// ```
@@ -47,7 +108,8 @@ use crate::util::derive_input_style_span;
 /// [`Arg`]: dbus::arg::Arg
 /// [`Get`]: dbus::arg::Get
 /// [`Append`]: dbus::arg::Append
-#[proc_macro_derive(DbusStruct, attributes(dbus_struct))]
+/// [`Arg::signature`]: dbus::arg::Arg::signature
+#[proc_macro_derive(DbusStruct, attributes(dbus_struct, dbus))]
 #[proc_macro_error]
 pub fn derive_dbus_struct(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
@@ -65,6 +127,33 @@ pub fn derive_dbus_struct(input: proc_macro::TokenStream) -> proc_macro::TokenSt
 
 /// Implements [`ArgAll`], [`ReadAll`] and [`AppendAll`] for an arbitrary struct.
 ///
+/// Structs with lifetimes don't implement [`ReadAll`], since it cannot borrow from a message.
+///
+/// Can be derived for an enum where every variant is an alternative shape of arguments, for
+/// example replies that differ between service versions. [`ReadAll`] picks the first variant,
+/// whose field signatures match every argument of a message. [`ArgAll`] is not implemented for
+/// enums.
+///
+/// # Attributes
+/// * `#[dbus_args(validate = "path::to::fn")]`: Calls `fn(&Self) -> Result<(), E>` after
+/// decoding, [`ReadAll`] returns [`TypeMismatchError`] if it fails.
+/// * `#[dbus_args(test_roundtrip)]`, `#[dbus_args(test_roundtrip = "path::to::fn")]`: Same as
+/// for [`DbusStruct`], but goes through [`AppendAll`] and [`ReadAll`].
+/// * `#[dbus_args(builder)]`: Generates `{Name}Builder` with a setter for every field and
+///   `Name::builder()`. `build()` returns an error naming the first required field that is not set,
+///   or if built value doesn't pass `validate`.
+///   Only structs with named fields are supported.
+/// * `#[dbus_args(introspect)]`: Generates `introspect(direction) -> String`, which returns
+///   introspection XML `<arg>` element for every field, documented with field doc comments.
+///   Unnamed fields are named `arg0`, `arg1` and so on. Only structs are supported. It is a
+///   function rather than a const, since [`Arg::signature`] of field types is not const.
+///
+/// # Field attributes
+/// * `#[dbus_args(default)]`: Makes field optional for the builder, [`Default`] value is used if
+///   it's not set.
+/// * `#[dbus_args(skip)]`: Leaves field out of messages, it's [`Default`] when read. Meant for
+///   caches derived from other fields. Only supported for structs.
+///
 /// # Examples
 /// ```
 /// use dbus_derive::DbusArgs;
@@ -92,7 +181,9 @@ pub fn derive_dbus_struct(input: proc_macro::TokenStream) -> proc_macro::TokenSt
 /// [`ArgAll`]: dbus::arg::ArgAll
 /// [`ReadAll`]: dbus::arg::ReadAll
 /// [`AppendAll`]: dbus::arg::AppendAll
-#[proc_macro_derive(DbusArgs, attributes(dbus_args))]
+/// [`TypeMismatchError`]: dbus::arg::TypeMismatchError
+/// [`Arg::signature`]: dbus::arg::Arg::signature
+#[proc_macro_derive(DbusArgs, attributes(dbus_args, dbus))]
 #[proc_macro_error]
 pub fn derive_dbus_args(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
@@ -115,6 +206,10 @@ pub fn derive_dbus_args(input: proc_macro::TokenStream) -> proc_macro::TokenStre
 ///
 /// # Attributes
 /// * `#[dbus_enum(as_type = "u8")]`: Maps given enum to [`u8`]
+/// * `#[dbus_enum(as_type = "i32", discriminants)]`: Implements [`From<EnumType>`] and
+///   [`TryFrom<MappedType>`] using variant discriminants, which could be non-contiguous or
+///   negative. Requires integer `as_type`.
+/// * `#[dbus_enum(zbus)]`: Implements zbus traits, see [crate level docs](crate#zbus).
 ///
 /// # Examples
 /// ```
@@ -184,11 +279,24 @@ pub fn derive_dbus_enum(input: proc_macro::TokenStream) -> proc_macro::TokenStre
 /// Implements [`Arg`], [`Get`] and [`Append`] for a struct that behaves like [`PropMap`].
 ///
 /// Can be derived for a struct where every field is an option, uses field name as a key for
-/// accessing [`PropMap`].
+/// accessing [`PropMap`]. Every key should be unique, otherwise compilation fails.
+///
+/// # Attributes
+/// * `#[dbus_propmap(rename_all = "kebab-case")]`: Converts every field name into given case for
+///   accessing [`PropMap`]. Supports the same cases as serde.
+/// * `#[dbus_propmap(append_changed)]`: Generates `changed_since(&self, base: &Self) -> Self`,
+///   which keeps only fields that differ from `base`, and `append_changed(&self, base, ia)`,
+///   which appends the result. Requires [`PartialEq`] for every field.
+/// * `#[dbus_propmap(lenient)]`: Values of unexpected type or ones that fail `arg_type`
+///   conversion are reported with `dbus_traits::lenient::warn`, instead of silently becoming
+///   [`None`]. Requires `dbus-traits` as a dependency.
+/// * `#[dbus_propmap(zbus)]`: Implements zbus traits, see [crate level docs](crate#zbus).
 ///
 /// # Field attributes
 /// * `#[dbus_propmap(rename="key-name")]`: Overrides field name with given string for accessing
 /// [`PropMap`].
+/// * `#[dbus_propmap(alias="old-key-name")]`: Reads field from given key, if canonical one is
+///   missing. Could be repeated. Values are always written with canonical key.
 ///
 /// # Examples
 /// ```
@@ -213,7 +321,7 @@ pub fn derive_dbus_enum(input: proc_macro::TokenStream) -> proc_macro::TokenStre
 /// [`Get`]: dbus::arg::Get
 /// [`Append`]: dbus::arg::Append
 /// [`PropMap`]: dbus::arg::PropMap
-#[proc_macro_derive(DbusPropMap, attributes(dbus_propmap))]
+#[proc_macro_derive(DbusPropMap, attributes(dbus_propmap, dbus))]
 #[proc_macro_error]
 pub fn derive_dbus_propmap(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
@@ -1,8 +1,14 @@
-use darling::ast::Style;
+use darling::{
+    ast::Style,
+    util::{Flag, Override, SpannedValue},
+};
 use proc_macro2::{Span, TokenStream};
 use proc_macro_error::{abort, emit_error};
-use quote::{format_ident, quote, ToTokens};
-use syn::{spanned::Spanned, DeriveInput, Ident, Type};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
+use syn::{
+    parse_quote, spanned::Spanned, DeriveInput, Expr, GenericParam, Generics, Ident, Lifetime,
+    LifetimeParam, Path, Type,
+};
 
 pub fn derive_input_style_span(input: DeriveInput) -> Span {
     match input.data {
@@ -12,22 +18,330 @@ pub fn derive_input_style_span(input: DeriveInput) -> Span {
     }
 }
 
+/// Field conversion through `dbus_traits::DbusArg`, set with `#[dbus(arg_type = "T")]`.
+///
+/// Optional fields could add `#[dbus(sentinel = ...)]`, so [`None`] is sent as reserved value, or
+/// `#[dbus(variant)]`, so the value is sent in a variant and [`None`] as an empty marker.
+#[derive(Debug, Clone)]
+pub struct ArgType {
+    pub ty: Type,
+    pub sentinel: Option<Expr>,
+    pub variant: bool,
+}
+
+impl ArgType {
+    /// Combines field attributes, emits an error if sentinel or variant is set without arg_type,
+    /// or if both are set.
+    pub fn from_attrs(
+        arg_type: &Option<Type>,
+        sentinel: &Option<Expr>,
+        variant: &Flag,
+    ) -> Option<Self> {
+        if let (None, Some(sentinel)) = (arg_type, sentinel) {
+            emit_error!(
+                sentinel,
+                "dbus_derive - sentinel requires arg_type to be set"
+            );
+        }
+        if arg_type.is_none() && variant.is_present() {
+            emit_error!(
+                variant.span(),
+                "dbus_derive - variant requires arg_type to be set"
+            );
+        }
+        if sentinel.is_some() && variant.is_present() {
+            emit_error!(
+                variant.span(),
+                "dbus_derive - variant and sentinel are alternatives, set only one of them"
+            );
+        }
+        arg_type.as_ref().map(|ty| ArgType {
+            ty: ty.clone(),
+            sentinel: sentinel.clone(),
+            variant: variant.is_present(),
+        })
+    }
+
+    /// Type used to represent a field in a message.
+    pub fn wire(&self) -> Type {
+        match self.variant {
+            true => parse_quote!(::dbus::arg::Variant<::std::boxed::Box<dyn ::dbus::arg::RefArg>>),
+            false => self.ty.clone(),
+        }
+    }
+
+    /// Expression converting a reference `var` to a field with type `ty` into
+    /// `Result<arg_type, DbusArgError>`. `field` is added to error path.
+    pub fn encode(&self, var: &TokenStream, ty: &Type, field: &str) -> TokenStream {
+        let arg_type = &self.ty;
+        let converted = match &self.sentinel {
+            Some(sentinel) => quote_spanned!(arg_type.span() =>
+                ::dbus_traits::option_to_dbus::<_, #arg_type>(#var, #sentinel)
+            ),
+            None if self.variant => quote_spanned!(arg_type.span() =>
+                ::dbus_traits::option_to_variant::<_, #arg_type>(#var)
+            ),
+            None => quote_spanned!(arg_type.span() =>
+                <#ty as ::dbus_traits::DbusArg<#arg_type>>::to_dbus(#var)
+            ),
+        };
+        quote!(#converted.map_err(|err| err.in_field(#field)))
+    }
+
+    /// Expression converting `value` of arg_type into `Result<ty, DbusArgError>`. `field` is
+    /// added to error path.
+    pub fn decode(&self, value: &TokenStream, ty: &Type, field: &str) -> TokenStream {
+        let arg_type = &self.ty;
+        let converted = match &self.sentinel {
+            Some(sentinel) => quote_spanned!(arg_type.span() =>
+                ::dbus_traits::option_from_dbus::<_, #arg_type>(#value, &(#sentinel))
+            ),
+            None if self.variant => quote_spanned!(arg_type.span() =>
+                ::dbus_traits::option_from_variant::<_, #arg_type>(#value)
+            ),
+            None => quote_spanned!(arg_type.span() =>
+                <#ty as ::dbus_traits::DbusArg<#arg_type>>::from_dbus(#value)
+            ),
+        };
+        quote!(#converted.map_err(|err| err.in_field(#field)))
+    }
+}
+
+/// Name of a field in conversion error path, unnamed fields use their position.
+pub fn field_path_name(ident: &Option<Ident>, idx: usize) -> String {
+    match ident {
+        Some(ident) => ident.to_string(),
+        None => idx.to_string(),
+    }
+}
+
+/// Type used to represent a field in a message. Differs from field type if field is converted
+/// with `#[dbus(arg_type = "T")]`.
+pub fn wire_type(ty: &Type, arg_type: &Option<ArgType>) -> Type {
+    arg_type.as_ref().map_or_else(|| ty.clone(), ArgType::wire)
+}
+
+/// Statement converting a reference `var` to a field into an owned value of arg_type, which
+/// shadows `var`. Failed conversion returns `DbusArgError` from the enclosing function.
+pub fn to_wire(var: &Ident, field: &str, ty: &Type, arg_type: &ArgType) -> TokenStream {
+    let converted = arg_type.encode(&quote!(#var), ty, field);
+    quote_spanned!(arg_type.ty.span() => let #var = #converted?;)
+}
+
+/// Appending code for `ident`. `body` appends `self` to `ia` and could return `DbusArgError`
+/// from field conversions, which run before anything is appended.
+///
+/// If any field is converted, `body` goes into inherent `try_append`, which is returned along
+/// with a body of [`Append`] calling it. [`Append`] cannot fail, so failed conversion panics
+/// with `parent` type name and error, which includes a path to a field. Otherwise `body` is
+/// appended directly.
+///
+/// [`Append`]: dbus::arg::Append
+pub fn try_append(
+    ident: &Ident,
+    generics: &Generics,
+    converted: bool,
+    body: TokenStream,
+    parent: TokenStream,
+) -> (Option<TokenStream>, TokenStream) {
+    if !converted {
+        return (None, body);
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let method = quote! {
+        #[automatically_derived]
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// Appends `self` like `Append` does, but returns an error instead of panicking if
+            /// a field fails `arg_type` conversion. Nothing is appended then.
+            pub fn try_append(
+                &self,
+                ia: &mut ::dbus::arg::IterAppend,
+            ) -> ::core::result::Result<(), ::dbus_traits::DbusArgError> {
+                #body
+                ::core::result::Result::Ok(())
+            }
+        }
+    };
+    let append = quote! {
+        if let ::core::result::Result::Err(err) = Self::try_append(self, ia) {
+            ::core::panic!("dbus_derive - Could not convert {}: {}", #parent, err);
+        }
+    };
+    (Some(method), append)
+}
+
+/// Expression that converts `value` read from a message into `Result<ty, DbusArgError>`.
+/// Returns `None` if field is not converted.
+pub fn from_wire(
+    value: TokenStream,
+    ty: &Type,
+    field: &str,
+    arg_type: &Option<ArgType>,
+) -> Option<TokenStream> {
+    arg_type
+        .as_ref()
+        .map(|arg_type| arg_type.decode(&value, ty, field))
+}
+
+/// Wraps expression `read`, which reads field with type `wire_ty` from a message, so warnings
+/// from nested values include the field, see `dbus_traits::lenient::reading_field`.
+///
+/// Dictionaries share [`ArgType`] with arrays, so arrays are told apart by signature.
+///
+/// [`ArgType`]: dbus::arg::ArgType
+pub fn reading_field(field: &str, wire_ty: &Type, read: TokenStream) -> TokenStream {
+    quote! {
+        ::dbus_traits::lenient::reading_field(
+            #field,
+            || {
+                let sig = <#wire_ty as ::dbus::arg::Arg>::signature();
+                sig.starts_with('a') && !sig.starts_with("a{")
+            },
+            || #read,
+        )
+    }
+}
+
+/// Expression that reads a field from struct iterator `si`, replacing a value that has unexpected
+/// type or fails conversion with [`Default`] and reporting it with
+/// `dbus_traits::lenient::warn`.
+pub fn lenient_or_default(
+    field: &str,
+    ty: &Type,
+    wire_ty: &Type,
+    arg_type: &Option<ArgType>,
+) -> TokenStream {
+    let converted = from_wire(quote!(value), ty, field, arg_type)
+        .unwrap_or_else(|| quote!(::core::result::Result::Ok(value)));
+    let get = reading_field(field, wire_ty, quote!(si.get::<#wire_ty>()));
+    quote_spanned! {ty.span() => {
+        let value = match #get {
+            ::core::option::Option::Some(value) => #converted,
+            ::core::option::Option::None => ::core::result::Result::Err(
+                ::dbus_traits::DbusArgError::new(::std::format!(
+                    "expected {}, found {}",
+                    <#wire_ty as ::dbus::arg::Arg>::signature(),
+                    si.signature(),
+                ))
+                .in_field(#field),
+            ),
+        };
+        si.next();
+        value.unwrap_or_else(|err| {
+            ::dbus_traits::lenient::warn(err);
+            ::core::default::Default::default()
+        })
+    }}
+}
+
+/// Returns generics with additional lifetime `lt` for implementing a reading trait, like [`Get`]
+/// or serde's `Deserialize`.
+///
+/// If given generics declare lifetimes (struct borrows data), `lt` outlives every one of them
+/// and every field type is bound by `get_trait<lt>`, so borrowed fields could be read from
+/// message.
+///
+/// [`Get`]: dbus::arg::Get
+pub fn generics_with_get_lifetime(
+    generics: &Generics,
+    lt: &Lifetime,
+    field_types: &[Type],
+    get_trait: &TokenStream,
+) -> Generics {
+    let mut generics_with_lt = generics.clone();
+    let mut ltp = LifetimeParam::new(lt.clone());
+    ltp.bounds
+        .extend(generics.lifetimes().map(|l| l.lifetime.clone()));
+    let borrows = !ltp.bounds.is_empty();
+    // Lifetimes have to be declared before type parameters
+    generics_with_lt
+        .params
+        .insert(0, GenericParam::Lifetime(ltp));
+    if borrows {
+        let where_clause = generics_with_lt.make_where_clause();
+        for ty in field_types {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: #get_trait<#lt>));
+        }
+    }
+    generics_with_lt
+}
+
+/// Returns a unit test that pushes a value through [`Message`] and expects to read the same value.
+///
+/// Value is created with `value_fn`, or [`Default`] if it's not provided. `append` and `read`
+/// are statements that operate on `value` and `m` variables. `kind` distinguishes tests
+/// generated by different derives for the same type.
+///
+/// [`Message`]: dbus::Message
+pub fn roundtrip_test(
+    kind: &str,
+    ident: &Ident,
+    generics: &Generics,
+    value_fn: &Override<Path>,
+    append: TokenStream,
+    read: TokenStream,
+) -> TokenStream {
+    if !generics.params.is_empty() {
+        abort!(
+            generics,
+            "test_roundtrip is not supported for generic structs"
+        )
+    }
+    let value = match value_fn {
+        Override::Inherit => quote!(<#ident as ::core::default::Default>::default()),
+        Override::Explicit(value_fn) => quote!(#value_fn()),
+    };
+    let test_ident = format_ident!("{}_roundtrip_{}", kind, ident);
+    quote! {
+        #[cfg(test)]
+        #[test]
+        #[allow(non_snake_case)]
+        fn #test_ident() {
+            let value: #ident = #value;
+            let mut m = ::dbus::Message::new_method_call(
+                "org.freedesktop.DBus",
+                "/",
+                "org.freedesktop.DBus",
+                "Roundtrip",
+            )
+            .unwrap();
+            #append
+            let read_value: #ident = #read;
+            ::core::assert_eq!(value, read_value);
+        }
+    }
+}
+
 /// Returns struct constructor that is appropriate for given struct style
 pub fn fields_to_constructor(span: &Span, style: &Style, var_names: &[Ident]) -> TokenStream {
+    match style {
+        Style::Unit => {
+            abort!(span, "Unit structs not supported")
+        }
+        _ => fields_to_path_constructor(&quote!(Self), style, var_names),
+    }
+}
+
+/// Returns constructor for a struct or enum variant at given path, like `Self::Variant`
+pub fn fields_to_path_constructor(
+    path: &TokenStream,
+    style: &Style,
+    var_names: &[Ident],
+) -> TokenStream {
     match style {
         Style::Struct => {
             quote! {
-                Self { #(#var_names),* }
+                #path { #(#var_names),* }
             }
         }
         Style::Tuple => {
             quote! {
-                Self ( #(#var_names),* )
+                #path ( #(#var_names),* )
             }
         }
-        Style::Unit => {
-            abort!(span, "Unit structs not supported")
-        }
+        Style::Unit => path.clone(),
     }
 }
 
@@ -48,6 +362,92 @@ pub fn fields_to_var_idents(
         .collect()
 }
 
+/// Wraps an expression that evaluates to `Signature<'static>`, so it's built once and later calls
+/// borrow the cached string.
+///
+/// Types with type or const parameters are not cached, since a static inside of a generic function
+/// is shared between all instantiations.
+pub fn cached_signature(generics: &Generics, signature: TokenStream) -> TokenStream {
+    if generics.type_params().next().is_some() || generics.const_params().next().is_some() {
+        return signature;
+    }
+    quote! {{
+        static SIGNATURE: ::std::sync::OnceLock<::std::string::String> =
+            ::std::sync::OnceLock::new();
+        // Terminating nul is kept, so Signature could borrow the string instead of copying it
+        let signature = SIGNATURE.get_or_init(|| {
+            let signature: ::dbus::Signature<'static> = #signature;
+            ::std::format!("{}\0", signature)
+        });
+        ::dbus::Signature::from_slice(signature).expect("Cached signature should be valid")
+    }}
+}
+
+/// Returns an expression that evaluates to [`TypeMismatchError`] pointing at `iter` position.
+///
+/// [`TypeMismatchError`] cannot be constructed outside of dbus crate, so this reads a stub type
+/// that never matches anything.
+///
+/// [`TypeMismatchError`]: dbus::arg::TypeMismatchError
+pub fn rejected_read_error(iter: TokenStream) -> TokenStream {
+    quote! {{
+        struct Rejected;
+        impl ::dbus::arg::Arg for Rejected {
+            const ARG_TYPE: ::dbus::arg::ArgType = ::dbus::arg::ArgType::Invalid;
+
+            fn signature() -> ::dbus::Signature<'static> {
+                ::dbus::Signature::from("")
+            }
+        }
+        impl<'a> ::dbus::arg::Get<'a> for Rejected {
+            fn get(_: &mut ::dbus::arg::Iter<'a>) -> ::core::option::Option<Self> {
+                ::core::option::Option::None
+            }
+        }
+        let mut iter = #iter;
+        match iter.read::<Rejected>() {
+            ::core::result::Result::Err(err) => err,
+            ::core::result::Result::Ok(_) => ::core::unreachable!(),
+        }
+    }}
+}
+
+/// Converts snake_case field name into given case, same set of cases and results as serde
+/// `rename_all` on struct fields
+pub fn rename_case(name: &str, case: &SpannedValue<String>) -> String {
+    let words = name.split('_').filter(|w| !w.is_empty());
+    let capitalize = |w: &str| {
+        let mut chars = w.chars();
+        chars
+            .next()
+            .map(|c| c.to_uppercase().chain(chars).collect())
+            .unwrap_or_default()
+    };
+    match case.as_str() {
+        "lowercase" => name.to_lowercase(),
+        "UPPERCASE" => name.to_uppercase(),
+        "PascalCase" => words.map(capitalize).collect(),
+        "camelCase" => words
+            .enumerate()
+            .map(|(idx, w)| {
+                if idx == 0 {
+                    w.to_string()
+                } else {
+                    capitalize(w)
+                }
+            })
+            .collect(),
+        "snake_case" => name.to_string(),
+        "SCREAMING_SNAKE_CASE" => name.to_uppercase(),
+        "kebab-case" => name.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => name.replace('_', "-").to_uppercase(),
+        other => abort!(
+            case.span(), "dbus_derive - Unknown case \"{}\"", other;
+            help = "Use one of: lowercase, UPPERCASE, PascalCase, camelCase, snake_case, SCREAMING_SNAKE_CASE, kebab-case, SCREAMING-KEBAB-CASE"
+        ),
+    }
+}
+
 /// Extracts a generic argument idx from ty and parses it as syn::Type
 /// Emits compilation error and returns None if containted type cannot be extracted
 pub fn ty_generic_to_ty_contained(ty: &Type, container_name: &str, idx: usize) -> Option<Type> {
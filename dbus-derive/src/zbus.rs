@@ -0,0 +1,283 @@
+//! [zvariant] trait implementations, so the same types could be used with zbus.
+//!
+//! Implementations are opt-in for every type with `zbus` attribute, generated code expects
+//! `zvariant` and `serde` to be dependencies of a crate using derives.
+
+use proc_macro2::{Span, TokenStream};
+use proc_macro_error::emit_error;
+use quote::quote;
+use syn::{parse_quote, Generics, Ident, Lifetime, Path, Type};
+
+use crate::util::{generics_with_get_lifetime, wire_type, ArgType};
+
+/// Expression converting a reference `var` into something serializable, fields with `arg_type`
+/// are converted through DbusArg and fail serialization on error.
+fn serialize_value(var: &Ident, ty: &Type, field: &str, arg_type: &Option<ArgType>) -> TokenStream {
+    match arg_type {
+        Some(arg_type) => {
+            let converted = arg_type.encode(&quote!(#var), ty, field);
+            quote!(&#converted.map_err(<S::Error as ::serde::ser::Error>::custom)?)
+        }
+        None => quote!(#var),
+    }
+}
+
+/// Returns generics where every type parameter is additionally bound by `bound`.
+fn generics_with_param_bound(generics: &Generics, bound: TokenStream) -> Generics {
+    let mut bounded = generics.clone();
+    let params: Vec<_> = generics.type_params().map(|p| p.ident.clone()).collect();
+    let where_clause = bounded.make_where_clause();
+    for param in params {
+        where_clause.predicates.push(parse_quote!(#param: #bound));
+    }
+    bounded
+}
+
+/// Returns generics for [`Type`], [`Serialize`] and [`Deserialize`] implementations.
+///
+/// Type parameters are bound by traits, which are implemented, and [`Deserialize`] generics get
+/// additional `'de` lifetime, same as [`Get`] in derived [`dbus`] traits.
+///
+/// [`Type`]: zvariant::Type
+/// [`Serialize`]: serde::Serialize
+/// [`Deserialize`]: serde::Deserialize
+/// [`Get`]: dbus::arg::Get
+fn zbus_generics(
+    generics: &Generics,
+    field_types: &[Type],
+) -> (Generics, Generics, Lifetime, Generics) {
+    let type_generics = generics_with_param_bound(generics, quote!(::zvariant::Type));
+    let ser_generics =
+        generics_with_param_bound(generics, quote!(::zvariant::Type + ::serde::Serialize));
+    let de_lt = Lifetime::new("'derive_dbus_de", Span::call_site());
+    let de_generics = generics_with_get_lifetime(
+        &generics_with_param_bound(
+            generics,
+            quote!(::zvariant::Type + ::serde::Deserialize<#de_lt>),
+        ),
+        &de_lt,
+        field_types,
+        &quote!(::serde::Deserialize),
+    );
+    (type_generics, ser_generics, de_lt, de_generics)
+}
+
+/// Struct field, as seen by [`struct_impls`].
+pub struct StructField<'a> {
+    /// Variable, which holds field value in struct constructor
+    pub var: &'a Ident,
+    /// Rust type of a field
+    pub ty: &'a Type,
+    /// Name used in conversion errors
+    pub name: String,
+    /// Set for fields converted through DbusArg
+    pub arg_type: &'a Option<ArgType>,
+}
+
+/// Implements [`Type`], [`Serialize`] and [`Deserialize`] for a struct, mapped to D-Bus structure.
+///
+/// Fields are (de)serialized as a tuple, which limits structs to 16 fields. Deserialized value is
+/// rejected if it doesn't pass `validate`. Fields with `variant` hold dbus-rs variants, which
+/// zvariant can't serialize, so they are rejected.
+///
+/// [`Type`]: zvariant::Type
+/// [`Serialize`]: serde::Serialize
+/// [`Deserialize`]: serde::Deserialize
+pub fn struct_impls(
+    ident: &Ident,
+    generics: &Generics,
+    struct_constructor: &TokenStream,
+    fields: &[StructField],
+    validate: &Option<Path>,
+) -> TokenStream {
+    if let Some(f) = fields
+        .iter()
+        .find(|f| f.arg_type.as_ref().is_some_and(|arg_type| arg_type.variant))
+    {
+        emit_error!(
+            f.ty,
+            "dbus_derive - variant is not supported with zbus";
+            help = "Use sentinel instead"
+        );
+    }
+    let var_idents = fields.iter().map(|f| f.var);
+    let serialize_values = fields
+        .iter()
+        .map(|f| serialize_value(f.var, f.ty, &f.name, f.arg_type));
+    let deserialize_values = fields.iter().map(|f| match f.arg_type {
+        Some(arg_type) => {
+            let var = f.var;
+            let converted = arg_type.decode(&quote!(#var), f.ty, &f.name);
+            quote! {
+                let #var = #converted.map_err(<D::Error as ::serde::de::Error>::custom)?;
+            }
+        }
+        None => TokenStream::new(),
+    });
+    let field_types: Vec<_> = fields.iter().map(|f| wire_type(f.ty, f.arg_type)).collect();
+    let field_types = &field_types[..];
+    let validate_value = validate.as_ref().map(|validate| {
+        let rejected = format!("{ident} failed validation");
+        quote! {
+            if #validate(&value).is_err() {
+                return ::core::result::Result::Err(<D::Error as ::serde::de::Error>::custom(#rejected));
+            }
+        }
+    });
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let input_name = quote!(#ident #ty_generics);
+    let (type_generics, ser_generics, de_lt, de_generics) = zbus_generics(generics, field_types);
+    let (impl_type, _, where_type) = type_generics.split_for_impl();
+    let (impl_ser, _, where_ser) = ser_generics.split_for_impl();
+    let (impl_de, _, where_de) = de_generics.split_for_impl();
+
+    quote! {
+        #[automatically_derived]
+        impl #impl_type ::zvariant::Type for #input_name #where_type {
+            const SIGNATURE: &'static ::zvariant::Signature = &::zvariant::Signature::static_structure(
+                &[#(<#field_types as ::zvariant::Type>::SIGNATURE),*]
+            );
+        }
+
+        #[automatically_derived]
+        impl #impl_ser ::serde::Serialize for #input_name #where_ser {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error> {
+                let #struct_constructor = self;
+                ::serde::Serialize::serialize(&(#(#serialize_values,)*), serializer)
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_de ::serde::Deserialize<#de_lt> for #input_name #where_de {
+            fn deserialize<D: ::serde::Deserializer<#de_lt>>(deserializer: D) -> ::core::result::Result<Self, D::Error> {
+                let (#(#var_idents,)*) = <(#(#field_types,)*) as ::serde::Deserialize>::deserialize(deserializer)?;
+                #(#deserialize_values)*
+                let value = #struct_constructor;
+                #validate_value
+                ::core::result::Result::Ok(value)
+            }
+        }
+    }
+}
+
+/// Implements [`Type`], [`Serialize`] and [`Deserialize`] for an enum that behaves like `as_type`.
+///
+/// [`Type`]: zvariant::Type
+/// [`Serialize`]: serde::Serialize
+/// [`Deserialize`]: serde::Deserialize
+pub fn enum_impls(ident: &Ident, generics: &Generics, as_type: &Type) -> TokenStream {
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let input_name = quote!(#ident #ty_generics);
+    let (type_generics, ser_generics, de_lt, de_generics) = zbus_generics(generics, &[]);
+    let (impl_type, _, where_type) = type_generics.split_for_impl();
+    let (impl_ser, _, where_ser) = ser_generics.split_for_impl();
+    let (impl_de, _, where_de) = de_generics.split_for_impl();
+    let invalid_value = format!("invalid {ident} representation");
+
+    quote! {
+        #[automatically_derived]
+        impl #impl_type ::zvariant::Type for #input_name #where_type {
+            const SIGNATURE: &'static ::zvariant::Signature = <#as_type as ::zvariant::Type>::SIGNATURE;
+        }
+
+        #[automatically_derived]
+        impl #impl_ser ::serde::Serialize for #input_name #where_ser {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error> {
+                ::serde::Serialize::serialize(&::core::convert::Into::<#as_type>::into(*self), serializer)
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_de ::serde::Deserialize<#de_lt> for #input_name #where_de {
+            fn deserialize<D: ::serde::Deserializer<#de_lt>>(deserializer: D) -> ::core::result::Result<Self, D::Error> {
+                let value = <#as_type as ::serde::Deserialize>::deserialize(deserializer)?;
+                ::core::convert::TryFrom::<#as_type>::try_from(value)
+                    .map_err(|_| <D::Error as ::serde::de::Error>::custom(#invalid_value))
+            }
+        }
+    }
+}
+
+/// Implements [`Type`], [`Serialize`] and [`Deserialize`] for a struct that behaves like `a{sv}`.
+///
+/// Keys that are missing or have unexpected type are deserialized as [`None`], same as with
+/// dbus-rs [`Get`] implementation. `var_keys` contain canonical key, followed by aliases.
+/// `field_types` are types contained in [`Option`], `arg_types` are set for fields converted
+/// through DbusArg.
+///
+/// [`Type`]: zvariant::Type
+/// [`Serialize`]: serde::Serialize
+/// [`Deserialize`]: serde::Deserialize
+/// [`Get`]: dbus::arg::Get
+pub fn propmap_impls(
+    ident: &Ident,
+    generics: &Generics,
+    self_constructor: &TokenStream,
+    var_names: &[Ident],
+    var_keys: &[Vec<String>],
+    field_types: &[Type],
+    arg_types: &[Option<ArgType>],
+) -> TokenStream {
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let value_ident = Ident::new("f", Span::call_site());
+    let serialize_values = field_types
+        .iter()
+        .zip(var_keys)
+        .zip(arg_types)
+        .map(|((ty, keys), arg_type)| serialize_value(&value_ident, ty, &keys[0], arg_type));
+    let deserialize_values = field_types.iter().zip(var_keys).zip(arg_types).map(|((ty, keys), arg_type)| {
+        let wire_ty = wire_type(ty, arg_type);
+        let value = quote! {
+            .and_then(|f| <#wire_ty as ::core::convert::TryFrom<::zvariant::OwnedValue>>::try_from(f).ok())
+        };
+        match arg_type {
+            Some(arg_type) => {
+                let converted = arg_type.decode(&quote!(f), ty, &keys[0]);
+                quote!(#value.and_then(|f| #converted.ok()))
+            }
+            None => value,
+        }
+    });
+    let var_name_strs = var_keys.iter().map(|keys| &keys[0]);
+    let map_removals = var_keys.iter().map(|keys| {
+        let (key, aliases) = (&keys[0], &keys[1..]);
+        quote!(m.remove(#key) #(.or_else(|| m.remove(#aliases)))*)
+    });
+    let input_name = quote!(#ident #ty_generics);
+    let (type_generics, ser_generics, de_lt, de_generics) = zbus_generics(generics, &[]);
+    let (impl_type, _, where_type) = type_generics.split_for_impl();
+    let (impl_ser, _, where_ser) = ser_generics.split_for_impl();
+    let (impl_de, _, where_de) = de_generics.split_for_impl();
+
+    quote! {
+        #[automatically_derived]
+        impl #impl_type ::zvariant::Type for #input_name #where_type {
+            const SIGNATURE: &'static ::zvariant::Signature = &::zvariant::Signature::static_dict(
+                &::zvariant::Signature::Str,
+                &::zvariant::Signature::Variant,
+            );
+        }
+
+        #[automatically_derived]
+        impl #impl_ser ::serde::Serialize for #input_name #where_ser {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error> {
+                use ::serde::ser::SerializeMap;
+                let #self_constructor = self;
+                let mut m = serializer.serialize_map(::core::option::Option::None)?;
+                #(if let ::core::option::Option::Some(#value_ident) = #var_names {
+                    m.serialize_entry(#var_name_strs, &::zvariant::as_value::Serialize(#serialize_values))?;
+                })*
+                m.end()
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_de ::serde::Deserialize<#de_lt> for #input_name #where_de {
+            fn deserialize<D: ::serde::Deserializer<#de_lt>>(deserializer: D) -> ::core::result::Result<Self, D::Error> {
+                let mut m = <::std::collections::HashMap<::std::string::String, ::zvariant::OwnedValue> as ::serde::Deserialize>::deserialize(deserializer)?;
+                #(let #var_names = #map_removals #deserialize_values;)*
+                ::core::result::Result::Ok(#self_constructor)
+            }
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use dbus::arg::{Append, Arg, Iter, IterAppend, PropMap, ReadAll, RefArg, Variant};
+use dbus_derive::{DbusArgs, DbusPropMap, DbusStruct};
+use dbus_traits::{
+    testing::{new_message, roundtrip_args},
+    DbusArg, DbusArgError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scale {
+    Normal,
+    Double,
+}
+
+/// Sent as percents
+impl DbusArg<u32> for Scale {
+    fn to_dbus(&self) -> Result<u32, DbusArgError> {
+        Ok(match self {
+            Scale::Normal => 100,
+            Scale::Double => 200,
+        })
+    }
+
+    fn from_dbus(value: u32) -> Result<Self, DbusArgError> {
+        match value {
+            100 => Ok(Scale::Normal),
+            200 => Ok(Scale::Double),
+            _ => Err("Unsupported scale".into()),
+        }
+    }
+}
+
+#[derive(DbusStruct, Debug, PartialEq)]
+struct Monitor {
+    #[dbus(arg_type = "u32")]
+    id: usize,
+    #[dbus(arg_type = "u32")]
+    scale: Scale,
+    #[dbus(arg_type = "Vec<u32>")]
+    modes: [usize; 2],
+}
+
+#[derive(DbusStruct, Debug, PartialEq)]
+struct CrtcChange {
+    id: u32,
+    #[dbus(arg_type = "i32", sentinel = -1)]
+    mode_id: Option<u32>,
+}
+
+#[derive(DbusStruct, Debug, PartialEq)]
+struct ScalesChange {
+    id: u32,
+    #[dbus(arg_type = "Vec<u32>", variant)]
+    scales: Option<Vec<Scale>>,
+}
+
+#[derive(DbusArgs, Debug, PartialEq)]
+struct SetScaleArgs {
+    #[dbus(arg_type = "u32")]
+    serial: usize,
+    #[dbus(arg_type = "u32")]
+    scale: Scale,
+}
+
+#[derive(DbusPropMap, Debug, PartialEq)]
+struct MonitorProps {
+    #[dbus(arg_type = "u32")]
+    scale: Option<Scale>,
+    name: Option<String>,
+}
+
+#[test]
+fn struct_signature_uses_arg_type() {
+    assert_eq!("(uuau)", Monitor::signature().to_string());
+}
+
+#[test]
+fn struct_converted_fields() {
+    let monitor = Monitor {
+        id: 3,
+        scale: Scale::Double,
+        modes: [1, 2],
+    };
+    let mut m = new_message();
+    IterAppend::new(&mut m).append(&monitor);
+    let (id, scale, modes): (u32, u32, Vec<u32>) = Iter::new(&m).read().unwrap();
+    assert_eq!((3, 200, vec![1, 2]), (id, scale, modes));
+    assert_eq!(monitor, Iter::new(&m).read().unwrap());
+
+    let mut m = new_message();
+    IterAppend::new(&mut m).append((3u32, 150u32, vec![1u32, 2]));
+    assert!(Iter::new(&m).read::<Monitor>().is_err());
+}
+
+#[test]
+#[should_panic(expected = "Could not convert Monitor: id: ")]
+fn struct_append_conversion_failure() {
+    let monitor = Monitor {
+        id: u32::MAX as usize + 1,
+        scale: Scale::Normal,
+        modes: [1, 2],
+    };
+    monitor.append_by_ref(&mut IterAppend::new(&mut new_message()));
+}
+
+#[test]
+#[should_panic(expected = "Could not convert Monitor: modes[1]: ")]
+fn struct_append_conversion_failure_path() {
+    let monitor = Monitor {
+        id: 1,
+        scale: Scale::Normal,
+        modes: [1, u32::MAX as usize + 1],
+    };
+    monitor.append_by_ref(&mut IterAppend::new(&mut new_message()));
+}
+
+#[test]
+fn struct_try_append_conversion_failure() {
+    let monitor = Monitor {
+        id: 1,
+        scale: Scale::Normal,
+        modes: [1, u32::MAX as usize + 1],
+    };
+    let mut m = new_message();
+    let err = monitor
+        .try_append(&mut IterAppend::new(&mut m))
+        .unwrap_err();
+    assert_eq!("modes[1]", err.path());
+    assert!(Iter::new(&m).read::<Monitor>().is_err());
+
+    let props = MonitorProps {
+        scale: Some(Scale::Double),
+        name: None,
+    };
+    let mut m = new_message();
+    props.try_append(&mut IterAppend::new(&mut m)).unwrap();
+    assert_eq!(props, Iter::new(&m).read().unwrap());
+}
+
+#[test]
+fn args_converted_fields() {
+    let args = SetScaleArgs {
+        serial: 1,
+        scale: Scale::Normal,
+    };
+    assert_eq!(args, roundtrip_args(&args).unwrap());
+
+    let mut m = new_message();
+    IterAppend::new(&mut m).append(1u32);
+    IterAppend::new(&mut m).append(50u32);
+    assert!(<SetScaleArgs as ReadAll>::read(&mut Iter::new(&m)).is_err());
+}
+
+#[test]
+fn propmap_converted_fields() {
+    let props = MonitorProps {
+        scale: Some(Scale::Double),
+        name: Some("DP-1".to_string()),
+    };
+    let mut m = new_message();
+    IterAppend::new(&mut m).append(&props);
+    let map: PropMap = Iter::new(&m).read().unwrap();
+    assert_eq!(Some(200), map["scale"].as_u64());
+    assert_eq!(props, Iter::new(&m).read().unwrap());
+
+    // Value that cannot be converted is treated as missing
+    let mut map = PropMap::new();
+    map.insert("scale".to_string(), Variant(Box::new(50u32)));
+    let mut m = new_message();
+    IterAppend::new(&mut m).append(&map);
+    let props: MonitorProps = Iter::new(&m).read().unwrap();
+    assert_eq!(None, props.scale);
+}
+
+#[test]
+fn sentinel_for_none() {
+    assert_eq!("(ui)", CrtcChange::signature().to_string());
+
+    let mut m = new_message();
+    let mut ia = IterAppend::new(&mut m);
+    ia.append(&CrtcChange {
+        id: 1,
+        mode_id: None,
+    });
+    ia.append(&CrtcChange {
+        id: 2,
+        mode_id: Some(5),
+    });
+    ia.append((3u32, -2i32));
+
+    let mut i = Iter::new(&m);
+    assert_eq!((1u32, -1i32), i.get().unwrap());
+    assert_eq!(
+        CrtcChange {
+            id: 1,
+            mode_id: None
+        },
+        i.read().unwrap()
+    );
+    assert_eq!(
+        CrtcChange {
+            id: 2,
+            mode_id: Some(5)
+        },
+        i.read().unwrap()
+    );
+    // Negative values other than sentinel don't fit into u32
+    assert!(i.read::<CrtcChange>().is_err());
+}
+
+#[test]
+fn variant_for_none() {
+    assert_eq!("(uv)", ScalesChange::signature().to_string());
+
+    let none = ScalesChange {
+        id: 1,
+        scales: None,
+    };
+    let some = ScalesChange {
+        id: 2,
+        scales: Some(vec![Scale::Double]),
+    };
+    let mut m = new_message();
+    let mut ia = IterAppend::new(&mut m);
+    ia.append(&none);
+    ia.append(&some);
+    ia.append((3u32, Variant(vec![0u32])));
+
+    let mut i = Iter::new(&m);
+    let (_, marker): (u32, Variant<Box<dyn RefArg>>) = i.get().unwrap();
+    assert_eq!("av", marker.0.signature().to_string());
+    assert_eq!(none, i.read().unwrap());
+    assert_eq!(some, i.read().unwrap());
+    // 0 is not a valid scale
+    assert!(i.read::<ScalesChange>().is_err());
+}
@@ -0,0 +1,64 @@
+use dbus::arg::{AppendAll, IterAppend, PropMap};
+use dbus_derive::DbusArgs;
+use dbus_traits::testing::new_message;
+
+/// Reply that gained an additional argument in a newer version of a service
+#[derive(DbusArgs, Debug, PartialEq)]
+#[dbus_args(test_roundtrip = "GetStateReturn::sample")]
+pub enum GetStateReturn {
+    Modern {
+        serial: u32,
+        monitors: Vec<String>,
+        properties: Vec<(String, u32)>,
+    },
+    Legacy(u32, Vec<String>),
+    Empty,
+}
+
+impl GetStateReturn {
+    fn sample() -> Self {
+        Self::Legacy(1, vec!["HDMI-1".to_string()])
+    }
+}
+
+#[test]
+fn read_by_shape() {
+    let monitors = vec!["HDMI-1".to_string(), "DP-1".to_string()];
+
+    let legacy = new_message().append2(1u32, monitors.clone());
+    assert_eq!(
+        GetStateReturn::Legacy(1, monitors.clone()),
+        legacy.read_all().unwrap()
+    );
+
+    let modern = new_message().append3(2u32, monitors.clone(), vec![("scale".to_string(), 2u32)]);
+    assert_eq!(
+        GetStateReturn::Modern {
+            serial: 2,
+            monitors: monitors.clone(),
+            properties: vec![("scale".to_string(), 2)]
+        },
+        modern.read_all().unwrap()
+    );
+
+    assert_eq!(GetStateReturn::Empty, new_message().read_all().unwrap());
+
+    let unknown = new_message().append2(1u32, PropMap::new());
+    assert!(unknown.read_all::<GetStateReturn>().is_err());
+}
+
+#[test]
+fn append_variant() {
+    let mut m = new_message();
+    GetStateReturn::Modern {
+        serial: 3,
+        monitors: vec![],
+        properties: vec![],
+    }
+    .append(&mut IterAppend::new(&mut m));
+
+    let (serial, monitors, properties): (u32, Vec<String>, Vec<(String, u32)>) = m.read3().unwrap();
+    assert_eq!(3, serial);
+    assert!(monitors.is_empty());
+    assert!(properties.is_empty());
+}
@@ -0,0 +1,47 @@
+use std::cell::Cell;
+
+use dbus::arg::ArgAll;
+use dbus_derive::DbusArgs;
+use dbus_traits::testing::roundtrip_args;
+
+#[derive(DbusArgs, Debug, PartialEq)]
+#[dbus_args(builder)]
+pub struct CountedArgs {
+    serial: u32,
+    #[dbus_args(skip)]
+    lookups: Cell<u32>,
+    names: Vec<String>,
+}
+
+#[test]
+fn skipped_field_not_sent() {
+    let mut sig = String::new();
+    CountedArgs::strs_sig(("serial", "names"), |_, arg_sig| {
+        sig += &arg_sig.to_string()
+    });
+    assert_eq!("uas", sig);
+
+    let args = CountedArgs {
+        serial: 7,
+        lookups: Cell::new(3),
+        names: vec!["HDMI-1".to_string()],
+    };
+    let res = roundtrip_args(&args).unwrap();
+    assert_eq!(
+        CountedArgs {
+            lookups: Cell::new(0),
+            ..args
+        },
+        res
+    );
+}
+
+#[test]
+fn skipped_field_not_in_builder() {
+    let args = CountedArgs::builder()
+        .serial(1u32)
+        .names(vec![])
+        .build()
+        .unwrap();
+    assert_eq!(0, args.lookups.get());
+}
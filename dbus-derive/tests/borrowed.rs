@@ -0,0 +1,59 @@
+use dbus::arg::{AppendAll, Arg, ArgAll, Iter, IterAppend};
+use dbus_derive::{DbusArgs, DbusStruct};
+use dbus_traits::testing::new_message;
+
+#[derive(DbusStruct, DbusArgs, Debug, PartialEq)]
+pub struct ArgsBorrowed<'a> {
+    pub arg_str_ref: &'a str,
+    pub arg_slice: &'a [u32],
+}
+
+#[derive(DbusStruct, Debug, PartialEq)]
+pub struct ArgsBorrowedUnnamed<'a, 'b>(pub &'a str, pub &'b [f64]);
+
+#[test]
+fn signature() {
+    let mut args_sig = String::new();
+    ArgsBorrowed::strs_sig(("arg_str_ref", "arg_slice"), |_, sig| {
+        args_sig += &sig.to_string();
+    });
+
+    assert_eq!("sau", args_sig);
+    assert_eq!("(sau)", ArgsBorrowed::signature().to_string());
+    assert_eq!("(sad)", ArgsBorrowedUnnamed::signature().to_string());
+}
+
+#[test]
+fn roundtrip_struct() {
+    let owned_str = "HDMI-1".to_string();
+    let owned_slice = vec![1, 2, 3];
+    let arg = ArgsBorrowed {
+        arg_str_ref: &owned_str,
+        arg_slice: &owned_slice,
+    };
+    let arg_unnamed = ArgsBorrowedUnnamed(&owned_str, &[0.5, 1.0]);
+
+    let mut m = new_message();
+    let mut ia = IterAppend::new(&mut m);
+    ia.append(&arg);
+    ia.append(&arg_unnamed);
+
+    let mut i = Iter::new(&m);
+    assert_eq!(arg, i.read::<ArgsBorrowed>().unwrap());
+    assert_eq!(arg_unnamed, i.read::<ArgsBorrowedUnnamed>().unwrap());
+}
+
+#[test]
+fn append_args() {
+    let arg = ArgsBorrowed {
+        arg_str_ref: "HDMI-1",
+        arg_slice: &[1, 2, 3],
+    };
+
+    let mut m = new_message();
+    arg.append(&mut IterAppend::new(&mut m));
+
+    let (arg_str, arg_vec): (String, Vec<u32>) = m.read2().unwrap();
+    assert_eq!(arg.arg_str_ref, arg_str);
+    assert_eq!(arg.arg_slice, arg_vec);
+}
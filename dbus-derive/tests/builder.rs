@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use dbus_derive::DbusArgs;
+use dbus_traits::testing::roundtrip_args;
+
+#[derive(DbusArgs, Debug, PartialEq)]
+#[dbus_args(builder)]
+pub struct ConfigArgs {
+    serial: u32,
+    persistent: bool,
+    #[dbus_args(default)]
+    monitors: Vec<(String, u32)>,
+    #[dbus_args(default)]
+    properties: HashMap<String, u32>,
+}
+
+#[derive(DbusArgs, Debug)]
+#[dbus_args(builder, validate = "validate_scale")]
+pub struct SetScaleArgs {
+    scale: f64,
+}
+
+fn validate_scale(args: &SetScaleArgs) -> Result<(), &'static str> {
+    match args.scale > 0.0 {
+        true => Ok(()),
+        false => Err("scale should be positive"),
+    }
+}
+
+#[test]
+fn builds_with_defaults() {
+    let args = ConfigArgs::builder()
+        .serial(7u32)
+        .persistent(true)
+        .build()
+        .unwrap();
+    assert_eq!(
+        ConfigArgs {
+            serial: 7,
+            persistent: true,
+            monitors: vec![],
+            properties: HashMap::new(),
+        },
+        args
+    );
+}
+
+#[test]
+fn missing_required_field() {
+    let err = ConfigArgs::builder()
+        .persistent(false)
+        .monitors(vec![("DP-1".to_string(), 0)])
+        .build()
+        .unwrap_err();
+    assert_eq!("ConfigArgs: required field `serial` is not set", err);
+}
+
+#[test]
+fn built_args_validated() {
+    assert!(SetScaleArgs::builder().scale(1.5).build().is_ok());
+    let err = SetScaleArgs::builder().scale(0.0).build().unwrap_err();
+    assert_eq!("SetScaleArgs failed validation", err);
+}
+
+#[test]
+fn built_args_roundtrip() {
+    let args = ConfigArgs::builder()
+        .serial(1u32)
+        .persistent(false)
+        .monitors(vec![("HDMI-1".to_string(), 2)])
+        .build()
+        .unwrap();
+
+    assert_eq!(args, roundtrip_args(&args).unwrap());
+}
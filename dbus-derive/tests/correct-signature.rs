@@ -3,7 +3,7 @@ use std::{
     error::Error,
 };
 
-use dbus::arg::{Arg, ArgAll};
+use dbus::arg::{Append, Arg, ArgAll, Get};
 use dbus_derive::{DbusArgs, DbusEnum, DbusPropMap, DbusStruct};
 
 #[derive(DbusStruct, DbusArgs, Default, Debug)]
@@ -62,6 +62,11 @@ pub struct PropsArg {
     pub arg2: Option<u32>,
 }
 
+#[derive(DbusStruct, Default, Debug)]
+pub struct Generic<T: Arg + Append + for<'a> Get<'a>> {
+    pub value: T,
+}
+
 #[test]
 fn signature() -> Result<(), Box<dyn Error>> {
     let nested_sig = "(iusada{nq}a{xt}ya{sv})".to_string();
@@ -85,3 +90,12 @@ fn signature() -> Result<(), Box<dyn Error>> {
     assert_eq!(full_sig_struct, ArgsUnnamed::signature().to_string());
     Ok(())
 }
+
+#[test]
+fn cached_signature() {
+    // Repeated calls return cached signature
+    assert_eq!(NestedArg::signature(), NestedArg::signature());
+    // Instantiations of a generic struct don't share a signature
+    assert_eq!("(u)", Generic::<u32>::signature().to_string());
+    assert_eq!("(s)", Generic::<String>::signature().to_string());
+}
@@ -0,0 +1,37 @@
+use dbus::arg::{Arg, Iter, IterAppend};
+use dbus_derive::DbusEnum;
+use dbus_traits::testing::new_message;
+
+#[derive(DbusEnum, Debug, Clone, Copy, PartialEq)]
+#[dbus_enum(as_type = "i32", discriminants)]
+pub enum ModeState {
+    Disabled = -1,
+    Default,
+    Preferred = 4,
+    Custom = 10,
+}
+
+#[test]
+fn conversions() {
+    assert_eq!(-1, i32::from(ModeState::Disabled));
+    assert_eq!(0, i32::from(ModeState::Default));
+    assert_eq!(4, i32::from(ModeState::Preferred));
+    assert_eq!(Ok(ModeState::Custom), ModeState::try_from(10));
+    assert_eq!(Ok(ModeState::Disabled), ModeState::try_from(-1));
+    assert!(ModeState::try_from(1).is_err());
+}
+
+#[test]
+fn roundtrip() {
+    let mut m = new_message();
+    let mut ia = IterAppend::new(&mut m);
+    ia.append(ModeState::Disabled);
+    ia.append(ModeState::Custom);
+    ia.append(2i32);
+
+    let mut i = Iter::new(&m);
+    assert_eq!("i", ModeState::signature().to_string());
+    assert_eq!(ModeState::Disabled, i.read().unwrap());
+    assert_eq!(ModeState::Custom, i.read().unwrap());
+    assert!(i.read::<ModeState>().is_err());
+}
@@ -0,0 +1,43 @@
+use dbus_derive::{DbusArgs, DbusStruct};
+
+/// Mode supported by a monitor
+#[derive(DbusStruct)]
+#[dbus_struct(introspect)]
+struct Mode {
+    /// refresh rate in "Hz"
+    frequency: f64,
+    id: u32,
+}
+
+#[derive(DbusArgs)]
+#[dbus_args(introspect)]
+struct ChangeBacklightArgs {
+    serial: u32,
+    /// the API id of the output
+    output: u32,
+    /// new value, <0 means {unchanged}
+    value: i32,
+    modes: Vec<Mode>,
+}
+
+#[test]
+fn args_introspection() {
+    assert_eq!(
+        [
+            r#"<arg name="serial" type="u" direction="in"/>"#,
+            r#"<arg name="output" type="u" direction="in"><annotation name="org.gtk.GDBus.DocString" value="the API id of the output"/></arg>"#,
+            r#"<arg name="value" type="i" direction="in"><annotation name="org.gtk.GDBus.DocString" value="new value, &lt;0 means {unchanged}"/></arg>"#,
+            r#"<arg name="modes" type="a(du)" direction="in"/>"#,
+        ]
+        .join("\n"),
+        ChangeBacklightArgs::introspect("in")
+    );
+}
+
+#[test]
+fn struct_introspection() {
+    assert_eq!(
+        r#"<arg name="mode" type="(du)" direction="out"><annotation name="org.gtk.GDBus.DocString" value="Mode supported by a monitor&#10;* frequency: refresh rate in &quot;Hz&quot;"/></arg>"#,
+        Mode::introspect("mode", "out")
+    );
+}
@@ -0,0 +1,102 @@
+use dbus::arg::{Iter, IterAppend, PropMap, RefArg, Variant};
+use dbus_derive::{DbusEnum, DbusPropMap, DbusStruct};
+use dbus_traits::{lenient, testing::new_message};
+
+#[derive(DbusEnum, Debug, Default, Clone, Copy, PartialEq)]
+#[dbus_enum(as_type = "u32", discriminants)]
+pub enum Rotation {
+    #[default]
+    Normal = 0,
+    Left,
+}
+
+#[derive(DbusStruct, Debug, PartialEq)]
+pub struct Crtc {
+    pub id: u32,
+    #[dbus_struct(lenient)]
+    pub rotation: Rotation,
+    #[dbus(arg_type = "i32", lenient)]
+    pub width: u32,
+    pub name: String,
+}
+
+#[derive(DbusStruct, Debug, PartialEq)]
+pub struct Screen {
+    pub crtcs: Vec<Crtc>,
+    #[dbus_struct(lenient)]
+    pub rotation: Rotation,
+}
+
+#[derive(DbusPropMap, Debug, Default, PartialEq)]
+#[dbus_propmap(lenient)]
+pub struct Props {
+    pub vendor: Option<String>,
+    pub backlight: Option<i64>,
+}
+
+#[test]
+fn invalid_fields_replaced() {
+    let mut m = new_message();
+    IterAppend::new(&mut m).append((1u32, 9u32, -1920i32, "HDMI-1"));
+
+    let (crtc, warnings) = lenient::collect(|| Iter::new(&m).read::<Crtc>());
+    assert_eq!(
+        Crtc {
+            id: 1,
+            rotation: Rotation::Normal,
+            width: 0,
+            name: "HDMI-1".to_string(),
+        },
+        crtc.unwrap()
+    );
+    let paths: Vec<_> = warnings.iter().map(|w| w.path()).collect();
+    assert_eq!(vec!["Crtc.rotation", "Crtc.width"], paths);
+}
+
+#[test]
+fn unexpected_type_replaced() {
+    let mut m = new_message();
+    IterAppend::new(&mut m).append((1u32, "left", 1920i32, "HDMI-1"));
+
+    let (crtc, warnings) = lenient::collect(|| Iter::new(&m).read::<Crtc>());
+    let crtc = crtc.unwrap();
+    assert_eq!((Rotation::Normal, 1920), (crtc.rotation, crtc.width));
+    assert_eq!(
+        "Crtc.rotation: expected u, found s",
+        warnings[0].to_string()
+    );
+}
+
+#[test]
+fn nested_warnings_have_path() {
+    let mut m = new_message();
+    let crtcs = vec![(1u32, 0u32, 1920i32, "HDMI-1"), (2, 1, -1, "DP-1")];
+    IterAppend::new(&mut m).append((crtcs, 7u32));
+
+    let (screen, warnings) = lenient::collect(|| Iter::new(&m).read::<Screen>());
+    assert_eq!(Rotation::Left, screen.unwrap().crtcs[1].rotation);
+    let paths: Vec<_> = warnings.iter().map(|w| w.path()).collect();
+    assert_eq!(vec!["Screen.crtcs[1].width", "Screen.rotation"], paths);
+}
+
+#[test]
+fn mistyped_props_reported() {
+    let mut props = PropMap::new();
+    props.insert(
+        "vendor".to_string(),
+        Variant(Box::new("ACME".to_string()) as Box<dyn RefArg>),
+    );
+    props.insert("backlight".to_string(), Variant(Box::new(50i32)));
+    let mut m = new_message();
+    IterAppend::new(&mut m).append(props);
+
+    let (read, warnings) = lenient::collect(|| Iter::new(&m).read::<Props>());
+    assert_eq!(
+        Props {
+            vendor: Some("ACME".to_string()),
+            backlight: None,
+        },
+        read.unwrap()
+    );
+    assert_eq!("Props.backlight", warnings[0].path());
+}
@@ -0,0 +1,53 @@
+use dbus::arg::{Iter, IterAppend, PropMap};
+use dbus_derive::DbusPropMap;
+use dbus_traits::testing::new_message;
+
+#[derive(DbusPropMap, Debug, Default, Clone, PartialEq)]
+#[dbus_propmap(append_changed)]
+struct Props {
+    #[dbus_propmap(rename = "display-name")]
+    display_name: Option<String>,
+    backlight: Option<i64>,
+    primary: Option<bool>,
+    presentation: Option<bool>,
+}
+
+#[test]
+fn only_changed_keys_appended() {
+    let base = Props {
+        display_name: Some("Built-in display".to_string()),
+        backlight: Some(50),
+        primary: Some(true),
+        presentation: None,
+    };
+    let new = Props {
+        backlight: Some(70),
+        presentation: Some(false),
+        ..base.clone()
+    };
+
+    let mut m = new_message();
+    new.append_changed(&base, &mut IterAppend::new(&mut m));
+    let map: PropMap = Iter::new(&m).read().unwrap();
+
+    let mut keys: Vec<_> = map.keys().map(String::as_str).collect();
+    keys.sort();
+    assert_eq!(vec!["backlight", "presentation"], keys);
+    assert_eq!(
+        Props {
+            backlight: Some(70),
+            presentation: Some(false),
+            ..Default::default()
+        },
+        Iter::new(&m).read().unwrap()
+    );
+}
+
+#[test]
+fn unset_fields_are_not_changes() {
+    let base = Props {
+        primary: Some(true),
+        ..Default::default()
+    };
+    assert_eq!(Props::default(), Props::default().changed_since(&base));
+}
@@ -1,8 +1,7 @@
-use dbus::{
-    arg::{Append, Iter, IterAppend, PropMap, Variant},
-    Message,
-};
+use dbus::arg::{Append, Iter, IterAppend, PropMap, Variant};
 use dbus_derive::DbusPropMap;
+use dbus_traits::testing::new_message;
+use serde::Serialize;
 
 #[derive(DbusPropMap)]
 struct MappedToPropMap {
@@ -24,13 +23,7 @@ fn propmap_to_struct() {
         Variant(Box::new(field2.clone())),
     );
 
-    let mut m = Message::new_method_call(
-        "org.freedesktop.DBus",
-        "/",
-        "org.freedesktop.DBus",
-        "ListNames",
-    )
-    .unwrap();
+    let mut m = new_message();
     let mut ia = IterAppend::new(&mut m);
     map.append(&mut ia);
 
@@ -40,3 +33,92 @@ fn propmap_to_struct() {
     assert_eq!(Some(field1), res.field1);
     assert_eq!(Some(field2), res.field2_renamed);
 }
+
+#[derive(DbusPropMap, Debug, PartialEq)]
+#[dbus_propmap(rename_all = "kebab-case")]
+struct RenamedAll {
+    display_name: Option<String>,
+    #[dbus_propmap(alias = "is_primary", alias = "main")]
+    primary: Option<bool>,
+    #[dbus_propmap(rename = "layoutMode")]
+    layout_mode: Option<u32>,
+}
+
+#[test]
+fn propmap_rename_all_and_aliases() {
+    let mut map = PropMap::new();
+    map.insert(
+        "display-name".to_string(),
+        Variant(Box::new("Built-in display".to_string())),
+    );
+    map.insert("main".to_string(), Variant(Box::new(true)));
+    map.insert("layoutMode".to_string(), Variant(Box::new(2u32)));
+
+    let mut m = new_message();
+    map.append(&mut IterAppend::new(&mut m));
+    let res: RenamedAll = Iter::new(&m).read().unwrap();
+    assert_eq!(
+        RenamedAll {
+            display_name: Some("Built-in display".to_string()),
+            primary: Some(true),
+            layout_mode: Some(2),
+        },
+        res
+    );
+
+    // Aliases are only used for reading, canonical key is always written
+    let mut m = new_message();
+    IterAppend::new(&mut m).append(&res);
+    let written: PropMap = Iter::new(&m).read().unwrap();
+    assert!(written.contains_key("primary"));
+    assert!(!written.contains_key("main"));
+}
+
+/// Defines a struct renamed with `rename_all = $case` by both DbusPropMap and serde, and returns
+/// keys written by each of them.
+macro_rules! renamed_keys {
+    ($case:literal) => {{
+        #[derive(DbusPropMap, Serialize)]
+        #[dbus_propmap(rename_all = $case)]
+        #[serde(rename_all = $case)]
+        struct Renamed {
+            name: Option<u32>,
+            display_name: Option<u32>,
+            max_bpc_2: Option<u32>,
+        }
+
+        let value = Renamed {
+            name: Some(1),
+            display_name: Some(2),
+            max_bpc_2: Some(3),
+        };
+        let mut m = new_message();
+        IterAppend::new(&mut m).append(&value);
+        let written: PropMap = Iter::new(&m).read().unwrap();
+        let mut keys: Vec<_> = written.into_keys().collect();
+        keys.sort();
+        let serde_json::Value::Object(serialized) = serde_json::to_value(&value).unwrap() else {
+            unreachable!()
+        };
+        let mut serde_keys: Vec<_> = serialized.into_iter().map(|(key, _)| key).collect();
+        serde_keys.sort();
+        (keys, serde_keys)
+    }};
+}
+
+#[test]
+fn rename_all_matches_serde() {
+    let cases = [
+        renamed_keys!("lowercase"),
+        renamed_keys!("UPPERCASE"),
+        renamed_keys!("PascalCase"),
+        renamed_keys!("camelCase"),
+        renamed_keys!("snake_case"),
+        renamed_keys!("SCREAMING_SNAKE_CASE"),
+        renamed_keys!("kebab-case"),
+        renamed_keys!("SCREAMING-KEBAB-CASE"),
+    ];
+    for (keys, serde_keys) in cases {
+        assert_eq!(serde_keys, keys);
+    }
+}
@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+
+use dbus_derive::{DbusArgs, DbusStruct};
+
+#[derive(DbusStruct, Default, Debug, PartialEq)]
+#[dbus_struct(test_roundtrip)]
+pub struct NestedArg {
+    pub arg_i32: i32,
+    pub arg_string: String,
+    pub arg_vec: Vec<f64>,
+    pub arg_map: HashMap<String, u16>,
+}
+
+#[derive(DbusStruct, DbusArgs, Debug, PartialEq)]
+#[dbus_struct(test_roundtrip = "ArgsUnnamed::sample")]
+#[dbus_args(test_roundtrip = "ArgsUnnamed::sample")]
+pub struct ArgsUnnamed(pub u32, pub Vec<NestedArg>);
+
+impl ArgsUnnamed {
+    fn sample() -> Self {
+        Self(
+            42,
+            vec![
+                NestedArg::default(),
+                NestedArg {
+                    arg_i32: -1,
+                    arg_string: "HDMI-1".to_string(),
+                    arg_vec: vec![59.94, 60.0],
+                    arg_map: HashMap::from([("width".to_string(), 1920)]),
+                },
+            ],
+        )
+    }
+}
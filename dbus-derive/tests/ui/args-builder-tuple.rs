@@ -0,0 +1,7 @@
+use dbus_derive::DbusArgs;
+
+#[derive(DbusArgs)]
+#[dbus_args(builder)]
+pub struct Resolution(u32, u32);
+
+fn main() {}
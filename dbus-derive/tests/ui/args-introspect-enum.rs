@@ -0,0 +1,10 @@
+use dbus_derive::DbusArgs;
+
+#[derive(DbusArgs)]
+#[dbus_args(introspect)]
+pub enum Reply {
+    Short(u32),
+    Long(u32, String),
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use dbus_derive::DbusPropMap;
+
+#[derive(DbusPropMap)]
+#[dbus_propmap(rename_all = "kebab-case")]
+pub struct PropsArg {
+    pub display_name: Option<String>,
+    #[dbus_propmap(alias = "display-name")]
+    pub name: Option<String>,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use dbus_derive::DbusPropMap;
+
+#[derive(DbusPropMap)]
+pub struct PropsArg {
+    pub display_name: Option<String>,
+    #[dbus_propmap(rename = "display_name")]
+    pub name: Option<String>,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use dbus_derive::DbusStruct;
+
+#[derive(DbusStruct)]
+pub struct CrtcChange {
+    pub id: u32,
+    #[dbus(sentinel = -1)]
+    pub mode_id: Option<i32>,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use dbus_derive::DbusStruct;
+
+#[derive(DbusStruct)]
+pub struct CrtcChange {
+    pub id: u32,
+    #[dbus(arg_type = "i32", sentinel = -1, variant)]
+    pub mode_id: Option<u32>,
+}
+
+fn main() {}
@@ -0,0 +1,55 @@
+use dbus::arg::{Iter, IterAppend};
+use dbus_derive::{DbusArgs, DbusStruct};
+use dbus_traits::testing::new_message;
+
+#[derive(DbusStruct, DbusArgs, Debug, PartialEq)]
+#[dbus_struct(validate = "Geometry::validate")]
+#[dbus_args(validate = "Geometry::validate")]
+struct Geometry {
+    width: i32,
+    height: i32,
+}
+
+impl Geometry {
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.width < 0 || self.height < 0 {
+            return Err("Geometry should be non-negative");
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn get_validated() {
+    let mut m = new_message();
+    let mut ia = IterAppend::new(&mut m);
+    ia.append((1920i32, 1080i32));
+    ia.append((-1i32, 1080i32));
+
+    let mut i = Iter::new(&m);
+    let valid: Geometry = i.read().unwrap();
+    assert_eq!(
+        Geometry {
+            width: 1920,
+            height: 1080
+        },
+        valid
+    );
+    assert!(i.read::<Geometry>().is_err());
+}
+
+#[test]
+fn read_all_validated() {
+    let mut valid_m = new_message();
+    let mut ia = IterAppend::new(&mut valid_m);
+    ia.append(1920i32);
+    ia.append(1080i32);
+
+    let mut invalid_m = new_message();
+    let mut ia = IterAppend::new(&mut invalid_m);
+    ia.append(-1i32);
+    ia.append(1080i32);
+
+    assert!(valid_m.read_all::<Geometry>().is_ok());
+    assert!(invalid_m.read_all::<Geometry>().is_err());
+}
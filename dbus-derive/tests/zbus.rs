@@ -0,0 +1,175 @@
+use dbus::arg::{Append, Arg, Get};
+use dbus_derive::{DbusEnum, DbusPropMap, DbusStruct};
+use zvariant::{serialized::Context, to_bytes, Type, LE};
+
+#[derive(DbusStruct, Debug, PartialEq)]
+#[dbus_struct(zbus)]
+pub struct Mode {
+    pub id: u32,
+    pub width: u32,
+    pub height: u32,
+    pub frequency: f64,
+    pub transform: Transform,
+    pub props: Props,
+}
+
+#[derive(DbusStruct, Debug, PartialEq)]
+#[dbus_struct(zbus)]
+pub struct Borrowed<'a>(pub &'a str, pub u32);
+
+#[derive(DbusEnum, Debug, Clone, Copy, PartialEq)]
+#[dbus_enum(as_type = "u32", zbus)]
+pub enum Transform {
+    Normal,
+    Flipped,
+}
+
+impl From<Transform> for u32 {
+    fn from(value: Transform) -> Self {
+        match value {
+            Transform::Normal => 0,
+            Transform::Flipped => 4,
+        }
+    }
+}
+
+impl TryFrom<u32> for Transform {
+    type Error = &'static str;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Transform::Normal),
+            4 => Ok(Transform::Flipped),
+            _ => Err("Invalid Transform u32 representation"),
+        }
+    }
+}
+
+#[derive(DbusPropMap, Debug, Default, Clone, PartialEq)]
+#[dbus_propmap(zbus)]
+pub struct Props {
+    pub vendor: Option<String>,
+    #[dbus_propmap(rename = "display-name")]
+    pub display_name: Option<String>,
+    pub backlight: Option<i64>,
+}
+
+#[test]
+fn signature() {
+    assert_eq!("(uuudua{sv})", Mode::SIGNATURE.to_string());
+    assert_eq!("(su)", Borrowed::SIGNATURE.to_string());
+    assert_eq!("u", Transform::SIGNATURE.to_string());
+    assert_eq!("a{sv}", Props::SIGNATURE.to_string());
+}
+
+#[test]
+fn roundtrip() {
+    let ctxt = Context::new_dbus(LE, 0);
+    let mode = Mode {
+        id: 1,
+        width: 1920,
+        height: 1080,
+        frequency: 59.94,
+        transform: Transform::Flipped,
+        props: Props {
+            vendor: Some("GSM".to_string()),
+            display_name: None,
+            backlight: Some(50),
+        },
+    };
+
+    let encoded = to_bytes(ctxt, &mode).unwrap();
+    let (decoded, _): (Mode, _) = encoded.deserialize().unwrap();
+    assert_eq!(mode, decoded);
+
+    let borrowed = Borrowed("HDMI-1", 2);
+    let encoded = to_bytes(ctxt, &borrowed).unwrap();
+    let (decoded, _): (Borrowed, _) = encoded.deserialize().unwrap();
+    assert_eq!(borrowed, decoded);
+}
+
+#[derive(DbusStruct, Debug, PartialEq)]
+#[dbus_struct(zbus)]
+pub struct Converted {
+    #[dbus(arg_type = "u32")]
+    pub id: usize,
+    pub props: ConvertedProps,
+}
+
+#[derive(DbusPropMap, Debug, PartialEq)]
+#[dbus_propmap(zbus)]
+pub struct ConvertedProps {
+    #[dbus(arg_type = "u64")]
+    pub size: Option<usize>,
+}
+
+#[test]
+fn converted_fields() {
+    assert_eq!("(ua{sv})", Converted::SIGNATURE.to_string());
+
+    let ctxt = Context::new_dbus(LE, 0);
+    let converted = Converted {
+        id: 5,
+        props: ConvertedProps { size: Some(1024) },
+    };
+    let encoded = to_bytes(ctxt, &converted).unwrap();
+    let (decoded, _): (Converted, _) = encoded.deserialize().unwrap();
+    assert_eq!(converted, decoded);
+
+    let too_large = Converted {
+        id: u32::MAX as usize + 1,
+        props: ConvertedProps { size: None },
+    };
+    assert!(to_bytes(ctxt, &too_large).is_err());
+}
+
+#[derive(DbusStruct, Debug, PartialEq)]
+#[dbus_struct(zbus, validate = "validate_size")]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+fn validate_size(size: &Size) -> Result<(), &'static str> {
+    if size.width == 0 || size.height == 0 {
+        return Err("Size can't be empty");
+    }
+    Ok(())
+}
+
+#[test]
+fn validated() {
+    let ctxt = Context::new_dbus(LE, 0);
+    let encoded = to_bytes(ctxt, &(1920u32, 1080u32)).unwrap();
+    let (decoded, _): (Size, _) = encoded.deserialize().unwrap();
+    assert_eq!(
+        Size {
+            width: 1920,
+            height: 1080
+        },
+        decoded
+    );
+
+    let encoded = to_bytes(ctxt, &(0u32, 1080u32)).unwrap();
+    assert!(encoded.deserialize::<Size>().is_err());
+}
+
+#[derive(DbusStruct, Debug, PartialEq)]
+#[dbus_struct(zbus)]
+pub struct Generic<T: Arg + Append + for<'a> Get<'a>> {
+    pub value: T,
+}
+
+#[test]
+fn generic() {
+    assert_eq!("(u)", Generic::<u32>::SIGNATURE.to_string());
+    assert_eq!("(s)", Generic::<String>::SIGNATURE.to_string());
+
+    let ctxt = Context::new_dbus(LE, 0);
+    let generic = Generic {
+        value: "DP-1".to_string(),
+    };
+    let encoded = to_bytes(ctxt, &generic).unwrap();
+    let (decoded, _): (Generic<String>, _) = encoded.deserialize().unwrap();
+    assert_eq!(generic, decoded);
+}
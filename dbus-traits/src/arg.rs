@@ -0,0 +1,531 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    ffi::OsString,
+    fmt::Debug,
+    hash::Hash,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{error::DbusArgError, wire::Wire};
+#[cfg(feature = "dbus")]
+use dbus::arg::{RefArg, Variant};
+
+/// Conversion between `Self` and `T`, which has to be a [`Wire`] type, so derived types only send
+/// types that a D-Bus library understands. With dbus-rs `T` should also implement [`Arg`],
+/// [`Append`] and [`Get`].
+///
+/// Both directions could fail, since Rust type could be more or less restrictive than its D-Bus
+/// representation.
+///
+/// # Examples
+/// ```
+/// use dbus_traits::{DbusArg, DbusArgError};
+///
+/// struct Percent(u8);
+///
+/// impl DbusArg<f64> for Percent {
+///     fn to_dbus(&self) -> Result<f64, DbusArgError> {
+///         Ok(self.0 as f64 / 100.0)
+///     }
+///
+///     fn from_dbus(value: f64) -> Result<Self, DbusArgError> {
+///         if !(0.0..=1.0).contains(&value) {
+///             return Err("Percent should be in range 0..=1".into());
+///         }
+///         Ok(Percent((value * 100.0).round() as u8))
+///     }
+/// }
+///
+/// assert_eq!(0.5, Percent(50).to_dbus().unwrap());
+/// assert!(Percent::from_dbus(1.5).is_err());
+/// ```
+///
+/// [`Arg`]: dbus::arg::Arg
+/// [`Append`]: dbus::arg::Append
+/// [`Get`]: dbus::arg::Get
+pub trait DbusArg<T: Wire>: Sized {
+    /// Converts value into its D-Bus representation.
+    fn to_dbus(&self) -> Result<T, DbusArgError>;
+    /// Restores value from its D-Bus representation.
+    fn from_dbus(value: T) -> Result<Self, DbusArgError>;
+}
+
+/// Types that D-Bus allows as dictionary keys, which are basic types only.
+///
+/// Bounds wire key type of a map in [`DbusArg`] implementation for [`HashMap`], Rust key is
+/// converted through [`DbusArg`] as usual. The trait is open, so it could be used in generic code
+/// over maps, but it should only be implemented for types sent as a D-Bus basic type (integer,
+/// floating point, bool, string, object path or signature), since D-Bus libraries fail when
+/// sending dictionaries with container keys.
+///
+/// # Examples
+/// ```
+/// use std::{collections::HashMap, ffi::OsString};
+///
+/// use dbus_traits::{DbusArg, DbusArgError, DbusDictKey};
+///
+/// fn wire_keys<K, KW>(map: &HashMap<K, ()>) -> Result<Vec<KW>, DbusArgError>
+/// where
+///     K: DbusArg<KW>,
+///     KW: DbusDictKey,
+/// {
+///     map.keys().map(DbusArg::to_dbus).collect()
+/// }
+///
+/// let map = HashMap::from([(OsString::from("HDMI-1"), ())]);
+/// assert_eq!(vec!["HDMI-1".to_string()], wire_keys(&map).unwrap());
+/// ```
+pub trait DbusDictKey: Wire + Hash + Eq + Debug {}
+
+/// Implements [`DbusArg<Wire>`](DbusArg) for types implementing `TryFrom<Wire>` and
+/// `Into<Wire>`, which is the usual shape of domain newtypes. `Self` should also implement
+/// [`Clone`], since [`DbusArg::to_dbus`] takes a reference.
+///
+/// Error of [`TryFrom`] becomes the source of [`DbusArgError`], so it should implement
+/// [`Error`](std::error::Error) or be a string.
+///
+/// # Examples
+/// ```
+/// use dbus_traits::{dbus_newtype, DbusArg};
+///
+/// #[derive(Debug, Clone, Copy, PartialEq)]
+/// struct OutputId(u32);
+///
+/// impl From<u32> for OutputId {
+///     fn from(id: u32) -> Self {
+///         OutputId(id)
+///     }
+/// }
+///
+/// impl From<OutputId> for u32 {
+///     fn from(id: OutputId) -> Self {
+///         id.0
+///     }
+/// }
+///
+/// dbus_newtype!(OutputId => u32);
+///
+/// assert_eq!(5u32, OutputId(5).to_dbus().unwrap());
+/// assert_eq!(OutputId(5), OutputId::from_dbus(5).unwrap());
+/// ```
+#[macro_export]
+macro_rules! dbus_newtype {
+    ($($ty:ty => $wire:ty),* $(,)?) => {$(
+        impl $crate::DbusArg<$wire> for $ty {
+            fn to_dbus(&self) -> ::std::result::Result<$wire, $crate::DbusArgError> {
+                ::std::result::Result::Ok(::std::convert::Into::<$wire>::into(
+                    ::std::clone::Clone::clone(self),
+                ))
+            }
+
+            fn from_dbus(value: $wire) -> ::std::result::Result<Self, $crate::DbusArgError> {
+                <$ty as ::std::convert::TryFrom<$wire>>::try_from(value)
+                    .map_err($crate::DbusArgError::new)
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_identity {
+    ($($ty:ty),*) => {$(
+        impl DbusArg<$ty> for $ty {
+            fn to_dbus(&self) -> Result<$ty, DbusArgError> {
+                Ok(self.clone())
+            }
+
+            fn from_dbus(value: $ty) -> Result<Self, DbusArgError> {
+                Ok(value)
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_dict_key {
+    ($($ty:ty),*) => {$(
+        impl DbusDictKey for $ty {}
+    )*};
+}
+
+/// Conversions between integer types, which fail if value doesn't fit into target type.
+macro_rules! impl_try_from {
+    ($($ty:ty => $wire:ty),*) => {$(
+        impl DbusArg<$wire> for $ty {
+            fn to_dbus(&self) -> Result<$wire, DbusArgError> {
+                Ok(<$wire>::try_from(*self)?)
+            }
+
+            fn from_dbus(value: $wire) -> Result<Self, DbusArgError> {
+                Ok(<$ty>::try_from(value)?)
+            }
+        }
+    )*};
+}
+
+impl_identity!(u8, bool, i16, u16, i32, u32, i64, u64, f64, String);
+impl_dict_key!(u8, bool, i16, u16, i32, u32, i64, u64, String);
+#[cfg(feature = "dbus")]
+impl_identity!(dbus::Path<'static>, dbus::Signature<'static>);
+#[cfg(feature = "dbus")]
+impl_dict_key!(dbus::Path<'static>, dbus::Signature<'static>);
+impl_try_from!(usize => u32, usize => u64, isize => i32, isize => i64, u32 => i32);
+
+/// Converts every item, adding its position to error path.
+fn items_to_dbus<'a, T, W: Wire>(items: impl Iterator<Item = &'a T>) -> Result<Vec<W>, DbusArgError>
+where
+    T: DbusArg<W> + 'a,
+{
+    items
+        .enumerate()
+        .map(|(idx, item)| item.to_dbus().map_err(|err| err.at_index(idx)))
+        .collect()
+}
+
+/// Restores every item, adding its position to error path.
+fn items_from_dbus<T, W: Wire, C>(items: Vec<W>) -> Result<C, DbusArgError>
+where
+    T: DbusArg<W>,
+    C: FromIterator<T>,
+{
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(idx, item)| T::from_dbus(item).map_err(|err| err.at_index(idx)))
+        .collect()
+}
+
+impl<T, W: Wire> DbusArg<Vec<W>> for Vec<T>
+where
+    T: DbusArg<W>,
+{
+    fn to_dbus(&self) -> Result<Vec<W>, DbusArgError> {
+        items_to_dbus(self.iter())
+    }
+
+    fn from_dbus(value: Vec<W>) -> Result<Self, DbusArgError> {
+        items_from_dbus(value)
+    }
+}
+
+/// Arrays are sent as D-Bus arrays, reading fails if length doesn't match.
+impl<T, W: Wire, const N: usize> DbusArg<Vec<W>> for [T; N]
+where
+    T: DbusArg<W>,
+{
+    fn to_dbus(&self) -> Result<Vec<W>, DbusArgError> {
+        items_to_dbus(self.iter())
+    }
+
+    fn from_dbus(value: Vec<W>) -> Result<Self, DbusArgError> {
+        let len = value.len();
+        let items: Vec<_> = items_from_dbus(value)?;
+        items
+            .try_into()
+            .map_err(|_| format!("Expected array of {N} elements, got {len}").into())
+    }
+}
+
+/// Tuples are converted element by element, dbus-rs supports them up to 12 elements.
+macro_rules! impl_tuple {
+    ($($idx:tt $t:ident: $w:ident),+) => {
+        impl<$($t, $w: Wire),+> DbusArg<($($w,)+)> for ($($t,)+)
+        where
+            $($t: DbusArg<$w>),+
+        {
+            #[allow(non_snake_case)]
+            fn to_dbus(&self) -> Result<($($w,)+), DbusArgError> {
+                let ($($t,)+) = self;
+                Ok(($($t.to_dbus().map_err(|err| err.at_index($idx))?,)+))
+            }
+
+            #[allow(non_snake_case)]
+            fn from_dbus(value: ($($w,)+)) -> Result<Self, DbusArgError> {
+                let ($($w,)+) = value;
+                Ok(($($t::from_dbus($w).map_err(|err| err.at_index($idx))?,)+))
+            }
+        }
+    };
+}
+
+impl_tuple!(0 A1: W1);
+impl_tuple!(0 A1: W1, 1 A2: W2);
+impl_tuple!(0 A1: W1, 1 A2: W2, 2 A3: W3);
+impl_tuple!(0 A1: W1, 1 A2: W2, 2 A3: W3, 3 A4: W4);
+impl_tuple!(0 A1: W1, 1 A2: W2, 2 A3: W3, 3 A4: W4, 4 A5: W5);
+impl_tuple!(0 A1: W1, 1 A2: W2, 2 A3: W3, 3 A4: W4, 4 A5: W5, 5 A6: W6);
+impl_tuple!(0 A1: W1, 1 A2: W2, 2 A3: W3, 3 A4: W4, 4 A5: W5, 5 A6: W6, 6 A7: W7);
+impl_tuple!(0 A1: W1, 1 A2: W2, 2 A3: W3, 3 A4: W4, 4 A5: W5, 5 A6: W6, 6 A7: W7, 7 A8: W8);
+impl_tuple!(0 A1: W1, 1 A2: W2, 2 A3: W3, 3 A4: W4, 4 A5: W5, 5 A6: W6, 6 A7: W7, 7 A8: W8, 8 A9: W9);
+impl_tuple!(0 A1: W1, 1 A2: W2, 2 A3: W3, 3 A4: W4, 4 A5: W5, 5 A6: W6, 6 A7: W7, 7 A8: W8, 8 A9: W9, 9 A10: W10);
+impl_tuple!(0 A1: W1, 1 A2: W2, 2 A3: W3, 3 A4: W4, 4 A5: W5, 5 A6: W6, 6 A7: W7, 7 A8: W8, 8 A9: W9, 9 A10: W10, 10 A11: W11);
+impl_tuple!(0 A1: W1, 1 A2: W2, 2 A3: W3, 3 A4: W4, 4 A5: W5, 5 A6: W6, 6 A7: W7, 7 A8: W8, 8 A9: W9, 9 A10: W10, 10 A11: W11, 11 A12: W12);
+
+/// Sent as microseconds, fails if duration doesn't fit into [`u64`].
+impl DbusArg<u64> for Duration {
+    fn to_dbus(&self) -> Result<u64, DbusArgError> {
+        Ok(u64::try_from(self.as_micros())?)
+    }
+
+    fn from_dbus(value: u64) -> Result<Self, DbusArgError> {
+        Ok(Duration::from_micros(value))
+    }
+}
+
+/// Sent as microseconds since unix epoch, fails for time before epoch.
+impl DbusArg<u64> for SystemTime {
+    fn to_dbus(&self) -> Result<u64, DbusArgError> {
+        self.duration_since(UNIX_EPOCH)?.to_dbus()
+    }
+
+    fn from_dbus(value: u64) -> Result<Self, DbusArgError> {
+        UNIX_EPOCH
+            .checked_add(Duration::from_dbus(value)?)
+            .ok_or_else(|| format!("{value} microseconds since unix epoch is out of range").into())
+    }
+}
+
+/// D-Bus strings are UTF-8, so non UTF-8 strings fail instead of being converted lossy.
+impl DbusArg<String> for OsString {
+    fn to_dbus(&self) -> Result<String, DbusArgError> {
+        self.clone()
+            .into_string()
+            .map_err(|s| format!("{s:?} is not valid UTF-8").into())
+    }
+
+    fn from_dbus(value: String) -> Result<Self, DbusArgError> {
+        Ok(value.into())
+    }
+}
+
+/// Sent as a string with a single character, fails if string has a different length.
+impl DbusArg<String> for char {
+    fn to_dbus(&self) -> Result<String, DbusArgError> {
+        Ok(self.to_string())
+    }
+
+    fn from_dbus(value: String) -> Result<Self, DbusArgError> {
+        let mut chars = value.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(format!("Expected a single character, got {value:?}").into()),
+        }
+    }
+}
+
+/// Same as [`OsString`], fails for non UTF-8 paths.
+impl DbusArg<String> for PathBuf {
+    fn to_dbus(&self) -> Result<String, DbusArgError> {
+        self.as_os_str().to_os_string().to_dbus()
+    }
+
+    fn from_dbus(value: String) -> Result<Self, DbusArgError> {
+        Ok(value.into())
+    }
+}
+
+/// Types sent as their [`Display`](std::fmt::Display) string and read with
+/// [`FromStr`](std::str::FromStr), error includes the string that failed to parse.
+macro_rules! impl_string_repr {
+    ($($ty:ty => $what:literal),*) => {$(
+        impl DbusArg<String> for $ty {
+            fn to_dbus(&self) -> Result<String, DbusArgError> {
+                Ok(self.to_string())
+            }
+
+            fn from_dbus(value: String) -> Result<Self, DbusArgError> {
+                value
+                    .parse()
+                    .map_err(|err| format!("{value:?} is not a valid {}: {err}", $what).into())
+            }
+        }
+    )*};
+}
+
+// D-Bus has no 128-bit integers, so they are sent as decimal strings
+impl_string_repr!(u128 => "u128", i128 => "i128");
+impl_string_repr!(
+    IpAddr => "IP address",
+    Ipv4Addr => "IPv4 address",
+    Ipv6Addr => "IPv6 address",
+    SocketAddr => "socket address"
+);
+
+/// Sequences which are sent as D-Bus arrays, same as [`Vec`]. Duplicates are merged when reading
+/// into a set.
+macro_rules! impl_sequence {
+    ($($seq:ident $(where $first:path $(, $rest:path)*)?);*) => {$(
+        impl<T, W: Wire> DbusArg<Vec<W>> for $seq<T>
+        where
+            T: DbusArg<W> $(+ $first $(+ $rest)*)?,
+        {
+            fn to_dbus(&self) -> Result<Vec<W>, DbusArgError> {
+                items_to_dbus(self.iter())
+            }
+
+            fn from_dbus(value: Vec<W>) -> Result<Self, DbusArgError> {
+                items_from_dbus(value)
+            }
+        }
+    )*};
+}
+
+impl_sequence!(VecDeque; HashSet where Hash, Eq; BTreeSet where Ord);
+
+/// Borrowed value is converted the same way as its owned counterpart, reading always produces
+/// [`Cow::Owned`].
+impl<T, W: Wire> DbusArg<W> for Cow<'_, T>
+where
+    T: ToOwned + ?Sized,
+    T::Owned: DbusArg<W>,
+{
+    fn to_dbus(&self) -> Result<W, DbusArgError> {
+        match self {
+            Cow::Borrowed(value) => (*value).to_owned().to_dbus(),
+            Cow::Owned(value) => value.to_dbus(),
+        }
+    }
+
+    fn from_dbus(value: W) -> Result<Self, DbusArgError> {
+        Ok(Cow::Owned(T::Owned::from_dbus(value)?))
+    }
+}
+
+impl<K, V, KW, VW> DbusArg<HashMap<KW, VW>> for HashMap<K, V>
+where
+    K: DbusArg<KW> + Hash + Eq,
+    V: DbusArg<VW>,
+    KW: DbusDictKey,
+    VW: Wire,
+{
+    fn to_dbus(&self) -> Result<HashMap<KW, VW>, DbusArgError> {
+        self.iter()
+            .map(|(k, v)| {
+                let k = k.to_dbus()?;
+                let v = v.to_dbus().map_err(|err| err.at_key(&k))?;
+                Ok((k, v))
+            })
+            .collect()
+    }
+
+    fn from_dbus(value: HashMap<KW, VW>) -> Result<Self, DbusArgError> {
+        value
+            .into_iter()
+            .map(|(k, v)| {
+                let v = V::from_dbus(v).map_err(|err| err.at_key(&k))?;
+                Ok((K::from_dbus(k)?, v))
+            })
+            .collect()
+    }
+}
+
+/// Converts an optional value, representing [`None`] with reserved `sentinel`, like `-1` for
+/// "no id". Fails if converted value collides with `sentinel`.
+///
+/// D-Bus has no null value, so this is how optional arguments are usually modeled. Fields use it
+/// with `#[dbus(arg_type = "T", sentinel = ...)]`.
+///
+/// # Examples
+/// ```
+/// use dbus_traits::{option_from_dbus, option_to_dbus};
+///
+/// assert_eq!(-1i32, option_to_dbus::<u32, _>(&None, -1).unwrap());
+/// assert_eq!(Some(5u32), option_from_dbus(5i32, &-1).unwrap());
+/// assert_eq!(None::<u32>, option_from_dbus(-1i32, &-1).unwrap());
+/// ```
+pub fn option_to_dbus<T, W>(value: &Option<T>, sentinel: W) -> Result<W, DbusArgError>
+where
+    T: DbusArg<W>,
+    W: Wire + PartialEq + Debug,
+{
+    match value {
+        Some(value) => {
+            let value = value.to_dbus()?;
+            if value == sentinel {
+                return Err(format!("Value collides with sentinel {sentinel:?}").into());
+            }
+            Ok(value)
+        }
+        None => Ok(sentinel),
+    }
+}
+
+/// Restores an optional value, `sentinel` is read as [`None`]. See [`option_to_dbus`].
+pub fn option_from_dbus<T, W>(value: W, sentinel: &W) -> Result<Option<T>, DbusArgError>
+where
+    T: DbusArg<W>,
+    W: Wire + PartialEq,
+{
+    if &value == sentinel {
+        return Ok(None);
+    }
+    T::from_dbus(value).map(Some)
+}
+
+/// Marker sent instead of [`None`] by [`option_to_variant`]: an empty array of variants.
+#[cfg(feature = "dbus")]
+fn is_empty_marker(value: &dyn RefArg) -> bool {
+    &*value.signature() == "av"
+        && value
+            .as_iter()
+            .is_some_and(|mut items| items.next().is_none())
+}
+
+/// Converts an optional value into a variant, representing [`None`] with an empty marker, which
+/// is an empty array of variants `av`. Fails if converted value is such an array itself.
+///
+/// Unlike [`option_to_dbus`], no value has to be reserved, at the cost of sending a variant
+/// instead of `W`. Fields use it with `#[dbus(arg_type = "W", variant)]`.
+///
+/// # Examples
+/// ```
+/// use dbus::arg::RefArg;
+/// use dbus_traits::{option_from_variant, option_to_variant};
+///
+/// let none = option_to_variant::<u32, u32>(&None).unwrap();
+/// assert_eq!("av", none.0.signature().to_string());
+/// assert_eq!(None, option_from_variant::<u32, u32>(none).unwrap());
+///
+/// let some = option_to_variant::<usize, u32>(&Some(5)).unwrap();
+/// assert_eq!("u", some.0.signature().to_string());
+/// assert_eq!(Some(5), option_from_variant::<usize, u32>(some).unwrap());
+/// ```
+#[cfg(feature = "dbus")]
+pub fn option_to_variant<T, W>(value: &Option<T>) -> Result<Variant<Box<dyn RefArg>>, DbusArgError>
+where
+    T: DbusArg<W>,
+    W: Wire + RefArg + 'static,
+{
+    let Some(value) = value else {
+        return Ok(Variant(Box::new(Vec::<Variant<Box<dyn RefArg>>>::new())));
+    };
+    let value = value.to_dbus()?;
+    if is_empty_marker(&value) {
+        return Err("Value collides with empty marker".into());
+    }
+    Ok(Variant(Box::new(value)))
+}
+
+/// Restores an optional value, empty marker is read as [`None`]. See [`option_to_variant`].
+///
+/// Variants read from a message hold containers in dbus-rs internal types, so value is read
+/// as `W` from a scratch message. Fails if variant holds a value of another type.
+#[cfg(feature = "dbus")]
+pub fn option_from_variant<T, W>(value: Variant<Box<dyn RefArg>>) -> Result<Option<T>, DbusArgError>
+where
+    T: DbusArg<W>,
+    W: Wire + dbus::arg::Arg + for<'a> dbus::arg::Get<'a>,
+{
+    let value = value.0;
+    if is_empty_marker(&*value) {
+        return Ok(None);
+    }
+    let mut m = dbus::Message::new_signal("/", "org.freedesktop.DBus", "Variant")
+        .map_err(DbusArgError::new)?;
+    value.append(&mut dbus::arg::IterAppend::new(&mut m));
+    let value = dbus::arg::Iter::new(&m)
+        .read::<W>()
+        .map_err(|_| format!("Expected {}, found {}", W::signature(), value.signature()))?;
+    T::from_dbus(value).map(Some)
+}
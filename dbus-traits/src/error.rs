@@ -0,0 +1,135 @@
+use std::{
+    error::Error,
+    fmt::{self, Debug, Display},
+    num::TryFromIntError,
+    time::SystemTimeError,
+};
+
+/// Part of a path to a value that failed to convert.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// Named or tuple struct field
+    Field(String),
+    /// Position in an array or a tuple
+    Index(usize),
+    /// Dictionary key, formatted with [`Debug`]
+    Key(String),
+}
+
+/// Error returned by a failed conversion, together with a path to a value that failed, like
+/// `outputs[2].props.backlight`.
+///
+/// Containers prepend their part of the path while error propagates, so conversions only create
+/// an error from a message or an underlying error.
+///
+/// # Examples
+/// ```
+/// use dbus_traits::DbusArg;
+///
+/// let err = <Vec<(usize, usize)> as DbusArg<Vec<(u32, u32)>>>::to_dbus(&vec![(1, 2), (3, usize::MAX)])
+///     .unwrap_err()
+///     .in_field("sizes");
+/// assert_eq!("sizes[1][1]", err.path());
+/// ```
+pub struct DbusArgError {
+    path: Vec<PathSegment>,
+    source: Box<dyn Error + Send + Sync + 'static>,
+}
+
+impl DbusArgError {
+    /// Wraps an underlying error with an empty path.
+    pub fn new(source: impl Into<Box<dyn Error + Send + Sync + 'static>>) -> Self {
+        Self {
+            path: Vec::new(),
+            source: source.into(),
+        }
+    }
+
+    /// Prepends a path segment.
+    pub fn within(mut self, segment: PathSegment) -> Self {
+        self.path.insert(0, segment);
+        self
+    }
+
+    /// Prepends a struct field name.
+    pub fn in_field(self, name: impl Into<String>) -> Self {
+        self.within(PathSegment::Field(name.into()))
+    }
+
+    /// Prepends a position in an array or a tuple.
+    pub fn at_index(self, idx: usize) -> Self {
+        self.within(PathSegment::Index(idx))
+    }
+
+    /// Prepends a dictionary key.
+    pub fn at_key(self, key: &impl Debug) -> Self {
+        self.within(PathSegment::Key(format!("{key:?}")))
+    }
+
+    /// Segments of a path to a value that failed, outermost first.
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.path
+    }
+
+    /// Path to a value that failed, empty if conversion failed at the top level.
+    pub fn path(&self) -> String {
+        let mut path = String::new();
+        for segment in &self.path {
+            match segment {
+                PathSegment::Field(name) if path.is_empty() => path.push_str(name),
+                PathSegment::Field(name) => {
+                    path.push('.');
+                    path.push_str(name);
+                }
+                PathSegment::Index(idx) => path.push_str(&format!("[{idx}]")),
+                PathSegment::Key(key) => path.push_str(&format!("[{key}]")),
+            }
+        }
+        path
+    }
+}
+
+impl Display for DbusArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.source)
+        } else {
+            write!(f, "{}: {}", self.path(), self.source)
+        }
+    }
+}
+
+impl Debug for DbusArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DbusArgError")
+            .field("path", &self.path())
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+impl Error for DbusArgError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+// Blanket From impl would conflict with From<T> for T, so only common sources are listed,
+// others could be wrapped with DbusArgError::new
+macro_rules! impl_from {
+    ($($ty:ty),*) => {$(
+        impl From<$ty> for DbusArgError {
+            fn from(source: $ty) -> Self {
+                Self::new(source)
+            }
+        }
+    )*};
+}
+
+impl_from!(
+    &str,
+    String,
+    Box<dyn Error + Send + Sync + 'static>,
+    TryFromIntError,
+    SystemTimeError
+);
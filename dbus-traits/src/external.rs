@@ -0,0 +1,194 @@
+//! [`DbusArg`] impls for types from optional dependencies, each behind a feature with the same
+//! name as the crate, except `json` for serde_json.
+//!
+//! D-Bus has no dedicated types for these, so identifiers are sent as strings and timestamps
+//! either as RFC 3339 strings or as microseconds since unix epoch, same as
+//! [`SystemTime`](std::time::SystemTime).
+
+#[cfg(any(feature = "uuid", feature = "chrono", feature = "time"))]
+use crate::{DbusArg, DbusArgError};
+
+/// Sent as a hyphenated lowercase string, any format accepted by [`uuid::Uuid::parse_str`] is
+/// read.
+#[cfg(feature = "uuid")]
+impl DbusArg<String> for uuid::Uuid {
+    fn to_dbus(&self) -> Result<String, DbusArgError> {
+        Ok(self.hyphenated().to_string())
+    }
+
+    fn from_dbus(value: String) -> Result<Self, DbusArgError> {
+        uuid::Uuid::parse_str(&value).map_err(DbusArgError::new)
+    }
+}
+
+/// Sent as an RFC 3339 string, timestamps with an offset are converted to UTC when read.
+#[cfg(feature = "chrono")]
+impl DbusArg<String> for chrono::DateTime<chrono::Utc> {
+    fn to_dbus(&self) -> Result<String, DbusArgError> {
+        Ok(self.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true))
+    }
+
+    fn from_dbus(value: String) -> Result<Self, DbusArgError> {
+        chrono::DateTime::parse_from_rfc3339(&value)
+            .map(|time| time.with_timezone(&chrono::Utc))
+            .map_err(DbusArgError::new)
+    }
+}
+
+/// Sent as microseconds since unix epoch, negative for time before epoch.
+#[cfg(feature = "chrono")]
+impl DbusArg<i64> for chrono::DateTime<chrono::Utc> {
+    fn to_dbus(&self) -> Result<i64, DbusArgError> {
+        Ok(self.timestamp_micros())
+    }
+
+    fn from_dbus(value: i64) -> Result<Self, DbusArgError> {
+        chrono::DateTime::from_timestamp_micros(value)
+            .ok_or_else(|| format!("{value} microseconds since unix epoch is out of range").into())
+    }
+}
+
+/// Sent as an RFC 3339 string, offset is preserved.
+#[cfg(feature = "time")]
+impl DbusArg<String> for time::OffsetDateTime {
+    fn to_dbus(&self) -> Result<String, DbusArgError> {
+        self.format(&time::format_description::well_known::Rfc3339)
+            .map_err(DbusArgError::new)
+    }
+
+    fn from_dbus(value: String) -> Result<Self, DbusArgError> {
+        time::OffsetDateTime::parse(&value, &time::format_description::well_known::Rfc3339)
+            .map_err(DbusArgError::new)
+    }
+}
+
+/// Sent as microseconds since unix epoch, negative for time before epoch. Offset is not sent, so
+/// time is always read in UTC.
+#[cfg(feature = "time")]
+impl DbusArg<i64> for time::OffsetDateTime {
+    fn to_dbus(&self) -> Result<i64, DbusArgError> {
+        Ok(i64::try_from(self.unix_timestamp_nanos() / 1000)?)
+    }
+
+    fn from_dbus(value: i64) -> Result<Self, DbusArgError> {
+        time::OffsetDateTime::from_unix_timestamp_nanos(i128::from(value) * 1000)
+            .map_err(DbusArgError::new)
+    }
+}
+
+#[cfg(feature = "json")]
+mod json {
+    use dbus::arg::{ArgType, PropMap, RefArg, Variant};
+    use serde_json::{Map, Number, Value};
+
+    use crate::{DbusArg, DbusArgError};
+
+    /// Sent as a variant with a value of matching D-Bus type: numbers become `x`, `t` or `d`,
+    /// arrays become `av` and objects become `a{sv}`. Fails for `null`, since D-Bus has no null
+    /// value.
+    ///
+    /// Any variant is read, dictionaries become objects with keys formatted as strings and
+    /// structs become arrays.
+    ///
+    /// # Examples
+    /// ```
+    /// use dbus::arg::{RefArg, Variant};
+    /// use dbus_traits::DbusArg;
+    /// use serde_json::json;
+    ///
+    /// let value = json!({ "scale": 1.5, "outputs": ["HDMI-1"] });
+    /// let variant: Variant<Box<dyn RefArg>> = value.to_dbus().unwrap();
+    /// assert_eq!("a{sv}", variant.0.signature().to_string());
+    /// assert_eq!(value, serde_json::Value::from_dbus(variant).unwrap());
+    /// ```
+    impl DbusArg<Variant<Box<dyn RefArg>>> for Value {
+        fn to_dbus(&self) -> Result<Variant<Box<dyn RefArg>>, DbusArgError> {
+            let value: Box<dyn RefArg> = match self {
+                Value::Null => return Err("null can't be sent over D-Bus".into()),
+                Value::Bool(value) => Box::new(*value),
+                Value::Number(number) => {
+                    if let Some(number) = number.as_i64() {
+                        Box::new(number)
+                    } else if let Some(number) = number.as_u64() {
+                        Box::new(number)
+                    } else {
+                        Box::new(
+                            number
+                                .as_f64()
+                                .ok_or("Number is not representable as f64")?,
+                        )
+                    }
+                }
+                Value::String(value) => Box::new(value.clone()),
+                Value::Array(items) => Box::new(items.to_dbus()?),
+                Value::Object(map) => {
+                    let map = map
+                        .iter()
+                        .map(|(k, v)| Ok((k.clone(), v.to_dbus().map_err(|err| err.at_key(k))?)))
+                        .collect::<Result<PropMap, DbusArgError>>()?;
+                    Box::new(map)
+                }
+            };
+            Ok(Variant(value))
+        }
+
+        fn from_dbus(value: Variant<Box<dyn RefArg>>) -> Result<Self, DbusArgError> {
+            refarg_to_json(&value.0)
+        }
+    }
+
+    fn refarg_to_json(arg: &dyn RefArg) -> Result<Value, DbusArgError> {
+        let value = match arg.arg_type() {
+            ArgType::Boolean => Value::Bool(arg.as_i64() == Some(1)),
+            ArgType::Byte
+            | ArgType::Int16
+            | ArgType::UInt16
+            | ArgType::Int32
+            | ArgType::UInt32
+            | ArgType::Int64
+            | ArgType::UnixFd => Value::from(arg.as_i64().ok_or("Integer is not readable")?),
+            ArgType::UInt64 => Value::from(arg.as_u64().ok_or("Integer is not readable")?),
+            ArgType::Double => {
+                let number = arg.as_f64().ok_or("Number is not readable")?;
+                Value::Number(
+                    Number::from_f64(number)
+                        .ok_or_else(|| format!("{number} is not valid JSON"))?,
+                )
+            }
+            ArgType::String | ArgType::ObjectPath | ArgType::Signature => {
+                Value::from(arg.as_str().ok_or("String is not readable")?)
+            }
+            ArgType::Variant => {
+                let inner = arg
+                    .as_iter()
+                    .and_then(|mut iter| iter.next())
+                    .ok_or("Variant is empty")?;
+                refarg_to_json(inner)?
+            }
+            ArgType::Array if arg.signature().starts_with("a{") => {
+                let mut map = Map::new();
+                let mut iter = arg.as_iter().ok_or("Dictionary is not iterable")?;
+                while let (Some(k), Some(v)) = (iter.next(), iter.next()) {
+                    let key = match k.as_str() {
+                        Some(key) => key.to_string(),
+                        None => refarg_to_json(k)?.to_string(),
+                    };
+                    let value = refarg_to_json(v).map_err(|err| err.at_key(&key))?;
+                    map.insert(key, value);
+                }
+                Value::Object(map)
+            }
+            ArgType::Array | ArgType::Struct => Value::Array(
+                arg.as_iter()
+                    .ok_or("Container is not iterable")?
+                    .enumerate()
+                    .map(|(idx, item)| refarg_to_json(item).map_err(|err| err.at_index(idx)))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ArgType::DictEntry | ArgType::Invalid => {
+                return Err(format!("Unexpected {:?} value", arg.arg_type()).into())
+            }
+        };
+        Ok(value)
+    }
+}
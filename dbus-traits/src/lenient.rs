@@ -0,0 +1,113 @@
+//! Warnings from lenient decoding.
+//!
+//! Types derived with `#[dbus_struct(lenient)]` fields or `#[dbus_propmap(lenient)]` replace
+//! invalid values with defaults and report them with [`warn`], so a single unexpected value from
+//! a peer doesn't make the whole message unreadable. Warnings are dropped unless decoding runs
+//! inside of [`collect`].
+//!
+//! Derived types with lenient fields report fields and array elements they are reading with
+//! [`reading_field`] and [`reading_value`], so a warning from a nested value points to it from
+//! the outermost lenient value, like `Crtc.rotation`. Types without lenient fields don't touch the
+//! path, so reading them costs nothing extra.
+//!
+//! # Examples
+//! ```
+//! use dbus_traits::{lenient, DbusArgError};
+//!
+//! let (value, warnings) = lenient::collect(|| {
+//!     lenient::warn(DbusArgError::new("unknown transform 9").in_field("transform"));
+//!     42
+//! });
+//! assert_eq!(42, value);
+//! assert_eq!("transform: unknown transform 9", warnings[0].to_string());
+//! ```
+
+use std::cell::RefCell;
+
+use crate::DbusArgError;
+
+/// Field that is being read, with a number of values read within it.
+struct Frame {
+    name: &'static str,
+    array: fn() -> bool,
+    elements: usize,
+}
+
+thread_local! {
+    static WARNINGS: RefCell<Option<Vec<DbusArgError>>> = const { RefCell::new(None) };
+    static PATH: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Pops a frame pushed to [`PATH`], even if reading panics.
+struct PopFrame;
+
+impl Drop for PopFrame {
+    fn drop(&mut self) {
+        PATH.with_borrow_mut(|path| path.pop());
+    }
+}
+
+/// Reports a value that was replaced with a default. Path to a value that is being read by
+/// derived types is prepended to `err`.
+pub fn warn(err: DbusArgError) {
+    WARNINGS.with_borrow_mut(|warnings| {
+        if let Some(warnings) = warnings {
+            let err = PATH.with_borrow(|path| {
+                path.iter().rev().fold(err, |err, frame| {
+                    let err = match frame.elements.checked_sub(1) {
+                        Some(idx) if (frame.array)() => err.at_index(idx),
+                        _ => err,
+                    };
+                    err.in_field(frame.name)
+                })
+            });
+            warnings.push(err);
+        }
+    });
+}
+
+/// Runs `f`, which reads field `name`. Values read within a field are counted, so if `array`
+/// returns `true`, a warning points to an element. `array` is only called to report a warning,
+/// so it could inspect a signature.
+pub fn reading_field<T>(name: &'static str, array: fn() -> bool, f: impl FnOnce() -> T) -> T {
+    PATH.with_borrow_mut(|path| {
+        path.push(Frame {
+            name,
+            array,
+            elements: 0,
+        })
+    });
+    let _pop = PopFrame;
+    f()
+}
+
+/// Runs `f`, which reads a value of type named `ty`. Value is counted as an element, if it's read
+/// within an array field, and `ty` starts the path, if it's read outside of any field.
+pub fn reading_value<T>(ty: &'static str, f: impl FnOnce() -> T) -> T {
+    let root = PATH.with_borrow_mut(|path| match path.last_mut() {
+        Some(frame) => {
+            frame.elements += 1;
+            false
+        }
+        None => {
+            path.push(Frame {
+                name: ty,
+                array: || false,
+                elements: 0,
+            });
+            true
+        }
+    });
+    // Not using then_some, which would drop and pop a frame right away
+    let _pop = if root { Some(PopFrame) } else { None };
+    f()
+}
+
+/// Runs `f`, collecting warnings reported on the current thread while it runs. Nested calls
+/// collect separately.
+pub fn collect<T>(f: impl FnOnce() -> T) -> (T, Vec<DbusArgError>) {
+    let outer = WARNINGS.replace(Some(Vec::new()));
+    let value = f();
+    let warnings = WARNINGS.replace(outer).unwrap_or_default();
+    (value, warnings)
+}
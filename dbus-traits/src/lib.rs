@@ -0,0 +1,65 @@
+//! Conversion layer between Rust types and types, which could be sent over D-Bus.
+//!
+//! Fields of structs deriving `dbus_derive` traits are mapped through [`DbusArg`] with
+//! `#[dbus(arg_type = "T")]` attribute. Conversions target [`Wire`] types, which don't depend on
+//! a particular D-Bus library, dbus-rs specific types are behind `dbus` feature.
+//!
+//! # Encodings
+//! Types without a matching D-Bus type are sent as:
+//! * `char`, `u128`, `i128`, IP and socket addresses: strings, `u128`/`i128` in decimal.
+//! * `OsString` and `PathBuf`: UTF-8 strings, non UTF-8 values fail to convert.
+//! * `Duration` and `SystemTime`: `u64` microseconds, since unix epoch for `SystemTime`.
+//! * `Option<T>`: a reserved sentinel value, see [`option_to_dbus`], or with `dbus` feature a
+//!   variant holding the value or an empty marker, see `option_to_variant`.
+//!
+//! # Features
+//! * `derive` (default): re-exports derive macros from `dbus-derive` in [`prelude`].
+//! * `dbus` (default): [`Wire`] and [`DbusArg`] impls for dbus-rs object paths, signatures and
+//!   variants.
+//! * `uuid`: [`DbusArg`] impl for `uuid::Uuid` as a string.
+//! * `chrono`: [`DbusArg`] impls for `chrono::DateTime<Utc>` as an RFC 3339 string or
+//!   microseconds since unix epoch.
+//! * `time`: same as `chrono`, for `time::OffsetDateTime`. `time` implements
+//!   `PartialEq<time::Duration>` for `std::time::Duration`, so comparing a `Duration` against a
+//!   bare `DbusArg::from_dbus(..)` needs the result type spelled out, same as with `json` below.
+//! * `json`: [`DbusArg`] impl for `serde_json::Value` as a variant, objects are sent as `a{sv}`.
+//!   Note that `serde_json` implements `PartialEq<Value>` for primitives, so enabling this feature
+//!   can break inference in downstream code that compares against `to_dbus()` without naming the
+//!   wire type, e.g. `assert_eq!(7u32, 7usize.to_dbus()?)`. Spell it out with
+//!   `DbusArg::<u32>::to_dbus(&7usize)` instead.
+//! * `testing`: `testing` module with message roundtrip helpers and proptest strategies.
+
+mod arg;
+mod error;
+mod external;
+pub mod lenient;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod wire;
+
+pub use arg::{option_from_dbus, option_to_dbus, DbusArg, DbusDictKey};
+#[cfg(feature = "dbus")]
+pub use arg::{option_from_variant, option_to_variant};
+pub use error::{DbusArgError, PathSegment};
+pub use wire::Wire;
+
+/// Traits and derive macros needed to declare D-Bus types, meant for glob import.
+///
+#[cfg_attr(all(feature = "derive", feature = "dbus"), doc = "```")]
+#[cfg_attr(not(all(feature = "derive", feature = "dbus")), doc = "```ignore")]
+/// use dbus_traits::prelude::*;
+///
+/// #[derive(DbusStruct, Debug, PartialEq)]
+/// struct Mode {
+///     #[dbus(arg_type = "u32")]
+///     id: usize,
+///     refresh_rate: f64,
+/// }
+///
+/// assert_eq!("(ud)", <Mode as dbus::arg::Arg>::signature().to_string());
+/// ```
+pub mod prelude {
+    pub use crate::{dbus_newtype, DbusArg, DbusArgError, DbusDictKey, Wire};
+    #[cfg(feature = "derive")]
+    pub use dbus_derive::{DbusArgs, DbusEnum, DbusPropMap, DbusStruct};
+}
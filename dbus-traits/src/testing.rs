@@ -0,0 +1,123 @@
+//! Helpers for testing D-Bus bindings, which send a value through an in-memory
+//! [`dbus::Message`] and read it back. Reading fails if [`Append`] and [`Get`] implementations
+//! disagree on a signature, so the helpers catch signature drift between the two.
+//!
+//! [`strategy`] module has proptest strategies for values that D-Bus could represent.
+//!
+//! # Examples
+//! ```
+//! use std::collections::HashMap;
+//!
+//! use dbus_traits::testing::assert_roundtrip;
+//!
+//! assert_roundtrip(&HashMap::from([("HDMI-1".to_string(), vec![60.0, 59.94])]));
+//! ```
+
+use std::fmt::Debug;
+
+use dbus::{
+    arg::{Append, AppendAll, Arg, Get, IterAppend, ReadAll, TypeMismatchError},
+    Message,
+};
+
+/// Empty message, for tests which append or read wire values directly instead of a roundtrip.
+pub fn new_message() -> Message {
+    Message::new_method_call(
+        "org.freedesktop.DBus",
+        "/",
+        "org.freedesktop.DBus",
+        "Roundtrip",
+    )
+    .expect("Message parameters should be valid")
+}
+
+/// Appends `value` as a single argument of a message and reads it back.
+pub fn roundtrip<T>(value: &T) -> Result<T, TypeMismatchError>
+where
+    T: Arg + Append + for<'a> Get<'a>,
+{
+    let mut m = new_message();
+    value.append_by_ref(&mut IterAppend::new(&mut m));
+    m.read1()
+}
+
+/// Appends `value` as all arguments of a message and reads them back, intended for structs
+/// deriving `DbusArgs`.
+pub fn roundtrip_args<T>(value: &T) -> Result<T, TypeMismatchError>
+where
+    T: AppendAll + ReadAll,
+{
+    let mut m = new_message();
+    value.append(&mut IterAppend::new(&mut m));
+    T::read(&mut m.iter_init())
+}
+
+/// Asserts that `value` is read back unchanged and with the signature reported by [`Arg`].
+///
+/// # Panics
+/// If reading fails, value differs after roundtrip or signature doesn't match.
+#[track_caller]
+pub fn assert_roundtrip<T>(value: &T)
+where
+    T: Arg + Append + for<'a> Get<'a> + PartialEq + Debug,
+{
+    let mut m = new_message();
+    value.append_by_ref(&mut IterAppend::new(&mut m));
+    assert_eq!(
+        T::signature(),
+        m.iter_init().signature(),
+        "Appended signature differs from Arg::signature"
+    );
+    let read_value: T = m
+        .read1()
+        .unwrap_or_else(|err| panic!("Failed to read {value:?} back: {err}"));
+    assert_eq!(value, &read_value);
+}
+
+/// Asserts that arguments are read back unchanged, see [`roundtrip_args`].
+///
+/// # Panics
+/// If reading fails or value differs after roundtrip.
+#[track_caller]
+pub fn assert_args_roundtrip<T>(value: &T)
+where
+    T: AppendAll + ReadAll + PartialEq + Debug,
+{
+    let read_value =
+        roundtrip_args(value).unwrap_or_else(|err| panic!("Failed to read {value:?} back: {err}"));
+    assert_eq!(value, &read_value);
+}
+
+/// Proptest strategies for values, which survive a roundtrip through D-Bus unchanged.
+///
+/// Primitives other than strings and doubles could use [`proptest::arbitrary::any`] directly,
+/// and collections [`proptest::collection`].
+pub mod strategy {
+    use std::collections::HashMap;
+
+    use proptest::{collection::SizeRange, prelude::*};
+
+    /// Strings without NUL characters, which D-Bus strings can't contain.
+    pub fn string() -> impl Strategy<Value = String> {
+        "[^\u{0}]{0,32}"
+    }
+
+    /// Doubles except NaN, which is never equal to itself.
+    pub fn double() -> impl Strategy<Value = f64> {
+        any::<f64>().prop_filter("NaN never equals itself", |value| !value.is_nan())
+    }
+
+    /// Valid object paths, like `/org/gnome/Mutter/DisplayConfig`.
+    pub fn object_path() -> impl Strategy<Value = dbus::Path<'static>> {
+        prop_oneof![Just("/".to_string()), "(/[A-Za-z0-9_]{1,8}){1,4}"]
+            .prop_map(|path| dbus::Path::new(path).expect("Generated object path should be valid"))
+    }
+
+    /// Dictionaries with string keys, like properties of an object.
+    pub fn string_dict<V: Strategy>(
+        value: V,
+        size: impl Into<SizeRange>,
+    ) -> impl Strategy<Value = HashMap<String, V::Value>> {
+        proptest::collection::hash_map(string(), value, size)
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use crate::DbusDictKey;
+
+/// Type that a D-Bus library sends as is, the `T` in [`DbusArg<T>`](crate::DbusArg).
+///
+/// Basic types and containers of wire types are implemented for every backend, so conversions
+/// written against them don't depend on a particular D-Bus library. Types specific to a library,
+/// like object paths and variants of dbus-rs, are implemented behind a feature with the name of
+/// that library.
+///
+/// # Examples
+/// ```
+/// use std::collections::HashMap;
+///
+/// use dbus_traits::{DbusArg, DbusArgError, Wire};
+///
+/// fn to_wire<T: DbusArg<W>, W: Wire>(value: &T) -> Result<W, DbusArgError> {
+///     value.to_dbus()
+/// }
+///
+/// let wire: HashMap<String, Vec<u32>> =
+///     to_wire(&HashMap::from([("HDMI-1".to_string(), vec![1usize])])).unwrap();
+/// assert_eq!(vec![1u32], wire["HDMI-1"]);
+/// ```
+pub trait Wire {}
+
+macro_rules! impl_wire {
+    ($($ty:ty),*) => {$(
+        impl Wire for $ty {}
+    )*};
+}
+
+macro_rules! impl_wire_tuple {
+    ($($t:ident),+) => {
+        impl<$($t: Wire),+> Wire for ($($t,)+) {}
+    };
+}
+
+impl_wire!(u8, bool, i16, u16, i32, u32, i64, u64, f64, String);
+
+impl<T: Wire> Wire for Vec<T> {}
+
+impl<K: DbusDictKey, V: Wire> Wire for HashMap<K, V> {}
+
+impl_wire_tuple!(A1);
+impl_wire_tuple!(A1, A2);
+impl_wire_tuple!(A1, A2, A3);
+impl_wire_tuple!(A1, A2, A3, A4);
+impl_wire_tuple!(A1, A2, A3, A4, A5);
+impl_wire_tuple!(A1, A2, A3, A4, A5, A6);
+impl_wire_tuple!(A1, A2, A3, A4, A5, A6, A7);
+impl_wire_tuple!(A1, A2, A3, A4, A5, A6, A7, A8);
+impl_wire_tuple!(A1, A2, A3, A4, A5, A6, A7, A8, A9);
+impl_wire_tuple!(A1, A2, A3, A4, A5, A6, A7, A8, A9, A10);
+impl_wire_tuple!(A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11);
+impl_wire_tuple!(A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11, A12);
+
+#[cfg(feature = "dbus")]
+impl_wire!(
+    dbus::Path<'static>,
+    dbus::Signature<'static>,
+    dbus::arg::Variant<Box<dyn dbus::arg::RefArg>>
+);
+
+#[cfg(feature = "dbus")]
+impl<T: Wire> Wire for dbus::arg::Variant<T> {}
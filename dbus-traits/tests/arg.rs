@@ -0,0 +1,241 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    ffi::OsString,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use dbus_traits::{dbus_newtype, option_from_dbus, option_to_dbus, DbusArg};
+
+#[test]
+fn integer_conversion_out_of_range() {
+    assert_eq!(7u32, DbusArg::<u32>::to_dbus(&7usize).unwrap());
+    assert!(<usize as DbusArg<u32>>::to_dbus(&(u32::MAX as usize + 1)).is_err());
+    assert!(<isize as DbusArg<i32>>::from_dbus(-1).is_ok());
+}
+
+#[test]
+fn array_length_mismatch() {
+    let arr: [usize; 3] = DbusArg::<Vec<u32>>::from_dbus(vec![1, 2, 3]).unwrap();
+    assert_eq!([1, 2, 3], arr);
+
+    let err = <[usize; 3] as DbusArg<Vec<u32>>>::from_dbus(vec![1, 2]).unwrap_err();
+    assert_eq!("Expected array of 3 elements, got 2", err.to_string());
+}
+
+#[test]
+fn map_converts_keys_and_values() {
+    let map = HashMap::from([(1usize, vec![2usize, 3])]);
+    let wire: HashMap<u32, Vec<u64>> = map.to_dbus().unwrap();
+    assert_eq!(HashMap::from([(1u32, vec![2u64, 3])]), wire);
+    assert_eq!(map, DbusArg::from_dbus(wire).unwrap());
+}
+
+#[test]
+fn char_and_os_string_keys() {
+    let map = HashMap::from([('a', 1u32)]);
+    let wire: HashMap<String, u32> = map.to_dbus().unwrap();
+    assert_eq!(HashMap::from([("a".to_string(), 1)]), wire);
+    assert_eq!(map, DbusArg::from_dbus(wire).unwrap());
+    assert!(<char as DbusArg<String>>::from_dbus("ab".to_string()).is_err());
+
+    let map = HashMap::from([(OsString::from("HDMI-1"), 1u32)]);
+    let wire: HashMap<String, u32> = map.to_dbus().unwrap();
+    assert_eq!(map, DbusArg::from_dbus(wire).unwrap());
+}
+
+#[test]
+fn sequences_convert_to_vec() {
+    let deque = VecDeque::from([1usize, 2]);
+    assert_eq!(vec![1u32, 2], DbusArg::<Vec<u32>>::to_dbus(&deque).unwrap());
+
+    let set: BTreeSet<usize> = DbusArg::<Vec<u32>>::from_dbus(vec![3, 1, 3]).unwrap();
+    assert_eq!(BTreeSet::from([1, 3]), set);
+    assert_eq!(vec![1u32, 3], DbusArg::<Vec<u32>>::to_dbus(&set).unwrap());
+
+    let set: HashSet<String> = DbusArg::from_dbus(vec!["a".to_string(), "a".to_string()]).unwrap();
+    assert_eq!(HashSet::from(["a".to_string()]), set);
+}
+
+#[test]
+fn cow_converts_as_owned() {
+    let borrowed: Cow<str> = Cow::Borrowed("HDMI-1");
+    assert_eq!("HDMI-1", DbusArg::<String>::to_dbus(&borrowed).unwrap());
+
+    let ids: Cow<[usize]> = Cow::Borrowed(&[1, 2]);
+    assert_eq!(vec![1u32, 2], DbusArg::<Vec<u32>>::to_dbus(&ids).unwrap());
+
+    let read: Cow<str> = DbusArg::from_dbus("DP-1".to_string()).unwrap();
+    assert!(matches!(read, Cow::Owned(_)));
+}
+
+#[test]
+fn option_sentinel() {
+    assert_eq!(-1i32, option_to_dbus::<u32, _>(&None, -1).unwrap());
+    assert_eq!(7i32, option_to_dbus(&Some(7u32), -1).unwrap());
+    assert!(option_to_dbus(&Some(u32::MAX), -1i32).is_err());
+    // Value that converts into sentinel would be read back as None
+    assert!(option_to_dbus(&Some(0u32), 0i32).is_err());
+
+    assert_eq!(None::<u32>, option_from_dbus(-1i32, &-1).unwrap());
+    assert_eq!(Some(7u32), option_from_dbus(7i32, &-1).unwrap());
+    assert!(option_from_dbus::<u32, _>(-2i32, &-1).is_err());
+}
+
+#[cfg(feature = "dbus")]
+#[test]
+fn option_variant_marker() {
+    use dbus::arg::{RefArg, Variant};
+    use dbus_traits::{option_from_variant, option_to_variant};
+
+    let none = option_to_variant::<usize, u32>(&None).unwrap();
+    assert_eq!(None, option_from_variant::<usize, u32>(none).unwrap());
+    let some = option_to_variant::<usize, u32>(&Some(7)).unwrap();
+    assert_eq!(Some(7), option_from_variant::<usize, u32>(some).unwrap());
+
+    let other = Variant(Box::new("7".to_string()) as Box<dyn RefArg>);
+    let err = option_from_variant::<usize, u32>(other).unwrap_err();
+    assert_eq!("Expected u, found s", err.to_string());
+}
+
+#[test]
+fn time_as_micros() {
+    let duration = Duration::from_millis(1500);
+    assert_eq!(1_500_000u64, duration.to_dbus().unwrap());
+    let parsed: Duration = DbusArg::from_dbus(1_500_000u64).unwrap();
+    assert_eq!(duration, parsed);
+    assert!(DbusArg::<u64>::to_dbus(&Duration::MAX).is_err());
+
+    let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    assert_eq!(1_700_000_000_000_000u64, time.to_dbus().unwrap());
+    assert_eq!(
+        time,
+        SystemTime::from_dbus(1_700_000_000_000_000u64).unwrap()
+    );
+    assert!(DbusArg::<u64>::to_dbus(&(UNIX_EPOCH - Duration::from_secs(1))).is_err());
+}
+
+#[test]
+fn paths_as_strings() {
+    let path = PathBuf::from("/run/user/1000/bus");
+    assert_eq!(
+        "/run/user/1000/bus",
+        DbusArg::<String>::to_dbus(&path).unwrap()
+    );
+    assert_eq!(
+        path,
+        PathBuf::from_dbus("/run/user/1000/bus".to_string()).unwrap()
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_path_fails() {
+    use std::os::unix::ffi::OsStringExt;
+
+    let path = PathBuf::from(OsString::from_vec(vec![b'/', 0xff]));
+    assert!(DbusArg::<String>::to_dbus(&path).is_err());
+}
+
+#[test]
+fn wide_integers_as_decimal_strings() {
+    assert_eq!(
+        "340282366920938463463374607431768211455",
+        DbusArg::<String>::to_dbus(&u128::MAX).unwrap()
+    );
+    assert_eq!(-5i128, i128::from_dbus("-5".to_string()).unwrap());
+
+    let err = u128::from_dbus("-5".to_string()).unwrap_err();
+    assert_eq!(
+        "\"-5\" is not a valid u128: invalid digit found in string",
+        err.to_string()
+    );
+}
+
+#[test]
+fn addresses_as_strings() {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 8080));
+    assert_eq!("127.0.0.1:8080", DbusArg::<String>::to_dbus(&addr).unwrap());
+    assert_eq!(
+        addr,
+        SocketAddr::from_dbus("127.0.0.1:8080".to_string()).unwrap()
+    );
+
+    let ip: IpAddr = DbusArg::from_dbus("::1".to_string()).unwrap();
+    assert_eq!(IpAddr::V6(Ipv6Addr::LOCALHOST), ip);
+
+    let err = Ipv4Addr::from_dbus("::1".to_string()).unwrap_err();
+    assert_eq!(
+        "\"::1\" is not a valid IPv4 address: invalid IPv4 address syntax",
+        err.to_string()
+    );
+}
+
+#[test]
+fn tuples_convert_elementwise() {
+    // Same shape as GetCrtcGamma reply
+    let gamma: (Vec<u16>, Vec<u16>, Vec<u16>) = (vec![0, 1], vec![2], vec![]);
+    assert_eq!(gamma, gamma.to_dbus().unwrap());
+
+    let wire: (u32, String, Vec<u64>) =
+        (1usize, PathBuf::from("/tmp"), [2usize]).to_dbus().unwrap();
+    assert_eq!((1, "/tmp".to_string(), vec![2]), wire);
+
+    let twelve = (
+        1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8, 10u8, 11u8, 12u8,
+    );
+    assert_eq!(twelve, DbusArg::from_dbus(twelve).unwrap());
+
+    assert!(<(usize, usize) as DbusArg<(u32, u32)>>::to_dbus(&(1, usize::MAX)).is_err());
+}
+
+#[test]
+fn error_path_to_failed_value() {
+    let map = HashMap::from([("a".to_string(), vec![1usize, usize::MAX])]);
+    let err = <HashMap<String, Vec<usize>> as DbusArg<HashMap<String, Vec<u32>>>>::to_dbus(&map)
+        .unwrap_err();
+    assert_eq!("[\"a\"][1]", err.path());
+
+    let err = <(usize, usize) as DbusArg<(u32, u32)>>::to_dbus(&(1, usize::MAX)).unwrap_err();
+    assert_eq!("[1]", err.path());
+    assert!(err.to_string().starts_with("[1]: "));
+
+    let err = err.in_field("sizes");
+    assert_eq!("sizes[1]", err.path());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Backlight(u8);
+
+impl TryFrom<i32> for Backlight {
+    type Error = String;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match u8::try_from(value) {
+            Ok(value) if value <= 100 => Ok(Backlight(value)),
+            _ => Err(format!("Backlight {value} is out of range 0..=100")),
+        }
+    }
+}
+
+impl From<Backlight> for i32 {
+    fn from(value: Backlight) -> Self {
+        value.0.into()
+    }
+}
+
+dbus_newtype!(Backlight => i32);
+
+#[test]
+fn newtype_through_conversions() {
+    assert_eq!(42i32, Backlight(42).to_dbus().unwrap());
+    assert_eq!(Backlight(42), Backlight::from_dbus(42).unwrap());
+
+    let err = Vec::<Backlight>::from_dbus(vec![1, 101]).unwrap_err();
+    assert_eq!(
+        "[1]: Backlight 101 is out of range 0..=100",
+        err.to_string()
+    );
+}
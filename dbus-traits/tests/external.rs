@@ -0,0 +1,109 @@
+#[cfg(any(
+    feature = "uuid",
+    feature = "chrono",
+    feature = "time",
+    feature = "json"
+))]
+use dbus_traits::DbusArg;
+
+#[cfg(feature = "uuid")]
+#[test]
+fn uuid_as_string() {
+    let id = uuid::Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8);
+    assert_eq!(
+        "67e55044-10b1-426f-9247-bb680e5fe0c8",
+        DbusArg::<String>::to_dbus(&id).unwrap()
+    );
+    assert_eq!(
+        id,
+        uuid::Uuid::from_dbus("67E5504410B1426F9247BB680E5FE0C8".to_string()).unwrap()
+    );
+    assert!(uuid::Uuid::from_dbus("HDMI-1".to_string()).is_err());
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn chrono_as_string_and_micros() {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    let time = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+    assert_eq!(
+        "2024-01-02T03:04:05Z",
+        DbusArg::<String>::to_dbus(&time).unwrap()
+    );
+    assert_eq!(
+        time,
+        DateTime::<Utc>::from_dbus("2024-01-02T05:04:05+02:00".to_string()).unwrap()
+    );
+    assert!(DateTime::<Utc>::from_dbus("yesterday".to_string()).is_err());
+
+    assert_eq!(
+        1_704_164_645_000_000i64,
+        DbusArg::<i64>::to_dbus(&time).unwrap()
+    );
+    assert_eq!(
+        time,
+        DateTime::<Utc>::from_dbus(1_704_164_645_000_000i64).unwrap()
+    );
+    assert!(DateTime::<Utc>::from_dbus(i64::MAX).is_err());
+}
+
+#[cfg(feature = "time")]
+#[test]
+fn time_as_string_and_micros() {
+    use time::{OffsetDateTime, UtcOffset};
+
+    let time = OffsetDateTime::from_unix_timestamp(1_704_164_645)
+        .unwrap()
+        .to_offset(UtcOffset::from_hms(2, 0, 0).unwrap());
+    assert_eq!(
+        "2024-01-02T05:04:05+02:00",
+        DbusArg::<String>::to_dbus(&time).unwrap()
+    );
+    assert_eq!(
+        time,
+        OffsetDateTime::from_dbus("2024-01-02T05:04:05+02:00".to_string()).unwrap()
+    );
+
+    assert_eq!(
+        1_704_164_645_000_000i64,
+        DbusArg::<i64>::to_dbus(&time).unwrap()
+    );
+    assert_eq!(
+        time,
+        OffsetDateTime::from_dbus(1_704_164_645_000_000i64).unwrap()
+    );
+    assert!(OffsetDateTime::from_dbus(i64::MAX).is_err());
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_as_variant() {
+    use std::collections::HashMap;
+
+    use dbus::arg::{PropMap, RefArg, Variant};
+    use serde_json::{json, Value};
+
+    let value = json!({
+        "display-name": "Built-in display",
+        "backlight": -1,
+        "serial": u64::MAX,
+        "scales": [1.0, 1.5],
+        "primary": true,
+    });
+    let variant: Variant<Box<dyn RefArg>> = value.to_dbus().unwrap();
+    assert_eq!("a{sv}", variant.0.signature().to_string());
+    assert_eq!(value, Value::from_dbus(variant).unwrap());
+
+    let props: PropMap = HashMap::from([(
+        "modes".to_string(),
+        Variant(Box::new(vec![(1u32, "1920x1080".to_string())]) as Box<dyn RefArg>),
+    )]);
+    assert_eq!(
+        json!({ "modes": [[1, "1920x1080"]] }),
+        Value::from_dbus(Variant(Box::new(props))).unwrap()
+    );
+
+    let err = DbusArg::<Variant<Box<dyn RefArg>>>::to_dbus(&json!({ "vendor": null })).unwrap_err();
+    assert_eq!("[\"vendor\"]", err.path());
+}
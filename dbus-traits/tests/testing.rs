@@ -0,0 +1,31 @@
+#![cfg(feature = "testing")]
+
+use std::collections::HashMap;
+
+use dbus_traits::testing::{assert_roundtrip, roundtrip, strategy};
+use proptest::prelude::*;
+
+#[test]
+fn roundtrip_returns_read_value() {
+    let value = (1u32, "HDMI-1".to_string(), vec![60.0, 59.94]);
+    assert_eq!(value, roundtrip(&value).unwrap());
+}
+
+proptest! {
+    #[test]
+    fn primitives(a in any::<u32>(), b in any::<i64>(), c in strategy::double(), d in strategy::string()) {
+        assert_roundtrip(&(a, b, c, d));
+    }
+
+    #[test]
+    fn collections(
+        modes in proptest::collection::vec((any::<u32>(), strategy::double()), 0..8),
+        props in strategy::string_dict(any::<bool>(), 0..8),
+        path in strategy::object_path(),
+    ) {
+        assert_roundtrip(&modes);
+        assert_roundtrip(&props);
+        assert_roundtrip(&path);
+        assert_roundtrip(&HashMap::from([(path.clone(), modes.clone())]));
+    }
+}
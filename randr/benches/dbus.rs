@@ -0,0 +1,25 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use dbus::arg::{AppendAll, IterAppend, ReadAll};
+use dbus_traits::testing::new_message;
+use gnome_randr::{dbus_api::GetResourcesReturn, test_fixtures::large_resources};
+
+fn dbus(c: &mut Criterion) {
+    let resources = large_resources(8, 40);
+
+    c.bench_function("append GetResourcesReturn", |b| {
+        b.iter(|| {
+            let mut msg = new_message();
+            black_box(&resources).append(&mut IterAppend::new(&mut msg));
+            msg
+        })
+    });
+
+    let mut msg = new_message();
+    resources.append(&mut IterAppend::new(&mut msg));
+    c.bench_function("read GetResourcesReturn", |b| {
+        b.iter(|| GetResourcesReturn::read(&mut black_box(&msg).iter_init()).unwrap())
+    });
+}
+
+criterion_group!(benches, dbus);
+criterion_main!(benches);
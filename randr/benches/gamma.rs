@@ -0,0 +1,20 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use gnome_randr::gamma::GammaSettings;
+
+fn gamma(c: &mut Criterion) {
+    let settings = GammaSettings {
+        brightness: 0.8,
+        temperature: 4500,
+    };
+    let mut group = c.benchmark_group("gamma ramp");
+    // Common CRTC gamma sizes, 256 for most drivers and 1024 or 4096 for 10 and 12 bit ones
+    for len in [256, 1024, 4096] {
+        group.bench_with_input(BenchmarkId::from_parameter(len), &len, |b, &len| {
+            b.iter(|| black_box(&settings).ramp(len))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, gamma);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use gnome_randr::{
+    cli::{OutputArgs, OutputArgsBuilder},
+    dbus_api::Transform,
+    layout::{Align, Layout},
+    test_fixtures::large_resources,
+};
+
+/// Enables disabled outputs without positions, so they are placed right of the others, and
+/// rotates an enabled one, moving its neighbors.
+fn changes(count: u32) -> Vec<OutputArgs> {
+    let mut changes: Vec<OutputArgs> = (count / 2..count)
+        .map(|id| {
+            OutputArgsBuilder::default()
+                .name(format!("DP-{}", id + 1))
+                .auto(true)
+                .build()
+                .unwrap()
+        })
+        .collect();
+    changes.push(
+        OutputArgsBuilder::default()
+            .name("DP-3")
+            .rotate(Transform::Normal90)
+            .primary(true)
+            .build()
+            .unwrap(),
+    );
+    changes
+}
+
+fn layout(c: &mut Criterion) {
+    let resources = large_resources(8, 40);
+    let changes = changes(8);
+
+    c.bench_function("layout new", |b| {
+        b.iter(|| Layout::new(black_box(&resources)))
+    });
+    c.bench_function("layout 8 outputs", |b| {
+        b.iter(|| {
+            let mut layout = Layout::new(black_box(&resources)).with_align(Align::Center);
+            for args in &changes {
+                layout.apply(args).unwrap();
+            }
+            layout.check_not_empty(false).unwrap();
+            layout.into_config(false)
+        })
+    });
+}
+
+criterion_group!(benches, layout);
+criterion_main!(benches);
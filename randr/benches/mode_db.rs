@@ -0,0 +1,53 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use gnome_randr::{
+    dbus_api::{Mode, ModeId, Output, OutputId},
+    mode_db::ModeDb,
+    output,
+};
+
+/// 500 modes: 50 resolutions with 10 frequencies each, some of them rounding to the same value.
+fn modes() -> Vec<Mode> {
+    (0..500)
+        .map(|id| Mode {
+            id: ModeId(id),
+            width: 640 + (id / 10) * 64,
+            height: 480 + (id / 10) * 36,
+            frequency: 30.0 + (id % 10) as f64 * 7.5 + 0.01 * (id % 3) as f64,
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn output(modes: &[Mode]) -> Output {
+    Output {
+        id: OutputId(0),
+        winsys_id: 0,
+        crtc_id: None,
+        possible_crtc_ids: vec![],
+        connector_name: "HDMI-1".to_string(),
+        mode_ids: modes.iter().map(|mode| mode.id).collect(),
+        clone_ids: vec![],
+        props: Default::default(),
+    }
+}
+
+fn mode_db(c: &mut Criterion) {
+    let modes = modes();
+    let dbus_output = output(&modes);
+
+    c.bench_function("new", |b| b.iter(|| ModeDb::new(black_box(&modes))));
+
+    let mode_db = ModeDb::new(&modes);
+    c.bench_function("list output modes", |b| {
+        b.iter(|| {
+            let output = output::Output::new(black_box(&dbus_output), &mode_db);
+            mode_db
+                .group_by_res(&output.possible_modes)
+                .map(|res_freqs| res_freqs.to_string().len())
+                .sum::<usize>()
+        })
+    });
+}
+
+criterion_group!(benches, mode_db);
+criterion_main!(benches);
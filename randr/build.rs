@@ -0,0 +1,19 @@
+fn main() {
+    #[cfg(feature = "capi")]
+    capi_header();
+}
+
+/// Generates a header for the C API in `$OUT_DIR/gnome_randr.h`, build scripts can't write
+/// anywhere else.
+#[cfg(feature = "capi")]
+fn capi_header() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let header = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("gnome_randr.h");
+    cbindgen::generate(&crate_dir)
+        .expect("C API could be described")
+        .write_to_file(&header);
+    // Shown with `cargo build -vv`
+    println!("C API header: {}", header.display());
+    println!("cargo:rerun-if-changed=src/capi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
@@ -0,0 +1,25 @@
+# Messages of gnome-randr, embedded as a fallback for missing translations.
+# Translations are read from $XDG_DATA_DIRS/gnome-randr/locale/<LANGUAGE>.ftl
+
+## Output listing
+serial = serial: { $serial }
+index = index: { $index }
+cloned-with = [cloned with { $outputs }]
+ids = output id: { $output }, winsys id: { $winsys }
+crtc-ids = crtc id: { $crtc }, crtc winsys id: { $winsys }
+color-profile = color profile: { $profile }
+current = current: { $mode }
+disabled = disabled
+transforms = transforms: { $transforms }
+frequencies = { $resolution }, freqs: [{ $frequencies }]
+mode-timing = { $mode }: { $clock } MHz pixel clock, { $total } total, { $sync }
+mode-timing-estimated = { $mode }: { $clock } MHz pixel clock, { $total } total (estimated), { $sync }
+sync-unknown = unknown sync
+
+## Applying
+applied-layout = applied layout { $name }
+
+## Errors and warnings
+error = Error: { $error }
+warning = warning: { $message }
+note = note: { $message }
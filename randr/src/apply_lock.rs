@@ -0,0 +1,114 @@
+//! Advisory lock held while a configuration is read, changed and applied, so a running `lock`
+//! and a manual invocation don't apply configurations at the same time.
+//!
+//! The lock is reentrant within a thread, so a caller could hold it across several steps that
+//! acquire it themselves.
+
+use std::{
+    cell::Cell,
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+
+/// How long to wait for another invocation to finish applying, if `--lock-timeout` is not given.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Lock file in `$XDG_RUNTIME_DIR`, like the `lock` snapshot. Falls back to the temp dir, which
+/// is shared between users, so the name includes the UID there.
+pub fn path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("gnome-randr-apply.lock"),
+        None => {
+            // SAFETY: getuid is always successful and has no preconditions
+            let uid = unsafe { libc::getuid() };
+            std::env::temp_dir().join(format!("gnome-randr-apply-{uid}.lock"))
+        }
+    }
+}
+
+thread_local! {
+    /// Number of [`ApplyLock`]s held by the current thread
+    static HELD: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Held lock, released when the outermost one on a thread is dropped.
+#[derive(Debug)]
+pub struct ApplyLock {
+    _file: Option<File>,
+}
+
+impl Drop for ApplyLock {
+    fn drop(&mut self) {
+        HELD.set(HELD.get() - 1);
+    }
+}
+
+/// Waits until no other invocation holds the lock at [`path`]. Returns right away, if the current
+/// thread already holds it.
+pub fn acquire(timeout: Option<Duration>) -> anyhow::Result<ApplyLock> {
+    if HELD.get() > 0 {
+        HELD.set(HELD.get() + 1);
+        return Ok(ApplyLock { _file: None });
+    }
+    acquire_at(&path(), timeout.unwrap_or(DEFAULT_TIMEOUT))
+}
+
+fn acquire_at(path: &Path, timeout: Duration) -> anyhow::Result<ApplyLock> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("could not open {}", path.display()))?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        match file.try_lock() {
+            Ok(()) => {
+                HELD.set(HELD.get() + 1);
+                return Ok(ApplyLock { _file: Some(file) });
+            }
+            Err(std::fs::TryLockError::WouldBlock) if Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(std::fs::TryLockError::WouldBlock) => {
+                return Err(anyhow!(
+                    "another gnome-randr is applying a configuration, {} is still locked after {:.1}s",
+                    path.display(),
+                    timeout.as_secs_f64()
+                ));
+            }
+            Err(std::fs::TryLockError::Error(err)) => {
+                return Err(err).with_context(|| format!("could not lock {}", path.display()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_waits_for_holder() {
+        let path = std::env::temp_dir().join(format!(
+            "gnome-randr-apply-test-{}.lock",
+            std::process::id()
+        ));
+        let held = acquire_at(&path, Duration::ZERO).unwrap();
+        let err = acquire_at(&path, Duration::from_millis(100)).unwrap_err();
+        assert!(err.to_string().contains("still locked after 0.1s"));
+
+        let waiting = thread::spawn({
+            let path = path.clone();
+            move || acquire_at(&path, Duration::from_secs(5)).map(drop)
+        });
+        thread::sleep(Duration::from_millis(100));
+        drop(held);
+        waiting.join().unwrap().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+//! Backlight changes requested with `--backlight`, optionally faded with `--fade`, since instant
+//! jumps are jarring.
+
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+
+use crate::{
+    cancel::Cancel,
+    cli::OutputArgs,
+    dbus_api::{ChangeBacklightArgs, ConfigSerial, DisplayConfigApi, OutputId},
+    progress, suggest,
+};
+
+/// Backlight percents changed in a single step of a fade, unless `--fade-step` is given.
+pub const DEFAULT_FADE_STEP: u32 = 2;
+
+/// Values set one after another to go from `from` to `to`, at most `step` apart. Ends with
+/// `to`, doesn't include `from`.
+pub fn fade_steps(from: i32, to: i32, step: u32) -> Vec<i32> {
+    let count = (to - from).unsigned_abs().div_ceil(step.max(1)).max(1) as i32;
+    (1..=count)
+        .map(|idx| from + (to - from) * idx / count)
+        .collect()
+}
+
+/// Sets backlight of `outputs` back to values they had before, reporting outputs that couldn't
+/// be restored.
+fn restore(
+    display_config: &impl DisplayConfigApi,
+    serial: ConfigSerial,
+    outputs: &[(OutputId, &str, i32)],
+) -> anyhow::Result<()> {
+    let failed: Vec<_> = outputs
+        .iter()
+        .filter(|&&(output, _, value)| {
+            let args = ChangeBacklightArgs {
+                serial,
+                output,
+                value,
+            };
+            display_config.change_backlight(args).is_err()
+        })
+        .map(|&(_, name, _)| name)
+        .collect();
+    match failed.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "could not restore backlight of {}",
+            failed.join(", ")
+        )),
+    }
+}
+
+/// Changes backlight of every output with `--backlight`. Fades of different outputs run at the
+/// same time, a cancelled fade restores backlight it started from. If changing one output fails,
+/// outputs that were already changed are restored as well.
+pub fn apply(
+    display_config: &impl DisplayConfigApi,
+    outputs: &[OutputArgs],
+    cancel: &Cancel,
+) -> anyhow::Result<()> {
+    if outputs.iter().all(|args| args.backlight.is_none()) {
+        return Ok(());
+    }
+    // Configuration might have just been applied, which changes serial
+    let resources = display_config.get_resources()?;
+
+    let mut changes: Vec<(Duration, OutputId, i32)> = Vec::new();
+    let mut initial: Vec<(OutputId, &str, i32)> = Vec::new();
+    for args in outputs {
+        let Some(target) = args.backlight else {
+            continue;
+        };
+        let output = resources
+            .outputs
+            .iter()
+            .find(|output| output.connector_name == args.name)
+            .ok_or_else(|| {
+                anyhow!(suggest::did_you_mean(
+                    format!("output {} not found", args.name),
+                    &args.name,
+                    resources
+                        .outputs
+                        .iter()
+                        .map(|output| output.connector_name.as_str()),
+                    "",
+                ))
+            })?;
+        let current = output
+            .props
+            .backlight
+            .filter(|&backlight| backlight >= 0)
+            .ok_or_else(|| anyhow!("output {} doesn't support backlight", args.name))?;
+        initial.push((output.id, &output.connector_name, current as i32));
+        let fade = args.fade.unwrap_or_default();
+        let steps = fade_steps(
+            current as i32,
+            target as i32,
+            args.fade_step.unwrap_or(DEFAULT_FADE_STEP),
+        );
+        let count = steps.len() as u32;
+        changes.extend(
+            (1..)
+                .zip(steps)
+                .map(|(idx, value)| (fade * idx / count, output.id, value)),
+        );
+    }
+    changes.sort_by_key(|&(offset, _, _)| offset);
+
+    let start = Instant::now();
+    let count = changes.len();
+    let mut touched: Vec<OutputId> = Vec::new();
+    for (idx, (offset, output, value)) in (1..).zip(changes) {
+        // Sleeping until a deadline keeps the fade duration despite time spent in calls
+        let slept = cancel.sleep((start + offset).saturating_duration_since(Instant::now()));
+        if let Err(cancelled) = slept {
+            restore(display_config, resources.serial, &initial)?;
+            return Err(cancelled.into());
+        }
+        let changed = display_config.change_backlight(ChangeBacklightArgs {
+            serial: resources.serial,
+            output,
+            value,
+        });
+        if let Err(err) = changed {
+            let (_, name, _) = initial.iter().find(|&&(id, _, _)| id == output).unwrap();
+            let err = anyhow!("could not change backlight of {name}: {err}");
+            // The failed output could be half way through a fade
+            let changed: Vec<_> = initial
+                .iter()
+                .filter(|(id, _, _)| touched.contains(id))
+                .copied()
+                .collect();
+            return Err(match restore(display_config, resources.serial, &changed) {
+                Ok(()) => err,
+                Err(restore_err) => anyhow!("{err}, and {restore_err}"),
+            });
+        }
+        if !touched.contains(&output) {
+            touched.push(output);
+        }
+        progress::report("fade", 100.0 * idx as f64 / count as f64);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cli::OutputArgsBuilder, layout::tests::ScriptedApi};
+
+    #[test]
+    fn fade_step_values() {
+        assert_eq!(vec![40, 35, 30], fade_steps(45, 30, 5));
+        assert_eq!(vec![34, 38, 42, 46, 50], fade_steps(30, 50, 4));
+        assert_eq!(vec![30], fade_steps(30, 30, 5));
+        assert_eq!(vec![100], fade_steps(0, 100, 100));
+    }
+
+    #[test]
+    fn changed_outputs_restored_on_failure() {
+        let outputs = [("HDMI-1", 80), ("DP-1", 20)].map(|(name, backlight)| {
+            OutputArgsBuilder::default()
+                .name(name)
+                .backlight(backlight)
+                .fade_step(100)
+                .build()
+                .unwrap()
+        });
+        let api = ScriptedApi::failing("backlight 1 20");
+        let err = apply(&api, &outputs, &Cancel::new()).unwrap_err();
+        assert_eq!(
+            "could not change backlight of DP-1: backlight 1 20 failed",
+            err.to_string()
+        );
+        assert_eq!(
+            vec!["backlight 0 80", "backlight 1 20", "backlight 0 50"],
+            api.calls()
+        );
+
+        let api = ScriptedApi::failing("backlight 0");
+        let err = apply(&api, &outputs, &Cancel::new()).unwrap_err();
+        assert!(err.to_string().contains("backlight of HDMI-1"));
+        assert_eq!(vec!["backlight 0 80"], api.calls());
+    }
+}
@@ -0,0 +1,183 @@
+//! `bounds` command printing the desktop bounding box and the rectangle of every logical
+//! monitor, in the coordinates windows and screenshots use.
+//!
+//! Logical monitor size follows its transform, and its scale when Mutter lays monitors out in
+//! logical pixels, so it differs from CRTC size GetResources reports.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::{
+    dbus_api::{GetCurrentStateReturn, LayoutMode, LogicalMonitor},
+    layout::Rect,
+};
+
+/// Rectangle of a single logical monitor.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MonitorBounds {
+    /// Connectors showing this logical monitor, more than one when mirroring
+    pub connectors: Vec<String>,
+    #[serde(flatten)]
+    pub rect: Rect,
+    pub scale: f64,
+    pub primary: bool,
+}
+
+/// Bounding box of the desktop and rectangles of logical monitors it's made of.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Bounds {
+    /// `None` if no monitor is enabled
+    pub desktop: Option<Rect>,
+    pub monitors: Vec<MonitorBounds>,
+}
+
+impl Bounds {
+    pub fn new(state: &GetCurrentStateReturn) -> Self {
+        let logical_pixels = state.layout_mode() == LayoutMode::Logical;
+        let monitors: Vec<_> = state
+            .logical_monitors
+            .iter()
+            .filter_map(|logical| Self::monitor(state, logical, logical_pixels))
+            .collect();
+        let desktop = monitors
+            .iter()
+            .map(|monitor| monitor.rect)
+            .reduce(|desktop, rect| desktop.union(&rect));
+        Self { desktop, monitors }
+    }
+
+    /// Rectangle of `logical`, `None` if none of its monitors reports a current mode.
+    fn monitor(
+        state: &GetCurrentStateReturn,
+        logical: &LogicalMonitor,
+        logical_pixels: bool,
+    ) -> Option<MonitorBounds> {
+        let mode = logical.monitors.iter().find_map(|spec| {
+            state
+                .monitors
+                .iter()
+                .find(|monitor| monitor.spec == *spec)?
+                .modes
+                .iter()
+                .find(|mode| mode.props.is_current == Some(true))
+        })?;
+        let (mut width, mut height) = (mode.width as f64, mode.height as f64);
+        if logical.transform.is_sideways() {
+            (width, height) = (height, width);
+        }
+        if logical_pixels {
+            (width, height) = (width / logical.scale, height / logical.scale);
+        }
+        Some(MonitorBounds {
+            connectors: logical
+                .monitors
+                .iter()
+                .map(|spec| spec.connector.clone())
+                .collect(),
+            rect: Rect {
+                x: logical.x,
+                y: logical.y,
+                width: width.round() as i32,
+                height: height.round() as i32,
+            },
+            scale: logical.scale,
+            primary: logical.primary,
+        })
+    }
+}
+
+/// Geometry in X11 form, like `1920x1080+0+0`.
+struct Geometry(Rect);
+
+impl Display for Geometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Rect {
+            x,
+            y,
+            width,
+            height,
+        } = self.0;
+        write!(f, "{width}x{height}{x:+}{y:+}")
+    }
+}
+
+impl Display for Bounds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.desktop {
+            Some(desktop) => writeln!(f, "desktop: {}", Geometry(desktop))?,
+            None => writeln!(f, "desktop: none")?,
+        }
+        for monitor in &self.monitors {
+            write!(
+                f,
+                "{}: {} scale {}",
+                monitor.connectors.join(", "),
+                Geometry(monitor.rect),
+                monitor.scale
+            )?;
+            if monitor.primary {
+                f.write_str(" primary")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints bounds of `state`, as JSON if `json` is set.
+pub fn run(state: &GetCurrentStateReturn, json: bool) -> anyhow::Result<()> {
+    let bounds = Bounds::new(state);
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&bounds)?),
+        false => print!("{bounds}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dbus_api::Transform, layout::tests::current_state};
+
+    #[test]
+    fn logical_rectangles() {
+        let mut state = current_state();
+        for monitor in &mut state.monitors {
+            monitor.modes[0].props.is_current = Some(true);
+        }
+        state.props.layout_mode = Some(LayoutMode::Physical as u32);
+        assert_eq!(
+            "desktop: 3840x1080+0+0\nHDMI-1: 1920x1080+0+0 scale 1 primary\nDP-1: 1920x1080+1920+0 scale 2\n",
+            Bounds::new(&state).to_string()
+        );
+
+        // Logical pixels are divided by scale, rotation swaps sides
+        state.props.layout_mode = Some(LayoutMode::Logical as u32);
+        state.logical_monitors[0].transform = Transform::Normal90;
+        let bounds = Bounds::new(&state);
+        assert_eq!(
+            Rect {
+                x: 1920,
+                y: 0,
+                width: 960,
+                height: 540
+            },
+            bounds.monitors[1].rect
+        );
+        assert_eq!(
+            Some(Rect {
+                x: 0,
+                y: 0,
+                width: 2880,
+                height: 1920
+            }),
+            bounds.desktop
+        );
+        let json = serde_json::to_value(&bounds).unwrap();
+        assert_eq!(960, json["monitors"][1]["width"]);
+
+        state.monitors[1].modes[0].props.is_current = None;
+        assert_eq!(1, Bounds::new(&state).monitors.len());
+    }
+}
@@ -0,0 +1,106 @@
+//! Cancellation of long waits, like waiting for the service, fades, `watch` and `lock`.
+//!
+//! APIs that wait take a [`Cancel`] and check it between steps, so they could restore what they
+//! changed instead of being killed halfway.
+
+use std::{
+    error::Error,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+/// How often a cancellable sleep checks for cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Shared cancellation flag, clones cancel each other.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    /// Handle that is only cancelled with [`Cancel::cancel`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle cancelled by Ctrl+C or SIGTERM. A second signal terminates the process, in case
+    /// whatever runs doesn't check for cancellation.
+    pub fn on_interrupt() -> std::io::Result<Self> {
+        let cancel = Self::new();
+        for signal in [SIGINT, SIGTERM] {
+            // Registered first, so it only fires when the flag is already set
+            signal_hook::flag::register_conditional_shutdown(signal, 130, cancel.0.clone())?;
+            signal_hook::flag::register(signal, cancel.0.clone())?;
+        }
+        Ok(cancel)
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails with [`Cancelled`] if cancelled.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        match self.is_cancelled() {
+            true => Err(Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    /// Sleeps for `duration`, returning early with [`Cancelled`] when cancelled.
+    pub fn sleep(&self, duration: Duration) -> Result<(), Cancelled> {
+        let deadline = Instant::now() + duration;
+        loop {
+            self.check()?;
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(());
+            }
+            thread::sleep(left.min(POLL_INTERVAL));
+        }
+    }
+}
+
+/// Error of a wait stopped by [`Cancel`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("interrupted")
+    }
+}
+
+impl Error for Cancelled {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_cancelled() {
+        let cancel = Cancel::new();
+        assert_eq!(Ok(()), cancel.sleep(Duration::from_millis(1)));
+
+        let clone = cancel.clone();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            clone.cancel();
+        });
+        let start = Instant::now();
+        assert_eq!(Err(Cancelled), cancel.sleep(Duration::from_secs(30)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        canceller.join().unwrap();
+        assert_eq!(Err(Cancelled), cancel.check());
+    }
+}
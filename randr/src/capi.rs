@@ -0,0 +1,199 @@
+//! C API for tools that can't link Rust, enabled with the `capi` feature. Functions connect to
+//! the session bus on every call, so there is no state to set up.
+//!
+//! Functions returning `int` return 0 on success and -1 on failure, with the message available
+//! from [`gnome_randr_last_error`]. Strings returned by the library are freed with
+//! [`gnome_randr_string_free`]. Panics are caught and reported as failures, since they can't
+//! unwind into C.
+//!
+//! The header is generated by the build script into `$OUT_DIR/gnome_randr.h`, its path is
+//! printed with `cargo build --features capi -vv`. It could also be generated with cbindgen CLI:
+//! `cbindgen --crate gnome-randr --output gnome_randr.h` from the crate directory.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    apply_lock, backlight,
+    cancel::Cancel,
+    cli::OutputArgsBuilder,
+    dbus_api::{DisplayConfig, GetResourcesReturn},
+    lock::Snapshot,
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(err: anyhow::Error) {
+    // Interior NULs can't be passed to C, so the message is cut there
+    let message = format!("{err:#}");
+    let message = message.split('\0').next().unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+fn return_code(result: anyhow::Result<()>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            set_last_error(err);
+            -1
+        }
+    }
+}
+
+/// Runs `f`, turning a panic into a failure that returns `failed`.
+fn catch_panic<T>(failed: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown cause");
+        set_last_error(anyhow!("panicked: {message}"));
+        failed
+    })
+}
+
+/// Runs `f` with a connection to DisplayConfig, turning its result into a return code.
+fn with_display_config(f: impl FnOnce(&DisplayConfig) -> anyhow::Result<()>) -> c_int {
+    return_code(
+        dbus::blocking::Connection::new_session()
+            .map_err(anyhow::Error::from)
+            .and_then(|conn| f(&DisplayConfig::new(&conn))),
+    )
+}
+
+/// # Safety
+/// `ptr` must be a valid NUL-terminated string.
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> anyhow::Result<&'a str> {
+    if ptr.is_null() {
+        return Err(anyhow!("{name} is NULL"));
+    }
+    Ok(CStr::from_ptr(ptr).to_str()?)
+}
+
+/// Message of the last failure on this thread, NULL if nothing failed. Owned by the library and
+/// valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn gnome_randr_last_error() -> *const c_char {
+    catch_panic(std::ptr::null(), || {
+        LAST_ERROR.with(|last| {
+            last.borrow()
+                .as_ref()
+                .map_or(std::ptr::null(), |s| s.as_ptr())
+        })
+    })
+}
+
+/// Outputs, CRTCs and modes as GetResources JSON, like `gnome-randr --json` prints. NULL on
+/// failure.
+#[no_mangle]
+pub extern "C" fn gnome_randr_list_outputs() -> *mut c_char {
+    catch_panic(std::ptr::null_mut(), || {
+        let mut json = None;
+        let code = with_display_config(|display_config| {
+            let resources = display_config.get_resources()?;
+            json = Some(CString::new(serde_json::to_string(&resources)?)?);
+            Ok(())
+        });
+        match (code, json) {
+            (0, Some(json)) => json.into_raw(),
+            _ => std::ptr::null_mut(),
+        }
+    })
+}
+
+/// Applies a layout saved as GetResources JSON, like `gnome-randr --json` prints. Outputs that
+/// are connected but not enabled in the layout are disabled. Waits for the apply lock, like the
+/// `gnome-randr` commands, so it doesn't interleave with a running `watch` or `lock`.
+///
+/// # Safety
+/// `json` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gnome_randr_apply_json(json: *const c_char) -> c_int {
+    catch_panic(-1, || {
+        let layout = str_arg(json, "json").and_then(|json| {
+            let resources: GetResourcesReturn = serde_json::from_str(json)?;
+            Ok(Snapshot::capture(&resources))
+        });
+        // Arguments are checked before connecting
+        let layout = match layout {
+            Ok(layout) => layout,
+            Err(err) => return return_code(Err(err)),
+        };
+        with_display_config(|display_config| {
+            let _lock = apply_lock::acquire(None)?;
+            let config = layout.restore(&display_config.get_resources()?)?;
+            display_config.apply_configuration(config)?;
+            Ok(())
+        })
+    })
+}
+
+/// Sets backlight of output `connector` to `percent`, without a fade.
+///
+/// # Safety
+/// `connector` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gnome_randr_set_backlight(
+    connector: *const c_char,
+    percent: u32,
+) -> c_int {
+    catch_panic(-1, || {
+        let connector = match str_arg(connector, "connector") {
+            Ok(connector) => connector,
+            Err(err) => return return_code(Err(err)),
+        };
+        with_display_config(|display_config| {
+            let args = OutputArgsBuilder::default()
+                .name(connector.to_string())
+                .backlight(percent.min(100))
+                .build()?;
+            backlight::apply(display_config, &[args], &Cancel::new())
+        })
+    })
+}
+
+/// Frees a string returned by the library, NULL is ignored.
+///
+/// # Safety
+/// `s` must be returned by the library and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn gnome_randr_string_free(s: *mut c_char) {
+    catch_panic((), || {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_arguments_reported() {
+        let code = unsafe { gnome_randr_set_backlight(std::ptr::null(), 50) };
+        assert_eq!(-1, code);
+        let err = unsafe { CStr::from_ptr(gnome_randr_last_error()) };
+        assert_eq!("connector is NULL", err.to_str().unwrap());
+
+        let json = CString::new("{").unwrap();
+        assert_eq!(-1, unsafe { gnome_randr_apply_json(json.as_ptr()) });
+        let err = unsafe { CStr::from_ptr(gnome_randr_last_error()) };
+        assert!(err.to_str().unwrap().contains("EOF while parsing"));
+    }
+
+    #[test]
+    fn panics_reported() {
+        assert_eq!(-1, catch_panic(-1, || panic!("invalid {}", "layout")));
+        let err = unsafe { CStr::from_ptr(gnome_randr_last_error()) };
+        assert_eq!("panicked: invalid layout", err.to_str().unwrap());
+    }
+}
@@ -1,41 +1,367 @@
+use std::{path::PathBuf, time::Duration};
+
 use anyhow::anyhow;
 use derive_builder::Builder;
 use lexopt::ValueExt;
 
-use crate::mode_db;
+use crate::{
+    config::Config,
+    dbus_api::{ConfigSerial, LayoutMode, ModeId, Reflection, Transform},
+    get,
+    layout::{Align, Placement, Position, Units},
+    mode_db, progress,
+    style::ColorChoice,
+    suggest,
+    template::Template,
+    warnings,
+};
 
 #[derive(Debug)]
 pub struct Cli {
-    pub outputs: Vec<OutputArgs>,
+    pub global: GlobalArgs,
+    pub command: Command,
+}
+
+/// What to do, outputs are listed if nothing else is requested.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// List outputs and their modes
+    Query,
+    /// Change outputs, one `--output` block each
+    Outputs(Vec<OutputArgs>),
+    /// Move outputs together, keeping their arrangement
+    Shift(ShiftArgs),
+    /// Keep re-applying current configuration until unlocked
+    Lock,
+    /// Stop a running lock
+    Unlock,
+    /// Print output changes as they happen
+    Watch(WatchArgs),
+    /// List, delete or re-apply configurations stored by Mutter
+    Configs(ConfigsArgs),
+    /// Diagnose the environment
+    Doctor,
+    /// Print a single value
+    Get(GetArgs),
+    /// Compare the live layout with a saved one
+    Diff(DiffArgs),
+    /// Print the desktop bounding box and logical monitor rectangles
+    Bounds,
+    /// List or restore past configurations
+    History(HistoryArgs),
 }
 
+const USAGE: &str = "\
+Usage: gnome-randr [OPTIONS] [--output <OUTPUT>|winsys:<ID>|%<INDEX> [OUTPUT OPTIONS]]...
+       gnome-randr [OPTIONS] shift --outputs <OUTPUT>[,<OUTPUT>...] --by <X>x<Y>
+       gnome-randr [OPTIONS] lock|unlock|doctor|bounds
+       gnome-randr [OPTIONS] get <OUTPUT> <FIELD> | get primary
+       gnome-randr [OPTIONS] diff <SNAPSHOT_OR_JSON>
+       gnome-randr [OPTIONS] history [restore <INDEX> | diff <FROM> [<TO>]]
+       gnome-randr [OPTIONS] watch [--exec <COMMAND>] [--reapply-gamma] [--serve]
+                                   [--reapply-on-resume]
+       gnome-randr [OPTIONS] configs [--file <MONITORS_XML>] [--apply <INDEX> | --delete <INDEX>]
+
+Options: [--force] [--verbose] [--timings] [--wait-for-service <SECONDS>] [--wait-until-stable <SECONDS>]
+         [--name <LAYOUT>] [--notify] [--expect-serial <SERIAL>]
+         [--placement right-of|above|mirror] [--gap <PIXELS>] [--align start|center|end]
+         [--units logical|physical] [--config <PATH>] [--warnings text|json]
+         [--color auto|always|never] [--lock-timeout <SECONDS>]
+         [--json] [--fixture <GET_RESOURCES_JSON>] [--record <FILE>]
+         [--sync-text-scale] [--preserve-windows] [--settle <DURATION>] [--min-interval <DURATION>]
+         [--progress bar|json] [--layout-mode logical|physical]
+         [--query-current] [--format <FORMAT>]
+
+Output options: [--resolution <WIDTH>x<HEIGHT>] [--fps <FPS>] [--mode-id <ID>] [--pos <X>x<Y>]
+                [--auto] [--off]
+                [--primary] [--backlight <PERCENT> [--fade <DURATION>] [--fade-step <PERCENT>]]
+                [--brightness <MULTIPLIER>] [--temperature <KELVIN>]
+                [--rotate normal|left|inverted|right|flipped|flipped-left|flipped-inverted|flipped-right]
+                [--reflect normal|x|y|xy]
+                [--scale <SCALE>]
+
+Short options: -o --output, -m --mode, -r --rate, -p --pos, -s --scale,
+               -f --force, -v --verbose, -h --help
+Values can be attached like --mode=1920x1080 or -r144, and -- ends options.
+";
+
+const COMMANDS: &[&str] = &[
+    "shift", "lock", "unlock", "doctor", "bounds", "get", "diff", "watch", "configs", "history",
+];
+
 impl Cli {
     pub fn parse(mut p: lexopt::Parser) -> anyhow::Result<Self> {
-        let mut outputs = vec![];
+        let mut global = GlobalArgs::default();
+        let mut command = Command::Query;
 
         while let Some(arg) = p.next()? {
             use lexopt::prelude::*;
             match arg {
-                Long("help") => {
-                    println!("Usage: gnome-randr [--output <OUTPUT> [--resolution <WIDTH>x<HEIGHT>] [--fps <FPS>] [--auto] [--off]]")
+                Short('h') | Long("help") => print!("{USAGE}"),
+                Short('o') | Long("output") => {
+                    command = Command::Outputs(OutputArgs::parse(&mut p, &mut global)?);
+                    break;
                 }
-                Long("output") => {
-                    outputs = OutputArgs::parse(&mut p)?;
+                Value(name) => {
+                    command = match name.string()?.as_str() {
+                        "shift" => Command::Shift(ShiftArgs::parse(&mut p, &mut global)?),
+                        "lock" => Command::Lock,
+                        "unlock" => Command::Unlock,
+                        "doctor" => Command::Doctor,
+                        "bounds" => Command::Bounds,
+                        "get" => Command::Get(GetArgs::parse(&mut p, &mut global)?),
+                        "diff" => Command::Diff(DiffArgs::parse(&mut p, &mut global)?),
+                        "watch" => Command::Watch(WatchArgs::parse(&mut p, &mut global)?),
+                        "configs" => Command::Configs(ConfigsArgs::parse(&mut p, &mut global)?),
+                        "history" => Command::History(HistoryArgs::parse(&mut p, &mut global)?),
+                        name => {
+                            return Err(anyhow!(suggest::did_you_mean(
+                                format!("unknown command {name}"),
+                                name,
+                                COMMANDS.iter().copied(),
+                                "",
+                            )))
+                        }
+                    };
                     break;
                 }
-                _ => return Err(arg.unexpected().into()),
+                _ => match GlobalArgs::option(&arg) {
+                    Some(name) => global.parse(name, &mut p)?,
+                    None => return Err(unexpected(arg, &["help", "output"])),
+                },
             }
         }
 
-        Ok(Self { outputs })
+        // Switching layout mode changes the configuration, so it's applied like outputs are
+        if command == Command::Query && global.layout_mode.is_some() {
+            command = Command::Outputs(Vec::new());
+        }
+
+        Ok(Self { global, command })
     }
 
     pub fn parse_from_env() -> anyhow::Result<Self> {
         Self::parse(lexopt::Parser::from_env())
     }
+
+    /// Fills options not given on the command line from defaults of `config`.
+    pub fn apply_defaults(&mut self, config: &Config) {
+        if let (Command::Outputs(outputs), Some(scale)) = (&mut self.command, config.default_scale)
+        {
+            for output in outputs.iter_mut().filter(|output| !output.off) {
+                output.scale.get_or_insert(scale);
+            }
+        }
+    }
+
+    /// Replaces aliases from `config` with connector names.
+    pub fn resolve_aliases(&mut self, config: &Config) -> anyhow::Result<()> {
+        match &mut self.command {
+            Command::Outputs(outputs) => {
+                for idx in 0..outputs.len() {
+                    let name = config.resolve(&outputs[idx].name).to_string();
+                    if let Some(other) = outputs[..idx].iter().find(|other| other.name == name) {
+                        return Err(anyhow!(
+                            "--output {} and --output {} are both {name}",
+                            other.name,
+                            outputs[idx].name
+                        ));
+                    }
+                    outputs[idx].name = name;
+                }
+            }
+            Command::Get(GetArgs::Field { output, .. }) => {
+                *output = config.resolve(output).to_string();
+            }
+            Command::Shift(shift) => {
+                for name in &mut shift.outputs {
+                    *name = config.resolve(name).to_string();
+                }
+                shift.outputs.sort();
+                shift.outputs.dedup();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Options that are not specific to an output, accepted before and after `--output` blocks.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GlobalArgs {
+    /// Apply changes that would otherwise be refused, like disabling every output
+    pub force: bool,
+    /// Where outputs enabled without `--pos` are placed
+    pub placement: Placement,
+    /// How outputs enabled without `--pos` are aligned to others
+    pub align: Align,
+    /// Pixels left between placed outputs and others, negative to overlap
+    pub gap: i32,
+    /// Serial of the configuration the command is expected to act on
+    pub expect_serial: Option<ConfigSerial>,
+    /// How long to wait for DisplayConfig service to appear
+    pub wait_for_service: Option<Duration>,
+    /// Report time spent in D-Bus calls and layout resolution
+    pub timings: bool,
+    /// How long to wait for applied configuration to be reported back
+    pub wait_until_stable: Option<Duration>,
+    /// Name of applied layout, recorded in the state file and shown in notifications
+    pub name: Option<String>,
+    /// Show a desktop notification after configuration is applied
+    pub notify: bool,
+    /// Units of `--pos` and `shift --by`
+    pub units: Units,
+    /// Layout mode to switch to, if the session supports changing it
+    pub layout_mode: Option<LayoutMode>,
+    /// Configuration file used instead of the default one
+    pub config: Option<PathBuf>,
+    /// Print details like indices and winsys IDs when listing outputs
+    pub verbose: bool,
+    /// Format of warnings and notes printed to stderr
+    pub warnings: warnings::Format,
+    /// When to color query output
+    pub color: ColorChoice,
+    /// How long to wait for another invocation to finish applying a configuration
+    pub lock_timeout: Option<Duration>,
+    /// Print query output as JSON, which could be replayed with `--fixture`
+    pub json: bool,
+    /// Recorded GetResources reply used instead of D-Bus
+    pub fixture: Option<PathBuf>,
+    /// File every D-Bus call and signal is recorded to, for bug reports
+    pub record: Option<PathBuf>,
+    /// Change text scale when `--scale` changes scale of the primary monitor, so text keeps its
+    /// size
+    pub sync_text_scale: bool,
+    /// Move windows back to monitors they were on before the layout change
+    pub preserve_windows: bool,
+    /// Quiet time after MonitorsChanged before `watch` and `lock` act on it
+    pub settle: Option<Duration>,
+    /// Minimal time between actions of `watch` and `lock`
+    pub min_interval: Option<Duration>,
+    /// Format of progress of fades, waits and daemon startup, printed to stderr
+    pub progress: Option<progress::Format>,
+    /// List only enabled outputs, a line each
+    pub query_current: bool,
+    /// Format of listing lines, one per output, with placeholders like `{name}` or `{hz}`
+    pub format: Option<Template>,
+}
+
+impl GlobalArgs {
+    const OPTIONS: &'static [&'static str] = &[
+        "force",
+        "placement",
+        "gap",
+        "align",
+        "expect-serial",
+        "wait-for-service",
+        "timings",
+        "wait-until-stable",
+        "name",
+        "notify",
+        "units",
+        "layout-mode",
+        "config",
+        "verbose",
+        "warnings",
+        "color",
+        "lock-timeout",
+        "json",
+        "fixture",
+        "record",
+        "sync-text-scale",
+        "preserve-windows",
+        "settle",
+        "min-interval",
+        "progress",
+        "query-current",
+        "format",
+    ];
+
+    /// Short aliases of [`Self::OPTIONS`].
+    const SHORT_OPTIONS: &'static [(char, &'static str)] = &[('f', "force"), ('v', "verbose")];
+
+    /// Name of a global option, if the argument is one.
+    fn option(arg: &lexopt::Arg) -> Option<&'static str> {
+        match *arg {
+            lexopt::Arg::Long(name) => Self::OPTIONS.iter().copied().find(|&o| o == name),
+            lexopt::Arg::Short(short) => Self::SHORT_OPTIONS
+                .iter()
+                .find(|&&(s, _)| s == short)
+                .map(|&(_, name)| name),
+            lexopt::Arg::Value(_) => None,
+        }
+    }
+
+    fn parse(&mut self, name: &str, p: &mut lexopt::Parser) -> anyhow::Result<()> {
+        match name {
+            "force" => self.force = true,
+            "timings" => self.timings = true,
+            "notify" => self.notify = true,
+            "verbose" => self.verbose = true,
+            "json" => self.json = true,
+            "sync-text-scale" => self.sync_text_scale = true,
+            "preserve-windows" => self.preserve_windows = true,
+            "fixture" => self.fixture = Some(p.value()?.into()),
+            "record" => self.record = Some(p.value()?.into()),
+            "name" => self.name = Some(p.value()?.string()?),
+            "placement" => self.placement = p.value()?.parse()?,
+            "gap" => self.gap = p.value()?.parse()?,
+            "align" => self.align = p.value()?.parse()?,
+            "units" => self.units = p.value()?.parse()?,
+            "layout-mode" => self.layout_mode = Some(p.value()?.parse()?),
+            "warnings" => self.warnings = p.value()?.parse()?,
+            "color" => self.color = p.value()?.parse()?,
+            "progress" => self.progress = Some(p.value()?.parse()?),
+            "query-current" => self.query_current = true,
+            "format" => self.format = Some(p.value()?.parse()?),
+            "config" => self.config = Some(p.value()?.into()),
+            "expect-serial" => {
+                self.expect_serial = Some(ConfigSerial(p.value()?.parse()?));
+            }
+            "wait-for-service" => self.wait_for_service = Some(parse_secs(name, p)?),
+            "wait-until-stable" => self.wait_until_stable = Some(parse_secs(name, p)?),
+            "lock-timeout" => self.lock_timeout = Some(parse_secs(name, p)?),
+            "settle" => self.settle = Some(parse_secs(name, p)?),
+            "min-interval" => self.min_interval = Some(parse_secs(name, p)?),
+            _ => unreachable!("Only names from OPTIONS are parsed"),
+        }
+        Ok(())
+    }
+}
+
+/// Error for an option that isn't accepted, suggesting the closest one of `options` or global
+/// options if it looks like a typo.
+fn unexpected(arg: lexopt::Arg, options: &[&str]) -> anyhow::Error {
+    let err = arg.clone().unexpected();
+    match arg {
+        lexopt::Arg::Long(name) => anyhow!(suggest::did_you_mean(
+            err,
+            name,
+            options.iter().chain(GlobalArgs::OPTIONS).copied(),
+            "--",
+        )),
+        _ => err.into(),
+    }
+}
+
+fn parse_secs(name: &str, p: &mut lexopt::Parser) -> anyhow::Result<Duration> {
+    let value: String = p.value()?.parse()?;
+    parse_duration(&value).map_err(|err| anyhow!("invalid --{name} {value}: {err}"))
+}
+
+/// Parses durations like `500ms`, `1.5s` or `2`, plain numbers are seconds.
+pub fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let (number, scale) = match s.strip_suffix("ms") {
+        Some(millis) => (millis, 1000.0),
+        None => (s.strip_suffix('s').unwrap_or(s), 1.0),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("expected a number of seconds or milliseconds, like 1.5s or 500ms"))?;
+    Ok(Duration::try_from_secs_f64(number / scale)?)
 }
 
-#[derive(Debug, PartialEq, Eq, Builder)]
+#[derive(Debug, PartialEq, Builder)]
 pub struct OutputArgs {
     #[builder(setter(into))]
     pub name: String,
@@ -47,10 +373,123 @@ pub struct OutputArgs {
     pub resolution: Option<mode_db::Resolution>,
     #[builder(setter(strip_option), default)]
     pub framerate: Option<u32>,
+    /// Mode picked by its API ID, like ones printed with `--json`
+    #[builder(setter(strip_option), default)]
+    pub mode_id: Option<ModeId>,
+    #[builder(default)]
+    pub primary: bool,
+    #[builder(setter(strip_option), default)]
+    pub position: Option<Position>,
+    /// Backlight in percents
+    #[builder(setter(strip_option), default)]
+    pub backlight: Option<u32>,
+    /// How long a backlight change takes
+    #[builder(setter(strip_option), default)]
+    pub fade: Option<Duration>,
+    /// Backlight percents changed in a single step of a fade
+    #[builder(setter(strip_option), default)]
+    pub fade_step: Option<u32>,
+    /// Gamma multiplier, remembered and re-applied after configuration changes
+    #[builder(setter(strip_option), default)]
+    pub brightness: Option<f64>,
+    /// Color temperature in Kelvin, remembered like brightness
+    #[builder(setter(strip_option), default)]
+    pub temperature: Option<u32>,
+    /// Rotation and reflection, checked against transforms the CRTC supports
+    #[builder(setter(strip_option), default)]
+    pub rotate: Option<Transform>,
+    /// Scale of the logical monitor, applied with ApplyMonitorsConfig
+    #[builder(setter(strip_option), default)]
+    pub scale: Option<f64>,
 }
 
 impl OutputArgs {
-    fn parse(p: &mut lexopt::Parser) -> anyhow::Result<Vec<Self>> {
+    const OPTIONS: &'static [&'static str] = &[
+        "output",
+        "mode",
+        "resolution",
+        "mode-id",
+        "auto",
+        "preferred",
+        "off",
+        "rate",
+        "fps",
+        "pos",
+        "position",
+        "primary",
+        "backlight",
+        "brightness",
+        "temperature",
+        "scale",
+        "rotate",
+        "rotation",
+        "reflect",
+        "fade",
+        "fade-step",
+    ];
+
+    /// Checks options that can't be used together, like `--auto` with `--off`, or ones that need
+    /// another option, like `--fade` without `--backlight`.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let name = &self.name;
+
+        let mode_group: Vec<_> = [
+            ("resolution", self.resolution.is_some()),
+            ("mode-id", self.mode_id.is_some()),
+            ("auto", self.auto),
+            ("off", self.off),
+        ]
+        .into_iter()
+        .filter_map(|(flag, set)| set.then_some(flag))
+        .collect();
+        if let [rest @ .., last] = mode_group.as_slice() {
+            if !rest.is_empty() {
+                let mode_options = [rest.join(", "), last.to_string()].join(" and ");
+                return Err(anyhow!(
+                    "using {mode_options} at the same time for output {name}"
+                ));
+            }
+        }
+
+        if self.mode_id.is_some() && self.framerate.is_some() {
+            return Err(anyhow!(
+                "--mode-id already picks a rate, --rate can't be used with it for output {name}"
+            ));
+        }
+
+        for (flag, set) in [
+            ("fade", self.fade.is_some()),
+            ("fade-step", self.fade_step.is_some()),
+        ] {
+            if set && self.backlight.is_none() {
+                return Err(anyhow!("--{flag} requires --backlight for output {name}"));
+            }
+        }
+
+        if self.off {
+            for (flag, set) in [
+                ("rate", self.framerate.is_some()),
+                ("primary", self.primary),
+                ("pos", self.position.is_some()),
+                // --reflect is folded into the rotation while parsing
+                ("rotate", self.rotate.is_some()),
+                ("scale", self.scale.is_some()),
+                ("backlight", self.backlight.is_some()),
+                ("brightness", self.brightness.is_some()),
+                ("temperature", self.temperature.is_some()),
+            ] {
+                if set {
+                    return Err(anyhow!(
+                        "using off and {flag} at the same time for output {name}"
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse(p: &mut lexopt::Parser, global: &mut GlobalArgs) -> anyhow::Result<Vec<Self>> {
         let mut outputs = vec![];
         loop {
             let name: String = p.value()?.parse()?;
@@ -61,6 +500,7 @@ impl OutputArgs {
 
             let mut output_builder = OutputArgsBuilder::default();
             output_builder.name(name.clone());
+            let mut reflect: Option<Reflection> = None;
 
             while let Some(arg) = p.next()? {
                 use lexopt::prelude::*;
@@ -71,16 +511,22 @@ impl OutputArgs {
                     Value(arg) => arg.to_string_lossy().to_string(),
                 };
                 match arg {
-                    Long("output") => {
+                    Short('o') | Long("output") => {
                         next_output = true;
                         break;
                     }
-                    Long("mode") | Long("resolution") => {
+                    Short('m') | Long("mode") | Long("resolution") => {
                         if output_builder.resolution.is_some() {
                             return Err(anyhow!("{arg_str} duplicated for output {name}"));
                         }
                         output_builder.resolution(p.value()?.parse()?);
                     }
+                    Long("mode-id") => {
+                        if output_builder.mode_id.is_some() {
+                            return Err(anyhow!("{arg_str} duplicated for output {name}"));
+                        }
+                        output_builder.mode_id(ModeId(p.value()?.parse()?));
+                    }
                     Long("auto") | Long("preferred") => {
                         if output_builder.auto.is_some() {
                             return Err(anyhow!("{arg_str} duplicated for output {name}"));
@@ -99,30 +545,109 @@ impl OutputArgs {
                         }
                         output_builder.framerate(p.value()?.parse()?);
                     }
-                    _ => return Err(arg.unexpected().into()),
+                    Short('p') | Long("pos") | Long("position") => {
+                        if output_builder.position.is_some() {
+                            return Err(anyhow!("{arg_str} duplicated for output {name}"));
+                        }
+                        output_builder.position(p.value()?.parse()?);
+                    }
+                    Long("primary") => {
+                        if output_builder.primary.is_some() {
+                            return Err(anyhow!("{arg_str} duplicated for output {name}"));
+                        }
+                        output_builder.primary(true);
+                    }
+                    Long("backlight") => {
+                        if output_builder.backlight.is_some() {
+                            return Err(anyhow!("{arg_str} duplicated for output {name}"));
+                        }
+                        let backlight: u32 = p.value()?.parse()?;
+                        if backlight > 100 {
+                            return Err(anyhow!(
+                                "--backlight {backlight} for output {name} is over 100%"
+                            ));
+                        }
+                        output_builder.backlight(backlight);
+                    }
+                    Long("brightness") => {
+                        if output_builder.brightness.is_some() {
+                            return Err(anyhow!("{arg_str} duplicated for output {name}"));
+                        }
+                        let brightness: f64 = p.value()?.parse()?;
+                        if !(brightness > 0.0 && brightness <= 2.0) {
+                            return Err(anyhow!(
+                                "--brightness for output {name} must be over 0 and at most 2"
+                            ));
+                        }
+                        output_builder.brightness(brightness);
+                    }
+                    Long("temperature") => {
+                        if output_builder.temperature.is_some() {
+                            return Err(anyhow!("{arg_str} duplicated for output {name}"));
+                        }
+                        let temperature: u32 = p.value()?.parse()?;
+                        if !(1000..=25000).contains(&temperature) {
+                            return Err(anyhow!(
+                                "--temperature for output {name} must be between 1000 and 25000"
+                            ));
+                        }
+                        output_builder.temperature(temperature);
+                    }
+                    Short('s') | Long("scale") => {
+                        if output_builder.scale.is_some() {
+                            return Err(anyhow!("{arg_str} duplicated for output {name}"));
+                        }
+                        let scale: f64 = p.value()?.parse()?;
+                        if !(scale > 0.0 && scale <= 4.0) {
+                            return Err(anyhow!(
+                                "--scale for output {name} must be over 0 and at most 4"
+                            ));
+                        }
+                        output_builder.scale(scale);
+                    }
+                    Long("rotate") | Long("rotation") => {
+                        if output_builder.rotate.is_some() {
+                            return Err(anyhow!("{arg_str} duplicated for output {name}"));
+                        }
+                        output_builder.rotate(p.value()?.parse()?);
+                    }
+                    Long("reflect") => {
+                        if reflect.is_some() {
+                            return Err(anyhow!("{arg_str} duplicated for output {name}"));
+                        }
+                        reflect = Some(p.value()?.parse()?);
+                    }
+                    Long("fade") => {
+                        if output_builder.fade.is_some() {
+                            return Err(anyhow!("{arg_str} duplicated for output {name}"));
+                        }
+                        output_builder.fade(parse_duration(&p.value()?.string()?)?);
+                    }
+                    Long("fade-step") => {
+                        if output_builder.fade_step.is_some() {
+                            return Err(anyhow!("{arg_str} duplicated for output {name}"));
+                        }
+                        let step: u32 = p.value()?.parse()?;
+                        if step == 0 {
+                            return Err(anyhow!("--fade-step for output {name} must be positive"));
+                        }
+                        output_builder.fade_step(step);
+                    }
+                    _ => match GlobalArgs::option(&arg) {
+                        Some(name) => global.parse(name, p)?,
+                        None => return Err(unexpected(arg, OutputArgs::OPTIONS)),
+                    },
                 }
             }
 
-            let mode_group: Vec<_> = [
-                output_builder.resolution.clone().map(|_| "resolution"),
-                output_builder.auto.map(|_| "auto"),
-                output_builder.off.map(|_| "off"),
-            ]
-            .into_iter()
-            .flatten()
-            .collect();
-            if mode_group.len() > 1 {
-                let mode_options = [
-                    mode_group[..mode_group.len() - 1].join(", "),
-                    mode_group.last().unwrap().to_string(),
-                ]
-                .join(" and ");
-                return Err(anyhow!(
-                    "using {mode_options} at the same time for output {name}"
-                ));
+            // Reflection applies to the rotation given with it, or to no rotation like in xrandr
+            if let Some(reflect) = reflect {
+                let rotate = output_builder.rotate.flatten().unwrap_or_default();
+                output_builder.rotate(rotate.reflected(reflect));
             }
-
-            outputs.push(output_builder.build()?);
+            let output = output_builder.build()?;
+            output.validate()?;
+            outputs.push(output);
 
             if next_output {
                 continue;
@@ -134,6 +659,240 @@ impl OutputArgs {
     }
 }
 
+/// Arguments of `shift` command.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ShiftArgs {
+    /// Connector names of outputs to move
+    pub outputs: Vec<String>,
+    /// Delta added to position of every output
+    pub by: Position,
+}
+
+impl ShiftArgs {
+    const OPTIONS: &'static [&'static str] = &["outputs", "by"];
+
+    fn parse(p: &mut lexopt::Parser, global: &mut GlobalArgs) -> anyhow::Result<Self> {
+        let (mut outputs, mut by) = (None, None);
+        while let Some(arg) = p.next()? {
+            use lexopt::prelude::*;
+            match arg {
+                Long("outputs") => {
+                    if outputs.is_some() {
+                        return Err(anyhow!("--outputs duplicated"));
+                    }
+                    let names: String = p.value()?.parse()?;
+                    let mut names: Vec<_> = names.split(',').map(str::to_string).collect();
+                    if names.iter().any(String::is_empty) {
+                        return Err(anyhow!("empty output name in --outputs"));
+                    }
+                    names.sort();
+                    names.dedup();
+                    outputs = Some(names);
+                }
+                Long("by") => {
+                    if by.is_some() {
+                        return Err(anyhow!("--by duplicated"));
+                    }
+                    by = Some(p.value()?.parse()?);
+                }
+                _ => match GlobalArgs::option(&arg) {
+                    Some(name) => global.parse(name, p)?,
+                    None => return Err(unexpected(arg, ShiftArgs::OPTIONS)),
+                },
+            }
+        }
+        Ok(Self {
+            outputs: outputs.ok_or_else(|| anyhow!("shift requires --outputs"))?,
+            by: by.ok_or_else(|| anyhow!("shift requires --by"))?,
+        })
+    }
+}
+
+/// Arguments of `get` command.
+#[derive(Debug, PartialEq, Eq)]
+pub enum GetArgs {
+    /// `get primary`, connector name of the primary output
+    Primary,
+    /// `get <OUTPUT> <FIELD>`
+    Field { output: String, field: get::Field },
+}
+
+impl GetArgs {
+    fn parse(p: &mut lexopt::Parser, global: &mut GlobalArgs) -> anyhow::Result<Self> {
+        let mut values = Vec::new();
+        while let Some(arg) = p.next()? {
+            use lexopt::prelude::*;
+            match arg {
+                Value(value) => values.push(value.string()?),
+                _ => match GlobalArgs::option(&arg) {
+                    Some(name) => global.parse(name, p)?,
+                    None => return Err(unexpected(arg, &[])),
+                },
+            }
+        }
+        match values.as_slice() {
+            [primary] if primary == "primary" => Ok(GetArgs::Primary),
+            [output, field] => Ok(GetArgs::Field {
+                output: output.clone(),
+                field: field.parse()?,
+            }),
+            _ => Err(anyhow!("get requires <OUTPUT> <FIELD>, or primary")),
+        }
+    }
+}
+
+/// Arguments of `diff` command.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DiffArgs {
+    /// Snapshot printed by `lock`, or GetResources JSON printed with `--json`
+    pub path: PathBuf,
+}
+
+impl DiffArgs {
+    fn parse(p: &mut lexopt::Parser, global: &mut GlobalArgs) -> anyhow::Result<Self> {
+        let mut path = None;
+        while let Some(arg) = p.next()? {
+            use lexopt::prelude::*;
+            match arg {
+                Value(value) if path.is_none() => path = Some(PathBuf::from(value)),
+                _ => match GlobalArgs::option(&arg) {
+                    Some(name) => global.parse(name, p)?,
+                    None => return Err(unexpected(arg, &[])),
+                },
+            }
+        }
+        let path = path.ok_or_else(|| anyhow!("diff requires a saved layout"))?;
+        Ok(Self { path })
+    }
+}
+
+/// Arguments of `history` command.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HistoryArgs {
+    /// `history`, recorded configurations with their indices, newest first
+    List,
+    /// `history restore <INDEX>`
+    Restore(usize),
+    /// `history diff <FROM> [<TO>]`, `to` is the next newer configuration by default, or the live
+    /// one for the newest configuration
+    Diff { from: usize, to: Option<usize> },
+}
+
+impl HistoryArgs {
+    fn parse(p: &mut lexopt::Parser, global: &mut GlobalArgs) -> anyhow::Result<Self> {
+        let mut values = Vec::new();
+        while let Some(arg) = p.next()? {
+            use lexopt::prelude::*;
+            match arg {
+                Value(value) => values.push(value.string()?),
+                _ => match GlobalArgs::option(&arg) {
+                    Some(name) => global.parse(name, p)?,
+                    None => return Err(unexpected(arg, &[])),
+                },
+            }
+        }
+        let index = |index: &String| {
+            index
+                .parse()
+                .map_err(|_| anyhow!("invalid history index {index}"))
+        };
+        match values.as_slice() {
+            [] => Ok(HistoryArgs::List),
+            [restore, idx] if restore == "restore" => Ok(HistoryArgs::Restore(index(idx)?)),
+            [diff, from] if diff == "diff" => Ok(HistoryArgs::Diff {
+                from: index(from)?,
+                to: None,
+            }),
+            [diff, from, to] if diff == "diff" => Ok(HistoryArgs::Diff {
+                from: index(from)?,
+                to: Some(index(to)?),
+            }),
+            _ => Err(anyhow!(
+                "history accepts no arguments, restore <INDEX> or diff <FROM> [<TO>]"
+            )),
+        }
+    }
+}
+
+/// Arguments of `watch` command.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct WatchArgs {
+    /// Shell command run for every event, described by `GNOME_RANDR_*` environment variables
+    pub exec: Option<String>,
+    /// Re-apply stored brightness and temperature after every change
+    pub reapply_gamma: bool,
+    /// Export `io.github.eaglesemanation.GnomeRandr` for switching profiles over D-Bus
+    pub serve: bool,
+    /// Re-apply the current profile, or the layout from before suspend, after resume
+    pub reapply_on_resume: bool,
+}
+
+impl WatchArgs {
+    const OPTIONS: &'static [&'static str] =
+        &["exec", "reapply-gamma", "serve", "reapply-on-resume"];
+
+    fn parse(p: &mut lexopt::Parser, global: &mut GlobalArgs) -> anyhow::Result<Self> {
+        let mut args = Self::default();
+        while let Some(arg) = p.next()? {
+            use lexopt::prelude::*;
+            match arg {
+                Long("exec") => {
+                    if args.exec.is_some() {
+                        return Err(anyhow!("--exec duplicated"));
+                    }
+                    args.exec = Some(p.value()?.string()?);
+                }
+                Long("reapply-gamma") => args.reapply_gamma = true,
+                Long("serve") => args.serve = true,
+                Long("reapply-on-resume") => args.reapply_on_resume = true,
+                _ => match GlobalArgs::option(&arg) {
+                    Some(name) => global.parse(name, p)?,
+                    None => return Err(unexpected(arg, WatchArgs::OPTIONS)),
+                },
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Arguments of `configs` command.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ConfigsArgs {
+    /// Path of `monitors.xml`, found in config directory by default
+    pub file: Option<PathBuf>,
+    /// Index of configuration to re-apply
+    pub apply: Option<usize>,
+    /// Index of configuration to delete
+    pub delete: Option<usize>,
+}
+
+impl ConfigsArgs {
+    const OPTIONS: &'static [&'static str] = &["file", "apply", "delete"];
+
+    fn parse(p: &mut lexopt::Parser, global: &mut GlobalArgs) -> anyhow::Result<Self> {
+        let mut args = Self::default();
+        while let Some(arg) = p.next()? {
+            use lexopt::prelude::*;
+            match arg {
+                Long("file") if args.file.is_none() => args.file = Some(p.value()?.into()),
+                Long("apply") if args.apply.is_none() => args.apply = Some(p.value()?.parse()?),
+                Long("delete") if args.delete.is_none() => args.delete = Some(p.value()?.parse()?),
+                Long(name @ ("file" | "apply" | "delete")) => {
+                    return Err(anyhow!("--{name} duplicated"))
+                }
+                _ => match GlobalArgs::option(&arg) {
+                    Some(name) => global.parse(name, p)?,
+                    None => return Err(unexpected(arg, ConfigsArgs::OPTIONS)),
+                },
+            }
+        }
+        if args.apply.is_some() && args.delete.is_some() {
+            return Err(anyhow!("using apply and delete at the same time"));
+        }
+        Ok(args)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -155,8 +914,8 @@ mod tests {
         ]))
         .unwrap();
         assert_eq!(
-            args.outputs,
-            &[
+            args.command,
+            Command::Outputs(vec![
                 OutputArgsBuilder::default()
                     .name("HDMI-1")
                     .auto(true)
@@ -175,10 +934,232 @@ mod tests {
                     })
                     .build()
                     .unwrap(),
-            ]
+            ])
         )
     }
 
+    #[test]
+    fn global_args_after_outputs() {
+        let args = Cli::parse(lexopt::Parser::from_iter(&[
+            "gnome-randr",
+            "--output",
+            "HDMI-1",
+            "--off",
+            "--force",
+        ]))
+        .unwrap();
+        assert!(args.global.force);
+        assert!(matches!(args.command, Command::Outputs(outputs) if outputs.len() == 1));
+
+        let args = Cli::parse(lexopt::Parser::from_iter(&[
+            "gnome-randr",
+            "--wait-for-service",
+            "1.5",
+        ]))
+        .unwrap();
+        assert_eq!(
+            Some(Duration::from_millis(1500)),
+            args.global.wait_for_service
+        );
+    }
+
+    #[test]
+    fn bare_layout_mode_is_applied() {
+        let args = Cli::parse(lexopt::Parser::from_iter(&[
+            "gnome-randr",
+            "--layout-mode",
+            "physical",
+        ]))
+        .unwrap();
+        assert_eq!(Some(LayoutMode::Physical), args.global.layout_mode);
+        assert_eq!(Command::Outputs(Vec::new()), args.command);
+    }
+
+    #[test]
+    fn shift_command() {
+        let args = Cli::parse(lexopt::Parser::from_iter(&[
+            "gnome-randr",
+            "shift",
+            "--outputs",
+            "DP-2,DP-1",
+            "--by",
+            "+0x-1080",
+        ]))
+        .unwrap();
+        assert_eq!(
+            Command::Shift(ShiftArgs {
+                outputs: vec!["DP-1".to_string(), "DP-2".to_string()],
+                by: Position { x: 0, y: -1080 },
+            }),
+            args.command
+        );
+
+        let args = Cli::parse(lexopt::Parser::from_iter(&[
+            "gnome-randr",
+            "shift",
+            "--by",
+            "1x1",
+        ]));
+        assert!(args.is_err_and(|err| err.to_string().contains("--outputs")));
+    }
+
+    #[test]
+    fn backlight_fade() {
+        let args = Cli::parse(lexopt::Parser::from_iter(&[
+            "gnome-randr",
+            "--output",
+            "eDP-1",
+            "--backlight",
+            "30",
+            "--fade",
+            "500ms",
+        ]))
+        .unwrap();
+        let Command::Outputs(outputs) = args.command else {
+            panic!("expected outputs");
+        };
+        assert_eq!(Some(30), outputs[0].backlight);
+        assert_eq!(Some(Duration::from_millis(500)), outputs[0].fade);
+
+        let args = Cli::parse(lexopt::Parser::from_iter(&[
+            "gnome-randr",
+            "--output",
+            "eDP-1",
+            "--fade",
+            "1s",
+        ]));
+        assert!(args.is_err_and(|err| err.to_string().contains("requires --backlight")));
+    }
+
+    #[test]
+    fn get_command() {
+        let parse = |args: &[&str]| {
+            Cli::parse(lexopt::Parser::from_iter(
+                ["gnome-randr", "get"].iter().chain(args),
+            ))
+        };
+        assert_eq!(
+            Command::Get(GetArgs::Primary),
+            parse(&["primary"]).unwrap().command
+        );
+        assert_eq!(
+            Command::Get(GetArgs::Field {
+                output: "DP-1".to_string(),
+                field: get::Field::Mode
+            }),
+            parse(&["DP-1", "mode", "--verbose"]).unwrap().command
+        );
+        assert!(parse(&["DP-1"]).is_err());
+        assert!(parse(&["DP-1", "mode", "extra"]).is_err());
+    }
+
+    #[test]
+    fn rotation_parsed() {
+        let parse = |rotation| {
+            Cli::parse(lexopt::Parser::from_iter(&[
+                "gnome-randr",
+                "--output",
+                "eDP-1",
+                "--rotate",
+                rotation,
+            ]))
+        };
+        let Command::Outputs(outputs) = parse("flipped-left").unwrap().command else {
+            panic!("expected outputs");
+        };
+        assert_eq!(Some(Transform::Flipped90), outputs[0].rotate);
+        let err = parse("sideways").unwrap_err().to_string();
+        assert!(err.contains("expected one of normal, left, inverted, right, flipped"));
+
+        let args = Cli::parse(lexopt::Parser::from_iter(&[
+            "gnome-randr",
+            "--output",
+            "eDP-1",
+            "--rotate",
+            "left",
+            "--reflect",
+            "x",
+            "--output",
+            "DP-1",
+            "--reflect",
+            "xy",
+        ]))
+        .unwrap();
+        let Command::Outputs(outputs) = args.command else {
+            panic!("expected outputs");
+        };
+        assert_eq!(Some(Transform::Flipped270), outputs[0].rotate);
+        assert_eq!(Some(Transform::Normal180), outputs[1].rotate);
+    }
+
+    #[test]
+    fn defaults_applied() {
+        let config: Config = "[defaults]\nscale = 1.5\n".parse().unwrap();
+        let mut args = Cli::parse(lexopt::Parser::from_iter(&[
+            "gnome-randr",
+            "--output",
+            "eDP-1",
+            "--auto",
+            "--output",
+            "DP-1",
+            "--scale",
+            "2",
+            "--output",
+            "HDMI-1",
+            "--off",
+        ]))
+        .unwrap();
+        args.apply_defaults(&config);
+        let Command::Outputs(outputs) = &args.command else {
+            panic!("expected outputs");
+        };
+        let scales: Vec<_> = outputs.iter().map(|output| output.scale).collect();
+        assert_eq!(vec![Some(1.5), Some(2.0), None], scales);
+    }
+
+    #[test]
+    fn aliases_resolved() {
+        let config: Config = "[aliases]\nLG Office = DP-3\n".parse().unwrap();
+        let mut args = Cli::parse(lexopt::Parser::from_iter(&[
+            "gnome-randr",
+            "--output",
+            "LG Office",
+            "--auto",
+        ]))
+        .unwrap();
+        args.resolve_aliases(&config).unwrap();
+        let Command::Outputs(outputs) = &args.command else {
+            panic!("expected outputs");
+        };
+        assert_eq!("DP-3", outputs[0].name);
+
+        let mut args = Cli::parse(lexopt::Parser::from_iter(&[
+            "gnome-randr",
+            "--output",
+            "LG Office",
+            "--output",
+            "DP-3",
+        ]))
+        .unwrap();
+        assert!(args
+            .resolve_aliases(&config)
+            .is_err_and(|err| err.to_string().contains("are both DP-3")));
+    }
+
+    #[test]
+    fn negative_gap() {
+        let args = Cli::parse(lexopt::Parser::from_iter(&[
+            "gnome-randr",
+            "--placement",
+            "above",
+            "--gap",
+            "-12",
+        ]))
+        .unwrap();
+        assert_eq!(Placement::Above, args.global.placement);
+        assert_eq!(-12, args.global.gap);
+    }
+
     #[test]
     fn no_duplicate_output() {
         let args = Cli::parse(lexopt::Parser::from_iter(&[
@@ -207,5 +1188,267 @@ mod tests {
             let err = err.to_string();
             err.contains("resolution") && err.contains("auto")
         }));
+
+        let parse = |args: &[&str]| {
+            Cli::parse(lexopt::Parser::from_iter(
+                ["gnome-randr", "--output", "HDMI-1"].iter().chain(args),
+            ))
+        };
+        let Command::Outputs(outputs) = parse(&["--mode-id", "42"]).unwrap().command else {
+            panic!("expected outputs");
+        };
+        assert_eq!(Some(ModeId(42)), outputs[0].mode_id);
+        let err = parse(&["--mode-id", "42", "--mode", "1920x1080"]).unwrap_err();
+        assert!(err.to_string().contains("resolution and mode-id"));
+        assert!(parse(&["--mode-id", "42", "--rate", "60"]).is_err());
+    }
+
+    #[test]
+    fn output_args_validated() {
+        type SetFlag = fn(&mut OutputArgsBuilder);
+        let output = || {
+            let mut builder = OutputArgsBuilder::default();
+            builder.name("HDMI-1");
+            builder
+        };
+        let err = |builder: &OutputArgsBuilder| builder.build().unwrap().validate().unwrap_err();
+
+        // Every pair out of the mode group conflicts
+        let mode_group: [(&str, SetFlag); 4] = [
+            ("resolution", |b| {
+                b.resolution("1920x1080".parse().unwrap());
+            }),
+            ("mode-id", |b| {
+                b.mode_id(ModeId(42));
+            }),
+            ("auto", |b| {
+                b.auto(true);
+            }),
+            ("off", |b| {
+                b.off(true);
+            }),
+        ];
+        for (i, (first, set_first)) in mode_group.iter().enumerate() {
+            let mut builder = output();
+            set_first(&mut builder);
+            builder.build().unwrap().validate().unwrap();
+            for (second, set_second) in &mode_group[i + 1..] {
+                let mut builder = output();
+                set_first(&mut builder);
+                set_second(&mut builder);
+                assert_eq!(
+                    format!("using {first} and {second} at the same time for output HDMI-1"),
+                    err(&builder).to_string()
+                );
+            }
+        }
+        let mut builder = output();
+        builder
+            .resolution("1920x1080".parse().unwrap())
+            .auto(true)
+            .off(true);
+        assert_eq!(
+            "using resolution, auto and off at the same time for output HDMI-1",
+            err(&builder).to_string()
+        );
+
+        // Nothing but a mode can be given for a disabled output
+        let off_conflicts: [(&str, SetFlag); 8] = [
+            ("rate", |b| {
+                b.framerate(60);
+            }),
+            ("primary", |b| {
+                b.primary(true);
+            }),
+            ("pos", |b| {
+                b.position("0x0".parse().unwrap());
+            }),
+            ("rotate", |b| {
+                b.rotate(Transform::Normal90);
+            }),
+            ("scale", |b| {
+                b.scale(2.0);
+            }),
+            ("backlight", |b| {
+                b.backlight(50);
+            }),
+            ("brightness", |b| {
+                b.brightness(0.8);
+            }),
+            ("temperature", |b| {
+                b.temperature(4500);
+            }),
+        ];
+        for (flag, set) in off_conflicts {
+            let mut builder = output();
+            set(&mut builder);
+            builder.build().unwrap().validate().unwrap();
+            builder.off(true);
+            assert_eq!(
+                format!("using off and {flag} at the same time for output HDMI-1"),
+                err(&builder).to_string()
+            );
+        }
+
+        let mut builder = output();
+        builder.mode_id(ModeId(42)).framerate(60);
+        assert!(err(&builder).to_string().contains("--rate can't be used"));
+        builder = output();
+        builder
+            .resolution("1920x1080".parse().unwrap())
+            .framerate(60);
+        builder.build().unwrap().validate().unwrap();
+
+        for set in [
+            |b: &mut OutputArgsBuilder| {
+                b.fade(Duration::from_millis(500));
+            },
+            |b: &mut OutputArgsBuilder| {
+                b.fade_step(5);
+            },
+        ] {
+            let mut builder = output();
+            set(&mut builder);
+            assert!(err(&builder).to_string().contains("requires --backlight"));
+            builder.backlight(30);
+            builder.build().unwrap().validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn short_and_attached_options() {
+        let parse = |args: &[&str]| {
+            Cli::parse(lexopt::Parser::from_iter(
+                ["gnome-randr"].iter().chain(args),
+            ))
+        };
+        let expected_global = GlobalArgs {
+            force: true,
+            verbose: true,
+            ..Default::default()
+        };
+        let expected = Command::Outputs(vec![
+            OutputArgsBuilder::default()
+                .name("DP-1")
+                .resolution("1920x1080".parse().unwrap())
+                .framerate(144)
+                .position("1920x0".parse().unwrap())
+                .scale(1.5)
+                .build()
+                .unwrap(),
+            OutputArgsBuilder::default()
+                .name("HDMI-1")
+                .off(true)
+                .build()
+                .unwrap(),
+        ]);
+        for args in [
+            &[
+                "--force",
+                "--verbose",
+                "--output",
+                "DP-1",
+                "--mode",
+                "1920x1080",
+                "--rate",
+                "144",
+                "--pos",
+                "1920x0",
+                "--scale",
+                "1.5",
+                "--output",
+                "HDMI-1",
+                "--off",
+            ][..],
+            &[
+                "-fv",
+                "-o",
+                "DP-1",
+                "-m",
+                "1920x1080",
+                "-r144",
+                "-p",
+                "1920x0",
+                "-s1.5",
+                "-oHDMI-1",
+                "--off",
+            ],
+            &[
+                "-vf",
+                "--output=DP-1",
+                "--mode=1920x1080",
+                "-r=144",
+                "--pos=1920x0",
+                "--scale=1.5",
+                "-o=HDMI-1",
+                "--off",
+            ],
+        ] {
+            let cli = parse(args).unwrap();
+            assert_eq!(expected_global, cli.global, "{args:?}");
+            assert_eq!(expected, cli.command, "{args:?}");
+        }
+
+        // Anything after -- is a value, not an option
+        let args = parse(&["-v", "--", "get", "--json", "backlight"]).unwrap();
+        assert_eq!(
+            Command::Get(GetArgs::Field {
+                output: "--json".to_string(),
+                field: "backlight".parse().unwrap(),
+            }),
+            args.command
+        );
+        assert!(!args.global.json);
+        assert!(parse(&["-o", "DP-1", "--", "--off"]).is_err());
+        assert!(parse(&["-o", "DP-1", "--auto=yes"]).is_err());
+    }
+
+    #[test]
+    fn typos_suggested() {
+        let parse = |args: &[&str]| {
+            Cli::parse(lexopt::Parser::from_iter(
+                ["gnome-randr"].iter().chain(args),
+            ))
+            .unwrap_err()
+            .to_string()
+        };
+        assert!(parse(&["--output", "DP-1", "--resoluton", "1920x1080"])
+            .ends_with("did you mean --resolution?"));
+        assert!(parse(&["--output", "DP-1", "--vrebose"]).ends_with("did you mean --verbose?"));
+        assert!(parse(&["--ouptut", "DP-1"]).ends_with("did you mean --output?"));
+        assert!(parse(&["watch", "--exce", "true"]).ends_with("did you mean --exec?"));
+        assert_eq!(
+            "unknown command dcotor, did you mean doctor?",
+            parse(&["dcotor"])
+        );
+        assert!(!parse(&["--output", "DP-1", "--frobnicate"]).contains("did you mean"));
+    }
+
+    #[test]
+    fn history_command() {
+        let parse = |args: &[&str]| {
+            Cli::parse(lexopt::Parser::from_iter(
+                ["gnome-randr", "history"].iter().chain(args),
+            ))
+            .map(|cli| cli.command)
+        };
+        assert_eq!(Command::History(HistoryArgs::List), parse(&[]).unwrap());
+        assert_eq!(
+            Command::History(HistoryArgs::Restore(2)),
+            parse(&["restore", "2"]).unwrap()
+        );
+        assert_eq!(
+            Command::History(HistoryArgs::Diff { from: 3, to: None }),
+            parse(&["diff", "3"]).unwrap()
+        );
+        assert_eq!(
+            Command::History(HistoryArgs::Diff {
+                from: 3,
+                to: Some(0)
+            }),
+            parse(&["diff", "3", "0"]).unwrap()
+        );
+        assert!(parse(&["restore"]).is_err());
+        assert!(parse(&["diff", "last"]).is_err());
     }
 }
@@ -0,0 +1,85 @@
+//! ICC profiles colord assigns to outputs, shown in `--verbose` listing.
+//!
+//! GNOME registers every monitor as a colord device with an `OutputEdidMd5` metadata key, so
+//! devices are found by EDID rather than by connector, which could change between docks.
+
+use std::{collections::HashMap, time::Duration};
+
+use dbus::{
+    blocking::{stdintf::org_freedesktop_dbus::Properties, Connection},
+    Path,
+};
+use md5::{Digest, Md5};
+
+use crate::dbus_api::GetResourcesReturn;
+
+const SERVICE: &str = "org.freedesktop.ColorManager";
+const TIMEOUT: Duration = Duration::from_millis(2000);
+
+/// Hex MD5 of EDID, as colord stores it.
+pub fn edid_md5(edid: &[u8]) -> String {
+    Md5::digest(edid)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// File name of the default profile of a monitor with `edid`, `None` if colord doesn't know the
+/// monitor or it has no profile.
+pub fn profile(conn: &Connection, edid: &[u8]) -> Result<Option<String>, dbus::Error> {
+    let manager = conn.with_proxy(SERVICE, "/org/freedesktop/ColorManager", TIMEOUT);
+    let found: Result<(Path,), _> = manager.method_call(
+        "org.freedesktop.ColorManager",
+        "FindDeviceByProperty",
+        ("OutputEdidMd5", edid_md5(edid)),
+    );
+    let device = match found {
+        Ok((device,)) => device,
+        Err(err) if err.name() == Some("org.freedesktop.ColorManager.NotFound") => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let profiles: Vec<Path> = conn
+        .with_proxy(SERVICE, device, TIMEOUT)
+        .get("org.freedesktop.ColorManager.Device", "Profiles")?;
+    // First profile is the default one
+    let Some(profile) = profiles.into_iter().next() else {
+        return Ok(None);
+    };
+    let filename: String = conn
+        .with_proxy(SERVICE, profile, TIMEOUT)
+        .get("org.freedesktop.ColorManager.Profile", "Filename")?;
+    Ok(Some(filename))
+}
+
+/// Profiles of every output with EDID, by connector. Without colord there are no profiles.
+pub fn profiles(
+    conn: &Connection,
+    resources: &GetResourcesReturn,
+) -> Result<HashMap<String, String>, dbus::Error> {
+    let mut profiles = HashMap::new();
+    for output in &resources.outputs {
+        let Some(edid) = &output.props.edid else {
+            continue;
+        };
+        match profile(conn, edid) {
+            Ok(Some(profile)) => {
+                profiles.insert(output.connector_name.clone(), profile);
+            }
+            Ok(None) => {}
+            Err(err) if err.name() == Some("org.freedesktop.DBus.Error.ServiceUnknown") => break,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(profiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edid_hashed() {
+        assert_eq!("d41d8cd98f00b204e9800998ecf8427e", edid_md5(&[]));
+        assert_eq!("900150983cd24fb0d6963f7d28e17f72", edid_md5(b"abc"));
+    }
+}
@@ -0,0 +1,315 @@
+//! User configuration, read from `$XDG_CONFIG_HOME/gnome-randr/config` unless `--config` is
+//! given. The file is made of `[section]` headers followed by `key = value` lines, `#` starts a
+//! comment:
+//!
+//! ```text
+//! include = laptop.conf
+//!
+//! [variables]
+//! dock = DP-3
+//!
+//! [aliases]
+//! LG Office = ${dock}
+//!
+//! [defaults]
+//! scale = 1.25
+//!
+//! [lid]
+//! internal = eDP-1
+//! closed = docked
+//! ```
+//!
+//! `include` lines come before the first section and read shared fragments, relative to the
+//! including file. Definitions of the including file override ones of fragments. Variables are
+//! substituted in values with `${name}` once they are defined.
+//!
+//! A `[lid]` section makes `watch` turn the internal panel off while the lid is closed and
+//! restore the layout once it opens. `internal` names the panel if it isn't an `eDP`, `LVDS` or
+//! `DSI` connector, `closed` and `opened` name profiles applied instead.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::lid::LidPolicy;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    /// Names accepted in place of connector names, mapped to connectors
+    pub aliases: BTreeMap<String, String>,
+    /// Values substituted for `${name}`
+    pub variables: BTreeMap<String, String>,
+    /// Scale of outputs enabled without `--scale`
+    pub default_scale: Option<f64>,
+    /// What `watch` does when the lid closes and opens, if the `[lid]` section is present
+    pub lid: Option<LidPolicy>,
+    /// Files definitions were read from, main file first even if it's missing
+    pub files: Vec<PathBuf>,
+}
+
+impl Config {
+    /// Reads configuration from `path`, a missing file is an empty configuration.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut config = Self {
+            files: vec![path.to_path_buf()],
+            ..Self::default()
+        };
+        let text = match fs::read_to_string(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(config),
+            text => text.with_context(|| format!("could not read {}", path.display()))?,
+        };
+        config
+            .parse_file(&text, Some(path), &mut vec![path.to_path_buf()])
+            .with_context(|| format!("could not parse {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Reads a fragment included by a file on `stack`, which must exist unlike the main file.
+    fn include(&mut self, path: &Path, stack: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+        if stack.iter().any(|included| included == path) {
+            return Err(anyhow!("{} includes itself", path.display()));
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        stack.push(path.to_path_buf());
+        self.files.push(path.to_path_buf());
+        self.parse_file(&text, Some(path), stack)
+            .with_context(|| format!("could not parse {}", path.display()))?;
+        stack.pop();
+        Ok(())
+    }
+
+    /// First alias of `connector`, in alphabetical order.
+    pub fn alias_of(&self, connector: &str) -> Option<&str> {
+        self.aliases
+            .iter()
+            .find(|(_, target)| *target == connector)
+            .map(|(alias, _)| alias.as_str())
+    }
+
+    /// Connector name for `name`, which is either an alias or a connector name already.
+    pub fn resolve<'a>(&'a self, name: &'a str) -> &'a str {
+        self.aliases.get(name).map_or(name, String::as_str)
+    }
+}
+
+/// Default location of configuration file.
+pub fn default_path() -> Option<PathBuf> {
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("gnome-randr/config"))
+}
+
+impl Config {
+    /// Adds definitions of `text` read from `path`, includes are only allowed with a path.
+    fn parse_file(
+        &mut self,
+        text: &str,
+        path: Option<&Path>,
+        stack: &mut Vec<PathBuf>,
+    ) -> anyhow::Result<()> {
+        let mut section = None;
+        // Keys defined by this file, fragments may define the same keys
+        let mut defined = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split_once('#').map_or(line, |(line, _)| line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: String| anyhow!("line {}: {msg}", idx + 1);
+            if let Some(name) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                section = match name.trim() {
+                    name @ ("aliases" | "variables" | "defaults") => Some(name),
+                    "lid" => {
+                        self.lid.get_or_insert_with(LidPolicy::default);
+                        Some("lid")
+                    }
+                    name => return Err(err(format!("unknown section [{name}]"))),
+                };
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| err("expected <KEY> = <VALUE>".to_string()))?;
+            let key = key.trim();
+            let value = self.substitute(value.trim()).map_err(err)?;
+            let Some(section) = section else {
+                if key != "include" {
+                    return Err(err(format!("{key} is outside of a section")));
+                }
+                let dir = path
+                    .and_then(Path::parent)
+                    .ok_or_else(|| err("include is only allowed in files".to_string()))?;
+                self.include(&dir.join(value), stack)
+                    .map_err(|e| err(format!("{e:#}")))?;
+                continue;
+            };
+            if defined.contains(&(section, key)) {
+                return Err(err(format!("{key} is duplicated")));
+            }
+            defined.push((section, key));
+            match section {
+                "aliases" => {
+                    self.aliases.insert(key.to_string(), value);
+                }
+                "variables" => {
+                    self.variables.insert(key.to_string(), value);
+                }
+                "lid" => {
+                    let lid = self.lid.get_or_insert_with(LidPolicy::default);
+                    let field = match key {
+                        "internal" => &mut lid.internal,
+                        "closed" => &mut lid.closed,
+                        "opened" => &mut lid.opened,
+                        key => return Err(err(format!("unknown lid setting {key}"))),
+                    };
+                    *field = Some(value);
+                }
+                _ => match key {
+                    "scale" => {
+                        let scale: f64 = value
+                            .parse()
+                            .map_err(|e| err(format!("invalid scale {value}: {e}")))?;
+                        if !(scale > 0.0 && scale <= 4.0) {
+                            return Err(err("scale must be over 0 and at most 4".to_string()));
+                        }
+                        self.default_scale = Some(scale);
+                    }
+                    key => return Err(err(format!("unknown default {key}"))),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces `${name}` in `value` with variables defined so far.
+    fn substitute(&self, value: &str) -> Result<String, String> {
+        let mut substituted = String::new();
+        let mut rest = value;
+        while let Some(start) = rest.find("${") {
+            substituted.push_str(&rest[..start]);
+            let (name, after) = rest[start + 2..]
+                .split_once('}')
+                .ok_or_else(|| format!("unterminated variable in {value}"))?;
+            let variable = self
+                .variables
+                .get(name.trim())
+                .ok_or_else(|| format!("undefined variable {}", name.trim()))?;
+            substituted.push_str(variable);
+            rest = after;
+        }
+        substituted.push_str(rest);
+        Ok(substituted)
+    }
+}
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Self::default();
+        config.parse_file(s, None, &mut Vec::new())?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_parsed() {
+        let config: Config =
+            "# monitors at the office\n[aliases]\nLG Office = DP-3\nTV=HDMI-1 # living room\n"
+                .parse()
+                .unwrap();
+        assert_eq!("DP-3", config.resolve("LG Office"));
+        assert_eq!("HDMI-1", config.resolve("TV"));
+        assert_eq!("DP-1", config.resolve("DP-1"));
+        assert_eq!(Some("TV"), config.alias_of("HDMI-1"));
+        assert_eq!(None, config.alias_of("DP-1"));
+
+        let err = "[aliases]\nTV = HDMI-1\nTV = HDMI-2\n"
+            .parse::<Config>()
+            .unwrap_err();
+        assert_eq!("line 3: TV is duplicated", err.to_string());
+        assert!("TV = HDMI-1".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn variables_substituted() {
+        let config: Config =
+            "[variables]\ndock = DP-3\n[aliases]\nDesk = ${dock}\n[defaults]\nscale = 1.25\n"
+                .parse()
+                .unwrap();
+        assert_eq!("DP-3", config.resolve("Desk"));
+        assert_eq!(Some(1.25), config.default_scale);
+
+        let err = "[aliases]\nDesk = ${dock}\n".parse::<Config>().unwrap_err();
+        assert_eq!("line 2: undefined variable dock", err.to_string());
+        let err = "[defaults]\nscale = 5\n".parse::<Config>().unwrap_err();
+        assert_eq!(
+            "line 2: scale must be over 0 and at most 4",
+            err.to_string()
+        );
+        assert!("include = laptop.conf\n".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn lid_policy_parsed() {
+        assert_eq!(None, "[aliases]\n".parse::<Config>().unwrap().lid);
+        assert_eq!(
+            Some(LidPolicy::default()),
+            "[lid]\n".parse::<Config>().unwrap().lid
+        );
+        let config: Config = "[lid]\ninternal = eDP-1\nclosed = docked\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            Some(LidPolicy {
+                internal: Some("eDP-1".to_string()),
+                closed: Some("docked".to_string()),
+                opened: None,
+            }),
+            config.lid
+        );
+        let err = "[lid]\nclose = docked\n".parse::<Config>().unwrap_err();
+        assert_eq!("line 2: unknown lid setting close", err.to_string());
+    }
+
+    #[test]
+    fn fragments_included() {
+        let dir = std::env::temp_dir().join(format!("gnome-randr-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("laptop.conf"),
+            "[variables]\ninternal = eDP-1\n[aliases]\nLaptop = ${internal}\nTV = HDMI-1\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("config"),
+            "include = laptop.conf\n[aliases]\nTV = HDMI-2\nBuiltin = ${internal}\n",
+        )
+        .unwrap();
+        fs::write(dir.join("loop.conf"), "include = loop.conf\n").unwrap();
+
+        let config = Config::load(&dir.join("config")).unwrap();
+        assert_eq!("eDP-1", config.resolve("Laptop"));
+        assert_eq!("eDP-1", config.resolve("Builtin"));
+        assert_eq!("HDMI-2", config.resolve("TV"));
+        assert_eq!(
+            vec![dir.join("config"), dir.join("laptop.conf")],
+            config.files
+        );
+        let err = Config::load(&dir.join("loop.conf")).unwrap_err();
+        assert!(format!("{err:#}").contains("includes itself"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
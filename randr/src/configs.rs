@@ -0,0 +1,465 @@
+//! Persistent configurations Mutter stores in `monitors.xml`, one for every set of monitors it
+//! has seen.
+
+use std::{
+    fmt::Display,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    cli::{ConfigsArgs, GlobalArgs, OutputArgsBuilder},
+    dbus_api::{DisplayConfigApi, GetResourcesReturn, MonitorSpec},
+    layout::{self, LogicalPosition, Position},
+    mode_db::Resolution,
+};
+
+/// A monitor enabled by a stored configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredMonitor {
+    pub spec: MonitorSpec,
+    pub width: u32,
+    pub height: u32,
+    pub rate: f64,
+    /// Position of the logical monitor, in logical pixels if layout mode is logical
+    pub position: Position,
+    pub scale: f64,
+    /// Transform as defined by the wayland protocol
+    pub transform: u32,
+    pub primary: bool,
+}
+
+/// A `<configuration>` entry of `monitors.xml`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredConfig {
+    /// Byte range of the entry in the file, used to delete it without rewriting the rest
+    pub range: Range<usize>,
+    pub logical_layout: bool,
+    pub monitors: Vec<StoredMonitor>,
+    pub disabled: Vec<MonitorSpec>,
+}
+
+impl StoredConfig {
+    /// Specs of every monitor, enabled or not, sorted. Mutter picks a configuration by this set.
+    pub fn specs(&self) -> Vec<MonitorSpec> {
+        let mut specs: Vec<_> = self
+            .monitors
+            .iter()
+            .map(|monitor| monitor.spec.clone())
+            .chain(self.disabled.iter().cloned())
+            .collect();
+        specs.sort_by(|l, r| l.connector.cmp(&r.connector));
+        specs
+    }
+
+    /// Whether this configuration is for currently connected monitors.
+    pub fn matches(&self, resources: &GetResourcesReturn) -> bool {
+        self.specs() == current_specs(resources)
+    }
+
+    /// Changes `layout` so it matches this configuration.
+    fn restore(&self, layout: &mut layout::Layout) -> anyhow::Result<()> {
+        for spec in &self.disabled {
+            layout.apply(
+                &OutputArgsBuilder::default()
+                    .name(spec.connector.clone())
+                    .off(true)
+                    .build()?,
+            )?;
+        }
+        for monitor in &self.monitors {
+            // Physical layout mode stores CRTC positions as is
+            let position = match self.logical_layout {
+                true => LogicalPosition(monitor.position).to_physical(monitor.scale),
+                false => monitor.position,
+            };
+            let name = &monitor.spec.connector;
+            layout.apply(
+                &OutputArgsBuilder::default()
+                    .name(name.clone())
+                    .resolution(Resolution {
+                        width: monitor.width,
+                        height: monitor.height,
+                    })
+                    .framerate(monitor.rate.round() as u32)
+                    .position(position)
+                    .primary(monitor.primary)
+                    .build()?,
+            )?;
+            layout.set_transform(name, monitor.transform)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for StoredConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, monitor) in self.monitors.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(", ")?;
+            }
+            write!(
+                f,
+                "{} {}x{}@{:.2} {} scale {}",
+                monitor.spec.connector,
+                monitor.width,
+                monitor.height,
+                monitor.rate,
+                monitor.position,
+                monitor.scale
+            )?;
+            if monitor.primary {
+                f.write_str(" primary")?;
+            }
+        }
+        if !self.disabled.is_empty() {
+            let disabled: Vec<_> = self
+                .disabled
+                .iter()
+                .map(|spec| spec.connector.as_str())
+                .collect();
+            write!(f, "; disabled {}", disabled.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Specs of connected monitors, sorted by connector.
+pub fn current_specs(resources: &GetResourcesReturn) -> Vec<MonitorSpec> {
+    let mut specs: Vec<_> = resources
+        .outputs
+        .iter()
+        .map(|output| MonitorSpec {
+            connector: output.connector_name.clone(),
+            vendor: output.props.vendor.clone().unwrap_or_default(),
+            product: output.props.product.clone().unwrap_or_default(),
+            serial: output.props.serial.clone().unwrap_or_default(),
+        })
+        .collect();
+    specs.sort_by(|l, r| l.connector.cmp(&r.connector));
+    specs
+}
+
+/// Default location of `monitors.xml`.
+pub fn default_path() -> Option<PathBuf> {
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("monitors.xml"))
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).map(|child| child.text().unwrap_or("").trim())
+}
+
+fn parse_value<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> anyhow::Result<T> {
+    let value = text(node, name).ok_or_else(|| anyhow!("missing <{name}>"))?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("invalid <{name}> value {value:?}"))
+}
+
+fn parse_spec(spec: roxmltree::Node) -> MonitorSpec {
+    let field = |name| text(spec, name).unwrap_or("").to_string();
+    MonitorSpec {
+        connector: field("connector"),
+        vendor: field("vendor"),
+        product: field("product"),
+        serial: field("serial"),
+    }
+}
+
+/// Transform from `<transform>`, rotation is counter-clockwise like in the wayland protocol.
+fn parse_transform(node: roxmltree::Node) -> anyhow::Result<u32> {
+    let Some(transform) = child(node, "transform") else {
+        return Ok(0);
+    };
+    let rotation = match text(transform, "rotation").unwrap_or("normal") {
+        "normal" => 0,
+        "left" => 1,
+        "upside_down" => 2,
+        "right" => 3,
+        rotation => return Err(anyhow!("unknown rotation {rotation}")),
+    };
+    let flipped = text(transform, "flipped") == Some("yes");
+    Ok(rotation + if flipped { 4 } else { 0 })
+}
+
+fn parse_config(node: roxmltree::Node) -> anyhow::Result<StoredConfig> {
+    let logical_layout = text(node, "layoutmode") != Some("physical");
+    let mut monitors = Vec::new();
+    let mut disabled = Vec::new();
+    for element in node.children().filter(roxmltree::Node::is_element) {
+        match element.tag_name().name() {
+            "logicalmonitor" => {
+                let position = Position {
+                    x: parse_value(element, "x")?,
+                    y: parse_value(element, "y")?,
+                };
+                let scale = match text(element, "scale") {
+                    Some(_) => parse_value(element, "scale")?,
+                    None => 1.0,
+                };
+                let primary = text(element, "primary") == Some("yes");
+                let transform = parse_transform(element)?;
+                for monitor in element
+                    .children()
+                    .filter(|node| node.has_tag_name("monitor"))
+                {
+                    let spec = child(monitor, "monitorspec")
+                        .ok_or_else(|| anyhow!("missing <monitorspec>"))?;
+                    let mode = child(monitor, "mode").ok_or_else(|| anyhow!("missing <mode>"))?;
+                    monitors.push(StoredMonitor {
+                        spec: parse_spec(spec),
+                        width: parse_value(mode, "width")?,
+                        height: parse_value(mode, "height")?,
+                        rate: parse_value(mode, "rate")?,
+                        position,
+                        scale,
+                        transform,
+                        primary,
+                    });
+                }
+            }
+            "disabled" => {
+                disabled.extend(
+                    element
+                        .children()
+                        .filter(|node| node.has_tag_name("monitorspec"))
+                        .map(parse_spec),
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(StoredConfig {
+        range: node.range(),
+        logical_layout,
+        monitors,
+        disabled,
+    })
+}
+
+/// Every `<configuration>` in `monitors.xml` contents.
+pub fn parse(text: &str) -> anyhow::Result<Vec<StoredConfig>> {
+    let doc = roxmltree::Document::parse(text)?;
+    doc.root_element()
+        .children()
+        .filter(|node| node.has_tag_name("configuration"))
+        .enumerate()
+        .map(|(idx, node)| parse_config(node).with_context(|| format!("configuration {idx}")))
+        .collect()
+}
+
+/// `text` without configuration, along with indentation before it.
+pub fn remove(text: &str, config: &StoredConfig) -> String {
+    let start = text[..config.range.start]
+        .trim_end_matches([' ', '\t'])
+        .len();
+    let start = text[..start].strip_suffix('\n').map_or(start, str::len);
+    format!("{}{}", &text[..start], &text[config.range.end..])
+}
+
+fn read(path: &Path) -> anyhow::Result<(String, Vec<StoredConfig>)> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("could not read {}", path.display()))?;
+    let configs = parse(&text).with_context(|| format!("could not parse {}", path.display()))?;
+    Ok((text, configs))
+}
+
+/// Runs `configs` command: lists stored configurations, deletes or re-applies one of them.
+pub fn run(
+    api: &impl DisplayConfigApi,
+    global: &GlobalArgs,
+    args: &ConfigsArgs,
+) -> anyhow::Result<()> {
+    let path = match &args.file {
+        Some(path) => path.clone(),
+        None => default_path().ok_or_else(|| anyhow!("could not find monitors.xml"))?,
+    };
+    let (text, configs) = read(&path)?;
+    let get = |idx: usize| {
+        configs.get(idx).ok_or_else(|| {
+            anyhow!(
+                "no configuration {idx}, {} has {} configurations",
+                path.display(),
+                configs.len()
+            )
+        })
+    };
+
+    if let Some(idx) = args.delete {
+        let config = get(idx)?;
+        fs::write(&path, remove(&text, config))
+            .with_context(|| format!("could not write {}", path.display()))?;
+        println!("deleted configuration {idx}: {config}");
+        return Ok(());
+    }
+    if let Some(idx) = args.apply {
+        let config = get(idx)?;
+        return layout::change_layout(api, global, |layout| config.restore(layout));
+    }
+
+    let resources = api.get_resources()?;
+    for (idx, config) in configs.iter().enumerate() {
+        let current = if config.matches(&resources) {
+            " (current monitors)"
+        } else {
+            ""
+        };
+        println!("{idx}: {config}{current}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dbus_api::{CrtcId, OutputId},
+        layout::tests::{resources, RecordingApi},
+    };
+
+    const MONITORS_XML: &str = r#"<monitors version="2">
+  <configuration>
+    <logicalmonitor>
+      <x>0</x>
+      <y>0</y>
+      <scale>1</scale>
+      <primary>yes</primary>
+      <monitor>
+        <monitorspec>
+          <connector>HDMI-1</connector>
+          <vendor>GSM</vendor>
+          <product>LG</product>
+          <serial>1</serial>
+        </monitorspec>
+        <mode>
+          <width>1920</width>
+          <height>1080</height>
+          <rate>60.000</rate>
+        </mode>
+      </monitor>
+    </logicalmonitor>
+  </configuration>
+  <configuration>
+    <logicalmonitor>
+      <x>0</x>
+      <y>0</y>
+      <scale>1</scale>
+      <monitor>
+        <monitorspec>
+          <connector>HDMI-1</connector>
+          <vendor></vendor>
+          <product></product>
+          <serial></serial>
+        </monitorspec>
+        <mode>
+          <width>1280</width>
+          <height>720</height>
+          <rate>59.940</rate>
+        </mode>
+      </monitor>
+    </logicalmonitor>
+    <logicalmonitor>
+      <x>640</x>
+      <y>360</y>
+      <scale>2</scale>
+      <primary>yes</primary>
+      <transform>
+        <rotation>left</rotation>
+        <flipped>no</flipped>
+      </transform>
+      <monitor>
+        <monitorspec>
+          <connector>DP-1</connector>
+          <vendor></vendor>
+          <product></product>
+          <serial></serial>
+        </monitorspec>
+        <mode>
+          <width>1920</width>
+          <height>1080</height>
+          <rate>50.000</rate>
+        </mode>
+      </monitor>
+    </logicalmonitor>
+    <disabled>
+      <monitorspec>
+        <connector>HDMI-2</connector>
+        <vendor></vendor>
+        <product></product>
+        <serial></serial>
+      </monitorspec>
+    </disabled>
+  </configuration>
+</monitors>
+"#;
+
+    #[test]
+    fn stored_configs_listed() {
+        let configs = parse(MONITORS_XML).unwrap();
+        assert_eq!(2, configs.len());
+        assert_eq!(
+            "HDMI-1 1920x1080@60.00 0x0 scale 1 primary",
+            configs[0].to_string()
+        );
+        assert_eq!(
+            "HDMI-1 1280x720@59.94 0x0 scale 1, DP-1 1920x1080@50.00 640x360 scale 2 primary; disabled HDMI-2",
+            configs[1].to_string()
+        );
+        assert!(!configs[0].matches(&resources()));
+        assert!(configs[1].matches(&resources()));
+
+        let removed = remove(MONITORS_XML, &configs[0]);
+        let remaining = parse(&removed).unwrap();
+        assert_eq!(1, remaining.len());
+        assert_eq!(configs[1].monitors, remaining[0].monitors);
+        assert!(removed.starts_with(
+            "<monitors version=\"2\">\n  <configuration>\n    <logicalmonitor>\n      <x>0</x>"
+        ));
+    }
+
+    #[test]
+    fn stored_config_applied() {
+        let api = RecordingApi::default();
+        let config = &parse(MONITORS_XML).unwrap()[1];
+        layout::change_layout(&api, &GlobalArgs::default(), |layout| {
+            config.restore(layout)
+        })
+        .unwrap();
+
+        let applied = &api.applied.borrow()[0];
+        let crtcs: Vec<_> = applied
+            .crtcs
+            .iter()
+            .map(|crtc| {
+                (
+                    crtc.id,
+                    crtc.x,
+                    crtc.y,
+                    crtc.transform,
+                    crtc.output_ids.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (CrtcId(0), 0, 0, 0, vec![OutputId(0)]),
+                (CrtcId(1), 1280, 720, 1, vec![OutputId(1)]),
+            ],
+            crtcs
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! D-Bus interface exported by `watch --serve`, so other apps and keybinding daemons could
+//! switch profiles without spawning the CLI.
+//!
+//! Calls are queued while the connection is processed and answered from the watch loop, which
+//! is free to call DisplayConfig on the same connection.
+
+use std::{
+    ffi::CString,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use dbus::{
+    blocking::Connection,
+    channel::{MatchingReceiver, Sender},
+    message::MatchRule,
+    strings::ErrorName,
+    Message,
+};
+
+use crate::{cli::GlobalArgs, dbus_api::DisplayConfig, profiles, status};
+
+pub const SERVICE: &str = "io.github.eaglesemanation.GnomeRandr";
+pub const PATH: &str = "/io/github/eaglesemanation/GnomeRandr";
+pub const INTERFACE: &str = "io.github.eaglesemanation.GnomeRandr";
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="io.github.eaglesemanation.GnomeRandr">
+    <method name="ApplyProfile">
+      <arg name="name" type="s" direction="in"/>
+    </method>
+    <method name="CurrentProfile">
+      <arg name="name" type="s" direction="out"/>
+    </method>
+    <signal name="ProfileChanged">
+      <arg name="name" type="s"/>
+    </signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml" type="s" direction="out"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+/// Exported interface, calls are answered by [`Server::handle`].
+pub struct Server {
+    calls: Arc<Mutex<Vec<Message>>>,
+}
+
+impl Server {
+    /// Takes the service name and starts queueing calls to [`PATH`].
+    pub fn start(conn: &Connection) -> anyhow::Result<Self> {
+        conn.request_name(SERVICE, false, true, true)
+            .with_context(|| format!("could not own {SERVICE}, is another watch serving it?"))?;
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        conn.start_receive(
+            MatchRule::new_method_call().with_path(PATH),
+            Box::new({
+                let calls = calls.clone();
+                move |msg, _| {
+                    calls.lock().unwrap().push(msg);
+                    true
+                }
+            }),
+        );
+        Ok(Self { calls })
+    }
+
+    /// Answers calls queued so far.
+    pub fn handle(
+        &self,
+        conn: &Connection,
+        display_config: &DisplayConfig,
+        global: &GlobalArgs,
+    ) -> anyhow::Result<()> {
+        let calls = std::mem::take(&mut *self.calls.lock().unwrap());
+        for call in calls {
+            let reply = match (call.interface().as_deref(), call.member().as_deref()) {
+                (Some(INTERFACE), Some("ApplyProfile")) => match call.read1::<String>() {
+                    Ok(name) => match profiles::apply(display_config, global, &name) {
+                        Ok(()) => {
+                            let signal = Message::new_signal(PATH, INTERFACE, "ProfileChanged")
+                                .map_err(anyhow::Error::msg)?
+                                .append1(&name);
+                            send(conn, signal)?;
+                            call.method_return()
+                        }
+                        Err(err) => error(&call, "Failed", &format!("{err:#}")),
+                    },
+                    Err(err) => error(&call, "InvalidArgs", &err.to_string()),
+                },
+                (Some(INTERFACE), Some("CurrentProfile")) => {
+                    let current = status::state_dir()
+                        .and_then(|dir| status::current_profile(&dir))
+                        .unwrap_or_default();
+                    call.method_return().append1(current)
+                }
+                (Some("org.freedesktop.DBus.Introspectable"), Some("Introspect")) => {
+                    call.method_return().append1(INTROSPECTION)
+                }
+                _ => error(&call, "UnknownMethod", "No such method"),
+            };
+            send(conn, reply)?;
+        }
+        Ok(())
+    }
+}
+
+fn send(conn: &Connection, msg: Message) -> anyhow::Result<()> {
+    conn.send(msg)
+        .map(drop)
+        .map_err(|()| anyhow::anyhow!("could not send reply, connection is closed"))
+}
+
+fn error(call: &Message, name: &str, text: &str) -> Message {
+    let name = match name {
+        "Failed" => format!("{INTERFACE}.Error.Failed"),
+        name => format!("org.freedesktop.DBus.Error.{name}"),
+    };
+    let name = ErrorName::new(name).expect("error names are valid");
+    let text = CString::new(text.replace('\0', " ")).expect("NULs are replaced");
+    call.error(&name, &text)
+}
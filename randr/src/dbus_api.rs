@@ -1,13 +1,83 @@
-use std::{fmt::Display, time::Duration};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    hash::Hash,
+    str::FromStr,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
 
-use dbus::blocking;
+use dbus::{
+    arg::TypeMismatchError,
+    blocking::{self, BlockingSender},
+    Message,
+};
 use dbus_derive::{DbusArgs, DbusEnum, DbusPropMap, DbusStruct};
-use num_derive::{FromPrimitive, ToPrimitive};
-use num_traits::{FromPrimitive, ToPrimitive};
+use dbus_traits::{dbus_newtype, DbusArg, DbusArgError};
+use serde::{Deserialize, Serialize};
 
-#[derive(DbusEnum, FromPrimitive, ToPrimitive, Debug, Clone, Copy)]
-#[dbus_enum(as_type = "u32")]
+use crate::{
+    cancel::Cancel,
+    progress, record, timings,
+    warnings::{self, Kind},
+};
+
+/// Declares an id newtype, which is sent as `u32`. Ids that could be absent are additionally
+/// sent as `i32`, with -1 meaning "none".
+macro_rules! id_newtype {
+    ($($(#[$attr:meta])* $name:ident),*) => {$(
+        $(#[$attr])*
+        #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(pub u32);
+
+        impl From<u32> for $name {
+            fn from(id: u32) -> Self {
+                Self(id)
+            }
+        }
+
+        impl From<$name> for u32 {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                Display::fmt(&self.0, f)
+            }
+        }
+
+        dbus_newtype!($name => u32);
+
+        impl DbusArg<i32> for $name {
+            fn to_dbus(&self) -> Result<i32, DbusArgError> {
+                self.0.to_dbus()
+            }
+
+            fn from_dbus(value: i32) -> Result<Self, DbusArgError> {
+                u32::from_dbus(value).map(Self)
+            }
+        }
+    )*};
+}
+
+id_newtype!(
+    /// The ID in the API of an output
+    OutputId,
+    /// The ID in the API of a CRTC
+    CrtcId,
+    /// The ID in the API of a mode
+    ModeId,
+    /// ID of current state of screen, which should be passed back with every change
+    ConfigSerial
+);
+
+#[derive(DbusEnum, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[dbus_enum(as_type = "u32", discriminants)]
 pub enum Transform {
+    #[default]
     Normal = 0,
     Normal90,
     Normal180,
@@ -18,27 +88,170 @@ pub enum Transform {
     Flipped270,
 }
 
-impl From<Transform> for u32 {
-    fn from(value: Transform) -> Self {
-        value.to_u32().unwrap()
+impl Transform {
+    pub const ALL: [Transform; 8] = [
+        Transform::Normal,
+        Transform::Normal90,
+        Transform::Normal180,
+        Transform::Normal270,
+        Transform::Flipped,
+        Transform::Flipped90,
+        Transform::Flipped180,
+        Transform::Flipped270,
+    ];
+
+    /// Name used by `--rotate`, rotations are counterclockwise like in the wayland protocol.
+    pub fn name(self) -> &'static str {
+        match self {
+            Transform::Normal => "normal",
+            Transform::Normal90 => "left",
+            Transform::Normal180 => "inverted",
+            Transform::Normal270 => "right",
+            Transform::Flipped => "flipped",
+            Transform::Flipped90 => "flipped-left",
+            Transform::Flipped180 => "flipped-inverted",
+            Transform::Flipped270 => "flipped-right",
+        }
+    }
+
+    /// Counterclockwise rotation in degrees and whether the output is flipped around the vertical
+    /// axis, flipping comes first like in the wayland protocol.
+    pub fn parts(self) -> (u32, bool) {
+        let idx = self as u32;
+        ((idx % 4) * 90, idx >= 4)
+    }
+
+    /// Transform made of `parts`, `None` if rotation isn't a multiple of 90 degrees.
+    pub fn from_parts(degrees: u32, flipped: bool) -> Option<Self> {
+        degrees
+            .is_multiple_of(90)
+            .then(|| Self::from_quarters(degrees / 90, flipped))
+    }
+
+    fn from_quarters(quarters: u32, flipped: bool) -> Self {
+        Self::ALL[(quarters % 4 + if flipped { 4 } else { 0 }) as usize]
+    }
+
+    /// This transform followed by `then`.
+    pub fn compose(self, then: Transform) -> Self {
+        let (degrees, flipped) = self.parts();
+        let (then_degrees, then_flipped) = then.parts();
+        // Flipping after a rotation is the same as flipping before the opposite rotation
+        let quarters = match then_flipped {
+            true => 4 - degrees / 90,
+            false => degrees / 90,
+        };
+        Self::from_quarters(quarters + then_degrees / 90, flipped != then_flipped)
+    }
+
+    /// Transform undoing this one.
+    pub fn inverse(self) -> Self {
+        match self.parts() {
+            (degrees, false) => Self::from_quarters(4 - degrees / 90, false),
+            // Flipped transforms undo themselves
+            _ => self,
+        }
+    }
+
+    /// This transform followed by a counterclockwise rotation, `None` if `degrees` isn't a
+    /// multiple of 90. Negative degrees rotate clockwise.
+    pub fn rotated(self, degrees: i32) -> Option<Self> {
+        let degrees = degrees.rem_euclid(360) as u32;
+        Some(self.compose(Self::from_parts(degrees, false)?))
+    }
+
+    /// This transform followed by mirroring left to right.
+    pub fn flipped_x(self) -> Self {
+        self.compose(Transform::Flipped)
+    }
+
+    /// This transform followed by mirroring top to bottom.
+    pub fn flipped_y(self) -> Self {
+        self.compose(Transform::Flipped180)
+    }
+
+    /// This transform followed by `reflection`.
+    pub fn reflected(self, reflection: Reflection) -> Self {
+        match reflection {
+            Reflection::Normal => self,
+            Reflection::X => self.flipped_x(),
+            Reflection::Y => self.flipped_y(),
+            Reflection::XY => self.flipped_x().flipped_y(),
+        }
     }
+
+    /// Whether width and height are swapped, for rotations by 90 or 270 degrees.
+    pub fn is_sideways(self) -> bool {
+        self.parts().0 % 180 == 90
+    }
+
+    /// Names of supported transforms, skipping unknown ones.
+    pub fn names(transforms: &[u32]) -> String {
+        transforms
+            .iter()
+            .filter_map(|&transform| Transform::try_from(transform).ok())
+            .map(Transform::name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Transform {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Transform::ALL
+            .into_iter()
+            .find(|transform| transform.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Transform::ALL.map(Transform::name).into();
+                anyhow::anyhow!("unknown rotation {s}, expected one of {}", names.join(", "))
+            })
+    }
+}
+
+/// Mirroring applied after rotation by `--reflect`, named like in xrandr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reflection {
+    Normal,
+    /// Left to right
+    X,
+    /// Top to bottom
+    Y,
+    /// Both, same as rotating by 180 degrees
+    XY,
 }
 
-impl TryFrom<u32> for Transform {
-    type Error = &'static str;
+impl FromStr for Reflection {
+    type Err = anyhow::Error;
 
-    fn try_from(value: u32) -> Result<Self, Self::Error> {
-        <Self as FromPrimitive>::from_u32(value).ok_or("Transform u32 representation out of bound")
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(Reflection::Normal),
+            "x" => Ok(Reflection::X),
+            "y" => Ok(Reflection::Y),
+            "xy" => Ok(Reflection::XY),
+            _ => Err(anyhow::anyhow!(
+                "unknown reflection {s}, expected normal, x, y or xy"
+            )),
+        }
     }
 }
 
 /// A CRTC (CRT controller) is a logical monitor, ie a portion of the compositor coordinate space.
 /// It might correspond to multiple monitors, when in clone mode, but note that
 /// it is possible to implement clone mode also by setting different CRTCs to the same coordinates.
-#[derive(DbusStruct, Clone, Debug)]
+#[derive(DbusStruct, Clone, Debug, Serialize, Deserialize)]
 pub struct CrtController {
     /// The ID in the API of this CRTC
-    pub id: u32,
+    #[dbus(arg_type = "u32")]
+    pub id: CrtcId,
     /// The low-level ID of this CRTC (which might be a XID, a KMS handle or something entirely different)
     pub winsys_id: i64,
     /// The geometry of this CRTC (might be invalid if the CRTC is not in use)
@@ -46,13 +259,17 @@ pub struct CrtController {
     /// The geometry of this CRTC (might be invalid if the CRTC is not in use)
     pub y: i32,
     /// The geometry of this CRTC (might be invalid if the CRTC is not in use)
-    pub width: i32,
+    #[dbus(arg_type = "i32", lenient)]
+    pub width: u32,
     /// The geometry of this CRTC (might be invalid if the CRTC is not in use)
-    pub height: i32,
-    /// The current mode of the CRTC, or -1 if this CRTC is not used.
+    #[dbus(arg_type = "i32", lenient)]
+    pub height: u32,
+    /// The current mode of the CRTC, or None if this CRTC is not used.
     /// Note: the size of the mode will always correspond to the width and height of the CRTC
-    pub mode_id: i32,
+    #[dbus(arg_type = "i32", sentinel = -1)]
+    pub mode_id: Option<ModeId>,
     /// The current transform (exspressed according to the wayland protocol)
+    #[dbus(lenient)]
     pub transform: Transform,
     /// All posible transforms
     pub transforms: Vec<u32>,
@@ -64,12 +281,15 @@ pub struct CrtController {
     //_properties: dbus::arg::PropMap,
 }
 
-#[derive(DbusStruct, Clone, Debug)]
+#[derive(DbusStruct, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[dbus_struct(test_roundtrip)]
 pub struct CrtControllerChange {
     /// The API ID from the corresponding GetResources() call
-    pub id: u32,
-    /// The API ID of the new mode to configure the CRTC with, or -1 if the CRTC should be disabled
-    pub mode_id: i32,
+    #[dbus(arg_type = "u32")]
+    pub id: CrtcId,
+    /// The API ID of the new mode to configure the CRTC with, or None if the CRTC should be disabled
+    #[dbus(arg_type = "i32", sentinel = -1)]
+    pub mode_id: Option<ModeId>,
     /// The new coordinates of the top left corner.
     /// The geometry will be completed with the size information from new_mode.
     pub x: i32,
@@ -79,57 +299,88 @@ pub struct CrtControllerChange {
     /// The desired transform
     pub transform: u32,
     /// The API ID of outputs that should be assigned to this CRTC
-    pub output_ids: Vec<u32>,
+    #[dbus(arg_type = "Vec<u32>")]
+    pub output_ids: Vec<OutputId>,
 }
 
 /// An output represents a physical screen, connected somewhere to the computer. Floating connectors are not exposed in the API.
-#[derive(DbusStruct, Clone, Debug)]
+#[derive(DbusStruct, Clone, Debug, Serialize, Deserialize)]
 pub struct Output {
     /// The ID in the API
-    pub id: u32,
+    #[dbus(arg_type = "u32")]
+    pub id: OutputId,
     /// The low-level ID of this output (XID or KMS handle)
     pub winsys_id: i64,
-    /// The CRTC that is currently driving this output, or -1 if the output is disabled
-    pub crtc_id: i32,
+    /// The CRTC that is currently driving this output, or None if the output is disabled
+    #[dbus(arg_type = "i32", sentinel = -1)]
+    pub crtc_id: Option<CrtcId>,
     /// All CRTCs that can control this output
-    pub possible_crtc_ids: Vec<u32>,
+    #[dbus(arg_type = "Vec<u32>")]
+    pub possible_crtc_ids: Vec<CrtcId>,
     /// The name of the connector to which the output is attached (like VGA1 or HDMI)
     pub connector_name: String,
     /// Valid modes for this output
-    pub mode_ids: Vec<u32>,
+    #[dbus(arg_type = "Vec<u32>")]
+    pub mode_ids: Vec<ModeId>,
     /// Valid clones for this output, ie other outputs that can be assigned the same CRTC as this one;
     /// if you want to mirror two outputs that don't have each other in the clone list, you must configure two different CRTCs for the same geometry
-    pub clone_ids: Vec<u32>,
+    #[dbus(arg_type = "Vec<u32>")]
+    pub clone_ids: Vec<OutputId>,
     /// Other high-level properties that affect this output; they are not necessarily reflected in the hardware.
     pub props: OutputProperties,
 }
 
-#[derive(DbusStruct, Clone, Debug)]
+#[derive(DbusStruct, Clone, Debug, Serialize, Deserialize)]
 pub struct OutputChange {
     /// The API ID of the output to change
-    pub id: u32,
+    #[dbus(arg_type = "u32")]
+    pub id: OutputId,
     /// Properties whose value should be changed
     pub props: OutputProperties,
 }
 
+impl OutputChange {
+    /// Creates a change that includes only properties differing from `current`, since
+    /// ApplyConfiguration applies every included property.
+    pub fn new(id: OutputId, props: &OutputProperties, current: &OutputProperties) -> Self {
+        Self {
+            id,
+            props: props.changed_since(current),
+        }
+    }
+}
+
 /// Other high-level properties that affect this output; they are not necessarily reflected in the hardware.
-#[derive(DbusPropMap, Default, Clone, Debug)]
+#[derive(DbusPropMap, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[dbus_propmap(append_changed, lenient)]
+#[serde(default)]
+#[non_exhaustive]
 pub struct OutputProperties {
     /// The human readable name of the manufacturer
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub vendor: Option<String>,
     /// The human readable name
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub product: Option<String>,
     /// The serial number of this particular hardware part
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub serial: Option<String>,
     /// A human readable name of this output, to be shown in the UI
     #[dbus_propmap(rename = "display-name")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub display_name: Option<String>,
     /// The backlight value as a percentage (-1 if not supported)
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub backlight: Option<i64>,
     /// Whether this output is primary or not
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub primary: Option<bool>,
     /// Whether this output is for presentation only
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub presentation: Option<bool>,
+    /// Raw EDID of the monitor, if Mutter could read it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edid: Option<Vec<u8>>,
 }
 
 impl Display for OutputProperties {
@@ -159,10 +410,12 @@ impl Display for OutputProperties {
 /// A mode represents a set of parameters that are applied to each output, such as resolution and refresh rate.
 /// It is a separate object so that it can be referenced by CRTCs and outputs.
 /// Multiple outputs in the same CRTCs must all have the same mode.
-#[derive(DbusStruct, Clone, Debug)]
+#[derive(DbusStruct, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[dbus_struct(test_roundtrip)]
 pub struct Mode {
     /// The ID in the API
-    pub id: u32,
+    #[dbus(arg_type = "u32")]
+    pub id: ModeId,
     /// The low-level ID of this mode
     pub winsys_id: i64,
     /// The resolution
@@ -175,11 +428,12 @@ pub struct Mode {
     pub flags: u32,
 }
 
-/// Current hardware layout
-#[derive(DbusArgs, Clone, Debug)]
+/// Current hardware layout, also stored as JSON in `--fixture` files
+#[derive(DbusArgs, Clone, Debug, Serialize, Deserialize)]
 pub struct GetResourcesReturn {
     /// ID of current state of screen. Incremented by server to keep track of config changes
-    pub serial: u32,
+    #[dbus(arg_type = "u32")]
+    pub serial: ConfigSerial,
     /// Available CRTCs
     pub crtcs: Vec<CrtController>,
     /// Available outputs
@@ -188,11 +442,281 @@ pub struct GetResourcesReturn {
     pub modes: Vec<Mode>,
     pub max_screen_width: i32,
     pub max_screen_height: i32,
+    /// Built on the first lookup by ID
+    #[dbus(skip)]
+    #[serde(skip)]
+    ids: OnceLock<IdMaps>,
+}
+
+impl GetResourcesReturn {
+    /// Fields are public, but ID maps are private, so resources are built with a constructor.
+    pub fn new(
+        serial: ConfigSerial,
+        crtcs: Vec<CrtController>,
+        outputs: Vec<Output>,
+        modes: Vec<Mode>,
+        max_screen_width: i32,
+        max_screen_height: i32,
+    ) -> Self {
+        Self {
+            serial,
+            crtcs,
+            outputs,
+            modes,
+            max_screen_width,
+            max_screen_height,
+            ids: OnceLock::new(),
+        }
+    }
+
+    fn ids(&self) -> &IdMaps {
+        self.ids.get_or_init(|| IdMaps::new(self))
+    }
+
+    pub fn crtc_by_id(&self, id: CrtcId) -> Option<&CrtController> {
+        lookup(&self.crtcs, &self.ids().crtcs, id, |crtc| crtc.id)
+    }
+
+    pub fn output_by_id(&self, id: OutputId) -> Option<&Output> {
+        lookup(&self.outputs, &self.ids().outputs, id, |output| output.id)
+    }
+
+    pub fn mode_by_id(&self, id: ModeId) -> Option<&Mode> {
+        lookup(&self.modes, &self.ids().modes, id, |mode| mode.id)
+    }
+
+    /// CRTC driving `output`, if it has one.
+    pub fn crtc_of(&self, output: &Output) -> Option<&CrtController> {
+        output.crtc_id.and_then(|id| self.crtc_by_id(id))
+    }
+
+    /// Outputs assigned to every CRTC, more than one if they are cloned. Assignments change
+    /// with every modeset, so unlike ID maps they are not cached.
+    pub fn outputs_by_crtc(&self) -> HashMap<CrtcId, Vec<&Output>> {
+        let mut outputs: HashMap<_, Vec<_>> = HashMap::new();
+        for output in &self.outputs {
+            if let Some(crtc) = output.crtc_id {
+                outputs.entry(crtc).or_default().push(output);
+            }
+        }
+        outputs
+    }
+}
+
+/// Positions of CRTCs, outputs and modes in [`GetResourcesReturn`] by their IDs.
+///
+/// Docks with MST hubs and virtual displays report dozens of outputs with hundreds of modes, so
+/// looking up every output's CRTC and modes with a linear search is quadratic.
+#[derive(Debug, Default, Clone)]
+struct IdMaps {
+    crtcs: HashMap<CrtcId, usize>,
+    outputs: HashMap<OutputId, usize>,
+    modes: HashMap<ModeId, usize>,
+}
+
+impl IdMaps {
+    fn new(resources: &GetResourcesReturn) -> Self {
+        fn positions<T, Id: Hash + Eq>(items: &[T], id: impl Fn(&T) -> Id) -> HashMap<Id, usize> {
+            let mut positions = HashMap::with_capacity(items.len());
+            for (position, item) in items.iter().enumerate() {
+                // Same as a linear search, the first one wins if IDs repeat
+                positions.entry(id(item)).or_insert(position);
+            }
+            positions
+        }
+        Self {
+            crtcs: positions(&resources.crtcs, |crtc| crtc.id),
+            outputs: positions(&resources.outputs, |output| output.id),
+            modes: positions(&resources.modes, |mode| mode.id),
+        }
+    }
+}
+
+/// Item with `id` at its position from `positions`. Fields of [`GetResourcesReturn`] are public
+/// and could change after maps are built, so a stale position falls back to a linear search.
+fn lookup<'a, T, Id: Hash + Eq + Copy>(
+    items: &'a [T],
+    positions: &HashMap<Id, usize>,
+    id: Id,
+    id_of: impl Fn(&T) -> Id,
+) -> Option<&'a T> {
+    positions
+        .get(&id)
+        .and_then(|&position| items.get(position))
+        .filter(|&item| id_of(item) == id)
+        .or_else(|| items.iter().find(|&item| id_of(item) == id))
+}
+
+/// Identifies a physical monitor
+#[derive(DbusStruct, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MonitorSpec {
+    /// The name of the connector to which the monitor is attached
+    pub connector: String,
+    pub vendor: String,
+    pub product: String,
+    pub serial: String,
+}
+
+#[derive(DbusPropMap, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[dbus_propmap(rename_all = "kebab-case")]
+#[serde(default)]
+#[non_exhaustive]
+pub struct MonitorModeProperties {
+    /// Whether this mode is currently used
+    pub is_current: Option<bool>,
+    /// Whether this mode is preferred by the monitor
+    pub is_preferred: Option<bool>,
+    pub is_interlaced: Option<bool>,
+}
+
+/// A mode of a monitor, including scales it can be used with
+#[derive(DbusStruct, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MonitorMode {
+    /// Mode ID, unrelated to [`ModeId`] of GetResources
+    pub id: String,
+    #[dbus(arg_type = "i32")]
+    pub width: u32,
+    #[dbus(arg_type = "i32")]
+    pub height: u32,
+    pub refresh_rate: f64,
+    /// Scale picked by default for this mode
+    pub preferred_scale: f64,
+    /// Scales logical monitors using this mode can be configured with
+    pub supported_scales: Vec<f64>,
+    pub props: MonitorModeProperties,
+}
+
+#[derive(DbusPropMap, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[dbus_propmap(rename_all = "kebab-case")]
+#[serde(default)]
+#[non_exhaustive]
+pub struct MonitorProperties {
+    /// Whether this is a built-in panel, like a laptop display
+    pub is_builtin: Option<bool>,
+    /// A human readable name of this monitor
+    pub display_name: Option<String>,
+}
+
+#[derive(DbusStruct, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Monitor {
+    pub spec: MonitorSpec,
+    pub modes: Vec<MonitorMode>,
+    pub props: MonitorProperties,
+}
+
+#[derive(DbusPropMap, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct LogicalMonitorProperties {}
+
+/// A region of the screen, shown by one or more monitors with the same scale
+#[derive(DbusStruct, Default, Clone, Debug, Serialize, Deserialize)]
+pub struct LogicalMonitor {
+    pub x: i32,
+    pub y: i32,
+    pub scale: f64,
+    #[dbus(lenient)]
+    pub transform: Transform,
+    pub primary: bool,
+    /// Monitors showing this logical monitor
+    pub monitors: Vec<MonitorSpec>,
+    pub props: LogicalMonitorProperties,
 }
 
-#[derive(DbusArgs, Clone, Debug)]
+#[derive(DbusPropMap, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[dbus_propmap(rename_all = "kebab-case")]
+#[serde(default)]
+#[non_exhaustive]
+pub struct CurrentStateProperties {
+    /// 1 if logical monitors are positioned in logical pixels, 2 if in physical ones
+    pub layout_mode: Option<u32>,
+    pub supports_changing_layout_mode: Option<bool>,
+    /// Whether every logical monitor must use the same scale
+    pub global_scale_required: Option<bool>,
+}
+
+/// Current monitor state, including scaling, which GetResources doesn't report
+#[derive(DbusArgs, Clone, Debug, Serialize, Deserialize)]
+pub struct GetCurrentStateReturn {
+    #[dbus(arg_type = "u32")]
+    pub serial: ConfigSerial,
+    pub monitors: Vec<Monitor>,
+    pub logical_monitors: Vec<LogicalMonitor>,
+    pub props: CurrentStateProperties,
+}
+
+/// How logical monitors are sized, the `layout-mode` property.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutMode {
+    /// Logical monitor size is mode size divided by scale, so positions are in logical pixels
+    #[default]
+    Logical = 1,
+    /// Logical monitor size is mode size, scaling only enlarges contents
+    Physical = 2,
+}
+
+impl LayoutMode {
+    /// Layout mode of a `layout-mode` value, `None` for unknown ones.
+    pub fn from_value(value: u32) -> Option<Self> {
+        match value {
+            1 => Some(LayoutMode::Logical),
+            2 => Some(LayoutMode::Physical),
+            _ => None,
+        }
+    }
+}
+
+impl Display for LayoutMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LayoutMode::Logical => "logical",
+            LayoutMode::Physical => "physical",
+        })
+    }
+}
+
+impl FromStr for LayoutMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "logical" => Ok(LayoutMode::Logical),
+            "physical" => Ok(LayoutMode::Physical),
+            _ => Err(anyhow::anyhow!(
+                "unknown layout mode {s}, expected logical or physical"
+            )),
+        }
+    }
+}
+
+impl GetCurrentStateReturn {
+    /// Current layout mode, logical if Mutter doesn't report a known one.
+    pub fn layout_mode(&self) -> LayoutMode {
+        self.props
+            .layout_mode
+            .and_then(LayoutMode::from_value)
+            .unwrap_or_default()
+    }
+
+    /// Scale of every enabled connector.
+    pub fn scales(&self) -> HashMap<String, f64> {
+        self.logical_monitors
+            .iter()
+            .flat_map(|logical| {
+                logical
+                    .monitors
+                    .iter()
+                    .map(|spec| (spec.connector.clone(), logical.scale))
+            })
+            .collect()
+    }
+}
+
+#[derive(DbusArgs, Clone, Debug, Serialize, Deserialize)]
+#[dbus_args(builder)]
 pub struct ApplyConfigurationArgs {
-    pub serial: u32,
+    #[dbus(arg_type = "u32")]
+    pub serial: ConfigSerial,
     pub persistent: bool,
     /// crtcs represents the new logical configuration, as a list of structures.
     /// Note: CRTCs not referenced in the array will be disabled.
@@ -202,19 +726,69 @@ pub struct ApplyConfigurationArgs {
     ///
     /// Note: unrecognized properties will have no effect, but if the configuration change succeeds
     /// the property will be reported by the next GetResources() call, and if @persistent is true, it will also be saved to disk.
+    #[dbus_args(default)]
     pub outputs: Vec<OutputChange>,
 }
 
-#[derive(DbusArgs, Clone, Debug)]
+#[derive(DbusPropMap, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct MonitorConfigProperties {}
+
+/// Monitor shown by a logical monitor in ApplyMonitorsConfig
+#[derive(DbusStruct, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MonitorConfig {
+    pub connector: String,
+    /// ID of a [`MonitorMode`] from GetCurrentState
+    pub mode_id: String,
+    pub props: MonitorConfigProperties,
+}
+
+/// Logical monitor in ApplyMonitorsConfig, with its position in units of the layout mode
+#[derive(DbusStruct, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LogicalMonitorConfig {
+    pub x: i32,
+    pub y: i32,
+    pub scale: f64,
+    /// Transform as defined by the wayland protocol
+    pub transform: u32,
+    pub primary: bool,
+    pub monitors: Vec<MonitorConfig>,
+}
+
+#[derive(DbusPropMap, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[dbus_propmap(rename_all = "kebab-case")]
+#[serde(default)]
+#[non_exhaustive]
+pub struct MonitorsConfigProperties {
+    /// Layout mode to switch to, only if `supports-changing-layout-mode` is set
+    pub layout_mode: Option<u32>,
+}
+
+/// Configuration of logical monitors, which unlike ApplyConfiguration includes scales. Monitors
+/// not included are disabled.
+#[derive(DbusArgs, Clone, Debug, Serialize, Deserialize)]
+pub struct ApplyMonitorsConfigArgs {
+    #[dbus(arg_type = "u32")]
+    pub serial: ConfigSerial,
+    /// 0 only verifies configuration, 1 applies it temporarily, 2 also stores it in monitors.xml
+    pub method: u32,
+    pub logical_monitors: Vec<LogicalMonitorConfig>,
+    pub props: MonitorsConfigProperties,
+}
+
+#[derive(DbusArgs, Clone, Debug, Serialize, Deserialize)]
 pub struct ChangeBacklightArgs {
-    pub serial: u32,
+    #[dbus(arg_type = "u32")]
+    pub serial: ConfigSerial,
     /// the API id of the output
-    pub output: u32,
+    #[dbus(arg_type = "u32")]
+    pub output: OutputId,
     /// the new backlight value
     pub value: i32,
 }
 
-#[derive(DbusArgs, Clone, Debug)]
+#[derive(DbusArgs, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct CrtcGamma {
     /// red gamma ramp
     pub red: Vec<u16>,
@@ -224,6 +798,292 @@ pub struct CrtcGamma {
     pub blue: Vec<u16>,
 }
 
+impl CrtcGamma {
+    /// Number of entries in each ramp.
+    pub fn len(&self) -> usize {
+        self.red.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.red.is_empty()
+    }
+
+    /// Identity ramps with `len` entries.
+    pub fn linear(len: usize) -> Self {
+        let ramp = resample(&[0, u16::MAX], len);
+        Self {
+            red: ramp.clone(),
+            green: ramp.clone(),
+            blue: ramp,
+        }
+    }
+
+    /// Ramps linearly interpolated to `len` entries, since SetCrtcGamma only accepts ramps of
+    /// the size CRTC has.
+    pub fn resized(&self, len: usize) -> Self {
+        Self {
+            red: resample(&self.red, len),
+            green: resample(&self.green, len),
+            blue: resample(&self.blue, len),
+        }
+    }
+}
+
+impl From<CrtcGammaRef<'_>> for CrtcGamma {
+    fn from(gamma: CrtcGammaRef<'_>) -> Self {
+        Self {
+            red: gamma.red.to_vec(),
+            green: gamma.green.to_vec(),
+            blue: gamma.blue.to_vec(),
+        }
+    }
+}
+
+fn resample(ramp: &[u16], len: usize) -> Vec<u16> {
+    match (ramp.len(), len) {
+        (_, 0) | (0, _) => vec![0; len],
+        (1, _) | (_, 1) => vec![ramp[0]; len],
+        (from, _) => (0..len)
+            .map(|idx| {
+                let pos = idx as f64 * (from - 1) as f64 / (len - 1) as f64;
+                let (low, high) = (ramp[pos.floor() as usize], ramp[pos.ceil() as usize]);
+                (low as f64 + (high as f64 - low as f64) * pos.fract()).round() as u16
+            })
+            .collect(),
+    }
+}
+
+/// Gamma ramps borrowed from a GetCrtcGamma reply, avoiding a copy of up to three 4096 entry
+/// arrays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CrtcGammaRef<'a> {
+    pub red: &'a [u16],
+    pub green: &'a [u16],
+    pub blue: &'a [u16],
+}
+
+impl<'a> CrtcGammaRef<'a> {
+    pub fn read(reply: &'a Message) -> Result<Self, TypeMismatchError> {
+        let (red, green, blue) = reply.read3()?;
+        Ok(Self { red, green, blue })
+    }
+
+    pub fn len(&self) -> usize {
+        self.red.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.red.is_empty()
+    }
+}
+
+/// Well-known name of DisplayConfig service, owned by gnome-shell.
+pub const DISPLAY_CONFIG_SERVICE: &str = "org.gnome.Mutter.DisplayConfig";
+
+/// DisplayConfig service is not running on the session bus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceUnavailable {
+    /// How long the service was waited for
+    pub waited: Option<Duration>,
+}
+
+impl ServiceUnavailable {
+    /// Checks if a method call failed because nobody owns the service name.
+    pub fn is_cause_of(err: &dbus::Error) -> bool {
+        matches!(
+            err.name(),
+            Some("org.freedesktop.DBus.Error.ServiceUnknown")
+                | Some("org.freedesktop.DBus.Error.NameHasNoOwner")
+        )
+    }
+}
+
+impl Display for ServiceUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{DISPLAY_CONFIG_SERVICE} is not available on the session bus"
+        )?;
+        if let Some(waited) = self.waited {
+            write!(f, " after waiting for {}s", waited.as_secs_f64())?;
+        }
+        f.write_str(", this is likely not a GNOME session or gnome-shell is not running")?;
+        if self.waited.is_none() {
+            f.write_str("; use --wait-for-service <SECONDS> if it is starting")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ServiceUnavailable {}
+
+/// Checks that DisplayConfig service is running, polling for it up to `wait` if it's not,
+/// unless cancelled.
+pub fn ensure_service(
+    conn: &blocking::Connection,
+    wait: Option<Duration>,
+    cancel: &Cancel,
+) -> anyhow::Result<()> {
+    let bus = conn.with_proxy(
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        Duration::from_millis(5000),
+    );
+    let start = Instant::now();
+    let deadline = wait.map(|wait| start + wait);
+    loop {
+        let (has_owner,): (bool,) = timings::measure("NameHasOwner", || {
+            bus.method_call(
+                "org.freedesktop.DBus",
+                "NameHasOwner",
+                (DISPLAY_CONFIG_SERVICE,),
+            )
+        })?;
+        if has_owner {
+            if wait.is_some() {
+                progress::report("wait for service", 100.0);
+            }
+            return Ok(());
+        }
+        match deadline {
+            Some(deadline) if Instant::now() < deadline => {
+                let waited = start.elapsed().as_secs_f64() / (deadline - start).as_secs_f64();
+                progress::report("wait for service", 100.0 * waited);
+                cancel.sleep(Duration::from_millis(100))?
+            }
+            _ => return Err(ServiceUnavailable { waited: wait }.into()),
+        }
+    }
+}
+
+/// Calls `f` with new PowerSaveMode whenever it changes, while connection is processed.
+pub fn on_power_save_mode_changed(
+    conn: &blocking::Connection,
+    mut f: impl FnMut(i32) + Send + 'static,
+) -> Result<(), dbus::Error> {
+    use blocking::stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged;
+
+    let rule = dbus::message::MatchRule::new_signal(
+        "org.freedesktop.DBus.Properties",
+        "PropertiesChanged",
+    )
+    .with_sender(DISPLAY_CONFIG_SERVICE)
+    .with_path("/org/gnome/Mutter/DisplayConfig");
+    conn.add_match(rule, move |changed: PropertiesPropertiesChanged, _, _| {
+        let mode = changed
+            .changed_properties
+            .get("PowerSaveMode")
+            .and_then(|value| value.0.as_i64());
+        if let Some(mode) = mode.filter(|_| changed.interface_name == DISPLAY_CONFIG_SERVICE) {
+            record::signal("PropertiesChanged");
+            f(mode as i32);
+        }
+        true
+    })?;
+    Ok(())
+}
+
+/// Calls `f` on every MonitorsChanged signal, while connection is processed.
+pub fn on_monitors_changed(
+    conn: &blocking::Connection,
+    mut f: impl FnMut() + Send + 'static,
+) -> Result<(), dbus::Error> {
+    let rule = dbus::message::MatchRule::new_signal(DISPLAY_CONFIG_SERVICE, "MonitorsChanged");
+    conn.add_match(rule, move |(): (), _, _| {
+        record::signal("MonitorsChanged");
+        f();
+        true
+    })?;
+    Ok(())
+}
+
+/// DisplayConfig methods needed to change configuration, so changes could be tested without a
+/// running compositor.
+///
+/// Backlight, gamma and power saving are not available everywhere, like in fixtures, so they
+/// fail with `UnknownMethod` unless implemented.
+pub trait DisplayConfigApi {
+    fn get_resources(&self) -> Result<GetResourcesReturn, dbus::Error>;
+    fn get_current_state(&self) -> Result<GetCurrentStateReturn, dbus::Error>;
+    fn apply_configuration(&self, args: ApplyConfigurationArgs) -> Result<(), dbus::Error>;
+
+    fn change_backlight(&self, _args: ChangeBacklightArgs) -> Result<(), dbus::Error> {
+        Err(not_available("ChangeBacklight"))
+    }
+
+    fn get_crtc_gamma(
+        &self,
+        _serial: ConfigSerial,
+        _crtc: CrtcId,
+    ) -> Result<CrtcGamma, dbus::Error> {
+        Err(not_available("GetCrtcGamma"))
+    }
+
+    fn set_crtc_gamma(
+        &self,
+        _serial: ConfigSerial,
+        _crtc: CrtcId,
+        _gamma: CrtcGamma,
+    ) -> Result<(), dbus::Error> {
+        Err(not_available("SetCrtcGamma"))
+    }
+
+    fn power_save_mode(&self) -> Result<i32, dbus::Error> {
+        Err(not_available("PowerSaveMode"))
+    }
+
+    fn set_power_save_mode(&self, _value: i32) -> Result<(), dbus::Error> {
+        Err(not_available("PowerSaveMode"))
+    }
+}
+
+fn not_available(method: &str) -> dbus::Error {
+    dbus::Error::new_custom(
+        "org.freedesktop.DBus.Error.UnknownMethod",
+        &format!("{method} is not available"),
+    )
+}
+
+impl DisplayConfigApi for DisplayConfig<'_, '_> {
+    fn get_resources(&self) -> Result<GetResourcesReturn, dbus::Error> {
+        DisplayConfig::get_resources(self)
+    }
+
+    fn get_current_state(&self) -> Result<GetCurrentStateReturn, dbus::Error> {
+        DisplayConfig::get_current_state(self)
+    }
+
+    fn apply_configuration(&self, args: ApplyConfigurationArgs) -> Result<(), dbus::Error> {
+        DisplayConfig::apply_configuration(self, args)
+    }
+
+    fn change_backlight(&self, args: ChangeBacklightArgs) -> Result<(), dbus::Error> {
+        DisplayConfig::change_backlight(self, args)
+    }
+
+    fn get_crtc_gamma(&self, serial: ConfigSerial, crtc: CrtcId) -> Result<CrtcGamma, dbus::Error> {
+        DisplayConfig::get_crtc_gamma(self, serial, crtc)
+    }
+
+    fn set_crtc_gamma(
+        &self,
+        serial: ConfigSerial,
+        crtc: CrtcId,
+        gamma: CrtcGamma,
+    ) -> Result<(), dbus::Error> {
+        let CrtcGamma { red, green, blue } = gamma;
+        DisplayConfig::set_crtc_gamma(self, serial, crtc, red, green, blue)
+    }
+
+    fn power_save_mode(&self) -> Result<i32, dbus::Error> {
+        DisplayConfig::power_save_mode(self)
+    }
+
+    fn set_power_save_mode(&self, value: i32) -> Result<(), dbus::Error> {
+        DisplayConfig::set_power_save_mode(self, value)
+    }
+}
+
 pub struct OrgGnomeMutterDisplayConfig<'a, C> {
     proxy: blocking::Proxy<'a, C>,
 }
@@ -233,7 +1093,7 @@ pub type DisplayConfig<'a, 'b> = OrgGnomeMutterDisplayConfig<'a, &'b blocking::C
 impl<'a> DisplayConfig<'_, 'a> {
     pub fn new(conn: &'a blocking::Connection) -> Self {
         let proxy = blocking::Proxy::new(
-            "org.gnome.Mutter.DisplayConfig",
+            DISPLAY_CONFIG_SERVICE,
             "/org/gnome/Mutter/DisplayConfig",
             Duration::from_millis(5000),
             conn,
@@ -241,58 +1101,341 @@ impl<'a> DisplayConfig<'_, 'a> {
         Self { proxy }
     }
 
+    /// Invalid values in a reply, like unknown transforms or negative sizes, are replaced with
+    /// defaults and reported as warnings.
     pub fn get_resources(&self) -> Result<GetResourcesReturn, dbus::Error> {
-        self.proxy
-            .method_call("org.gnome.Mutter.DisplayConfig", "GetResources", ())
+        let (resources, warnings) = timings::measure("GetResources", || {
+            dbus_traits::lenient::collect(|| {
+                self.proxy
+                    .method_call("org.gnome.Mutter.DisplayConfig", "GetResources", ())
+            })
+        });
+        for warning in warnings {
+            warnings::warn(
+                Kind::InvalidReply,
+                format!("ignoring invalid value in GetResources reply: {warning}"),
+            );
+        }
+        record::call("GetResources", (), &resources);
+        resources
+    }
+
+    pub fn get_current_state(&self) -> Result<GetCurrentStateReturn, dbus::Error> {
+        let (state, warnings) = timings::measure("GetCurrentState", || {
+            dbus_traits::lenient::collect(|| {
+                self.proxy
+                    .method_call("org.gnome.Mutter.DisplayConfig", "GetCurrentState", ())
+            })
+        });
+        for warning in warnings {
+            warnings::warn(
+                Kind::InvalidReply,
+                format!("ignoring invalid value in GetCurrentState reply: {warning}"),
+            );
+        }
+        record::call("GetCurrentState", (), &state);
+        state
     }
 
     pub fn apply_configuration(&self, args: ApplyConfigurationArgs) -> Result<(), dbus::Error> {
-        self.proxy
-            .method_call("org.gnome.Mutter.DisplayConfig", "ApplyConfiguration", args)
+        let recorded = record::enabled().then(|| args.clone());
+        let result = timings::measure("ApplyConfiguration", || {
+            self.proxy
+                .method_call("org.gnome.Mutter.DisplayConfig", "ApplyConfiguration", args)
+        });
+        if let Some(args) = recorded {
+            record::call("ApplyConfiguration", args, &result);
+        }
+        result
+    }
+
+    pub fn apply_monitors_config(&self, args: ApplyMonitorsConfigArgs) -> Result<(), dbus::Error> {
+        let recorded = record::enabled().then(|| args.clone());
+        let result = timings::measure("ApplyMonitorsConfig", || {
+            self.proxy.method_call(
+                "org.gnome.Mutter.DisplayConfig",
+                "ApplyMonitorsConfig",
+                args,
+            )
+        });
+        if let Some(args) = recorded {
+            record::call("ApplyMonitorsConfig", args, &result);
+        }
+        result
     }
 
     pub fn change_backlight(&self, args: ChangeBacklightArgs) -> Result<(), dbus::Error> {
-        self.proxy
-            .method_call("org.gnome.Mutter.DisplayConfig", "ChangeBacklight", args)
+        let recorded = record::enabled().then(|| args.clone());
+        let result =
+            self.proxy
+                .method_call("org.gnome.Mutter.DisplayConfig", "ChangeBacklight", args);
+        if let Some(args) = recorded {
+            record::call("ChangeBacklight", args, &result);
+        }
+        result
+    }
+
+    pub fn get_crtc_gamma(
+        &self,
+        serial: ConfigSerial,
+        crtc: CrtcId,
+    ) -> Result<CrtcGamma, dbus::Error> {
+        self.get_crtc_gamma_with(serial, crtc, |gamma| gamma.into())
     }
 
-    pub fn get_crtc_gamma(&self, serial: u32, crtc: u32) -> Result<CrtcGamma, dbus::Error> {
-        self.proxy.method_call(
+    /// Reads gamma ramps without copying them out of the reply, passing them to `f`.
+    pub fn get_crtc_gamma_with<R>(
+        &self,
+        serial: ConfigSerial,
+        crtc: CrtcId,
+        f: impl FnOnce(CrtcGammaRef<'_>) -> R,
+    ) -> Result<R, dbus::Error> {
+        let msg = Message::new_method_call(
+            &self.proxy.destination,
+            &self.proxy.path,
             "org.gnome.Mutter.DisplayConfig",
             "GetCrtcGamma",
-            (serial, crtc),
         )
+        .map_err(|err| dbus::Error::new_failed(&err))?
+        .append2(serial.0, crtc.0);
+        let reply = self
+            .proxy
+            .connection
+            .send_with_reply_and_block(msg, self.proxy.timeout);
+        if record::enabled() {
+            let gamma = match &reply {
+                Ok(reply) => CrtcGammaRef::read(reply)
+                    .map(CrtcGamma::from)
+                    .map_err(dbus::Error::from),
+                Err(err) => Err(dbus::Error::new_custom(
+                    err.name().unwrap_or("org.freedesktop.DBus.Error.Failed"),
+                    err.message().unwrap_or_default(),
+                )),
+            };
+            record::call("GetCrtcGamma", (serial, crtc), &gamma);
+        }
+        Ok(f(CrtcGammaRef::read(&reply?)?))
     }
 
     pub fn set_crtc_gamma(
         &self,
-        serial: u32,
-        crtc: u32,
+        serial: ConfigSerial,
+        crtc: CrtcId,
         red: Vec<u16>,
         green: Vec<u16>,
         blue: Vec<u16>,
     ) -> Result<(), dbus::Error> {
-        self.proxy.method_call(
+        let recorded = record::enabled().then(|| CrtcGamma {
+            red: red.clone(),
+            green: green.clone(),
+            blue: blue.clone(),
+        });
+        let result = self.proxy.method_call(
             "org.gnome.Mutter.DisplayConfig",
             "SetCrtcGamma",
-            (serial, crtc, red, green, blue),
-        )
+            (serial.0, crtc.0, red, green, blue),
+        );
+        if let Some(gamma) = recorded {
+            record::call("SetCrtcGamma", (serial, crtc, gamma), &result);
+        }
+        result
     }
 
     pub fn power_save_mode(&self) -> Result<i32, dbus::Error> {
-        blocking::stdintf::org_freedesktop_dbus::Properties::get(
+        let result = blocking::stdintf::org_freedesktop_dbus::Properties::get(
             &self.proxy,
             "org.gnome.Mutter.DisplayConfig",
             "PowerSaveMode",
-        )
+        );
+        record::call("Get", "PowerSaveMode", &result);
+        result
     }
 
     pub fn set_power_save_mode(&self, value: i32) -> Result<(), dbus::Error> {
-        blocking::stdintf::org_freedesktop_dbus::Properties::set(
+        let result = blocking::stdintf::org_freedesktop_dbus::Properties::set(
             &self.proxy,
             "org.gnome.Mutter.DisplayConfig",
             "PowerSaveMode",
             value,
-        )
+        );
+        record::call("Set", ("PowerSaveMode", value), &result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dbus::arg::AppendAll;
+    use dbus_traits::testing::{new_message, roundtrip_args};
+
+    use super::*;
+
+    #[test]
+    fn transform_algebra() {
+        for transform in Transform::ALL {
+            let (degrees, flipped) = transform.parts();
+            assert_eq!(Some(transform), Transform::from_parts(degrees, flipped));
+            assert_eq!(Transform::Normal, transform.compose(transform.inverse()));
+            for then in Transform::ALL {
+                for last in Transform::ALL {
+                    assert_eq!(
+                        transform.compose(then).compose(last),
+                        transform.compose(then.compose(last))
+                    );
+                }
+            }
+        }
+        assert_eq!(None, Transform::from_parts(45, false));
+        assert_eq!(Some(Transform::Normal), Transform::Normal90.rotated(-90));
+        assert_eq!(Some(Transform::Normal), Transform::Normal270.rotated(450));
+        // Mirroring a rotated output is a flip followed by the opposite rotation
+        assert_eq!(Transform::Flipped270, Transform::Normal90.flipped_x());
+        assert_eq!(Transform::Flipped90, Transform::Normal90.flipped_y());
+        assert_eq!(
+            Transform::Normal180,
+            Transform::Normal.reflected(Reflection::XY)
+        );
+        assert!(Transform::Flipped270.is_sideways());
+        assert!(!Transform::Flipped180.is_sideways());
+    }
+
+    #[test]
+    fn gamma_read_borrowed() {
+        let gamma = CrtcGamma::linear(256);
+        let mut msg = new_message();
+        gamma.append(&mut dbus::arg::IterAppend::new(&mut msg));
+
+        let borrowed = CrtcGammaRef::read(&msg).unwrap();
+        assert_eq!(256, borrowed.len());
+        assert_eq!(gamma.red, CrtcGamma::from(borrowed).red);
+    }
+
+    #[test]
+    fn current_state_signature() {
+        use dbus::arg::ArgAll;
+
+        let mut sig = String::new();
+        GetCurrentStateReturn::strs_sig(("", "", "", ""), |_, arg_sig| sig += &arg_sig.to_string());
+        assert_eq!(
+            "ua((ssss)a(siiddada{sv})a{sv})a(iiduba(ssss)a{sv})a{sv}",
+            sig
+        );
+        let state = roundtrip_args(&crate::layout::tests::current_state()).unwrap();
+        assert_eq!(Some(&2.0), state.scales().get("DP-1"));
+    }
+
+    #[test]
+    fn monitors_config_signature() {
+        use dbus::arg::ArgAll;
+
+        let mut sig = String::new();
+        ApplyMonitorsConfigArgs::strs_sig(("", "", "", ""), |_, arg_sig| {
+            sig += &arg_sig.to_string()
+        });
+        assert_eq!("uua(iiduba(ssa{sv}))a{sv}", sig);
+    }
+
+    #[test]
+    fn gamma_resized() {
+        let gamma = CrtcGamma::linear(3);
+        assert_eq!(vec![0, 32768, 65535], gamma.red);
+
+        let resized = gamma.resized(5);
+        assert_eq!(5, resized.len());
+        assert_eq!(vec![0, 16384, 32768, 49152, 65535], resized.blue);
+        assert_eq!(gamma.red, resized.resized(3).red);
+    }
+
+    #[test]
+    fn large_resources_decoded() {
+        let resources = crate::test_fixtures::large_resources(48, 600);
+        let decoded = roundtrip_args(&resources).unwrap();
+        assert_eq!((48, 600), (decoded.outputs.len(), decoded.modes.len()));
+
+        let output = decoded.output_by_id(OutputId(47)).unwrap();
+        assert_eq!("DP-48", output.connector_name);
+        assert_eq!(None, decoded.crtc_of(output).map(|crtc| crtc.id));
+        assert_eq!(1936, decoded.mode_by_id(ModeId(599)).unwrap().width);
+        assert_eq!(None, decoded.mode_by_id(ModeId(600)));
+        let outputs_by_crtc = decoded.outputs_by_crtc();
+        let cloned: Vec<_> = (outputs_by_crtc[&CrtcId(0)].iter())
+            .map(|output| output.connector_name.as_str())
+            .collect();
+        assert_eq!(vec!["DP-1", "DP-2"], cloned);
+        assert!(!outputs_by_crtc.contains_key(&CrtcId(1)));
+    }
+
+    #[test]
+    fn lenient_warning_path() {
+        use dbus::arg::{PropMap, Variant};
+
+        let resources = crate::test_fixtures::large_resources(3, 4);
+        // Same signature as Output, but props could hold a value of unexpected type
+        let outputs: Vec<_> = (resources.outputs.iter())
+            .map(|output| {
+                let mut props = PropMap::new();
+                if output.id == OutputId(2) {
+                    props.insert(
+                        "backlight".to_string(),
+                        Variant(Box::new("full".to_string())),
+                    );
+                }
+                let none = Vec::<u32>::new;
+                (
+                    output.id.0,
+                    output.winsys_id,
+                    -1,
+                    none(),
+                    output.connector_name.clone(),
+                    none(),
+                    none(),
+                    props,
+                )
+            })
+            .collect();
+        let msg = new_message()
+            .append3(resources.serial.0, &resources.crtcs, outputs)
+            .append3(
+                &resources.modes,
+                resources.max_screen_width,
+                resources.max_screen_height,
+            );
+
+        let (decoded, warnings) =
+            dbus_traits::lenient::collect(|| msg.read_all::<GetResourcesReturn>());
+        assert_eq!(None, decoded.unwrap().outputs[2].props.backlight);
+        let paths: Vec<_> = warnings.iter().map(|warning| warning.path()).collect();
+        assert_eq!(vec!["OutputProperties.backlight"], paths);
+    }
+
+    #[test]
+    fn lookups_follow_changed_resources() {
+        let mut resources = crate::layout::tests::resources();
+        assert_eq!(
+            Some(1920),
+            resources.crtc_by_id(CrtcId(1)).map(|crtc| crtc.x)
+        );
+        // Maps are built by now, but still reflect changes
+        resources.crtcs[1].x = 100;
+        resources.crtcs.swap(0, 1);
+        resources.modes.remove(0);
+        assert_eq!(
+            Some(100),
+            resources.crtc_by_id(CrtcId(1)).map(|crtc| crtc.x)
+        );
+        assert_eq!(Some(0), resources.crtc_by_id(CrtcId(0)).map(|crtc| crtc.x));
+        assert_eq!(None, resources.mode_by_id(ModeId(0)));
+        assert_eq!(
+            Some(50.0),
+            resources.mode_by_id(ModeId(1)).map(|mode| mode.frequency)
+        );
+        assert!(resources.output_by_id(OutputId(3)).is_none());
+
+        let fixture: GetResourcesReturn =
+            serde_json::from_str(&serde_json::to_string(&resources).unwrap()).unwrap();
+        assert_eq!(
+            "DP-1",
+            fixture.output_by_id(OutputId(1)).unwrap().connector_name
+        );
     }
 }
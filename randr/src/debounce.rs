@@ -0,0 +1,100 @@
+//! Settling MonitorsChanged signals before `watch` and `lock` act on them. Docks emit several
+//! signals while negotiating, and applying configurations in quick succession can wedge the
+//! compositor.
+
+use std::time::{Duration, Instant};
+
+use crate::cli::GlobalArgs;
+
+/// How long to block on D-Bus while nothing is pending.
+const IDLE_POLL: Duration = Duration::from_millis(500);
+/// How long to block on D-Bus while waiting for changes to settle.
+const PENDING_POLL: Duration = Duration::from_millis(50);
+
+/// Tracks pending changes, deciding when they are acted on.
+#[derive(Debug, Clone)]
+pub struct Debounce {
+    /// Quiet time required after the last change
+    settle: Duration,
+    /// Minimal time between two actions
+    min_interval: Duration,
+    last_change: Option<Instant>,
+    last_action: Option<Instant>,
+}
+
+impl Debounce {
+    pub fn new(settle: Duration, min_interval: Duration) -> Self {
+        Self {
+            settle,
+            min_interval,
+            last_change: None,
+            last_action: None,
+        }
+    }
+
+    /// Debounce configured with `--settle` and `--min-interval`, acting right away by default.
+    pub fn from_args(global: &GlobalArgs) -> Self {
+        Self::new(
+            global.settle.unwrap_or_default(),
+            global.min_interval.unwrap_or_default(),
+        )
+    }
+
+    /// Records a change signal received at `now`.
+    pub fn changed(&mut self, now: Instant) {
+        self.last_change = Some(now);
+    }
+
+    /// Whether pending changes should be acted on at `now`. Returning `true` counts as an
+    /// action, so pending changes are cleared.
+    pub fn ready(&mut self, now: Instant) -> bool {
+        let Some(last_change) = self.last_change else {
+            return false;
+        };
+        let settled = now >= last_change + self.settle;
+        let allowed = self
+            .last_action
+            .is_none_or(|last_action| now >= last_action + self.min_interval);
+        if settled && allowed {
+            self.last_change = None;
+            self.last_action = Some(now);
+        }
+        settled && allowed
+    }
+
+    /// How long to wait for the next signal, shorter while changes are pending.
+    pub fn poll_interval(&self) -> Duration {
+        match self.last_change {
+            Some(_) => PENDING_POLL,
+            None => IDLE_POLL,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settled_and_limited() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut debounce = Debounce::new(Duration::from_millis(750), Duration::from_secs(5));
+        assert!(!debounce.ready(at(0)));
+
+        debounce.changed(at(0));
+        debounce.changed(at(500));
+        assert!(!debounce.ready(at(1000)));
+        assert!(debounce.ready(at(1250)));
+        assert!(!debounce.ready(at(2500)));
+
+        // Settled, but too soon after the previous action
+        debounce.changed(at(2000));
+        assert!(!debounce.ready(at(3000)));
+        assert!(debounce.ready(at(6250)));
+
+        let mut immediate = Debounce::new(Duration::ZERO, Duration::ZERO);
+        immediate.changed(at(0));
+        assert!(immediate.ready(at(0)));
+    }
+}
@@ -0,0 +1,209 @@
+//! Comparing the live layout with a saved one, without applying anything.
+//!
+//! A saved layout is either a snapshot printed by `lock`, or GetResources JSON printed with
+//! `--json`.
+
+use std::{fmt::Display, fs, path::Path};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    dbus_api::{GetResourcesReturn, Transform},
+    lock::{OutputState, Snapshot},
+};
+
+/// A way the live layout differs from the saved one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// Output is enabled in the saved layout only
+    Missing {
+        expected: OutputState,
+        connected: bool,
+    },
+    /// Output is enabled in the live layout only
+    Unexpected(OutputState),
+    /// Output is enabled in both, but `field` differs
+    Changed {
+        connector: String,
+        field: &'static str,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difference::Missing {
+                expected,
+                connected,
+            } => {
+                let state = match connected {
+                    true => "disabled",
+                    false => "not connected",
+                };
+                write!(f, "{} is {state}, expected {expected}", expected.connector)
+            }
+            Difference::Unexpected(actual) => {
+                write!(
+                    f,
+                    "{} is enabled, expected disabled: {actual}",
+                    actual.connector
+                )
+            }
+            Difference::Changed {
+                connector,
+                field,
+                expected,
+                actual,
+            } => write!(f, "{connector} {field}: expected {expected}, got {actual}"),
+        }
+    }
+}
+
+/// Reads a saved layout from `path`, JSON is told apart by its opening brace.
+pub fn load(path: &Path) -> anyhow::Result<Snapshot> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("could not read {}", path.display()))?;
+    let snapshot = match text.trim_start().starts_with('{') {
+        true => serde_json::from_str(&text)
+            .map(|resources| Snapshot::capture(&resources))
+            .map_err(anyhow::Error::from),
+        false => text.parse(),
+    };
+    snapshot.with_context(|| format!("could not parse {}", path.display()))
+}
+
+fn transform_name(transform: u32) -> String {
+    Transform::try_from(transform).map_or_else(|_| transform.to_string(), |t| t.to_string())
+}
+
+/// Differences of `resources` from `expected`, ordered by connector.
+pub fn differences(expected: &Snapshot, resources: &GetResourcesReturn) -> Vec<Difference> {
+    compare(expected, &Snapshot::capture(resources), |connector| {
+        resources
+            .outputs
+            .iter()
+            .any(|output| output.connector_name == connector)
+    })
+}
+
+/// Differences of `actual` from `expected`, ordered by connector. `connected` tells if an output
+/// missing from `actual` is still connected.
+pub fn compare(
+    expected: &Snapshot,
+    actual: &Snapshot,
+    connected: impl Fn(&str) -> bool,
+) -> Vec<Difference> {
+    let mut connectors: Vec<_> = expected
+        .outputs
+        .iter()
+        .chain(&actual.outputs)
+        .map(|state| state.connector.as_str())
+        .collect();
+    connectors.sort();
+    connectors.dedup();
+
+    let state = |snapshot: &Snapshot, connector: &str| -> Option<OutputState> {
+        snapshot
+            .outputs
+            .iter()
+            .find(|state| state.connector == connector)
+            .cloned()
+    };
+    let mut differences = Vec::new();
+    for connector in connectors {
+        match (state(expected, connector), state(actual, connector)) {
+            (Some(expected), None) => differences.push(Difference::Missing {
+                connected: connected(connector),
+                expected,
+            }),
+            (None, Some(actual)) => differences.push(Difference::Unexpected(actual)),
+            (Some(expected), Some(actual)) => {
+                let fields = [
+                    ("mode", expected.mode.to_string(), actual.mode.to_string()),
+                    (
+                        "position",
+                        expected.position.to_string(),
+                        actual.position.to_string(),
+                    ),
+                    (
+                        "rotation",
+                        transform_name(expected.transform),
+                        transform_name(actual.transform),
+                    ),
+                    (
+                        "primary",
+                        expected.primary.to_string(),
+                        actual.primary.to_string(),
+                    ),
+                ];
+                for (field, expected, actual) in fields {
+                    if expected != actual {
+                        differences.push(Difference::Changed {
+                            connector: connector.to_string(),
+                            field,
+                            expected,
+                            actual,
+                        });
+                    }
+                }
+            }
+            (None, None) => unreachable!("{connector} is from one of the snapshots"),
+        }
+    }
+    differences
+}
+
+/// Prints differences from the layout saved at `path`, failing if there are any.
+pub fn run(resources: &GetResourcesReturn, path: &Path) -> anyhow::Result<()> {
+    let differences = differences(&load(path)?, resources);
+    for difference in &differences {
+        println!("{difference}");
+    }
+    match differences.len() {
+        0 => Ok(()),
+        count => Err(anyhow!(
+            "layout differs from {} in {count} ways",
+            path.display()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dbus_api::{CrtcId, ModeId},
+        layout::tests::resources,
+    };
+
+    #[test]
+    fn layouts_compared() {
+        let saved = Snapshot::capture(&resources());
+        assert!(differences(&saved, &resources()).is_empty());
+
+        let mut live = resources();
+        // HDMI-1 switched to 50Hz and moved, DP-1 unplugged, HDMI-2 enabled
+        live.crtcs[0].mode_id = Some(ModeId(1));
+        live.crtcs[0].x = 100;
+        live.outputs.remove(1);
+        live.crtcs[1].mode_id = None;
+        live.crtcs[2].mode_id = Some(ModeId(2));
+        live.outputs[1].crtc_id = Some(CrtcId(2));
+
+        let described: Vec<_> = differences(&saved, &live)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            vec![
+                "DP-1 is not connected, expected DP-1 1920x1080@60 1920x0 transform=0",
+                "HDMI-1 mode: expected 1920x1080@60, got 1920x1080@50",
+                "HDMI-1 position: expected 0x0, got 100x0",
+                "HDMI-2 is enabled, expected disabled: HDMI-2 1280x720@60 0x0 transform=0",
+            ],
+            described
+        );
+    }
+}
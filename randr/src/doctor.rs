@@ -0,0 +1,319 @@
+//! `doctor` command: checks of the environment gnome-randr needs, with suggested fixes.
+
+use std::{fmt::Display, time::Duration};
+
+use anyhow::anyhow;
+use dbus::blocking::{stdintf::org_freedesktop_dbus::Properties, Connection};
+
+use crate::dbus_api::{DisplayConfig, DISPLAY_CONFIG_SERVICE};
+
+const DISPLAY_CONFIG_INTERFACE: &str = "org.gnome.Mutter.DisplayConfig";
+
+/// Methods gnome-randr calls, or will call, and whether they are required.
+const METHODS: &[(&str, bool)] = &[
+    ("GetResources", true),
+    ("ApplyConfiguration", true),
+    ("GetCurrentState", false),
+    ("ApplyMonitorsConfig", false),
+    ("ChangeBacklight", false),
+    ("SetCrtcGamma", false),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // pad, unlike write_str, honors width of {:<5}
+        f.pad(match self {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        })
+    }
+}
+
+/// Result of a single check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    /// Suggested fix, shown for failed and warned checks
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn problem(
+        name: &'static str,
+        status: Status,
+        detail: impl Into<String>,
+        fix: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:<5} {}: {}", self.status, self.name, self.detail)?;
+        if let Some(fix) = &self.fix {
+            write!(f, "\n      fix: {fix}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Method names of DisplayConfig interface from introspection XML.
+fn introspected_methods(xml: &str) -> anyhow::Result<Vec<String>> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let interface = doc
+        .descendants()
+        .find(|node| {
+            node.has_tag_name("interface")
+                && node.attribute("name") == Some(DISPLAY_CONFIG_INTERFACE)
+        })
+        .ok_or_else(|| anyhow!("{DISPLAY_CONFIG_INTERFACE} is not introspected"))?;
+    Ok(interface
+        .children()
+        .filter(|node| node.has_tag_name("method"))
+        .filter_map(|node| node.attribute("name"))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Checks of a running DisplayConfig service.
+fn service_checks(conn: &Connection) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let proxy = conn.with_proxy(
+        DISPLAY_CONFIG_SERVICE,
+        "/org/gnome/Mutter/DisplayConfig",
+        Duration::from_millis(5000),
+    );
+
+    let introspected: Result<(String,), _> =
+        proxy.method_call("org.freedesktop.DBus.Introspectable", "Introspect", ());
+    match introspected
+        .map_err(anyhow::Error::from)
+        .and_then(|(xml,)| introspected_methods(&xml))
+    {
+        Ok(methods) => {
+            let missing = |required| -> Vec<_> {
+                METHODS
+                    .iter()
+                    .filter(|&&(name, req)| req == required && !methods.iter().any(|m| m == name))
+                    .map(|(name, _)| *name)
+                    .collect()
+            };
+            let (required, optional) = (missing(true), missing(false));
+            checks.push(if !required.is_empty() {
+                Check::problem(
+                    "API",
+                    Status::Fail,
+                    format!("missing {}", required.join(", ")),
+                    "this Mutter version is not supported, update GNOME",
+                )
+            } else if !optional.is_empty() {
+                Check::problem(
+                    "API",
+                    Status::Warn,
+                    format!("missing {}", optional.join(", ")),
+                    "scale checks, backlight or gamma won't work, update GNOME for full support",
+                )
+            } else {
+                Check::ok("API", format!("{} methods available", methods.len()))
+            });
+        }
+        Err(err) => checks.push(Check::problem(
+            "API",
+            Status::Warn,
+            format!("could not introspect DisplayConfig: {err:#}"),
+            "methods will be tried anyway, errors will show what's missing",
+        )),
+    }
+
+    let allowed: Result<bool, _> =
+        proxy.get(DISPLAY_CONFIG_INTERFACE, "ApplyMonitorsConfigAllowed");
+    checks.push(match allowed {
+        Ok(true) => Check::ok("ApplyMonitorsConfig", "allowed"),
+        Ok(false) => Check::problem(
+            "ApplyMonitorsConfig",
+            Status::Warn,
+            "not allowed",
+            "configuration is locked down, like on a remote or kiosk session",
+        ),
+        Err(err) => Check::problem(
+            "ApplyMonitorsConfig",
+            Status::Warn,
+            format!("unknown: {}", err.message().unwrap_or("no message")),
+            "older Mutter versions don't report it, changes may still work",
+        ),
+    });
+
+    let display_config = DisplayConfig::new(conn);
+    checks.push(match display_config.get_resources() {
+        Ok(resources) => Check::ok(
+            "GetResources",
+            format!(
+                "serial {}, {} outputs, {} enabled",
+                resources.serial,
+                resources.outputs.len(),
+                resources
+                    .outputs
+                    .iter()
+                    .filter(|output| output.crtc_id.is_some())
+                    .count()
+            ),
+        ),
+        Err(err) => Check::problem(
+            "GetResources",
+            Status::Fail,
+            format!("call failed: {err}"),
+            "check the compositor log, like journalctl --user -b",
+        ),
+    });
+    match display_config.get_current_state() {
+        Ok(state) => {
+            let layout = match state.props.layout_mode {
+                Some(1) => "logical",
+                Some(2) => "physical",
+                _ => "unknown",
+            };
+            checks.push(Check::ok(
+                "GetCurrentState",
+                format!(
+                    "{} logical monitors, {layout} layout mode",
+                    state.logical_monitors.len()
+                ),
+            ));
+        }
+        Err(err) => checks.push(Check::problem(
+            "GetCurrentState",
+            Status::Warn,
+            format!("call failed: {err}"),
+            "scale checks and --units logical won't work",
+        )),
+    }
+    checks
+}
+
+/// Runs every check, continuing past failures where possible.
+pub fn checks() -> Vec<Check> {
+    let mut checks = vec![Check::ok(
+        "build",
+        format!(
+            "gnome-randr {}, no nightly-only features",
+            env!("CARGO_PKG_VERSION")
+        ),
+    )];
+    let conn = match Connection::new_session() {
+        Ok(conn) => {
+            checks.push(Check::ok("session bus", "connected"));
+            conn
+        }
+        Err(err) => {
+            checks.push(Check::problem(
+                "session bus",
+                Status::Fail,
+                err.message().unwrap_or("could not connect").to_string(),
+                "run inside a graphical session, or set DBUS_SESSION_BUS_ADDRESS, like over SSH",
+            ));
+            return checks;
+        }
+    };
+
+    let bus = conn.with_proxy(
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        Duration::from_millis(5000),
+    );
+    let owner: Result<(String,), _> = bus.method_call(
+        "org.freedesktop.DBus",
+        "GetNameOwner",
+        (DISPLAY_CONFIG_SERVICE,),
+    );
+    match owner {
+        Ok((owner,)) => {
+            checks.push(Check::ok(
+                "DisplayConfig service",
+                format!("owned by {owner}"),
+            ));
+            checks.extend(service_checks(&conn));
+        }
+        Err(_) => checks.push(Check::problem(
+            "DisplayConfig service",
+            Status::Fail,
+            format!("{DISPLAY_CONFIG_SERVICE} has no owner"),
+            "gnome-randr only works under GNOME Shell/Mutter; use the bus of the GNOME session",
+        )),
+    }
+    checks
+}
+
+/// Prints every check with a summary, failing if any check failed.
+pub fn run() -> anyhow::Result<()> {
+    let checks = checks();
+    for check in &checks {
+        println!("{check}");
+    }
+    let count = |status| checks.iter().filter(|check| check.status == status).count();
+    let (failed, warned) = (count(Status::Fail), count(Status::Warn));
+    println!("{failed} failed, {warned} warnings");
+    match failed {
+        0 => Ok(()),
+        _ => Err(anyhow!("{failed} checks failed")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn methods_introspected() {
+        let xml = r#"<node>
+  <interface name="org.freedesktop.DBus.Properties"><method name="Get"/></interface>
+  <interface name="org.gnome.Mutter.DisplayConfig">
+    <method name="GetResources"><arg type="u" direction="out"/></method>
+    <method name="ApplyConfiguration"/>
+    <property name="PowerSaveMode" type="i" access="readwrite"/>
+  </interface>
+</node>"#;
+        assert_eq!(
+            vec!["GetResources", "ApplyConfiguration"],
+            introspected_methods(xml).unwrap()
+        );
+        assert!(introspected_methods("<node/>").is_err());
+    }
+
+    #[test]
+    fn check_displayed() {
+        let check = Check::problem("session bus", Status::Fail, "not found", "start a session");
+        assert_eq!(
+            "FAIL  session bus: not found\n      fix: start a session",
+            check.to_string()
+        );
+        assert_eq!(
+            "ok    API: 6 methods",
+            Check::ok("API", "6 methods").to_string()
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Parsing of the few EDID parts used to estimate link bandwidth and to show mode timings:
+//! detailed timings and maximum TMDS clock advertised by HDMI sinks.
+
+use std::fmt::Display;
+
+/// Polarity of horizontal and vertical sync pulses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncPolarity {
+    pub h_positive: bool,
+    pub v_positive: bool,
+}
+
+impl SyncPolarity {
+    /// Polarity from mode flags as defined in xf86drmMode.h, `None` if flags don't set both.
+    pub fn from_mode_flags(flags: u32) -> Option<Self> {
+        const PHSYNC: u32 = 1 << 0;
+        const NHSYNC: u32 = 1 << 1;
+        const PVSYNC: u32 = 1 << 2;
+        const NVSYNC: u32 = 1 << 3;
+        let polarity = |positive, negative| match (flags & positive != 0, flags & negative != 0) {
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            _ => None,
+        };
+        Some(Self {
+            h_positive: polarity(PHSYNC, NHSYNC)?,
+            v_positive: polarity(PVSYNC, NVSYNC)?,
+        })
+    }
+}
+
+impl Display for SyncPolarity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = |positive| if positive { '+' } else { '-' };
+        write!(
+            f,
+            "{}hsync {}vsync",
+            sign(self.h_positive),
+            sign(self.v_positive)
+        )
+    }
+}
+
+/// Timing of a mode from an 18 byte detailed timing descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetailedTiming {
+    /// Pixel clock in kHz
+    pub pixel_clock: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Width including horizontal blanking
+    pub h_total: u32,
+    /// Height including vertical blanking
+    pub v_total: u32,
+    /// Sync polarity, only known for digital separate sync
+    pub sync: Option<SyncPolarity>,
+}
+
+impl DetailedTiming {
+    fn parse(desc: &[u8]) -> Option<Self> {
+        let pixel_clock = u16::from_le_bytes([desc[0], desc[1]]) as u32 * 10;
+        // Zero pixel clock marks a display descriptor, like monitor name
+        if pixel_clock == 0 {
+            return None;
+        }
+        let high = |byte: u8| (byte as u32 >> 4) << 8;
+        let low = |byte: u8| (byte as u32 & 0xf) << 8;
+        let width = desc[2] as u32 | high(desc[4]);
+        let h_blank = desc[3] as u32 | low(desc[4]);
+        let height = desc[5] as u32 | high(desc[7]);
+        let v_blank = desc[6] as u32 | low(desc[7]);
+        // Bits 4-3 of features are 0b11 for digital separate sync, then bits 2-1 are polarities
+        let features = desc[17];
+        let sync = (features >> 3 & 0b11 == 0b11).then_some(SyncPolarity {
+            h_positive: features & 0b10 != 0,
+            v_positive: features & 0b100 != 0,
+        });
+        Some(Self {
+            pixel_clock,
+            width,
+            height,
+            h_total: width + h_blank,
+            v_total: height + v_blank,
+            sync,
+        })
+    }
+
+    /// Refresh rate in Hz.
+    pub fn refresh(&self) -> f64 {
+        self.pixel_clock as f64 * 1000.0 / (self.h_total as f64 * self.v_total as f64)
+    }
+}
+
+/// Checks header and returns base block with extension blocks that are present.
+fn blocks(edid: &[u8]) -> impl Iterator<Item = &[u8]> {
+    const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+    let valid = edid.len() >= 128 && edid[..8] == HEADER;
+    edid.chunks_exact(128).take_while(move |_| valid)
+}
+
+/// Detailed timings of base block and CTA-861 extensions.
+pub fn detailed_timings(edid: &[u8]) -> Vec<DetailedTiming> {
+    let mut timings = Vec::new();
+    for (idx, block) in blocks(edid).enumerate() {
+        let descriptors = match idx {
+            0 => &block[54..126],
+            // CTA extension, byte 2 is the offset of detailed timings
+            _ if block[0] == 0x02 && (4..=109).contains(&block[2]) => {
+                &block[block[2] as usize..127]
+            }
+            _ => continue,
+        };
+        timings.extend(
+            descriptors
+                .chunks_exact(18)
+                .filter_map(DetailedTiming::parse),
+        );
+    }
+    timings
+}
+
+/// Maximum TMDS clock in kHz from HDMI vendor specific data blocks, the highest of HDMI 1.x and
+/// HDMI Forum ones.
+pub fn max_tmds_clock(edid: &[u8]) -> Option<u32> {
+    const HDMI_OUI: [u8; 3] = [0x03, 0x0c, 0x00];
+    const HDMI_FORUM_OUI: [u8; 3] = [0xd8, 0x5d, 0xc4];
+    let mut max = None;
+    for block in blocks(edid).skip(1).filter(|block| block[0] == 0x02) {
+        let end = (block[2] as usize).clamp(4, 127);
+        let mut pos = 4;
+        while pos < end {
+            let (tag, len) = (block[pos] >> 5, (block[pos] & 0x1f) as usize);
+            let Some(data) = block.get(pos + 1..pos + 1 + len) else {
+                break;
+            };
+            // Vendor specific data block
+            if tag == 3 && len >= 7 {
+                let clock = match &data[..3] {
+                    oui if *oui == HDMI_OUI => data[6] as u32 * 5000,
+                    oui if *oui == HDMI_FORUM_OUI => data[4] as u32 * 5000,
+                    _ => 0,
+                };
+                if clock > 0 {
+                    max = max.max(Some(clock));
+                }
+            }
+            pos += 1 + len;
+        }
+    }
+    max
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Base block with 1920x1080@60 CEA timing and a CTA extension with HDMI 1.x block
+    /// advertising 300 MHz TMDS clock.
+    pub fn edid() -> Vec<u8> {
+        let mut edid = vec![0; 256];
+        edid[..8].copy_from_slice(&[0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00]);
+        edid[126] = 1;
+        // 148.5 MHz, 1920 + 280, 1080 + 45
+        edid[54..62].copy_from_slice(&[0x02, 0x3a, 0x80, 0x18, 0x71, 0x38, 0x2d, 0x40]);
+        // Digital separate sync, +hsync +vsync
+        edid[71] = 0x1e;
+        let ext = &mut edid[128..];
+        ext[..4].copy_from_slice(&[0x02, 0x03, 12, 0x00]);
+        ext[4..12].copy_from_slice(&[0x67, 0x03, 0x0c, 0x00, 0x10, 0x00, 0x00, 60]);
+        edid
+    }
+
+    #[test]
+    fn timings_parsed() {
+        let edid = edid();
+        let timings = detailed_timings(&edid);
+        assert_eq!(
+            vec![DetailedTiming {
+                pixel_clock: 148500,
+                width: 1920,
+                height: 1080,
+                h_total: 2200,
+                v_total: 1125,
+                sync: Some(SyncPolarity {
+                    h_positive: true,
+                    v_positive: true
+                }),
+            }],
+            timings
+        );
+        assert_eq!(60.0, timings[0].refresh());
+        assert_eq!(Some(300000), max_tmds_clock(&edid));
+        assert!(detailed_timings(&edid[1..]).is_empty());
+    }
+
+    #[test]
+    fn sync_polarity_from_flags() {
+        let polarity = SyncPolarity::from_mode_flags(0b1001).unwrap();
+        assert_eq!("+hsync -vsync", polarity.to_string());
+        assert_eq!(None, SyncPolarity::from_mode_flags(0b0001));
+        assert_eq!(None, SyncPolarity::from_mode_flags(0b0111));
+    }
+}
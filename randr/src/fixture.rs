@@ -0,0 +1,73 @@
+//! Developer mode replaying GetResources replies recorded with `--json`, so layouts reported by
+//! users could be reproduced without their hardware.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+
+use crate::dbus_api::{
+    ApplyConfigurationArgs, DisplayConfigApi, GetCurrentStateReturn, GetResourcesReturn,
+};
+
+/// DisplayConfig replaying a recorded reply. Configurations are printed as JSON instead of
+/// being applied.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    resources: GetResourcesReturn,
+}
+
+impl Fixture {
+    pub fn new(resources: GetResourcesReturn) -> Self {
+        Self { resources }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("could not read fixture {}", path.display()))?;
+        let resources = serde_json::from_str(&text)
+            .with_context(|| format!("invalid fixture {}", path.display()))?;
+        Ok(Self::new(resources))
+    }
+}
+
+impl DisplayConfigApi for Fixture {
+    fn get_resources(&self) -> Result<GetResourcesReturn, dbus::Error> {
+        Ok(self.resources.clone())
+    }
+
+    /// Fixtures don't have current state, so it's handled like on Mutter versions without it.
+    fn get_current_state(&self) -> Result<GetCurrentStateReturn, dbus::Error> {
+        Err(dbus::Error::new_custom(
+            "org.freedesktop.DBus.Error.UnknownMethod",
+            "GetCurrentState is not recorded in fixtures",
+        ))
+    }
+
+    fn apply_configuration(&self, args: ApplyConfigurationArgs) -> Result<(), dbus::Error> {
+        let json = serde_json::to_string_pretty(&args)
+            .map_err(|err| dbus::Error::new_failed(&err.to_string()))?;
+        println!("{json}");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::tests::resources;
+
+    #[test]
+    fn fixture_roundtrip() {
+        let json = serde_json::to_string(&resources()).unwrap();
+        let fixture = Fixture::new(serde_json::from_str(&json).unwrap());
+        let replayed = fixture.get_resources().unwrap();
+        assert_eq!(json, serde_json::to_string(&replayed).unwrap());
+        assert!(fixture.get_current_state().is_err());
+
+        // Absent properties are left out and default when loaded
+        assert!(!json.contains("\"edid\""));
+        let output = &replayed.outputs[0];
+        assert_eq!(None, output.props.edid);
+        assert_eq!("HDMI-1", output.connector_name);
+    }
+}
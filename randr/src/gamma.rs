@@ -0,0 +1,333 @@
+//! Brightness and color temperature applied through gamma ramps. Mutter resets gamma on every
+//! modeset, so settings are stored and re-applied after configuration changes.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    cli::OutputArgs,
+    dbus_api::{CrtcGamma, DisplayConfigApi},
+    status,
+    warnings::{self, Kind},
+};
+
+/// Color temperature which doesn't change colors.
+pub const NEUTRAL_TEMPERATURE: u32 = 6500;
+
+/// Gamma settings of a single output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GammaSettings {
+    /// Multiplier of every channel, 1 keeps colors as is
+    pub brightness: f64,
+    /// Color temperature in Kelvin
+    pub temperature: u32,
+}
+
+impl Default for GammaSettings {
+    fn default() -> Self {
+        Self {
+            brightness: 1.0,
+            temperature: NEUTRAL_TEMPERATURE,
+        }
+    }
+}
+
+impl GammaSettings {
+    /// Channel multipliers for color temperature, approximated as described by Tanner Helland
+    /// and normalized so that [`NEUTRAL_TEMPERATURE`] is white.
+    fn white_point(temperature: u32) -> [f64; 3] {
+        let channels = |temperature: u32| {
+            let t = temperature as f64 / 100.0;
+            let red = if t <= 66.0 {
+                255.0
+            } else {
+                329.698727446 * (t - 60.0).powf(-0.1332047592)
+            };
+            let green = if t <= 66.0 {
+                99.4708025861 * t.ln() - 161.1195681661
+            } else {
+                288.1221695283 * (t - 60.0).powf(-0.0755148492)
+            };
+            let blue = if t >= 66.0 {
+                255.0
+            } else if t <= 19.0 {
+                0.0
+            } else {
+                138.5177312231 * (t - 10.0).ln() - 305.0447927307
+            };
+            [red, green, blue].map(|channel| channel.clamp(0.0, 255.0))
+        };
+        let (color, neutral) = (channels(temperature), channels(NEUTRAL_TEMPERATURE));
+        [0, 1, 2].map(|idx| (color[idx] / neutral[idx]).min(1.0))
+    }
+
+    /// Ramps with `len` entries, as CRTC expects.
+    pub fn ramp(&self, len: usize) -> CrtcGamma {
+        let [red, green, blue] = Self::white_point(self.temperature).map(|multiplier| {
+            let linear = CrtcGamma::linear(len).red;
+            linear
+                .into_iter()
+                .map(|value| {
+                    (value as f64 * multiplier * self.brightness).clamp(0.0, u16::MAX as f64) as u16
+                })
+                .collect()
+        });
+        CrtcGamma { red, green, blue }
+    }
+}
+
+impl Display for GammaSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "brightness={} temperature={}",
+            self.brightness, self.temperature
+        )
+    }
+}
+
+impl FromStr for GammaSettings {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut settings = Self::default();
+        for part in s.split_whitespace() {
+            match part.split_once('=') {
+                Some(("brightness", value)) => settings.brightness = value.parse()?,
+                Some(("temperature", value)) => settings.temperature = value.parse()?,
+                _ => return Err(anyhow!("unexpected {part}")),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+/// Gamma settings of every output that has them, stored in a `gamma` state file with one
+/// output per line.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GammaStore {
+    pub outputs: BTreeMap<String, GammaSettings>,
+}
+
+impl GammaStore {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            text => text.with_context(|| format!("could not read {}", path.display()))?,
+        };
+        text.parse()
+            .with_context(|| format!("could not parse {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(path, self.to_string()))
+            .with_context(|| format!("could not write {}", path.display()))
+    }
+
+    /// Records `--brightness` and `--temperature` of `outputs`. Outputs reset to defaults are
+    /// removed. Returns whether anything changed.
+    pub fn update(&mut self, outputs: &[OutputArgs]) -> bool {
+        let mut changed = false;
+        for args in outputs {
+            if args.brightness.is_none() && args.temperature.is_none() {
+                continue;
+            }
+            let current = self.outputs.get(&args.name).copied().unwrap_or_default();
+            let settings = GammaSettings {
+                brightness: args.brightness.unwrap_or(current.brightness),
+                temperature: args.temperature.unwrap_or(current.temperature),
+            };
+            changed |= settings != current;
+            if settings == GammaSettings::default() {
+                self.outputs.remove(&args.name);
+            } else {
+                self.outputs.insert(args.name.clone(), settings);
+            }
+        }
+        changed
+    }
+
+    /// Sets gamma of every enabled output with stored settings. If setting one output fails,
+    /// outputs that were already set get their previous ramps back.
+    pub fn apply(&self, display_config: &impl DisplayConfigApi) -> anyhow::Result<()> {
+        if self.outputs.is_empty() {
+            return Ok(());
+        }
+        let resources = display_config.get_resources()?;
+        let mut previous = Vec::new();
+        for output in &resources.outputs {
+            let (Some(settings), Some(crtc)) =
+                (self.outputs.get(&output.connector_name), output.crtc_id)
+            else {
+                continue;
+            };
+            let name = &output.connector_name;
+            let set = display_config
+                .get_crtc_gamma(resources.serial, crtc)
+                .and_then(|gamma| {
+                    let ramp = settings.ramp(gamma.len());
+                    display_config.set_crtc_gamma(resources.serial, crtc, ramp)?;
+                    Ok(gamma)
+                });
+            match set {
+                Ok(gamma) => previous.push((name, crtc, gamma)),
+                Err(err) => {
+                    let err = anyhow!("could not set gamma of {name}: {err}");
+                    let failed: Vec<_> = previous
+                        .into_iter()
+                        .rev()
+                        .filter_map(|(name, crtc, gamma)| {
+                            let restored =
+                                display_config.set_crtc_gamma(resources.serial, crtc, gamma);
+                            restored.is_err().then_some(name.as_str())
+                        })
+                        .collect();
+                    return Err(match failed.is_empty() {
+                        true => err,
+                        false => anyhow!(
+                            "{err}, and could not restore gamma of {}",
+                            failed.join(", ")
+                        ),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// File storing [`GammaStore`], `None` if there is no state directory.
+pub fn store_path() -> Option<PathBuf> {
+    status::state_dir().map(|dir| dir.join("gamma"))
+}
+
+/// Records gamma settings from `outputs` and applies every stored setting, which is needed after
+/// each configuration change.
+pub fn reapply(
+    display_config: &impl DisplayConfigApi,
+    outputs: &[OutputArgs],
+) -> anyhow::Result<()> {
+    let path = store_path();
+    let mut store = match &path {
+        Some(path) => GammaStore::load(path)?,
+        None => GammaStore::default(),
+    };
+    if store.update(outputs) {
+        match &path {
+            Some(path) => store.save(path)?,
+            None => warnings::warn(
+                Kind::Gamma,
+                "no state directory, gamma settings won't be remembered",
+            ),
+        }
+    }
+    store.apply(display_config)
+}
+
+impl Display for GammaStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (connector, settings) in &self.outputs {
+            writeln!(f, "{connector} {settings}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for GammaStore {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut outputs = BTreeMap::new();
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            let (connector, settings) = line
+                .trim()
+                .split_once(' ')
+                .ok_or_else(|| anyhow!("missing settings in {line:?}"))?;
+            outputs.insert(connector.to_string(), settings.parse()?);
+        }
+        Ok(Self { outputs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cli::OutputArgsBuilder, layout::tests::ScriptedApi};
+
+    #[test]
+    fn ramp_scaled() {
+        let ramp = GammaSettings::default().ramp(3);
+        assert_eq!(CrtcGamma::linear(3), ramp);
+
+        let dimmed = GammaSettings {
+            brightness: 0.5,
+            ..Default::default()
+        }
+        .ramp(3);
+        assert_eq!(vec![0, 16384, 32767], dimmed.green);
+
+        let warm = GammaSettings {
+            temperature: 3000,
+            ..Default::default()
+        }
+        .ramp(2);
+        assert_eq!(u16::MAX, warm.red[1]);
+        assert!(warm.green[1] < warm.red[1] && warm.blue[1] < warm.green[1]);
+    }
+
+    #[test]
+    fn store_updated() {
+        let mut store = GammaStore::default();
+        let args = |brightness, temperature| {
+            let mut builder = OutputArgsBuilder::default();
+            builder.name("DP-1");
+            if let Some(brightness) = brightness {
+                builder.brightness(brightness);
+            }
+            if let Some(temperature) = temperature {
+                builder.temperature(temperature);
+            }
+            builder.build().unwrap()
+        };
+
+        assert!(store.update(&[args(Some(0.8), None)]));
+        assert!(store.update(&[args(None, Some(4500))]));
+        assert!(!store.update(&[args(Some(0.8), None)]));
+        let text = store.to_string();
+        assert_eq!("DP-1 brightness=0.8 temperature=4500\n", text);
+        assert_eq!(store, text.parse().unwrap());
+
+        assert!(store.update(&[args(Some(1.0), Some(NEUTRAL_TEMPERATURE))]));
+        assert!(store.outputs.is_empty());
+    }
+
+    #[test]
+    fn set_outputs_restored_on_failure() {
+        let store: GammaStore = "HDMI-1 brightness=0.5\nDP-1 temperature=4000\n"
+            .parse()
+            .unwrap();
+        let api = ScriptedApi::failing("gamma 1");
+        let err = store.apply(&api).unwrap_err();
+        assert_eq!(
+            "could not set gamma of DP-1: gamma 1 ramp 257 failed",
+            err.to_string()
+        );
+        assert_eq!(
+            vec!["gamma 0 ramp 256", "gamma 1 ramp 257", "gamma 0 linear 256"],
+            api.calls()
+        );
+
+        let api = ScriptedApi::default();
+        store.apply(&api).unwrap();
+        assert_eq!(vec!["gamma 0 ramp 256", "gamma 1 ramp 257"], api.calls());
+    }
+}
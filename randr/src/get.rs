@@ -0,0 +1,153 @@
+//! `get` command printing a single undecorated value, for command substitution in scripts.
+
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
+use crate::{
+    cli::GetArgs,
+    dbus_api::GetResourcesReturn,
+    layout::{Layout, Position},
+    mode_db::ModeDb,
+};
+
+/// Value of an output printed by `get <OUTPUT> <FIELD>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// Current mode, like `1920x1080@60`
+    Mode,
+    Resolution,
+    /// Rounded refresh rate
+    Rate,
+    /// Position in physical pixels, like `1920x0`
+    Position,
+    /// `--rotate` name of the current transform
+    Rotation,
+    /// `true` if the output has a CRTC
+    Enabled,
+    /// `true` if the output is primary
+    Primary,
+    /// Backlight in percents
+    Backlight,
+    Vendor,
+    Product,
+    Serial,
+    /// Human readable name, shown in the UI
+    DisplayName,
+}
+
+impl Field {
+    const NAMES: &'static [(&'static str, Field)] = &[
+        ("mode", Field::Mode),
+        ("resolution", Field::Resolution),
+        ("rate", Field::Rate),
+        ("position", Field::Position),
+        ("rotation", Field::Rotation),
+        ("enabled", Field::Enabled),
+        ("primary", Field::Primary),
+        ("backlight", Field::Backlight),
+        ("vendor", Field::Vendor),
+        ("product", Field::Product),
+        ("serial", Field::Serial),
+        ("display-name", Field::DisplayName),
+    ];
+}
+
+impl FromStr for Field {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Field::NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|&(_, field)| field)
+            .ok_or_else(|| {
+                let names: Vec<_> = Field::NAMES.iter().map(|(name, _)| *name).collect();
+                anyhow!("unknown field {s}, expected one of {}", names.join(", "))
+            })
+    }
+}
+
+/// Value requested by `args`. Values that are not available, like a mode of a disabled output,
+/// are errors, so scripts could check the exit code.
+pub fn value(resources: &GetResourcesReturn, args: &GetArgs) -> anyhow::Result<String> {
+    let layout = Layout::new(resources);
+    let GetArgs::Field { output, field } = args else {
+        return resources
+            .outputs
+            .iter()
+            .find(|output| output.props.primary == Some(true))
+            .map(|output| output.connector_name.clone())
+            .ok_or_else(|| anyhow!("no output is primary"));
+    };
+    let output = layout.output(output)?;
+    let crtc = resources
+        .crtc_of(output)
+        .filter(|crtc| crtc.mode_id.is_some());
+    let disabled = || anyhow!("output {} is disabled", output.connector_name);
+    let mode = || {
+        crtc.and_then(|crtc| crtc.mode_id)
+            .and_then(|id| ModeDb::new(&resources.modes).mode_by_id(id))
+            .ok_or_else(disabled)
+    };
+    let prop = |value: &Option<String>, name: &str| {
+        value
+            .clone()
+            .ok_or_else(|| anyhow!("output {} has no {name}", output.connector_name))
+    };
+    let props = &output.props;
+    Ok(match field {
+        Field::Mode => mode()?.to_string(),
+        Field::Resolution => mode()?.res.to_string(),
+        Field::Rate => mode()?.frequency.to_string(),
+        Field::Position => {
+            let crtc = crtc.ok_or_else(disabled)?;
+            Position {
+                x: crtc.x,
+                y: crtc.y,
+            }
+            .to_string()
+        }
+        Field::Rotation => crtc.ok_or_else(disabled)?.transform.to_string(),
+        Field::Enabled => crtc.is_some().to_string(),
+        Field::Primary => (props.primary == Some(true)).to_string(),
+        // -1 means backlight is not supported
+        Field::Backlight => match props.backlight {
+            Some(backlight) if backlight >= 0 => backlight.to_string(),
+            _ => return Err(anyhow!("output {} has no backlight", output.connector_name)),
+        },
+        Field::Vendor => prop(&props.vendor, "vendor")?,
+        Field::Product => prop(&props.product, "product")?,
+        Field::Serial => prop(&props.serial, "serial")?,
+        Field::DisplayName => prop(&props.display_name, "display name")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::tests::resources;
+
+    #[test]
+    fn values() {
+        let resources = resources();
+        let get = |output: &str, field: &str| {
+            value(
+                &resources,
+                &GetArgs::Field {
+                    output: output.to_string(),
+                    field: field.parse().unwrap(),
+                },
+            )
+        };
+        assert_eq!("HDMI-1", value(&resources, &GetArgs::Primary).unwrap());
+        assert_eq!("1920x1080@60", get("HDMI-1", "mode").unwrap());
+        assert_eq!("1920x0", get("DP-1", "position").unwrap());
+        assert_eq!("normal", get("DP-1", "rotation").unwrap());
+        assert_eq!("false", get("HDMI-2", "enabled").unwrap());
+        assert_eq!("true", get("hdmi1", "primary").unwrap());
+        let err = get("HDMI-2", "mode").unwrap_err();
+        assert_eq!("output HDMI-2 is disabled", err.to_string());
+        assert!("size".parse::<Field>().is_err());
+    }
+}
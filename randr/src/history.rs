@@ -0,0 +1,340 @@
+//! Past configurations, kept in `$XDG_STATE_HOME/gnome-randr/history` so they could be listed and
+//! restored with `history`.
+//!
+//! Every file is a snapshot in the format printed by `lock`, named by the time it was recorded in
+//! milliseconds since the Unix epoch. Only the newest [`KEEP`] snapshots are kept.
+
+use std::{
+    cmp::Reverse,
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    apply_lock,
+    cli::{GlobalArgs, HistoryArgs},
+    dbus_api::{DisplayConfig, DisplayConfigApi, GetResourcesReturn},
+    diff::{self, Difference},
+    lock::Snapshot,
+    status,
+    warnings::{self, Kind},
+};
+
+/// Number of snapshots kept, older ones are removed when a new one is recorded.
+pub const KEEP: usize = 50;
+
+/// A recorded configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub time: SystemTime,
+    pub snapshot: Snapshot,
+}
+
+/// Directory of snapshots, rotated on every record.
+#[derive(Debug, Clone)]
+pub struct History {
+    dir: PathBuf,
+    keep: usize,
+}
+
+impl History {
+    pub fn new(dir: PathBuf, keep: usize) -> Self {
+        Self { dir, keep }
+    }
+
+    /// History in the state directory, `None` if it's unknown.
+    pub fn open() -> Option<Self> {
+        Some(Self::new(status::state_dir()?.join("history"), KEEP))
+    }
+
+    /// Recorded snapshot files with their times, newest first. Files that are not named like
+    /// snapshots are ignored.
+    fn files(&self) -> anyhow::Result<Vec<(SystemTime, PathBuf)>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("could not read {}", self.dir.display()))
+            }
+        };
+        let mut files = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let millis = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.parse::<u64>().ok());
+            if let Some(millis) = millis {
+                files.push((SystemTime::UNIX_EPOCH + Duration::from_millis(millis), path));
+            }
+        }
+        files.sort_by_key(|&(time, _)| Reverse(time));
+        Ok(files)
+    }
+
+    /// Recorded configurations, newest first.
+    pub fn entries(&self) -> anyhow::Result<Vec<Entry>> {
+        self.files()?
+            .into_iter()
+            .map(|(time, path)| {
+                let text = fs::read_to_string(&path)
+                    .with_context(|| format!("could not read {}", path.display()))?;
+                let snapshot = text
+                    .parse()
+                    .with_context(|| format!("could not parse {}", path.display()))?;
+                Ok(Entry { time, snapshot })
+            })
+            .collect()
+    }
+
+    /// Configuration `index` entries back, 0 is the newest one.
+    pub fn entry(&self, index: usize) -> anyhow::Result<Entry> {
+        let mut entries = self.entries()?;
+        let count = entries.len();
+        if index >= count {
+            return Err(anyhow!(
+                "no configuration {index} in history, there are {count}"
+            ));
+        }
+        Ok(entries.swap_remove(index))
+    }
+
+    /// Records `snapshot` unless it's the same as the newest one, removing snapshots over the
+    /// limit. Returns if it was recorded.
+    pub fn record(&self, snapshot: &Snapshot, time: SystemTime) -> anyhow::Result<bool> {
+        if self.entries()?.first().map(|entry| &entry.snapshot) == Some(snapshot) {
+            return Ok(false);
+        }
+        let millis = time.duration_since(SystemTime::UNIX_EPOCH)?.as_millis();
+        let path = self.dir.join(millis.to_string());
+        fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(&path, snapshot.to_string()))
+            .with_context(|| format!("could not write {}", path.display()))?;
+        for (_, path) in self.files()?.into_iter().skip(self.keep) {
+            fs::remove_file(&path)
+                .with_context(|| format!("could not remove {}", path.display()))?;
+        }
+        Ok(true)
+    }
+}
+
+/// Records the current configuration of `api`. History is only a convenience, so failures are
+/// warnings.
+pub fn record_current(api: &impl DisplayConfigApi) {
+    let Some(history) = History::open() else {
+        return;
+    };
+    let recorded = api
+        .get_resources()
+        .map_err(anyhow::Error::from)
+        .and_then(|resources| history.record(&Snapshot::capture(&resources), SystemTime::now()));
+    if let Err(err) = recorded {
+        warnings::warn(
+            Kind::Status,
+            format!("could not record configuration history: {err:#}"),
+        );
+    }
+}
+
+/// `time` in UTC, like `2024-03-01 09:05:00 UTC`.
+pub fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Civil date from days since the epoch, from Howard Hinnant's `civil_from_days`
+    let days = days as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Change from one configuration to another, like `DP-1 position: 0x0 -> 1920x0`.
+fn describe(difference: &Difference) -> String {
+    match difference {
+        Difference::Missing { expected, .. } => {
+            format!("{} removed, was {expected}", expected.connector)
+        }
+        Difference::Unexpected(actual) => format!("{} added: {actual}", actual.connector),
+        Difference::Changed {
+            connector,
+            field,
+            expected,
+            actual,
+        } => format!("{connector} {field}: {expected} -> {actual}"),
+    }
+}
+
+/// Changes from configuration `from` to `to`, or to the live configuration if `to` is `None`.
+pub fn changes(
+    from: &Snapshot,
+    to: Option<&Snapshot>,
+    resources: &GetResourcesReturn,
+) -> Vec<String> {
+    let differences = match to {
+        Some(to) => diff::compare(from, to, |_| true),
+        None => diff::differences(from, resources),
+    };
+    differences.iter().map(describe).collect()
+}
+
+/// Lists recorded configurations, restores one of them, or shows changes between them.
+pub fn run(
+    display_config: &DisplayConfig,
+    global: &GlobalArgs,
+    args: &HistoryArgs,
+) -> anyhow::Result<()> {
+    let history =
+        History::open().ok_or_else(|| anyhow!("state directory is unknown, HOME is not set"))?;
+    match args {
+        HistoryArgs::List => {
+            let resources = display_config.get_resources()?;
+            for (index, entry) in history.entries()?.iter().enumerate() {
+                let current = match entry.snapshot.deviates(&resources) {
+                    true => "",
+                    false => " (current)",
+                };
+                println!("{index}: {}{current}", format_time(entry.time));
+                for output in &entry.snapshot.outputs {
+                    println!("    {output}");
+                }
+            }
+        }
+        HistoryArgs::Restore(index) => {
+            let entry = history.entry(*index)?;
+            {
+                let _lock = apply_lock::acquire(global.lock_timeout)?;
+                let config = entry.snapshot.restore(&display_config.get_resources()?)?;
+                display_config.apply_configuration(config)?;
+            }
+            record_current(display_config);
+        }
+        HistoryArgs::Diff { from, to } => {
+            let from_entry = history.entry(*from)?;
+            // The newest configuration is compared with the live one
+            let to = match to.or(from.checked_sub(1)) {
+                Some(to) => Some((to, history.entry(to)?)),
+                None => None,
+            };
+            let target = match &to {
+                Some((index, entry)) => format!("{index} ({})", format_time(entry.time)),
+                None => "the live configuration".to_string(),
+            };
+            println!(
+                "changes from {from} ({}) to {target}:",
+                format_time(from_entry.time)
+            );
+            let resources = display_config.get_resources()?;
+            let changes = changes(
+                &from_entry.snapshot,
+                to.as_ref().map(|(_, entry)| &entry.snapshot),
+                &resources,
+            );
+            if changes.is_empty() {
+                println!("    none");
+            }
+            for change in changes {
+                println!("    {change}");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dbus_api::{CrtcId, ModeId},
+        layout::tests::resources,
+    };
+
+    #[test]
+    fn snapshots_rotated() {
+        let dir = std::env::temp_dir().join(format!("gnome-randr-history-{}", std::process::id()));
+        let history = History::new(dir.clone(), 2);
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        assert!(history.entries().unwrap().is_empty());
+
+        let first = Snapshot::capture(&resources());
+        let mut changed = resources();
+        changed.crtcs[0].mode_id = Some(ModeId(1));
+        let second = Snapshot::capture(&changed);
+        changed.crtcs[0].x = 100;
+        let third = Snapshot::capture(&changed);
+
+        assert!(history.record(&first, at(10)).unwrap());
+        // Same as the newest one
+        assert!(!history.record(&first, at(20)).unwrap());
+        assert!(history.record(&second, at(30)).unwrap());
+        assert!(history.record(&third, at(40)).unwrap());
+
+        let entries = history.entries().unwrap();
+        assert_eq!(
+            vec![(at(40), &third), (at(30), &second)],
+            entries
+                .iter()
+                .map(|entry| (entry.time, &entry.snapshot))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(second, history.entry(1).unwrap().snapshot);
+        assert!(history.entry(2).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn changes_described() {
+        let before = Snapshot::capture(&resources());
+        let mut changed = resources();
+        // HDMI-1 moved, DP-1 disabled, HDMI-2 enabled
+        changed.crtcs[0].x = 100;
+        changed.outputs[1].crtc_id = None;
+        changed.crtcs[1].mode_id = None;
+        changed.crtcs[2].mode_id = Some(ModeId(2));
+        changed.outputs[2].crtc_id = Some(CrtcId(2));
+        let after = Snapshot::capture(&changed);
+
+        assert_eq!(
+            vec![
+                "DP-1 removed, was DP-1 1920x1080@60 1920x0 transform=0",
+                "HDMI-1 position: 0x0 -> 100x0",
+                "HDMI-2 added: HDMI-2 1280x720@60 0x0 transform=0",
+            ],
+            changes(&before, Some(&after), &resources())
+        );
+        assert!(changes(&before, Some(&before), &resources()).is_empty());
+        // Live configuration
+        assert_eq!(
+            "HDMI-1 position: 100x0 -> 0x0",
+            changes(&after, None, &resources())[1]
+        );
+    }
+
+    #[test]
+    fn time_formatted() {
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!("1970-01-01 00:00:00 UTC", format_time(at(0)));
+        assert_eq!("2000-02-29 23:59:59 UTC", format_time(at(951868799)));
+        assert_eq!("2024-03-01 09:05:00 UTC", format_time(at(1709283900)));
+    }
+}
@@ -0,0 +1,226 @@
+//! User-facing messages, looked up in a catalog so distributions could translate them.
+//!
+//! English messages from `locale/en-US.ftl` are embedded. With the `i18n` feature, the catalog
+//! is a Fluent bundle per language from `LANGUAGE`, `LC_ALL`, `LC_MESSAGES` or `LANG`, read from
+//! `$XDG_DATA_DIRS/gnome-randr/locale/<LANGUAGE>.ftl` and falling back to English message by
+//! message. Without it, the embedded English file is used as is.
+
+use std::sync::OnceLock;
+
+/// Embedded English messages.
+const EN_US: &str = include_str!("../locale/en-US.ftl");
+
+static GLOBAL: OnceLock<Localizer> = OnceLock::new();
+
+/// Message catalog, passed to output code.
+pub struct Localizer {
+    #[cfg(feature = "i18n")]
+    bundles: Vec<fluent_bundle::concurrent::FluentBundle<fluent_bundle::FluentResource>>,
+    #[cfg(not(feature = "i18n"))]
+    messages: Vec<(&'static str, &'static str)>,
+}
+
+impl std::fmt::Debug for Localizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Localizer").finish_non_exhaustive()
+    }
+}
+
+impl Localizer {
+    /// Catalog with embedded English messages only.
+    pub fn english() -> Self {
+        #[cfg(feature = "i18n")]
+        {
+            let langid: unic_langid::LanguageIdentifier = "en-US".parse().unwrap();
+            let bundle = fluent::bundle(langid, EN_US.to_string())
+                .expect("embedded messages are valid Fluent");
+            Self {
+                bundles: vec![bundle],
+            }
+        }
+        #[cfg(not(feature = "i18n"))]
+        Self {
+            messages: parse_messages(EN_US),
+        }
+    }
+
+    /// Catalog for the language of the environment. Translations that can't be read are
+    /// skipped, English is always there.
+    pub fn from_env() -> Self {
+        #[cfg(feature = "i18n")]
+        {
+            let mut localizer = Self::english();
+            let english = localizer.bundles.remove(0);
+            localizer.bundles = fluent::translations();
+            localizer.bundles.push(english);
+            localizer
+        }
+        #[cfg(not(feature = "i18n"))]
+        Self::english()
+    }
+
+    /// Message `id` with `args` substituted for `{ $name }` placeholders. Unknown ids are
+    /// returned as is, so a broken catalog doesn't hide output.
+    pub fn message(&self, id: &str, args: &[(&str, String)]) -> String {
+        #[cfg(feature = "i18n")]
+        {
+            self.bundles
+                .iter()
+                .find_map(|bundle| fluent::format(bundle, id, args))
+                .unwrap_or_else(|| id.to_string())
+        }
+        #[cfg(not(feature = "i18n"))]
+        {
+            let Some(&(_, pattern)) = self.messages.iter().find(|(key, _)| *key == id) else {
+                return id.to_string();
+            };
+            substitute(pattern, args)
+        }
+    }
+}
+
+/// Sets the catalog used by code that reports messages globally, like warnings.
+pub fn set_global(localizer: Localizer) {
+    // Only the first catalog is kept, which is the one of the invocation
+    let _ = GLOBAL.set(localizer);
+}
+
+/// Catalog set with [`set_global`], English if none was set.
+pub fn global() -> &'static Localizer {
+    GLOBAL.get_or_init(Localizer::english)
+}
+
+/// `id = pattern` lines of a Fluent file, which is enough for the embedded messages since they
+/// only use placeholders.
+#[cfg(not(feature = "i18n"))]
+fn parse_messages(text: &'static str) -> Vec<(&'static str, &'static str)> {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once(" = "))
+        .map(|(id, pattern)| (id.trim(), pattern.trim()))
+        .collect()
+}
+
+#[cfg(not(feature = "i18n"))]
+fn substitute(pattern: &str, args: &[(&str, String)]) -> String {
+    let mut message = pattern.to_string();
+    for (name, value) in args {
+        message = message.replace(&format!("{{ ${name} }}"), value);
+    }
+    message
+}
+
+#[cfg(feature = "i18n")]
+mod fluent {
+    use std::path::PathBuf;
+
+    use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource};
+    use unic_langid::LanguageIdentifier;
+
+    pub(super) fn bundle(
+        langid: LanguageIdentifier,
+        text: String,
+    ) -> Option<FluentBundle<FluentResource>> {
+        let resource = FluentResource::try_new(text).ok()?;
+        // Concurrent, so the catalog could be global
+        let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+        // Isolation marks end up in terminals and scripts parsing output
+        bundle.set_use_isolating(false);
+        bundle.add_resource(resource).ok()?;
+        Some(bundle)
+    }
+
+    pub(super) fn format(
+        bundle: &FluentBundle<FluentResource>,
+        id: &str,
+        args: &[(&str, String)],
+    ) -> Option<String> {
+        let pattern = bundle.get_message(id)?.value()?;
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, value.clone());
+        }
+        let mut errors = Vec::new();
+        let message = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+        errors.is_empty().then(|| message.into_owned())
+    }
+
+    /// Languages of the environment in order of preference, like `de-DE` from `de_DE.UTF-8`.
+    pub(super) fn languages() -> Vec<LanguageIdentifier> {
+        let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+        // LANGUAGE is a list, the others are a single locale
+        let locales: Vec<String> = match var("LANGUAGE") {
+            Some(list) => list.split(':').map(str::to_string).collect(),
+            None => ["LC_ALL", "LC_MESSAGES", "LANG"]
+                .into_iter()
+                .find_map(var)
+                .into_iter()
+                .collect(),
+        };
+        locales
+            .iter()
+            .filter_map(|locale| {
+                let locale = locale.split(['.', '@']).next()?;
+                locale.replace('_', "-").parse().ok()
+            })
+            .filter(|langid: &LanguageIdentifier| langid.language.as_str() != "en")
+            .collect()
+    }
+
+    fn locale_dirs() -> Vec<PathBuf> {
+        let data_dirs = std::env::var("XDG_DATA_DIRS")
+            .ok()
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+        data_dirs
+            .split(':')
+            .map(|dir| PathBuf::from(dir).join("gnome-randr/locale"))
+            .collect()
+    }
+
+    /// Bundles of every environment language with a catalog, `de.ftl` is used for `de-DE` too.
+    pub(super) fn translations() -> Vec<FluentBundle<FluentResource>> {
+        let mut bundles = Vec::new();
+        for langid in languages() {
+            let names = [langid.to_string(), langid.language.to_string()];
+            let text = locale_dirs().iter().find_map(|dir| {
+                names
+                    .iter()
+                    .find_map(|name| std::fs::read_to_string(dir.join(format!("{name}.ftl"))).ok())
+            });
+            if let Some(bundle) = text.and_then(|text| bundle(langid, text)) {
+                bundles.push(bundle);
+            }
+        }
+        bundles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english_messages() {
+        let localizer = Localizer::english();
+        assert_eq!(
+            "crtc id: 1, crtc winsys id: 62",
+            localizer.message(
+                "crtc-ids",
+                &[("crtc", "1".to_string()), ("winsys", "62".to_string())]
+            )
+        );
+        assert_eq!(
+            "1920x1080, freqs: [60.00, 50.00]",
+            localizer.message(
+                "frequencies",
+                &[
+                    ("resolution", "1920x1080".to_string()),
+                    ("frequencies", "60.00, 50.00".to_string())
+                ]
+            )
+        );
+        assert_eq!("disabled", localizer.message("disabled", &[]));
+        assert_eq!("missing-id", localizer.message("missing-id", &[]));
+    }
+}
@@ -0,0 +1,1859 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    apply_lock,
+    cli::{GlobalArgs, OutputArgs, ShiftArgs},
+    dbus_api::{
+        self, ApplyConfigurationArgs, ConfigSerial, CrtControllerChange, CrtcId, DisplayConfigApi,
+        GetCurrentStateReturn, GetResourcesReturn, ModeId, OutputChange, OutputId,
+        OutputProperties,
+    },
+    link,
+    mode_db::{ModeDb, Resolution},
+    progress, scale, suggest, timings,
+    warnings::{self, Kind},
+};
+
+/// Position of top left corner of an output in compositor coordinate space, or a delta between
+/// two positions. Coordinates could have an explicit sign, like `+0x-1080`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{}x{}", self.x, self.y))
+    }
+}
+
+impl FromStr for Position {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        static POSITION_RE: OnceLock<Regex> = OnceLock::new();
+        let re = POSITION_RE.get_or_init(|| Regex::new(r"^([+-]?\d+)x([+-]?\d+)$").unwrap());
+        let (_, [x, y]) = re
+            .captures_iter(s)
+            .map(|c| c.extract())
+            .next()
+            .ok_or(anyhow!("wrong position format"))?;
+        let x = x
+            .parse()
+            .map_err(|_| anyhow!("could not parse position x"))?;
+        let y = y
+            .parse()
+            .map_err(|_| anyhow!("could not parse position y"))?;
+        Ok(Position { x, y })
+    }
+}
+
+/// Position in logical pixels, which differ from physical ones by output scale when fractional
+/// scaling is active. This is what GNOME Settings shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogicalPosition(pub Position);
+
+impl LogicalPosition {
+    /// Physical position on an output with `scale`, one logical pixel covers `scale` physical
+    /// ones.
+    pub fn to_physical(self, scale: f64) -> Position {
+        let scaled = |coord: i32| (coord as f64 * scale).round() as i32;
+        Position {
+            x: scaled(self.0.x),
+            y: scaled(self.0.y),
+        }
+    }
+}
+
+impl Position {
+    /// Logical position on an output with `scale`.
+    pub fn to_logical(self, scale: f64) -> LogicalPosition {
+        let scaled = |coord: i32| (coord as f64 / scale).round() as i32;
+        LogicalPosition(Position {
+            x: scaled(self.x),
+            y: scaled(self.y),
+        })
+    }
+}
+
+/// Units of positions given on the command line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Units {
+    /// CRTC pixels, as used by ApplyConfiguration
+    #[default]
+    Physical,
+    /// Pixels divided by output scale, see [`LogicalPosition`]
+    Logical,
+}
+
+impl FromStr for Units {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "physical" => Ok(Units::Physical),
+            "logical" => Ok(Units::Logical),
+            _ => Err(anyhow!("unknown units {s}, expected logical or physical")),
+        }
+    }
+}
+
+/// Connector name in a form that ignores case and separators, so `hdmi1` matches `HDMI-1`.
+pub fn normalize_connector(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '-' | '_' | ' '))
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Rectangle in compositor coordinate space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    /// Smallest rectangle containing both `self` and `other`.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+/// Where an output that is enabled without `--pos` is placed relative to already enabled
+/// outputs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// Right of the bounding box, aligned to its top, same as xrandr
+    #[default]
+    RightOf,
+    /// Above the bounding box, aligned to its left
+    Above,
+    /// Same position as top left output, mirroring it
+    Mirror,
+}
+
+impl FromStr for Placement {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "right-of" => Ok(Placement::RightOf),
+            "above" => Ok(Placement::Above),
+            "mirror" => Ok(Placement::Mirror),
+            _ => Err(anyhow!(
+                "unknown placement {s}, expected right-of, above or mirror"
+            )),
+        }
+    }
+}
+
+/// Alignment of an output placed right of the bounding box, vertically, or above it,
+/// horizontally.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    /// Top or left edges are aligned, same as xrandr
+    #[default]
+    Start,
+    /// Smaller outputs are centered against taller or wider neighbors
+    Center,
+    /// Bottom or right edges are aligned
+    End,
+}
+
+impl Align {
+    /// Offset of an output of `size` from the start of bounding box of `outer` size.
+    fn offset(self, outer: i32, size: i32) -> i32 {
+        match self {
+            Align::Start => 0,
+            Align::Center => (outer - size) / 2,
+            Align::End => outer - size,
+        }
+    }
+}
+
+impl FromStr for Align {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "start" => Ok(Align::Start),
+            "center" => Ok(Align::Center),
+            "end" => Ok(Align::End),
+            _ => Err(anyhow!(
+                "unknown alignment {s}, expected start, center or end"
+            )),
+        }
+    }
+}
+
+/// Result of [`Layout::mirror_group`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MirrorGroup {
+    /// Resolution shared by the group, the largest one every member supports if there is one
+    pub resolution: Resolution,
+    /// Members that don't support the shared resolution, with the closest one they use instead
+    pub deviations: Vec<(String, Resolution)>,
+}
+
+/// Desired configuration of CRTCs, built from current state and then modified by every
+/// `--output` from CLI.
+///
+/// ApplyConfiguration replaces the whole configuration at once, so all outputs are validated
+/// while building a layout and sent with a single call. Any error leaves configuration untouched.
+///
+/// Layout always keeps a primary output if any output is enabled: when current primary is
+/// disabled, left-most enabled output is promoted, unless another output is set as primary.
+///
+/// Outputs enabled without `--pos` are placed according to [`Placement`] and [`Align`], so they
+/// don't overlap already enabled ones. Rotating an output moves outputs right of or below it to
+/// stay adjacent, as its sides swap.
+#[derive(Debug, Clone)]
+pub struct Layout<'a> {
+    resources: &'a GetResourcesReturn,
+    crtcs: Vec<CrtControllerChange>,
+    /// Output requested to be primary with `--primary`
+    primary: Option<OutputId>,
+    /// Presentation flags set with [`Layout::set_presentation`]
+    presentation: HashMap<OutputId, bool>,
+    placement: Placement,
+    align: Align,
+    /// Pixels between a placed output and the bounding box, negative to overlap
+    gap: i32,
+    /// Scale of every enabled connector if positions are given in logical units
+    scales: Option<HashMap<String, f64>>,
+}
+
+impl<'a> Layout<'a> {
+    /// Creates a layout matching current configuration.
+    pub fn new(resources: &'a GetResourcesReturn) -> Self {
+        let mut outputs_by_crtc = resources.outputs_by_crtc();
+        let crtcs = resources
+            .crtcs
+            .iter()
+            .filter(|crtc| crtc.mode_id.is_some())
+            .map(|crtc| CrtControllerChange {
+                id: crtc.id,
+                mode_id: crtc.mode_id,
+                x: crtc.x,
+                y: crtc.y,
+                transform: crtc.transform.into(),
+                output_ids: outputs_by_crtc
+                    .remove(&crtc.id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|output| output.id)
+                    .collect(),
+            })
+            .collect();
+        Self {
+            resources,
+            crtcs,
+            primary: None,
+            presentation: HashMap::new(),
+            placement: Placement::default(),
+            align: Align::default(),
+            gap: 0,
+            scales: None,
+        }
+    }
+
+    /// Sets placement of outputs enabled without a position.
+    pub fn with_placement(mut self, placement: Placement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    /// Sets alignment of outputs enabled without a position.
+    pub fn with_align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Leaves `gap` pixels between outputs placed right of or above others, to compensate for
+    /// bezels. Negative gap makes them overlap instead.
+    pub fn with_gap(mut self, gap: i32) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    /// Treats positions given to [`Layout::apply`] and [`Layout::shift`] as logical, scaled by
+    /// output scale from `scales`. Outputs missing from `scales`, like disabled ones, use scale 1.
+    pub fn with_logical_units(mut self, scales: HashMap<String, f64>) -> Self {
+        self.scales = Some(scales);
+        self
+    }
+
+    /// Scale of an output if logical units are used.
+    fn scale(&self, output: &dbus_api::Output) -> Option<f64> {
+        let scales = self.scales.as_ref()?;
+        Some(scales.get(&output.connector_name).copied().unwrap_or(1.0))
+    }
+
+    /// Finds an output by its connector name, like `HDMI-1`.
+    ///
+    /// Names are also matched ignoring case and separators, like `hdmi1` for `HDMI-1`, as long as
+    /// only one output matches. `winsys:<ID>` selects an output by its low-level ID, like a KMS
+    /// connector ID, and `%<INDEX>` by its position among enabled outputs in listings, starting
+    /// from 0 like `xrandr --listmonitors`.
+    pub fn output(&self, name: &str) -> anyhow::Result<&'a dbus_api::Output> {
+        let outputs = &self.resources.outputs;
+        if let Some(winsys_id) = name.strip_prefix("winsys:") {
+            let winsys_id: i64 = winsys_id
+                .parse()
+                .map_err(|_| anyhow!("invalid winsys ID in {name}"))?;
+            return outputs
+                .iter()
+                .find(|output| output.winsys_id == winsys_id)
+                .ok_or_else(|| anyhow!("no output with winsys ID {winsys_id}"));
+        }
+        if let Some(index) = name.strip_prefix('%') {
+            let index: usize = index
+                .parse()
+                .map_err(|_| anyhow!("invalid output index in {name}"))?;
+            let enabled = outputs.iter().filter(|output| output.crtc_id.is_some());
+            return enabled.clone().nth(index).ok_or_else(|| {
+                anyhow!(
+                    "no output with index {index}, there are {} enabled outputs",
+                    enabled.count()
+                )
+            });
+        }
+        if let Some(output) = outputs.iter().find(|output| output.connector_name == name) {
+            return Ok(output);
+        }
+        let normalized = normalize_connector(name);
+        let mut matching = outputs
+            .iter()
+            .filter(|output| normalize_connector(&output.connector_name) == normalized);
+        match (matching.next(), matching.next()) {
+            (Some(output), None) => Ok(output),
+            (Some(first), Some(second)) => Err(anyhow!(
+                "output {name} is ambiguous, it could be {} or {}",
+                first.connector_name,
+                second.connector_name
+            )),
+            _ => Err(anyhow!(suggest::did_you_mean(
+                format!("output {name} not found"),
+                name,
+                outputs.iter().map(|output| output.connector_name.as_str()),
+                "",
+            ))),
+        }
+    }
+
+    fn output_by_id(&self, id: OutputId) -> &'a dbus_api::Output {
+        self.resources
+            .output_by_id(id)
+            .expect("Output ids come from the same resources")
+    }
+
+    /// CRTC that drives given output in this layout.
+    pub fn crtc_of(&self, output: OutputId) -> Option<&CrtControllerChange> {
+        self.crtcs
+            .iter()
+            .find(|crtc| crtc.output_ids.contains(&output))
+    }
+
+    /// Applies options of a single `--output`.
+    pub fn apply(&mut self, args: &OutputArgs) -> anyhow::Result<()> {
+        let output = self.output(&args.name)?;
+        let resources = self.resources;
+        if args.primary {
+            if let Some(primary) = self.primary.filter(|&id| id != output.id) {
+                let primary = self.output_by_id(primary);
+                return Err(anyhow!(
+                    "--primary is set for both {} and {}",
+                    primary.connector_name,
+                    output.connector_name
+                ));
+            }
+            self.primary = Some(output.id);
+        }
+        if args.off {
+            let shared: Vec<_> = (self.crtc_of(output.id).into_iter())
+                .flat_map(|crtc| &crtc.output_ids)
+                .filter(|&&id| id != output.id)
+                .map(|&id| self.output_by_id(id).connector_name.as_str())
+                .collect();
+            if !shared.is_empty() {
+                warnings::warn(
+                    Kind::Clone,
+                    format!(
+                        "{} shares its CRTC with {}, turning it off changes the shared CRTC",
+                        output.connector_name,
+                        shared.join(", ")
+                    ),
+                );
+            }
+            self.disable(output.id);
+            return Ok(());
+        }
+        let scale = self.scale(output);
+        let position = args.position.map(|position| match scale {
+            Some(scale) => LogicalPosition(position).to_physical(scale),
+            None => position,
+        });
+        let current_mode = self.crtc_of(output.id).and_then(|crtc| crtc.mode_id);
+        let mode_id = self
+            .select_mode(output, args, current_mode)
+            .with_context(|| format!("output {}", args.name))?;
+
+        let crtc_idx = self
+            .crtcs
+            .iter()
+            .position(|crtc| crtc.output_ids.contains(&output.id));
+        match (crtc_idx, mode_id) {
+            (Some(idx), mode_id) => {
+                let old_rect = self.crtc_rect(&self.crtcs[idx]);
+                let crtc = &mut self.crtcs[idx];
+                if let Some(mode_id) = mode_id {
+                    crtc.mode_id = Some(mode_id);
+                }
+                if let Some(transform) = args.rotate {
+                    check_transform(resources, output, crtc.id, transform)?;
+                    crtc.transform = transform.into();
+                }
+                if let Some(position) = position {
+                    (crtc.x, crtc.y) = (position.x, position.y);
+                }
+                if args.rotate.is_some() && position.is_none() {
+                    let new_rect = self.crtc_rect(&self.crtcs[idx]);
+                    self.keep_adjacent(idx, old_rect, new_rect);
+                }
+            }
+            (None, Some(mode_id)) => {
+                let id = self.free_crtc(output)?;
+                if let Some(transform) = args.rotate {
+                    check_transform(resources, output, id, transform)?;
+                }
+                let mut crtc = CrtControllerChange {
+                    id,
+                    mode_id: Some(mode_id),
+                    x: 0,
+                    y: 0,
+                    transform: args.rotate.unwrap_or_default().into(),
+                    output_ids: vec![output.id],
+                };
+                match position {
+                    Some(position) => {
+                        (crtc.x, crtc.y) = (position.x, position.y);
+                        self.crtcs.push(crtc);
+                    }
+                    None => {
+                        self.place(&mut crtc);
+                        self.crtcs.push(crtc);
+                        self.normalize();
+                    }
+                }
+            }
+            (None, None) if position.is_some() || args.rotate.is_some() => {
+                return Err(anyhow!(
+                    "output {} is disabled, --mode or --auto is required",
+                    args.name
+                ));
+            }
+            (None, None) => {}
+        }
+        Ok(())
+    }
+
+    /// Area taken by a CRTC, accounting for rotation.
+    pub fn crtc_rect(&self, crtc: &CrtControllerChange) -> Rect {
+        let (width, height) = crtc
+            .mode_id
+            .and_then(|id| self.resources.mode_by_id(id))
+            .map(|mode| (mode.width as i32, mode.height as i32))
+            .unwrap_or_default();
+        // Odd transforms are rotated by 90 or 270 degrees
+        let (width, height) = if crtc.transform % 2 == 1 {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        Rect {
+            x: crtc.x,
+            y: crtc.y,
+            width,
+            height,
+        }
+    }
+
+    /// Bounding box of all enabled outputs, `None` if every output is disabled.
+    pub fn bounds(&self) -> Option<Rect> {
+        self.crtcs
+            .iter()
+            .map(|crtc| self.crtc_rect(crtc))
+            .reduce(|l, r| l.union(&r))
+    }
+
+    /// Positions a newly enabled CRTC according to placement, might result in negative
+    /// coordinates.
+    fn place(&self, crtc: &mut CrtControllerChange) {
+        let Some(bounds) = self.bounds() else {
+            return;
+        };
+        let rect = self.crtc_rect(crtc);
+        (crtc.x, crtc.y) = match self.placement {
+            Placement::RightOf => (
+                bounds.x + bounds.width + self.gap,
+                bounds.y + self.align.offset(bounds.height, rect.height),
+            ),
+            Placement::Above => (
+                bounds.x + self.align.offset(bounds.width, rect.width),
+                bounds.y - rect.height - self.gap,
+            ),
+            Placement::Mirror => (bounds.x, bounds.y),
+        };
+    }
+
+    /// Moves CRTCs right of or below `old` rectangle of CRTC `idx` by the change of its size, so
+    /// they stay adjacent to `new` one.
+    fn keep_adjacent(&mut self, idx: usize, old: Rect, new: Rect) {
+        for (other_idx, crtc) in self.crtcs.iter_mut().enumerate() {
+            if other_idx == idx {
+                continue;
+            }
+            if old.x + old.width <= crtc.x {
+                crtc.x += new.width - old.width;
+            }
+            if old.y + old.height <= crtc.y {
+                crtc.y += new.height - old.height;
+            }
+        }
+    }
+
+    /// Moves every CRTC by the same delta, so none of them has negative coordinates.
+    fn normalize(&mut self) {
+        let Some(bounds) = self.bounds() else {
+            return;
+        };
+        let (dx, dy) = ((-bounds.x).max(0), (-bounds.y).max(0));
+        for crtc in &mut self.crtcs {
+            crtc.x += dx;
+            crtc.y += dy;
+        }
+    }
+
+    /// Sets transform of an enabled output, as defined by the wayland protocol.
+    pub fn set_transform(&mut self, name: &str, transform: u32) -> anyhow::Result<()> {
+        let output = self.output(name)?;
+        let crtc = self
+            .crtcs
+            .iter_mut()
+            .find(|crtc| crtc.output_ids.contains(&output.id))
+            .ok_or_else(|| anyhow!("output {name} is disabled"))?;
+        crtc.transform = transform;
+        Ok(())
+    }
+
+    /// Moves named outputs by `by`, keeping their relative arrangement. If any output ends up
+    /// with negative coordinates, the whole layout is moved to compensate.
+    pub fn shift(&mut self, names: &[String], by: Position) -> anyhow::Result<()> {
+        let mut crtc_ids = Vec::new();
+        let mut group_scales = Vec::new();
+        for name in names {
+            let output = self.output(name)?;
+            group_scales.extend(self.scale(output));
+            let crtc = self
+                .crtc_of(output.id)
+                .ok_or_else(|| anyhow!("output {name} is disabled"))?;
+            // Cloned outputs share a CRTC, which should only move once
+            if !crtc_ids.contains(&crtc.id) {
+                crtc_ids.push(crtc.id);
+            }
+        }
+        // Moving outputs by different physical deltas would break their arrangement
+        let by = match group_scales.split_first() {
+            Some((&scale, others)) => {
+                if others.iter().any(|&other| other != scale) {
+                    return Err(anyhow!(
+                        "shifted outputs have different scales, use --units physical"
+                    ));
+                }
+                LogicalPosition(by).to_physical(scale)
+            }
+            None => by,
+        };
+        for crtc in &mut self.crtcs {
+            if crtc_ids.contains(&crtc.id) {
+                crtc.x += by.x;
+                crtc.y += by.y;
+            }
+        }
+        self.normalize();
+        Ok(())
+    }
+
+    /// Removes output from its CRTC, CRTC is disabled if it has no outputs left.
+    fn disable(&mut self, output: OutputId) {
+        for crtc in &mut self.crtcs {
+            crtc.output_ids.retain(|&id| id != output);
+        }
+        self.crtcs.retain(|crtc| !crtc.output_ids.is_empty());
+    }
+
+    /// First CRTC that could drive given output and isn't used by this layout.
+    fn free_crtc(&self, output: &dbus_api::Output) -> anyhow::Result<CrtcId> {
+        output
+            .possible_crtc_ids
+            .iter()
+            .copied()
+            .find(|id| self.crtcs.iter().all(|crtc| crtc.id != *id))
+            .ok_or_else(|| anyhow!("no free CRTC to enable output {}", output.connector_name))
+    }
+
+    /// Modes supported by output.
+    fn mode_db(&self, output: &dbus_api::Output) -> ModeDb {
+        let supported: HashSet<_> = output.mode_ids.iter().collect();
+        let modes = self
+            .resources
+            .modes
+            .iter()
+            .filter(|mode| supported.contains(&mode.id));
+        timings::measure("ModeDb", || ModeDb::new(modes))
+    }
+
+    /// Picks a mode requested by `args` among modes supported by output, `None` if mode should
+    /// not change.
+    fn select_mode(
+        &self,
+        output: &dbus_api::Output,
+        args: &OutputArgs,
+        current_mode: Option<ModeId>,
+    ) -> anyhow::Result<Option<ModeId>> {
+        if let Some(mode_id) = args.mode_id {
+            if !output.mode_ids.contains(&mode_id) {
+                let ids: Vec<_> = output.mode_ids.iter().map(ModeId::to_string).collect();
+                return Err(anyhow!(
+                    "mode ID {mode_id} is not supported, supported IDs: {}",
+                    ids.join(", ")
+                ));
+            }
+            return Ok(Some(mode_id));
+        }
+        let mode_db = self.mode_db(output);
+
+        let res = match (&args.resolution, args.auto, args.framerate) {
+            (Some(res), _, _) => *res,
+            (None, true, _) => mode_db
+                .resolutions()
+                .next()
+                .ok_or_else(|| anyhow!("no supported modes"))?,
+            (None, false, Some(_)) => {
+                let mode = current_mode
+                    .filter(|id| output.mode_ids.contains(id))
+                    .and_then(|id| self.resources.mode_by_id(id))
+                    .ok_or_else(|| anyhow!("output is disabled, --mode is required"))?;
+                Resolution {
+                    width: mode.width,
+                    height: mode.height,
+                }
+            }
+            (None, false, None) => return Ok(None),
+        };
+
+        let freqs = mode_db
+            .frequencies(&res)
+            .ok_or_else(|| anyhow!("resolution {res} is not supported"))?;
+        let frequency = match args.framerate {
+            Some(frequency) if freqs.contains(&frequency) => frequency,
+            Some(frequency) => {
+                return Err(anyhow!(
+                    "{frequency}Hz is not supported for {res}, supported: {freqs:?}"
+                ))
+            }
+            None => freqs[0],
+        };
+        let mode = mode_db
+            .find(&res, frequency)
+            .expect("Frequency is supported, mode should exist");
+        Ok(Some(mode_db.id(mode)))
+    }
+
+    /// Fails if layout has no enabled outputs, since a machine without a display could be hard
+    /// to recover. `force` skips the check.
+    pub fn check_not_empty(&self, force: bool) -> anyhow::Result<()> {
+        if force || !self.crtcs.is_empty() {
+            return Ok(());
+        }
+        let enabled: Vec<_> = self
+            .resources
+            .outputs
+            .iter()
+            .filter(|output| output.crtc_id.is_some())
+            .map(|output| output.connector_name.as_str())
+            .collect();
+        Err(anyhow!(
+            "refusing to disable every output, none of {} would remain enabled; use --force to apply anyway",
+            enabled.join(", ")
+        ))
+    }
+
+    /// Refuses modes that likely exceed link bandwidth of their outputs, since they often result
+    /// in a black screen. Only changed modes are checked, current ones evidently work. `force`
+    /// skips the check.
+    pub fn check_link_bandwidth(&self, force: bool) -> anyhow::Result<()> {
+        if force {
+            return Ok(());
+        }
+        let mut exceeded = Vec::new();
+        for crtc in &self.crtcs {
+            let current = self
+                .resources
+                .crtc_by_id(crtc.id)
+                .and_then(|current| current.mode_id);
+            let Some(mode) = crtc
+                .mode_id
+                .filter(|&id| Some(id) != current)
+                .and_then(|id| self.resources.mode_by_id(id))
+            else {
+                continue;
+            };
+            for &output in &crtc.output_ids {
+                exceeded.extend(link::check(self.output_by_id(output), mode));
+            }
+        }
+        if exceeded.is_empty() {
+            return Ok(());
+        }
+        let reasons: Vec<_> = exceeded.iter().map(ToString::to_string).collect();
+        Err(anyhow!(
+            "{}; use --force to apply anyway",
+            reasons.join("; ")
+        ))
+    }
+
+    /// Output that is going to be primary after this layout is applied.
+    ///
+    /// Output set with `--primary` is used first, then current primary if it stays enabled and
+    /// finally the left-most enabled output, top-most if there are several.
+    pub fn primary(&self) -> Option<OutputId> {
+        if self.primary.is_some() {
+            return self.primary;
+        }
+        let current = self
+            .resources
+            .outputs
+            .iter()
+            .find(|output| output.props.primary == Some(true))
+            .map(|output| output.id);
+        if let Some(current) = current.filter(|&id| self.crtc_of(id).is_some()) {
+            return Some(current);
+        }
+        self.crtcs
+            .iter()
+            .min_by_key(|crtc| (crtc.x, crtc.y))
+            .and_then(|crtc| crtc.output_ids.first().copied())
+    }
+
+    /// Makes an enabled output primary, replacing output set as primary before.
+    pub fn set_primary(&mut self, name: &str) -> anyhow::Result<()> {
+        let output = self.output(name)?;
+        if self.crtc_of(output.id).is_none() {
+            return Err(anyhow!("output {name} is disabled"));
+        }
+        self.primary = Some(output.id);
+        Ok(())
+    }
+
+    /// Whether an output is going to be for presentation only after this layout is applied.
+    pub fn presentation(&self, name: &str) -> anyhow::Result<bool> {
+        let output = self.output(name)?;
+        Ok(match self.presentation.get(&output.id) {
+            Some(&presentation) => presentation,
+            None => output.props.presentation == Some(true),
+        })
+    }
+
+    /// Marks an output as presentation only, or clears the mark.
+    pub fn set_presentation(&mut self, name: &str, presentation: bool) -> anyhow::Result<()> {
+        let output = self.output(name)?;
+        self.presentation.insert(output.id, presentation);
+        Ok(())
+    }
+
+    /// Makes named outputs show the same area, using the largest resolution all of them support.
+    /// Members without a common resolution use the closest one they support and are reported
+    /// in [`MirrorGroup::deviations`].
+    ///
+    /// Members are placed where the first one is if it's enabled, or at the top left corner of
+    /// the layout otherwise. Each member keeps its own CRTC and transform.
+    pub fn mirror_group(&mut self, names: &[&str]) -> anyhow::Result<MirrorGroup> {
+        let mut outputs: Vec<&dbus_api::Output> = Vec::new();
+        for name in names {
+            let output = self.output(name)?;
+            if outputs.iter().any(|other| other.id == output.id) {
+                return Err(anyhow!(
+                    "output {} is in the mirror group twice",
+                    output.connector_name
+                ));
+            }
+            outputs.push(output);
+        }
+        if outputs.len() < 2 {
+            return Err(anyhow!("a mirror group needs at least two outputs"));
+        }
+        let mode_dbs: Vec<_> = outputs.iter().map(|output| self.mode_db(output)).collect();
+        let area = |res: &Resolution| res.width as u64 * res.height as u64;
+        let common = mode_dbs[0]
+            .resolutions()
+            .filter(|res| mode_dbs[1..].iter().all(|db| db.frequencies(res).is_some()))
+            .max_by_key(area);
+        // Without a common resolution, the member with the smallest maximum sets it
+        let resolution = match common {
+            Some(res) => res,
+            None => mode_dbs
+                .iter()
+                .filter_map(|db| db.resolutions().max_by_key(area))
+                .min_by_key(area)
+                .ok_or_else(|| anyhow!("no supported modes"))?,
+        };
+
+        let position = match self.crtc_of(outputs[0].id) {
+            Some(crtc) => (crtc.x, crtc.y),
+            None => self.bounds().map_or((0, 0), |bounds| (bounds.x, bounds.y)),
+        };
+        let mut deviations = Vec::new();
+        for (output, db) in outputs.iter().zip(&mode_dbs) {
+            let distance = |res: &Resolution| {
+                res.width.abs_diff(resolution.width) + res.height.abs_diff(resolution.height)
+            };
+            let res = db.resolutions().min_by_key(distance).ok_or_else(|| {
+                anyhow!("output {} has no supported modes", output.connector_name)
+            })?;
+            if res != resolution {
+                deviations.push((output.connector_name.clone(), res));
+            }
+            let freqs = db
+                .frequencies(&res)
+                .expect("Resolution comes from the same db");
+            let mode_id = db.id(db.find(&res, freqs[0]).expect("Frequency is supported"));
+            let crtc = match self
+                .crtcs
+                .iter_mut()
+                .find(|crtc| crtc.output_ids.contains(&output.id))
+            {
+                Some(crtc) => crtc,
+                None => {
+                    let id = self.free_crtc(output)?;
+                    self.crtcs.push(CrtControllerChange {
+                        id,
+                        mode_id: None,
+                        x: 0,
+                        y: 0,
+                        transform: dbus_api::Transform::Normal.into(),
+                        output_ids: vec![output.id],
+                    });
+                    self.crtcs.last_mut().expect("CRTC was just pushed")
+                }
+            };
+            crtc.mode_id = Some(mode_id);
+            (crtc.x, crtc.y) = position;
+        }
+        Ok(MirrorGroup {
+            resolution,
+            deviations,
+        })
+    }
+
+    /// Property changes of outputs, which move primary flag if primary output changes and set
+    /// presentation flags. Only changed keys are included.
+    fn output_changes(&self) -> Vec<OutputChange> {
+        let primary = self.primary();
+        self.resources
+            .outputs
+            .iter()
+            .filter_map(|output| {
+                let mut props = output.props.clone();
+                if let Some(primary) = primary {
+                    if (output.id == primary) != (output.props.primary == Some(true)) {
+                        props.primary = Some(output.id == primary);
+                    }
+                }
+                if let Some(&presentation) = self.presentation.get(&output.id) {
+                    props.presentation = Some(presentation);
+                }
+                let change = OutputChange::new(output.id, &props, &output.props);
+                (change.props != OutputProperties::default()).then_some(change)
+            })
+            .collect()
+    }
+
+    /// Arguments for a single ApplyConfiguration call.
+    pub fn into_config(self, persistent: bool) -> ApplyConfigurationArgs {
+        let outputs = self.output_changes();
+        ApplyConfigurationArgs::builder()
+            .serial(self.resources.serial)
+            .persistent(persistent)
+            .crtcs(self.crtcs)
+            .outputs(outputs)
+            .build()
+            .expect("All required fields are set")
+    }
+}
+
+/// Fails with a serial mismatch if configuration changed since the caller observed `expected`
+/// serial.
+pub fn check_serial(
+    resources: &GetResourcesReturn,
+    expected: Option<ConfigSerial>,
+) -> anyhow::Result<()> {
+    match expected {
+        Some(expected) if expected != resources.serial => Err(anyhow!(
+            "serial mismatch: expected {expected}, current configuration has serial {}",
+            resources.serial
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Applies all `outputs` with exactly one ApplyConfiguration call, using serial from the
+/// GetResources call the layout is based on. Nothing is sent if any output fails to apply.
+pub fn apply_outputs(
+    api: &impl DisplayConfigApi,
+    global: &GlobalArgs,
+    outputs: &[OutputArgs],
+) -> anyhow::Result<()> {
+    change_layout(api, global, |layout| {
+        // Different selectors, like a connector name and a winsys ID, could name the same output
+        let mut seen = HashMap::new();
+        for args in outputs {
+            let output = layout.output(&args.name)?;
+            if let Some(other) = seen.insert(output.id, &args.name) {
+                return Err(anyhow!(
+                    "--output {other} and --output {} are both {}",
+                    args.name,
+                    output.connector_name
+                ));
+            }
+        }
+        outputs.iter().try_for_each(|output| layout.apply(output))
+    })
+}
+
+/// Moves outputs from `args` with a single ApplyConfiguration call.
+pub fn shift_outputs(
+    api: &impl DisplayConfigApi,
+    global: &GlobalArgs,
+    args: &ShiftArgs,
+) -> anyhow::Result<()> {
+    change_layout(api, global, |layout| layout.shift(&args.outputs, args.by))
+}
+
+/// Builds a layout from current resources, changes it with `f` and applies the result.
+pub(crate) fn change_layout(
+    api: &impl DisplayConfigApi,
+    global: &GlobalArgs,
+    f: impl FnOnce(&mut Layout) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    // Held until the configuration is applied, so it's not based on resources that another
+    // invocation is about to change
+    let _lock = timings::measure("apply lock", || apply_lock::acquire(global.lock_timeout))?;
+    let resources = api.get_resources()?;
+    check_serial(&resources, global.expect_serial)?;
+    // Older Mutter versions don't have GetCurrentState, scales are only checked if it's there
+    let state = match global.units {
+        Units::Logical => Some(api.get_current_state()?),
+        Units::Physical => api.get_current_state().ok(),
+    };
+    let scales = match global.units {
+        Units::Logical => state.as_ref().map(GetCurrentStateReturn::scales),
+        Units::Physical => None,
+    };
+    let layout = timings::measure("layout", || {
+        let mut layout = Layout::new(&resources)
+            .with_placement(global.placement)
+            .with_align(global.align)
+            .with_gap(global.gap);
+        if let Some(scales) = scales {
+            layout = layout.with_logical_units(scales);
+        }
+        f(&mut layout)?;
+        layout.check_not_empty(global.force)?;
+        layout.check_link_bandwidth(global.force)?;
+        anyhow::Ok(layout)
+    })?;
+    let config = layout.into_config(false);
+    if let Some(state) = &state {
+        for warning in scale::warnings(&resources, state, &config) {
+            warnings::warn(Kind::Scale, warning);
+        }
+    }
+    match global.wait_until_stable {
+        Some(timeout) => {
+            api.apply_configuration(config.clone())?;
+            wait_until_stable(api, &config, timeout)
+        }
+        None => Ok(api.apply_configuration(config)?),
+    }
+}
+
+/// Checks that the CRTC driving `output` supports `transform`, since Mutter only reports that
+/// configuration is invalid.
+fn check_transform(
+    resources: &GetResourcesReturn,
+    output: &dbus_api::Output,
+    crtc_id: CrtcId,
+    transform: dbus_api::Transform,
+) -> anyhow::Result<()> {
+    let supported = resources
+        .crtc_by_id(crtc_id)
+        .map(|crtc| crtc.transforms.as_slice())
+        .unwrap_or_default();
+    match supported.contains(&transform.into()) {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "output {} can't be rotated {transform}, its CRTC supports {}",
+            output.connector_name,
+            match supported {
+                [] => "no transforms".to_string(),
+                _ => dbus_api::Transform::names(supported),
+            }
+        )),
+    }
+}
+
+/// Checks if `resources` were produced after `config` was applied and reflect it, so modes were
+/// actually set.
+pub fn is_latched(resources: &GetResourcesReturn, config: &ApplyConfigurationArgs) -> bool {
+    if resources.serial == config.serial {
+        return false;
+    }
+    let crtcs_match = config.crtcs.iter().all(|change| {
+        let Some(crtc) = resources.crtc_by_id(change.id) else {
+            return false;
+        };
+        crtc.mode_id == change.mode_id
+            && (change.mode_id.is_none()
+                || (crtc.x, crtc.y, u32::from(crtc.transform))
+                    == (change.x, change.y, change.transform))
+    });
+    // CRTCs not referenced in configuration are disabled, and so are their outputs
+    let mut assigned = HashMap::new();
+    for change in config
+        .crtcs
+        .iter()
+        .filter(|change| change.mode_id.is_some())
+    {
+        for &output in &change.output_ids {
+            assigned.entry(output).or_insert(change.id);
+        }
+    }
+    let outputs_match = resources.outputs.iter().all(|output| {
+        let expected = assigned.get(&output.id).copied();
+        let current = resources
+            .crtc_of(output)
+            .filter(|crtc| crtc.mode_id.is_some())
+            .map(|crtc| crtc.id);
+        expected == current
+    });
+    crtcs_match && outputs_match
+}
+
+/// Polls resources until applied `config` is reported back, or `timeout` passes.
+pub fn wait_until_stable(
+    api: &impl DisplayConfigApi,
+    config: &ApplyConfigurationArgs,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let deadline = start + timeout;
+    loop {
+        let resources = api.get_resources()?;
+        if is_latched(&resources, config) {
+            progress::report("wait until stable", 100.0);
+            return Ok(());
+        }
+        progress::report(
+            "wait until stable",
+            100.0 * start.elapsed().as_secs_f64() / timeout.as_secs_f64(),
+        );
+        if Instant::now() >= deadline {
+            return Err(anyhow!(
+                "configuration did not settle within {:.1}s, current serial is {}",
+                timeout.as_secs_f64(),
+                resources.serial
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::{
+        cli::{GlobalArgs, OutputArgsBuilder},
+        dbus_api::{
+            ChangeBacklightArgs, CrtController, CrtcGamma, LogicalMonitor, Mode, Monitor,
+            MonitorMode, MonitorSpec, Output, OutputProperties, Transform,
+        },
+    };
+
+    /// Three outputs, HDMI-1 and DP-1 are enabled, HDMI-2 is disabled. HDMI-1 is primary and
+    /// left of DP-1.
+    pub fn resources() -> GetResourcesReturn {
+        let mode = |id, width, height, frequency| Mode {
+            id: ModeId(id),
+            width,
+            height,
+            frequency,
+            ..Default::default()
+        };
+        let crtc = |id, mode_id: Option<u32>, x| CrtController {
+            id: CrtcId(id),
+            winsys_id: id.into(),
+            x,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            mode_id: mode_id.map(ModeId),
+            transform: Transform::Normal,
+            transforms: vec![0],
+        };
+        let output = |id, crtc_id: Option<u32>, name: &str, primary| Output {
+            id: OutputId(id),
+            winsys_id: id.into(),
+            crtc_id: crtc_id.map(CrtcId),
+            possible_crtc_ids: vec![CrtcId(0), CrtcId(1), CrtcId(2)],
+            connector_name: name.to_string(),
+            mode_ids: vec![ModeId(0), ModeId(1), ModeId(2)],
+            clone_ids: vec![],
+            props: OutputProperties {
+                primary: Some(primary),
+                ..Default::default()
+            },
+        };
+        GetResourcesReturn::new(
+            ConfigSerial(7),
+            vec![
+                crtc(0, Some(0), 0),
+                crtc(1, Some(0), 1920),
+                crtc(2, None, 0),
+            ],
+            vec![
+                output(0, Some(0), "HDMI-1", true),
+                output(1, Some(1), "DP-1", false),
+                output(2, None, "HDMI-2", false),
+            ],
+            vec![
+                mode(0, 1920, 1080, 60.0),
+                mode(1, 1920, 1080, 50.0),
+                mode(2, 1280, 720, 59.94),
+            ],
+            8192,
+            8192,
+        )
+    }
+
+    /// Current state matching [`resources`], HDMI-1 has scale 1 and DP-1 has scale 2.
+    pub fn current_state() -> GetCurrentStateReturn {
+        let spec = |connector: &str| MonitorSpec {
+            connector: connector.to_string(),
+            ..Default::default()
+        };
+        let monitor = |connector| Monitor {
+            spec: spec(connector),
+            modes: vec![MonitorMode {
+                id: "1920x1080@60".to_string(),
+                width: 1920,
+                height: 1080,
+                refresh_rate: 60.0,
+                preferred_scale: 1.0,
+                supported_scales: vec![1.0, 1.25, 1.5, 1.75, 2.0],
+                props: Default::default(),
+            }],
+            props: Default::default(),
+        };
+        let logical = |x, scale, primary, connector| LogicalMonitor {
+            x,
+            y: 0,
+            scale,
+            transform: Transform::Normal,
+            primary,
+            monitors: vec![spec(connector)],
+            props: Default::default(),
+        };
+        GetCurrentStateReturn {
+            serial: ConfigSerial(7),
+            monitors: vec![monitor("HDMI-1"), monitor("DP-1")],
+            logical_monitors: vec![
+                logical(0, 1.0, true, "HDMI-1"),
+                logical(1920, 2.0, false, "DP-1"),
+            ],
+            props: Default::default(),
+        }
+    }
+
+    /// Returns resources from [`resources`] and records every ApplyConfiguration call.
+    #[derive(Default)]
+    pub struct RecordingApi {
+        pub applied: RefCell<Vec<ApplyConfigurationArgs>>,
+    }
+
+    impl DisplayConfigApi for RecordingApi {
+        fn get_resources(&self) -> Result<GetResourcesReturn, dbus::Error> {
+            Ok(resources())
+        }
+
+        fn get_current_state(&self) -> Result<GetCurrentStateReturn, dbus::Error> {
+            Ok(current_state())
+        }
+
+        fn apply_configuration(&self, args: ApplyConfigurationArgs) -> Result<(), dbus::Error> {
+            self.applied.borrow_mut().push(args);
+            Ok(())
+        }
+    }
+
+    /// Like [`RecordingApi`], but records every changing call as text, like `backlight 0 80`,
+    /// and fails calls starting with `fail`. HDMI-1 has backlight at 50%, DP-1 at 70%, displays
+    /// are in power saving.
+    #[derive(Default)]
+    pub struct ScriptedApi {
+        pub calls: RefCell<Vec<String>>,
+        pub fail: RefCell<Option<String>>,
+    }
+
+    impl ScriptedApi {
+        pub fn failing(prefix: &str) -> Self {
+            let api = Self::default();
+            *api.fail.borrow_mut() = Some(prefix.to_string());
+            api
+        }
+
+        pub fn calls(&self) -> Vec<String> {
+            self.calls.borrow().clone()
+        }
+
+        fn call(&self, call: String) -> Result<(), dbus::Error> {
+            let failed = matches!(&*self.fail.borrow(), Some(prefix) if call.starts_with(prefix));
+            self.calls.borrow_mut().push(call.clone());
+            match failed {
+                true => Err(dbus::Error::new_failed(&format!("{call} failed"))),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl DisplayConfigApi for ScriptedApi {
+        fn get_resources(&self) -> Result<GetResourcesReturn, dbus::Error> {
+            let mut resources = resources();
+            resources.outputs[0].props.backlight = Some(50);
+            resources.outputs[1].props.backlight = Some(70);
+            Ok(resources)
+        }
+
+        fn get_current_state(&self) -> Result<GetCurrentStateReturn, dbus::Error> {
+            Ok(current_state())
+        }
+
+        fn apply_configuration(&self, _args: ApplyConfigurationArgs) -> Result<(), dbus::Error> {
+            self.call("apply".to_string())
+        }
+
+        fn change_backlight(&self, args: ChangeBacklightArgs) -> Result<(), dbus::Error> {
+            self.call(format!("backlight {} {}", args.output, args.value))
+        }
+
+        fn get_crtc_gamma(
+            &self,
+            _serial: ConfigSerial,
+            crtc: CrtcId,
+        ) -> Result<CrtcGamma, dbus::Error> {
+            // Ramps tell CRTCs apart
+            Ok(CrtcGamma::linear(256 + crtc.0 as usize))
+        }
+
+        fn set_crtc_gamma(
+            &self,
+            _serial: ConfigSerial,
+            crtc: CrtcId,
+            gamma: CrtcGamma,
+        ) -> Result<(), dbus::Error> {
+            let kind = match gamma == CrtcGamma::linear(gamma.len()) {
+                true => "linear",
+                false => "ramp",
+            };
+            self.call(format!("gamma {crtc} {kind} {}", gamma.len()))
+        }
+
+        fn power_save_mode(&self) -> Result<i32, dbus::Error> {
+            Ok(3)
+        }
+
+        fn set_power_save_mode(&self, value: i32) -> Result<(), dbus::Error> {
+            self.call(format!("power {value}"))
+        }
+    }
+
+    fn output_args(name: &str) -> OutputArgsBuilder {
+        let mut builder = OutputArgsBuilder::default();
+        builder.name(name);
+        builder
+    }
+
+    #[test]
+    fn outputs_applied_in_single_call() {
+        let api = RecordingApi::default();
+        let outputs = [
+            output_args("HDMI-1").framerate(50).build().unwrap(),
+            output_args("DP-1").off(true).build().unwrap(),
+            output_args("HDMI-2")
+                .resolution(Resolution {
+                    width: 1280,
+                    height: 720,
+                })
+                .build()
+                .unwrap(),
+        ];
+        apply_outputs(&api, &GlobalArgs::default(), &outputs).unwrap();
+
+        let applied = api.applied.borrow();
+        assert_eq!(1, applied.len());
+        assert_eq!(ConfigSerial(7), applied[0].serial);
+        let crtcs: Vec<_> = applied[0]
+            .crtcs
+            .iter()
+            .map(|crtc| (crtc.id, crtc.mode_id, crtc.output_ids.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (CrtcId(0), Some(ModeId(1)), vec![OutputId(0)]),
+                (CrtcId(1), Some(ModeId(2)), vec![OutputId(2)]),
+            ],
+            crtcs
+        );
+    }
+
+    #[test]
+    fn failed_output_applies_nothing() {
+        let api = RecordingApi::default();
+        let outputs = [
+            output_args("HDMI-1").auto(true).build().unwrap(),
+            output_args("HDMI-2").framerate(144).build().unwrap(),
+        ];
+        assert!(apply_outputs(&api, &GlobalArgs::default(), &outputs).is_err());
+
+        let outputs = [
+            output_args("HDMI-1").off(true).build().unwrap(),
+            output_args("VGA-1").auto(true).build().unwrap(),
+        ];
+        let err = apply_outputs(&api, &GlobalArgs::default(), &outputs).unwrap_err();
+        assert!(err.to_string().contains("VGA-1"));
+
+        assert!(api.applied.borrow().is_empty());
+    }
+
+    #[test]
+    fn last_output_requires_force() {
+        let api = RecordingApi::default();
+        let mut global = GlobalArgs::default();
+        let outputs = [
+            output_args("HDMI-1").off(true).build().unwrap(),
+            output_args("DP-1").off(true).build().unwrap(),
+        ];
+        let err = apply_outputs(&api, &global, &outputs).unwrap_err();
+        assert!(err.to_string().contains("HDMI-1, DP-1"));
+        assert!(api.applied.borrow().is_empty());
+
+        global.force = true;
+        apply_outputs(&api, &global, &outputs).unwrap();
+        assert!(api.applied.borrow()[0].crtcs.is_empty());
+    }
+
+    #[test]
+    fn serial_mismatch_applies_nothing() {
+        let api = RecordingApi::default();
+        let mut global = GlobalArgs {
+            expect_serial: Some(ConfigSerial(6)),
+            ..Default::default()
+        };
+        let outputs = [output_args("HDMI-1").auto(true).build().unwrap()];
+        let err = apply_outputs(&api, &global, &outputs).unwrap_err();
+        assert!(err.to_string().contains("serial mismatch"));
+        assert!(api.applied.borrow().is_empty());
+
+        global.expect_serial = Some(ConfigSerial(7));
+        apply_outputs(&api, &global, &outputs).unwrap();
+        assert_eq!(1, api.applied.borrow().len());
+    }
+
+    #[test]
+    fn latched_configuration() {
+        let config = Layout::new(&resources()).into_config(false);
+        let mut current = resources();
+        assert!(!is_latched(&current, &config), "serial must advance");
+        current.serial = ConfigSerial(8);
+        assert!(is_latched(&current, &config));
+
+        let mut moved = current.clone();
+        moved.crtcs[1].x = 0;
+        assert!(!is_latched(&moved, &config));
+
+        let mut enabled = current.clone();
+        enabled.crtcs[2].mode_id = Some(ModeId(2));
+        enabled.outputs[2].crtc_id = Some(CrtcId(2));
+        assert!(!is_latched(&enabled, &config));
+    }
+
+    #[test]
+    fn unstable_configuration_times_out() {
+        let api = RecordingApi::default();
+        let global = GlobalArgs {
+            wait_until_stable: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let outputs = [output_args("HDMI-1").framerate(50).build().unwrap()];
+        let err = apply_outputs(&api, &global, &outputs).unwrap_err();
+        assert!(err.to_string().contains("did not settle"));
+        assert_eq!(1, api.applied.borrow().len());
+    }
+
+    #[test]
+    fn logical_positions_scaled() {
+        let api = RecordingApi::default();
+        let global = GlobalArgs {
+            units: Units::Logical,
+            ..Default::default()
+        };
+        let outputs = [output_args("DP-1")
+            .position(Position { x: 1000, y: 100 })
+            .build()
+            .unwrap()];
+        apply_outputs(&api, &global, &outputs).unwrap();
+        let crtc = api.applied.borrow()[0]
+            .crtcs
+            .iter()
+            .find(|crtc| crtc.id == CrtcId(1))
+            .map(|crtc| (crtc.x, crtc.y));
+        assert_eq!(Some((2000, 200)), crtc);
+
+        let shift = ShiftArgs {
+            outputs: vec!["DP-1".to_string(), "HDMI-1".to_string()],
+            by: Position { x: 0, y: 100 },
+        };
+        let err = shift_outputs(&api, &global, &shift).unwrap_err();
+        assert!(err.to_string().contains("different scales"));
+        assert_eq!(
+            Position { x: 960, y: 540 },
+            Position { x: 1920, y: 1080 }.to_logical(2.0).0
+        );
+    }
+
+    #[test]
+    fn shifted_outputs_keep_arrangement() {
+        let resources = resources();
+        let positions = |layout: &Layout| {
+            layout
+                .crtcs
+                .iter()
+                .map(|crtc| (crtc.x, crtc.y))
+                .collect::<Vec<_>>()
+        };
+
+        let mut layout = Layout::new(&resources);
+        layout
+            .shift(&["DP-1".to_string()], "+100x+50".parse().unwrap())
+            .unwrap();
+        assert_eq!(vec![(0, 0), (2020, 50)], positions(&layout));
+
+        // Moving both outputs up keeps them on the top edge
+        let mut layout = Layout::new(&resources);
+        let both = ["HDMI-1".to_string(), "DP-1".to_string()];
+        layout.shift(&both, "+0x-1080".parse().unwrap()).unwrap();
+        assert_eq!(vec![(0, 0), (1920, 0)], positions(&layout));
+
+        let mut layout = Layout::new(&resources);
+        let err = layout
+            .shift(&["HDMI-2".to_string()], "0x0".parse().unwrap())
+            .unwrap_err();
+        assert!(err.to_string().contains("disabled"));
+    }
+
+    #[test]
+    fn rotation_checked() {
+        let mut resources = resources();
+        resources.crtcs[0].transforms = vec![0, 1, 2, 3];
+        let mut layout = Layout::new(&resources);
+        layout
+            .apply(
+                &output_args("HDMI-1")
+                    .rotate(Transform::Normal90)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        let crtc = layout.crtc_of(OutputId(0)).unwrap();
+        assert_eq!(1, crtc.transform);
+
+        let err = layout
+            .apply(
+                &output_args("HDMI-1")
+                    .rotate(Transform::Flipped)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            "output HDMI-1 can't be rotated flipped, its CRTC supports normal, left, inverted, right",
+            err.to_string()
+        );
+        let err = layout
+            .apply(
+                &output_args("DP-1")
+                    .rotate(Transform::Normal90)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("its CRTC supports normal"));
+    }
+
+    #[test]
+    fn normalized_names_matched() {
+        let resources = resources();
+        let layout = Layout::new(&resources);
+        assert_eq!(OutputId(0), layout.output("hdmi1").unwrap().id);
+        assert_eq!(OutputId(1), layout.output("dp_1").unwrap().id);
+        assert!(layout.output("hdmi3").is_err());
+        let err = layout.output("HMDI-2").unwrap_err();
+        assert_eq!(
+            "output HMDI-2 not found, did you mean HDMI-2?",
+            err.to_string()
+        );
+        let err = layout.output("VGA-1").unwrap_err();
+        assert_eq!("output VGA-1 not found", err.to_string());
+        assert_eq!(OutputId(2), layout.output("winsys:2").unwrap().id);
+        assert!(layout.output("winsys:7").is_err());
+        assert_eq!(OutputId(1), layout.output("%1").unwrap().id);
+        assert!(layout.output("%2").is_err());
+        assert!(layout.output("%x").is_err());
+
+        let outputs = [
+            output_args("winsys:1").auto(true).build().unwrap(),
+            output_args("DP-1").off(true).build().unwrap(),
+        ];
+        let err = apply_outputs(&RecordingApi::default(), &GlobalArgs::default(), &outputs);
+        assert!(err.is_err_and(|err| err.to_string().contains("are both DP-1")));
+    }
+
+    #[test]
+    fn index_skips_disabled_outputs() {
+        let mut resources = resources();
+        resources.outputs[0].crtc_id = None;
+        resources.outputs[2].crtc_id = Some(CrtcId(2));
+        let layout = Layout::new(&resources);
+        assert_eq!(OutputId(1), layout.output("%0").unwrap().id);
+        assert_eq!(OutputId(2), layout.output("%1").unwrap().id);
+        let err = layout.output("%2").unwrap_err();
+        assert_eq!(
+            "no output with index 2, there are 2 enabled outputs",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn enabled_output_is_placed() {
+        let resources = resources();
+        let hdmi2 = || output_args("HDMI-2").auto(true).build().unwrap();
+        let position = |placement| {
+            let mut layout = Layout::new(&resources).with_placement(placement);
+            layout.apply(&hdmi2()).unwrap();
+            layout
+                .crtcs
+                .iter()
+                .map(|crtc| (crtc.x, crtc.y))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![(0, 0), (1920, 0), (3840, 0)],
+            position(Placement::RightOf)
+        );
+        assert_eq!(
+            vec![(0, 1080), (1920, 1080), (0, 0)],
+            position(Placement::Above)
+        );
+        assert_eq!(vec![(0, 0), (1920, 0), (0, 0)], position(Placement::Mirror));
+
+        let mut layout = Layout::new(&resources).with_gap(-10);
+        layout.apply(&hdmi2()).unwrap();
+        assert_eq!((3830, 0), (layout.crtcs[2].x, layout.crtcs[2].y));
+        let mut layout = Layout::new(&resources)
+            .with_placement(Placement::Above)
+            .with_gap(40);
+        layout.apply(&hdmi2()).unwrap();
+        assert_eq!((0, 1120), (layout.crtcs[0].x, layout.crtcs[0].y));
+
+        let mut layout = Layout::new(&resources);
+        layout
+            .apply(
+                &output_args("HDMI-2")
+                    .auto(true)
+                    .position("100x-200".parse().unwrap())
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!((100, -200), (layout.crtcs[2].x, layout.crtcs[2].y));
+    }
+
+    #[test]
+    fn unsupported_framerate() {
+        let resources = resources();
+        let mut layout = Layout::new(&resources);
+        let err = layout
+            .apply(&output_args("HDMI-1").framerate(144).build().unwrap())
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("144Hz is not supported for 1920x1080"));
+    }
+
+    #[test]
+    fn mode_picked_by_id() {
+        let mut resources = resources();
+        resources.outputs[0].mode_ids = vec![ModeId(1), ModeId(2)];
+        let mut layout = Layout::new(&resources);
+        layout
+            .apply(&output_args("HDMI-1").mode_id(ModeId(1)).build().unwrap())
+            .unwrap();
+        assert_eq!(Some(ModeId(1)), layout.crtcs[0].mode_id);
+        let err = layout
+            .apply(&output_args("HDMI-1").mode_id(ModeId(0)).build().unwrap())
+            .unwrap_err();
+        assert_eq!(
+            "output HDMI-1: mode ID 0 is not supported, supported IDs: 1, 2",
+            format!("{err:#}")
+        );
+    }
+
+    fn primary_changes(config: &ApplyConfigurationArgs) -> Vec<(OutputId, Option<bool>)> {
+        config
+            .outputs
+            .iter()
+            .map(|change| (change.id, change.props.primary))
+            .collect()
+    }
+
+    #[test]
+    fn pivoted_outputs_packed() {
+        let mut resources = resources();
+        resources.crtcs[0].transforms = vec![0, 1, 2, 3];
+        let mut layout = Layout::new(&resources).with_align(Align::Center);
+        layout
+            .apply(
+                &output_args("HDMI-1")
+                    .rotate(Transform::Normal90)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        // DP-1 stays adjacent to pivoted HDMI-1
+        assert_eq!((1080, 0), (layout.crtcs[1].x, layout.crtcs[1].y));
+
+        layout
+            .apply(&output_args("HDMI-2").auto(true).build().unwrap())
+            .unwrap();
+        assert_eq!((3000, 420), (layout.crtcs[2].x, layout.crtcs[2].y));
+        assert_eq!(
+            Some(Rect {
+                x: 0,
+                y: 0,
+                width: 4920,
+                height: 1920
+            }),
+            layout.bounds()
+        );
+    }
+
+    #[test]
+    fn presentation_and_primary_set() {
+        let resources = resources();
+        let mut layout = Layout::new(&resources);
+        layout.set_presentation("DP-1", true).unwrap();
+        assert!(layout.presentation("DP-1").unwrap());
+        assert!(!layout.presentation("HDMI-1").unwrap());
+        assert!(layout.set_primary("HDMI-2").is_err());
+        layout.set_primary("DP-1").unwrap();
+        assert_eq!(Some(OutputId(1)), layout.primary());
+
+        let config = layout.into_config(false);
+        let props = |id| {
+            config
+                .outputs
+                .iter()
+                .find(|output| output.id == OutputId(id))
+                .map(|output| output.props.clone())
+        };
+        assert_eq!(
+            Some(OutputProperties {
+                primary: Some(false),
+                ..Default::default()
+            }),
+            props(0)
+        );
+        assert_eq!(
+            Some(OutputProperties {
+                primary: Some(true),
+                presentation: Some(true),
+                ..Default::default()
+            }),
+            props(1)
+        );
+        assert_eq!(None, props(2));
+    }
+
+    #[test]
+    fn mirror_group_uses_common_resolution() {
+        let mut resources = resources();
+        // DP-1 supports only 1280x720, HDMI-2 has a 1920x1080 and a 1280x720 mode
+        resources.outputs[1].mode_ids = vec![ModeId(2)];
+        let mut layout = Layout::new(&resources);
+        let group = layout.mirror_group(&["HDMI-1", "DP-1", "HDMI-2"]).unwrap();
+        assert_eq!(
+            MirrorGroup {
+                resolution: Resolution {
+                    width: 1280,
+                    height: 720
+                },
+                deviations: vec![],
+            },
+            group
+        );
+        let crtcs: Vec<_> = (layout.crtcs.iter())
+            .map(|crtc| (crtc.id, crtc.mode_id, crtc.x, crtc.y))
+            .collect();
+        assert_eq!(
+            vec![
+                (CrtcId(0), Some(ModeId(2)), 0, 0),
+                (CrtcId(1), Some(ModeId(2)), 0, 0),
+                (CrtcId(2), Some(ModeId(2)), 0, 0),
+            ],
+            crtcs
+        );
+
+        // Without a common resolution, members keep the closest one
+        resources.outputs[0].mode_ids = vec![ModeId(0)];
+        let mut layout = Layout::new(&resources);
+        let group = layout.mirror_group(&["DP-1", "HDMI-1"]).unwrap();
+        assert_eq!(
+            vec![(
+                "HDMI-1".to_string(),
+                Resolution {
+                    width: 1920,
+                    height: 1080
+                }
+            )],
+            group.deviations
+        );
+        assert!(layout.mirror_group(&["DP-1", "dp1"]).is_err());
+        assert!(layout.mirror_group(&["DP-1"]).is_err());
+    }
+
+    #[test]
+    fn disabled_primary_falls_back_to_left_most() {
+        let resources = resources();
+        let mut layout = Layout::new(&resources);
+        layout
+            .apply(&output_args("HDMI-1").off(true).build().unwrap())
+            .unwrap();
+        let config = layout.into_config(false);
+        assert_eq!(
+            vec![(OutputId(0), Some(false)), (OutputId(1), Some(true))],
+            primary_changes(&config)
+        );
+    }
+
+    #[test]
+    fn explicit_primary_overrides_fallback() {
+        let resources = resources();
+        let mut layout = Layout::new(&resources);
+        layout
+            .apply(&output_args("HDMI-1").off(true).build().unwrap())
+            .unwrap();
+        layout
+            .apply(
+                &output_args("HDMI-2")
+                    .auto(true)
+                    .primary(true)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        let config = layout.into_config(false);
+        assert_eq!(
+            vec![(OutputId(0), Some(false)), (OutputId(2), Some(true))],
+            primary_changes(&config)
+        );
+    }
+
+    #[test]
+    fn unchanged_primary_sends_no_properties() {
+        let resources = resources();
+        let mut layout = Layout::new(&resources);
+        layout
+            .apply(&output_args("DP-1").off(true).build().unwrap())
+            .unwrap();
+        assert!(layout.into_config(false).outputs.is_empty());
+    }
+
+    #[test]
+    fn large_resources_laid_out() {
+        let resources = crate::test_fixtures::large_resources(48, 600);
+        let mut layout = Layout::new(&resources);
+        assert_eq!(23, layout.crtcs.len());
+        assert_eq!(
+            vec![OutputId(0), OutputId(1)],
+            layout.crtc_of(OutputId(1)).unwrap().output_ids
+        );
+        assert_eq!(
+            vec![OutputId(23)],
+            layout.crtc_of(OutputId(23)).unwrap().output_ids
+        );
+
+        // Placed right of DP-24, which ends at 24 * 3840
+        layout
+            .apply(
+                &OutputArgsBuilder::default()
+                    .name("DP-48")
+                    .auto(true)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        let crtc = layout.crtc_of(OutputId(47)).unwrap();
+        // Highest resolution at the highest rate
+        assert_eq!((Some(ModeId(4)), 24 * 3840), (crtc.mode_id, crtc.x));
+
+        let config = layout.into_config(false);
+        let mut latched = resources.clone();
+        latched.serial = ConfigSerial(2);
+        assert!(!is_latched(&latched, &config));
+        latched.crtcs[1].mode_id = Some(ModeId(4));
+        latched.crtcs[1].x = 24 * 3840;
+        latched.outputs[47].crtc_id = Some(CrtcId(1));
+        assert!(is_latched(&latched, &config));
+    }
+}
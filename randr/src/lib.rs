@@ -1,4 +1,70 @@
+//! Library behind the `gnome-randr` command, configuring monitors through Mutter's
+//! DisplayConfig D-Bus API.
+//!
+//! [`prelude`] re-exports the stable part of the API: the D-Bus proxy with the types it sends
+//! and receives, [`layout::Layout`] to build configurations and [`watch`] events. Other modules
+//! serve the command line and may change between releases.
+//!
+//! Property maps like [`dbus_api::OutputProperties`] are `#[non_exhaustive]`, since GNOME adds
+//! keys to them, so they are created with `Default` and modified field by field.
+
+pub mod apply_lock;
+pub mod backlight;
+pub mod bounds;
+pub mod cancel;
+#[cfg(feature = "capi")]
+pub mod capi;
 pub mod cli;
+pub mod colord;
+pub mod config;
+pub mod configs;
+pub mod daemon;
 pub mod dbus_api;
+pub mod debounce;
+pub mod diff;
+pub mod doctor;
+pub mod edid;
+pub mod fixture;
+pub mod gamma;
+pub mod get;
+pub mod history;
+pub mod i18n;
+pub mod layout;
+pub mod lid;
+pub mod link;
+pub mod lock;
+pub mod logind;
 pub mod mode_db;
 pub mod output;
+pub mod profiles;
+pub mod progress;
+pub mod record;
+pub mod scale;
+pub mod status;
+pub mod style;
+pub mod suggest;
+pub mod template;
+#[cfg(any(test, feature = "test-fixtures"))]
+pub mod test_fixtures;
+pub mod text_scale;
+pub mod timings;
+pub mod transaction;
+pub mod warnings;
+pub mod watch;
+pub mod windows;
+
+/// Types most library consumers need, `use gnome_randr::prelude::*` brings them into scope.
+pub mod prelude {
+    pub use crate::{
+        cancel::{Cancel, Cancelled},
+        dbus_api::{
+            ApplyConfigurationArgs, ConfigSerial, CrtController, CrtcId, DisplayConfig,
+            DisplayConfigApi, GetCurrentStateReturn, GetResourcesReturn, LayoutMode,
+            LogicalMonitor, Mode, ModeId, Monitor, Output, OutputId, OutputProperties, Reflection,
+            ServiceUnavailable, Transform,
+        },
+        layout::{Layout, Position, Rect},
+        transaction::Transaction,
+        watch::{Action, Event, PowerEvent},
+    };
+}
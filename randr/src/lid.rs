@@ -0,0 +1,127 @@
+//! Turning the internal panel off while the lid is closed, following `[lid]` policy of the
+//! configuration file. Lid state comes from UPower on the system bus.
+
+use dbus::{blocking::Connection, message::MatchRule};
+
+use crate::{
+    cli::OutputArgsBuilder,
+    dbus_api::{ApplyConfigurationArgs, GetResourcesReturn},
+    layout::Layout,
+};
+
+pub const UPOWER_SERVICE: &str = "org.freedesktop.UPower";
+pub const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+
+/// Connector prefixes of internal panels, used when `[lid]` doesn't name one.
+const INTERNAL_PREFIXES: [&str; 3] = ["eDP", "LVDS", "DSI"];
+
+/// What to do when the lid closes and opens, the `[lid]` section of the configuration file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LidPolicy {
+    /// Connector of the internal panel, guessed from connector names if not set
+    pub internal: Option<String>,
+    /// Profile applied when the lid closes, instead of turning the internal panel off
+    pub closed: Option<String>,
+    /// Profile applied when the lid opens, instead of restoring the layout from before closing
+    pub opened: Option<String>,
+}
+
+impl LidPolicy {
+    /// Connector of the internal panel in `resources`.
+    pub fn internal<'a>(&'a self, resources: &'a GetResourcesReturn) -> Option<&'a str> {
+        if let Some(internal) = &self.internal {
+            return Some(internal);
+        }
+        resources
+            .outputs
+            .iter()
+            .map(|output| output.connector_name.as_str())
+            .find(|connector| {
+                INTERNAL_PREFIXES
+                    .iter()
+                    .any(|prefix| connector.starts_with(prefix))
+            })
+    }
+
+    /// Configuration turning the internal panel off, `None` if it's already off or no other
+    /// output is enabled. The left-most remaining output becomes primary if the panel was.
+    pub fn closed_config(
+        &self,
+        resources: &GetResourcesReturn,
+    ) -> anyhow::Result<Option<ApplyConfigurationArgs>> {
+        let Some(internal) = self.internal(resources) else {
+            return Ok(None);
+        };
+        let mut layout = Layout::new(resources);
+        let internal_id = layout.output(internal)?.id;
+        let others_enabled = resources
+            .outputs
+            .iter()
+            .any(|output| output.id != internal_id && layout.crtc_of(output.id).is_some());
+        if layout.crtc_of(internal_id).is_none() || !others_enabled {
+            return Ok(None);
+        }
+        layout.apply(
+            &OutputArgsBuilder::default()
+                .name(internal.to_string())
+                .off(true)
+                .build()?,
+        )?;
+        Ok(Some(layout.into_config(false)))
+    }
+}
+
+/// Calls `f` with the new lid state whenever it changes, while `system` connection is
+/// processed.
+pub fn on_lid_changed(
+    system: &Connection,
+    mut f: impl FnMut(bool) + Send + 'static,
+) -> Result<(), dbus::Error> {
+    use dbus::blocking::stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged;
+
+    let rule = MatchRule::new_signal("org.freedesktop.DBus.Properties", "PropertiesChanged")
+        .with_sender(UPOWER_SERVICE)
+        .with_path(UPOWER_PATH);
+    system.add_match(rule, move |changed: PropertiesPropertiesChanged, _, _| {
+        let closed = changed
+            .changed_properties
+            .get("LidIsClosed")
+            .and_then(|value| value.0.as_i64());
+        if let Some(closed) = closed.filter(|_| changed.interface_name == UPOWER_SERVICE) {
+            f(closed != 0);
+        }
+        true
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dbus_api::OutputId, layout::tests::resources};
+
+    #[test]
+    fn internal_panel_turned_off() {
+        let mut resources = resources();
+        resources.outputs[0].connector_name = "eDP-1".to_string();
+        let policy = LidPolicy::default();
+        assert_eq!(Some("eDP-1"), policy.internal(&resources));
+
+        let config = policy.closed_config(&resources).unwrap().unwrap();
+        assert!(config
+            .crtcs
+            .iter()
+            .all(|crtc| !crtc.output_ids.contains(&OutputId(0))));
+        let primary = config
+            .outputs
+            .iter()
+            .find(|output| output.props.primary == Some(true))
+            .unwrap();
+        assert_eq!(OutputId(1), primary.id);
+
+        // Nothing else would stay enabled
+        resources.outputs[1].crtc_id = None;
+        resources.crtcs[1].mode_id = None;
+        assert!(policy.closed_config(&resources).unwrap().is_none());
+    }
+}
@@ -0,0 +1,186 @@
+//! Estimates of whether a mode fits the link bandwidth of a connector. Modes over the limit are
+//! a common cause of black screens, so they are refused unless `--force` is given.
+//!
+//! GetResources doesn't report pixel clocks, so they are taken from EDID detailed timings when
+//! one matches and estimated with CVT reduced blanking v2 otherwise.
+
+use std::fmt::Display;
+
+use crate::{
+    dbus_api::{Mode, Output},
+    edid::{self, SyncPolarity},
+};
+
+/// Bits per pixel at 8 bits per channel, the lowest depth Mutter uses.
+const BITS_PER_PIXEL: f64 = 24.0;
+
+/// Link of a connector and the rate it carries, in Mbit/s of pixel data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Link {
+    pub name: &'static str,
+    pub max_rate: f64,
+}
+
+impl Link {
+    /// Link guessed from connector name, `None` for internal panels and unknown connectors.
+    pub fn for_output(output: &Output) -> Option<Self> {
+        let connector = output.connector_name.to_ascii_uppercase();
+        if connector.starts_with("HDMI") {
+            // 8b/10b TMDS encoding, 3 data channels
+            let (name, clock) = match output.props.edid.as_deref().and_then(edid::max_tmds_clock) {
+                Some(clock) => ("HDMI with EDID TMDS limit", clock as f64 / 1000.0),
+                None => ("HDMI 2.0", 600.0),
+            };
+            Some(Self {
+                name,
+                max_rate: clock * 3.0 * 8.0,
+            })
+        } else if connector.starts_with("DP") {
+            // HBR3, 4 lanes with 8b/10b encoding
+            Some(Self {
+                name: "DisplayPort HBR3 x4",
+                max_rate: 8100.0 * 4.0 * 0.8,
+            })
+        } else if connector.starts_with("DVI") {
+            Some(Self {
+                name: "single link DVI",
+                max_rate: 165.0 * BITS_PER_PIXEL,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Pixel clock of a mode and where it comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelClock {
+    /// Pixel clock in MHz
+    pub mhz: f64,
+    pub h_total: u32,
+    pub v_total: u32,
+    /// Sync polarity from mode flags, or from EDID timing if flags don't tell
+    pub sync: Option<SyncPolarity>,
+    pub from_edid: bool,
+}
+
+impl PixelClock {
+    /// Pixel clock from a matching EDID detailed timing, or estimated with CVT reduced blanking
+    /// v2: 80 pixel horizontal blanking and at least 460 µs vertical blanking.
+    pub fn of(mode: &Mode, edid: Option<&[u8]>) -> Self {
+        let timing = edid.map(edid::detailed_timings).and_then(|timings| {
+            timings.into_iter().find(|timing| {
+                (timing.width, timing.height) == (mode.width, mode.height)
+                    && (timing.refresh() - mode.frequency).abs() < 0.5
+            })
+        });
+        let sync = SyncPolarity::from_mode_flags(mode.flags);
+        match timing {
+            Some(timing) => Self {
+                mhz: timing.pixel_clock as f64 / 1000.0,
+                h_total: timing.h_total,
+                v_total: timing.v_total,
+                sync: sync.or(timing.sync),
+                from_edid: true,
+            },
+            None => {
+                let h_total = mode.width + 80;
+                let v_total = (mode.height as f64 / (1.0 - 460e-6 * mode.frequency)).ceil() as u32;
+                Self {
+                    mhz: h_total as f64 * v_total as f64 * mode.frequency / 1e6,
+                    h_total,
+                    v_total,
+                    sync,
+                    from_edid: false,
+                }
+            }
+        }
+    }
+
+    /// Data rate in Mbit/s.
+    pub fn rate(&self) -> f64 {
+        self.mhz * BITS_PER_PIXEL
+    }
+}
+
+/// Mode that likely exceeds link bandwidth, displayed with the estimate math.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exceeded {
+    pub connector: String,
+    pub mode: Mode,
+    pub clock: PixelClock,
+    pub link: Link,
+}
+
+impl Display for Exceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = match self.clock.from_edid {
+            true => "EDID timing",
+            false => "CVT-RB2 estimate",
+        };
+        write!(
+            f,
+            "{} {}x{}@{:.2} likely exceeds link bandwidth: {}x{} total pixels ({source}) × {:.2} Hz = {:.1} MHz pixel clock, × {BITS_PER_PIXEL} bpp = {:.2} Gbit/s, but {} carries {:.2} Gbit/s",
+            self.connector,
+            self.mode.width,
+            self.mode.height,
+            self.mode.frequency,
+            self.clock.h_total,
+            self.clock.v_total,
+            self.mode.frequency,
+            self.clock.mhz,
+            self.clock.rate() / 1000.0,
+            self.link.name,
+            self.link.max_rate / 1000.0,
+        )
+    }
+}
+
+/// Checks `mode` against link of `output`, `None` if it fits or link is unknown.
+pub fn check(output: &Output, mode: &Mode) -> Option<Exceeded> {
+    let link = Link::for_output(output)?;
+    let clock = PixelClock::of(mode, output.props.edid.as_deref());
+    (clock.rate() > link.max_rate).then(|| Exceeded {
+        connector: output.connector_name.clone(),
+        mode: mode.clone(),
+        clock,
+        link,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{edid::tests::edid, layout::tests::resources};
+
+    #[test]
+    fn bandwidth_estimated() {
+        let resources = resources();
+        let mut hdmi = resources.outputs[0].clone();
+        let mode = |width, height, frequency| Mode {
+            width,
+            height,
+            frequency,
+            ..Default::default()
+        };
+
+        assert_eq!(None, check(&hdmi, &mode(1920, 1080, 60.0)));
+        let exceeded = check(&hdmi, &mode(3840, 2160, 120.0)).unwrap();
+        assert_eq!(
+            "HDMI-1 3840x2160@120.00 likely exceeds link bandwidth: 3920x2287 total pixels (CVT-RB2 estimate) × 120.00 Hz = 1075.8 MHz pixel clock, × 24 bpp = 25.82 Gbit/s, but HDMI 2.0 carries 14.40 Gbit/s",
+            exceeded.to_string()
+        );
+
+        // EDID limits TMDS clock to 300 MHz and has exact timing for 1080p
+        hdmi.props.edid = Some(edid());
+        let clock = PixelClock::of(&mode(1920, 1080, 60.0), hdmi.props.edid.as_deref());
+        assert!(clock.from_edid);
+        assert_eq!(148.5, clock.mhz);
+        assert_eq!("+hsync +vsync", clock.sync.unwrap().to_string());
+        assert_eq!(None, check(&hdmi, &mode(1920, 1080, 60.0)));
+        assert!(check(&hdmi, &mode(3840, 2160, 60.0)).is_some());
+
+        let dp = &resources.outputs[1];
+        assert_eq!(None, check(dp, &mode(3840, 2160, 120.0)));
+    }
+}
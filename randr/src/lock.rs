@@ -0,0 +1,333 @@
+//! Locking configuration for kiosk setups: a snapshot of enabled outputs is re-applied whenever
+//! configuration deviates from it, like after a TV is power cycled.
+
+use std::{
+    fmt::Display,
+    fs,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use anyhow::{anyhow, Context};
+use dbus::blocking::Connection;
+
+use crate::{
+    apply_lock,
+    cancel::Cancel,
+    cli::{GlobalArgs, OutputArgsBuilder},
+    dbus_api::{self, ApplyConfigurationArgs, DisplayConfig, GetResourcesReturn},
+    debounce::Debounce,
+    gamma,
+    layout::{self, Layout, Position},
+    mode_db::{ModeDb, RoundedMode},
+    progress,
+    warnings::{self, Kind},
+};
+
+/// State of a single enabled output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputState {
+    pub connector: String,
+    pub mode: RoundedMode,
+    pub position: Position,
+    /// Transform as defined by the wayland protocol
+    pub transform: u32,
+    pub primary: bool,
+}
+
+impl Display for OutputState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} transform={}",
+            self.connector, self.mode, self.position, self.transform
+        )?;
+        if self.primary {
+            f.write_str(" primary")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for OutputState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let mut next = |what| parts.next().ok_or_else(|| anyhow!("missing {what}"));
+        let connector = next("connector")?.to_string();
+        let mode = next("mode")?.parse()?;
+        let position = next("position")?.parse()?;
+        let transform = next("transform")?
+            .strip_prefix("transform=")
+            .ok_or_else(|| anyhow!("wrong transform format"))?
+            .parse()?;
+        let primary = match parts.next() {
+            Some("primary") => true,
+            Some(extra) => return Err(anyhow!("unexpected {extra}")),
+            None => false,
+        };
+        Ok(Self {
+            connector,
+            mode,
+            position,
+            transform,
+            primary,
+        })
+    }
+}
+
+/// Enabled outputs, one line per output in text form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Sorted by connector
+    pub outputs: Vec<OutputState>,
+}
+
+impl Snapshot {
+    pub fn capture(resources: &GetResourcesReturn) -> Self {
+        let mode_db = ModeDb::new(&resources.modes);
+        let mut outputs: Vec<_> = resources
+            .outputs
+            .iter()
+            .filter_map(|output| {
+                let crtc = resources.crtc_of(output)?;
+                Some(OutputState {
+                    connector: output.connector_name.clone(),
+                    mode: mode_db.mode_by_id(crtc.mode_id?)?,
+                    position: Position {
+                        x: crtc.x,
+                        y: crtc.y,
+                    },
+                    transform: crtc.transform.into(),
+                    primary: output.props.primary.unwrap_or(false),
+                })
+            })
+            .collect();
+        outputs.sort_by(|l, r| l.connector.cmp(&r.connector));
+        Self { outputs }
+    }
+
+    /// Checks if `resources` differ from snapshot. Outputs from snapshot that are not connected
+    /// are ignored, since they can't be restored anyway.
+    pub fn deviates(&self, resources: &GetResourcesReturn) -> bool {
+        let connected = |connector: &str| {
+            resources
+                .outputs
+                .iter()
+                .any(|output| output.connector_name == connector)
+        };
+        let expected: Vec<_> = self
+            .outputs
+            .iter()
+            .filter(|state| connected(&state.connector))
+            .collect();
+        let current = Self::capture(resources);
+        expected != current.outputs.iter().collect::<Vec<_>>()
+    }
+
+    /// Configuration that brings `resources` back to snapshot, disabling outputs that are not
+    /// part of it.
+    pub fn restore(
+        &self,
+        resources: &GetResourcesReturn,
+    ) -> anyhow::Result<ApplyConfigurationArgs> {
+        let mut layout = Layout::new(resources);
+        for output in &resources.outputs {
+            let locked = self
+                .outputs
+                .iter()
+                .any(|state| state.connector == output.connector_name);
+            if !locked && layout.crtc_of(output.id).is_some() {
+                layout.apply(
+                    &OutputArgsBuilder::default()
+                        .name(output.connector_name.clone())
+                        .off(true)
+                        .build()?,
+                )?;
+            }
+        }
+        for state in &self.outputs {
+            if layout.output(&state.connector).is_err() {
+                continue;
+            }
+            layout.apply(
+                &OutputArgsBuilder::default()
+                    .name(state.connector.clone())
+                    .resolution(state.mode.res)
+                    .framerate(state.mode.frequency)
+                    .position(state.position)
+                    .primary(state.primary)
+                    .build()?,
+            )?;
+            layout.set_transform(&state.connector, state.transform)?;
+        }
+        Ok(layout.into_config(false))
+    }
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for output in &self.outputs {
+            writeln!(f, "{output}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let outputs = s
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(idx, line)| line.parse().with_context(|| format!("line {}", idx + 1)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { outputs })
+    }
+}
+
+/// File storing locked snapshot, removing it unlocks configuration.
+pub fn lock_path() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("gnome-randr.lock")
+}
+
+/// Stores current configuration and re-applies it on every deviation, until [`unlock`] is
+/// called. Cancelling unlocks as well.
+pub fn lock(
+    conn: &Connection,
+    display_config: &DisplayConfig,
+    global: &GlobalArgs,
+    cancel: &Cancel,
+) -> anyhow::Result<()> {
+    let path = lock_path();
+    if path.exists() {
+        return Err(anyhow!(
+            "configuration is already locked, see {}",
+            path.display()
+        ));
+    }
+    let resources = display_config.get_resources()?;
+    layout::check_serial(&resources, global.expect_serial)?;
+    let snapshot = Snapshot::capture(&resources);
+    fs::write(&path, snapshot.to_string())
+        .with_context(|| format!("could not write {}", path.display()))?;
+    print!("{snapshot}");
+
+    let changed = Arc::new(AtomicBool::new(false));
+    dbus_api::on_monitors_changed(conn, {
+        let changed = changed.clone();
+        move || changed.store(true, Ordering::Relaxed)
+    })?;
+    let mut debounce = Debounce::from_args(global);
+    progress::report("startup", 100.0);
+    // Lock file is polled, so unlock doesn't need to know about this process
+    while path.exists() {
+        if cancel.is_cancelled() {
+            return unlock();
+        }
+        conn.process(debounce.poll_interval())?;
+        if changed.swap(false, Ordering::Relaxed) {
+            debounce.changed(Instant::now());
+        }
+        if !debounce.ready(Instant::now()) {
+            continue;
+        }
+        // Manual changes are waited for, the snapshot is restored after them
+        let _lock = match apply_lock::acquire(global.lock_timeout) {
+            Ok(lock) => lock,
+            Err(err) => {
+                warnings::warn(Kind::Lock, format!("{err:#}"));
+                continue;
+            }
+        };
+        let resources = display_config.get_resources()?;
+        if snapshot.deviates(&resources) {
+            warnings::note(
+                Kind::Lock,
+                "configuration changed, restoring locked snapshot",
+            );
+            if let Err(err) = snapshot
+                .restore(&resources)
+                .and_then(|config| Ok(display_config.apply_configuration(config)?))
+            {
+                warnings::warn(
+                    Kind::Lock,
+                    format!("could not restore locked snapshot: {err:#}"),
+                );
+            } else if let Err(err) = gamma::reapply(display_config, &[]) {
+                warnings::warn(Kind::Gamma, format!("could not re-apply gamma: {err:#}"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Stops a running [`lock`].
+pub fn unlock() -> anyhow::Result<()> {
+    let path = lock_path();
+    fs::remove_file(&path)
+        .with_context(|| format!("configuration is not locked, {} is missing", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dbus_api::{CrtcId, ModeId, Transform},
+        layout::tests::resources,
+    };
+
+    #[test]
+    fn snapshot_text_roundtrip() {
+        let snapshot = Snapshot::capture(&resources());
+        let text = snapshot.to_string();
+        assert_eq!(
+            "DP-1 1920x1080@60 1920x0 transform=0\nHDMI-1 1920x1080@60 0x0 transform=0 primary\n",
+            text
+        );
+        assert_eq!(snapshot, text.parse().unwrap());
+    }
+
+    #[test]
+    fn deviation_restored() {
+        let snapshot = Snapshot::capture(&resources());
+        assert!(!snapshot.deviates(&resources()));
+
+        // DP-1 was moved and rotated, HDMI-2 was enabled
+        let mut changed = resources();
+        changed.crtcs[1].x = 0;
+        changed.crtcs[1].y = 1080;
+        changed.crtcs[1].transform = Transform::Normal90;
+        changed.crtcs[2].mode_id = Some(ModeId(2));
+        changed.outputs[2].crtc_id = Some(CrtcId(2));
+        assert!(snapshot.deviates(&changed));
+
+        let config = snapshot.restore(&changed).unwrap();
+        let crtcs: Vec<_> = config
+            .crtcs
+            .iter()
+            .map(|crtc| (crtc.id, crtc.x, crtc.y, crtc.transform))
+            .collect();
+        assert_eq!(vec![(CrtcId(0), 0, 0, 0), (CrtcId(1), 1920, 0, 0)], crtcs);
+    }
+
+    #[test]
+    fn disconnected_outputs_ignored() {
+        let snapshot = Snapshot::capture(&resources());
+        let mut unplugged = resources();
+        unplugged.outputs.remove(1);
+        unplugged.crtcs[1].mode_id = None;
+        assert!(!snapshot.deviates(&unplugged));
+    }
+}
@@ -0,0 +1,23 @@
+//! Signals of logind on the system bus, used by `watch` to notice suspend and resume.
+
+use dbus::{blocking::Connection, message::MatchRule};
+
+pub const SERVICE: &str = "org.freedesktop.login1";
+pub const PATH: &str = "/org/freedesktop/login1";
+pub const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+
+/// Calls `f` with `true` before the system sleeps and with `false` after it wakes up, while
+/// `system` connection is processed.
+pub fn on_prepare_for_sleep(
+    system: &Connection,
+    mut f: impl FnMut(bool) + Send + 'static,
+) -> Result<(), dbus::Error> {
+    let rule = MatchRule::new_signal(MANAGER_INTERFACE, "PrepareForSleep")
+        .with_sender(SERVICE)
+        .with_path(PATH);
+    system.add_match(rule, move |(sleeping,): (bool,), _, _| {
+        f(sleeping);
+        true
+    })?;
+    Ok(())
+}
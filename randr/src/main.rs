@@ -1,40 +1,441 @@
-use gnome_randr::{cli::Cli, dbus_api::DisplayConfig, mode_db::ModeDb, output::Output};
+use std::{collections::HashMap, process::ExitCode, time::Instant};
 
-fn main() -> anyhow::Result<()> {
-    let args = Cli::parse_from_env()?;
+use anyhow::anyhow;
 
-    let conn = dbus::blocking::Connection::new_session()?;
-    let display_config = DisplayConfig::new(&conn);
+use gnome_randr::{
+    apply_lock, backlight, bounds,
+    cancel::{Cancel, Cancelled},
+    cli::{Cli, Command, GlobalArgs},
+    colord,
+    config::{self, Config},
+    configs,
+    dbus_api::{
+        self, DisplayConfig, DisplayConfigApi, GetResourcesReturn, ServiceUnavailable, Transform,
+    },
+    diff, doctor,
+    fixture::Fixture,
+    gamma, get, history,
+    i18n::{self, Localizer},
+    layout,
+    link::PixelClock,
+    lock,
+    mode_db::ModeDb,
+    output::Output,
+    progress, record, scale, status,
+    style::{Style, Styler},
+    template, timings,
+    warnings::{self, Kind},
+    watch, windows,
+};
 
-    let resources = display_config.get_resources()?;
-    let mode_db = ModeDb::new(&resources.modes);
+/// Exit code when DisplayConfig service is not running, EX_UNAVAILABLE from sysexits.h
+const EXIT_SERVICE_UNAVAILABLE: u8 = 69;
+/// Exit code when interrupted, like shells report SIGINT
+const EXIT_INTERRUPTED: u8 = 130;
 
-    if args.outputs.is_empty() {
-        todo!("Convert dbus return into useful outputs struct");
-        //display_outputs(args, resources)?;
-    } else {
-        todo!("Actually modify config");
+fn main() -> ExitCode {
+    let start = Instant::now();
+    let result = run();
+    if let Some(report) = timings::report(start.elapsed()) {
+        eprint!("{report}");
+    }
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            let error = i18n::global().message("error", &[("error", format!("{err:?}"))]);
+            eprintln!("{error}");
+            let service_unavailable = err.downcast_ref::<ServiceUnavailable>().is_some()
+                || err
+                    .downcast_ref::<dbus::Error>()
+                    .is_some_and(ServiceUnavailable::is_cause_of);
+            if service_unavailable {
+                ExitCode::from(EXIT_SERVICE_UNAVAILABLE)
+            } else if err.downcast_ref::<Cancelled>().is_some() {
+                ExitCode::from(EXIT_INTERRUPTED)
+            } else {
+                ExitCode::FAILURE
+            }
+        }
     }
-
-    Ok(())
 }
 
-fn display_outputs(args: Cli, outputs: &[Output]) -> anyhow::Result<()> {
-    /*
-    for out in res.outputs {
-        let unique_supported_modes = mode_db.get_modes_by_ids(&out.mode_ids);
-        let grouped_modes = mode_db::group_modes_by_res(&unique_supported_modes);
-        if let Some(crtc) = res.crtcs.iter().find(|crtc| {
-            std::convert::TryInto::<i32>::try_into(crtc.id).expect("CRTC id doesn't fit in i32")
-                == out.crtc_id
-        }) {
-            if let Some(mode) =
-                mode_db.get_mode_by_id(crtc.mode_id.try_into().expect("Mode id doesn't fit in i32"))
+fn run() -> anyhow::Result<()> {
+    let mut args = Cli::parse_from_env()?;
+    let config_path = args.global.config.clone().or_else(config::default_path);
+    if let Some(path) = &config_path {
+        let config = Config::load(path)?;
+        args.resolve_aliases(&config)?;
+        args.apply_defaults(&config);
+    }
+    if args.global.timings {
+        timings::enable();
+    }
+    i18n::set_global(Localizer::from_env());
+    let localizer = i18n::global();
+    warnings::set_format(args.global.warnings);
+    progress::set_format(args.global.progress);
+    if let Some(path) = &args.global.record {
+        record::start(path)?;
+    }
+
+    // Unlocking only removes the lock file, so it works without a session bus
+    if args.command == Command::Unlock {
+        return lock::unlock();
+    }
+    // Doctor diagnoses connection problems itself
+    if args.command == Command::Doctor {
+        return doctor::run();
+    }
+
+    if let Some(path) = &args.global.fixture {
+        return run_fixture(&Fixture::load(path)?, &args, localizer);
+    }
+
+    // Ctrl+C kills short invocations right away, only long waits handle it
+    let cancel = match waits(&args) {
+        true => Cancel::on_interrupt()?,
+        false => Cancel::new(),
+    };
+    let conn = timings::measure("connect", dbus::blocking::Connection::new_session)?;
+    dbus_api::ensure_service(&conn, args.global.wait_for_service, &cancel)?;
+    let display_config = DisplayConfig::new(&conn);
+
+    match &args.command {
+        Command::Query => {
+            let resources = display_config.get_resources()?;
+            let profiles = match args.global.verbose
+                && !args.global.json
+                && !args.global.query_current
+                && args.global.format.is_none()
             {
+                true => color_profiles(&conn, &resources),
+                false => HashMap::new(),
+            };
+            query(&resources, &args.global, &profiles, localizer)?;
+        }
+        Command::Outputs(outputs) => {
+            let windows = capture_windows(&conn, &display_config, &args.global);
+            history::record_current(&display_config);
+            // Scales are a second modeset, so the lock is held across both, see scale::apply
+            let apply_lock = apply_lock::acquire(args.global.lock_timeout)?;
+            // A bare --layout-mode leaves the layout as it is
+            if !outputs.is_empty() {
+                layout::apply_outputs(&display_config, &args.global, outputs)?;
             }
+            scale::apply(&display_config, outputs, &args.global)?;
+            drop(apply_lock);
+            restore_windows(&conn, windows);
+            backlight::apply(&display_config, outputs, &cancel)?;
+            gamma::reapply(&display_config, outputs)?;
+            history::record_current(&display_config);
+            report_applied(&conn, &args.global, localizer);
+        }
+        Command::Shift(shift) => {
+            let windows = capture_windows(&conn, &display_config, &args.global);
+            history::record_current(&display_config);
+            layout::shift_outputs(&display_config, &args.global, shift)?;
+            restore_windows(&conn, windows);
+            gamma::reapply(&display_config, &[])?;
+            history::record_current(&display_config);
+            report_applied(&conn, &args.global, localizer);
+        }
+        Command::Lock => lock::lock(&conn, &display_config, &args.global, &cancel)?,
+        Command::Configs(configs) => configs::run(&display_config, &args.global, configs)?,
+        Command::Get(get) => println!("{}", get::value(&display_config.get_resources()?, get)?),
+        Command::Diff(diff) => diff::run(&display_config.get_resources()?, &diff.path)?,
+        Command::Bounds => bounds::run(&display_config.get_current_state()?, args.global.json)?,
+        Command::History(history) => history::run(&display_config, &args.global, history)?,
+        Command::Watch(watch) => watch::watch(
+            &conn,
+            &display_config,
+            watch,
+            &args.global,
+            config_path.as_deref(),
+            &cancel,
+        )?,
+        Command::Unlock | Command::Doctor => {
+            unreachable!("{:?} is handled before connecting", args.command)
         }
     }
-    */
 
     Ok(())
 }
+
+/// Whether `args` could wait long enough to be worth cancelling cleanly.
+fn waits(args: &Cli) -> bool {
+    args.global.wait_for_service.is_some()
+        || match &args.command {
+            Command::Outputs(outputs) => outputs.iter().any(|output| output.fade.is_some()),
+            Command::Lock | Command::Watch(_) => true,
+            _ => false,
+        }
+}
+
+/// Layout changes are printed instead of applied, backlight, gamma and D-Bus signals are not
+/// available.
+fn run_fixture(fixture: &Fixture, args: &Cli, localizer: &Localizer) -> anyhow::Result<()> {
+    // Layout mode is switched with ApplyMonitorsConfig, which needs GetCurrentState
+    if args.global.layout_mode.is_some() {
+        return Err(anyhow!("--layout-mode is not supported with --fixture"));
+    }
+    match &args.command {
+        Command::Query => query(
+            &fixture.get_resources()?,
+            &args.global,
+            &HashMap::new(),
+            localizer,
+        ),
+        Command::Outputs(outputs) => layout::apply_outputs(fixture, &args.global, outputs),
+        Command::Shift(shift) => layout::shift_outputs(fixture, &args.global, shift),
+        Command::Configs(configs) => configs::run(fixture, &args.global, configs),
+        Command::Get(get) => {
+            println!("{}", get::value(&fixture.get_resources()?, get)?);
+            Ok(())
+        }
+        Command::Diff(diff) => diff::run(&fixture.get_resources()?, &diff.path),
+        command => Err(anyhow!("{command:?} is not supported with --fixture")),
+    }
+}
+
+/// `profiles` are ICC profile files by connector, shown in verbose listing.
+fn query(
+    resources: &GetResourcesReturn,
+    global: &GlobalArgs,
+    profiles: &HashMap<String, String>,
+    localizer: &Localizer,
+) -> anyhow::Result<()> {
+    layout::check_serial(resources, global.expect_serial)?;
+    if global.query_current || global.format.is_some() {
+        let template = global.format.clone().unwrap_or_default();
+        for line in template::lines(resources, &template, global.query_current) {
+            println!("{line}");
+        }
+    } else if global.json {
+        println!("{}", serde_json::to_string_pretty(resources)?);
+    } else {
+        let styler = Styler::from_env(global.color)?;
+        display_outputs(resources, global.verbose, profiles, &styler, localizer);
+    }
+    Ok(())
+}
+
+/// Listing doesn't depend on colord, so its failures are only warnings.
+fn color_profiles(
+    conn: &dbus::blocking::Connection,
+    resources: &GetResourcesReturn,
+) -> HashMap<String, String> {
+    timings::measure("colord", || colord::profiles(conn, resources)).unwrap_or_else(|err| {
+        warnings::warn(Kind::Color, format!("could not get color profiles: {err}"));
+        HashMap::new()
+    })
+}
+
+/// Window placement before a layout change, if `--preserve-windows` is set and windows could be
+/// listed.
+fn capture_windows(
+    conn: &dbus::blocking::Connection,
+    display_config: &DisplayConfig,
+    global: &GlobalArgs,
+) -> Option<windows::WindowPlacement> {
+    if !global.preserve_windows {
+        return None;
+    }
+    let captured = display_config
+        .get_resources()
+        .map_err(anyhow::Error::from)
+        .and_then(|resources| {
+            let connectors: Vec<_> = resources
+                .outputs
+                .iter()
+                .map(|output| output.connector_name.clone())
+                .collect();
+            windows::capture(conn, &connectors)
+        });
+    captured
+        .map_err(|err| warnings::warn(Kind::Windows, format!("{err:#}")))
+        .ok()
+}
+
+fn restore_windows(conn: &dbus::blocking::Connection, placement: Option<windows::WindowPlacement>) {
+    if let Some(Err(err)) = placement.map(|placement| windows::restore(conn, &placement)) {
+        warnings::warn(
+            Kind::Windows,
+            format!("could not move windows back: {err:#}"),
+        );
+    }
+}
+
+/// Configuration is already applied at this point, so failures are only warnings.
+fn report_applied(conn: &dbus::blocking::Connection, global: &GlobalArgs, localizer: &Localizer) {
+    let name = global.name.as_deref();
+    if let Some(name) = name {
+        let applied = localizer.message("applied-layout", &[("name", name.to_string())]);
+        println!("{applied}");
+    }
+    if let Some(dir) = status::state_dir() {
+        if let Err(err) = status::record_current_profile(&dir, name) {
+            warnings::warn(Kind::Status, format!("{err:#}"));
+        }
+    }
+    if global.notify {
+        if let Err(err) = status::notify(conn, name) {
+            warnings::warn(Kind::Status, format!("could not show notification: {err}"));
+        }
+    }
+}
+
+/// Current mode is highlighted and disabled outputs are dimmed when `styler` has colors.
+fn display_outputs(
+    resources: &GetResourcesReturn,
+    verbose: bool,
+    profiles: &HashMap<String, String>,
+    styler: &Styler,
+    localizer: &Localizer,
+) {
+    let mode_db = timings::measure("ModeDb", || ModeDb::new(&resources.modes));
+    let message = |id, args: &[(&str, String)]| localizer.message(id, args);
+    println!(
+        "{}",
+        message("serial", &[("serial", resources.serial.to_string())])
+    );
+    let outputs_by_crtc = resources.outputs_by_crtc();
+    // Only enabled outputs are numbered, like monitors in `xrandr --listmonitors`
+    let mut enabled_indices = 0..;
+    for dbus_output in &resources.outputs {
+        let index = (dbus_output.crtc_id).and_then(|_| enabled_indices.next());
+        let output = Output::new(dbus_output, &mode_db);
+        let crtc = resources.crtc_of(dbus_output);
+        let current_mode = crtc
+            .and_then(|crtc| crtc.mode_id)
+            .and_then(|id| mode_db.mode_by_id(id));
+        // Every line of a disabled output is dimmed, other lines only highlight parts
+        let line = |text: String| match current_mode {
+            Some(_) => text,
+            None => styler.paint(Style::Disabled, text).to_string(),
+        };
+
+        let star = match dbus_output.props.primary {
+            Some(true) if styler.enabled() => styler.paint(Style::Primary, "*").to_string(),
+            _ => String::new(),
+        };
+        let name = match current_mode {
+            Some(_) => styler
+                .paint(Style::Name, &dbus_output.connector_name)
+                .to_string(),
+            None => dbus_output.connector_name.clone(),
+        };
+        let cloned: Vec<_> = (dbus_output.crtc_id)
+            .and_then(|id| outputs_by_crtc.get(&id))
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter(|other| other.id != dbus_output.id)
+            .map(|other| other.connector_name.as_str())
+            .collect();
+        let cloned = match cloned.is_empty() {
+            true => String::new(),
+            false => format!(
+                " {}",
+                message("cloned-with", &[("outputs", cloned.join(", "))])
+            ),
+        };
+        println!(
+            "{}",
+            line(format!("{name}{star}{cloned} {}", dbus_output.props))
+        );
+
+        if verbose {
+            if let Some(index) = index {
+                let index = message("index", &[("index", format!("%{index}"))]);
+                println!("{}", line(format!("    {index}")));
+            }
+            let mut ids = message(
+                "ids",
+                &[
+                    ("output", dbus_output.id.to_string()),
+                    ("winsys", dbus_output.winsys_id.to_string()),
+                ],
+            );
+            if let Some(crtc) = crtc {
+                let crtc_ids = message(
+                    "crtc-ids",
+                    &[
+                        ("crtc", crtc.id.to_string()),
+                        ("winsys", crtc.winsys_id.to_string()),
+                    ],
+                );
+                ids = format!("{ids}, {crtc_ids}");
+            }
+            println!("{}", line(format!("    {ids}")));
+            if let Some(profile) = profiles.get(&dbus_output.connector_name) {
+                let profile = message("color-profile", &[("profile", profile.clone())]);
+                println!("{}", line(format!("    {profile}")));
+            }
+        }
+        match current_mode {
+            Some(mode) => {
+                let mode = styler.paint(Style::Current, mode).to_string();
+                println!("    {}", message("current", &[("mode", mode)]));
+            }
+            None => println!("{}", line(format!("    {}", message("disabled", &[])))),
+        }
+        if let Some(crtc) = crtc.filter(|_| current_mode.is_some()) {
+            let transforms: Vec<_> = crtc
+                .transforms
+                .iter()
+                .filter_map(|&transform| Transform::try_from(transform).ok())
+                .map(|transform| match transform == crtc.transform {
+                    true => styler.paint(Style::Current, transform).to_string(),
+                    false => transform.to_string(),
+                })
+                .collect();
+            let transforms = message("transforms", &[("transforms", transforms.join(", "))]);
+            println!("    {transforms}");
+        }
+        for res_freqs in mode_db.group_by_res(&output.possible_modes) {
+            let freqs: Vec<_> = res_freqs
+                .frequencies()
+                .map(|frequency| match current_mode {
+                    Some(mode) if mode.res == res_freqs.res() && mode.frequency == frequency => {
+                        styler.paint(Style::Current, frequency).to_string()
+                    }
+                    _ => frequency.to_string(),
+                })
+                .collect();
+            let frequencies = message(
+                "frequencies",
+                &[
+                    ("resolution", res_freqs.res().to_string()),
+                    ("frequencies", freqs.join(", ")),
+                ],
+            );
+            println!("{}", line(format!("    {frequencies}")));
+        }
+        if verbose {
+            let modes = (dbus_output.mode_ids.iter()).filter_map(|&id| resources.mode_by_id(id));
+            for mode in modes {
+                let clock = PixelClock::of(mode, dbus_output.props.edid.as_deref());
+                let id = match clock.from_edid {
+                    true => "mode-timing",
+                    false => "mode-timing-estimated",
+                };
+                let sync = match clock.sync {
+                    Some(sync) => sync.to_string(),
+                    None => message("sync-unknown", &[]),
+                };
+                let timing = message(
+                    id,
+                    &[
+                        (
+                            "mode",
+                            format!("{}x{}@{:.2}", mode.width, mode.height, mode.frequency),
+                        ),
+                        ("clock", format!("{:.2}", clock.mhz)),
+                        ("total", format!("{}x{}", clock.h_total, clock.v_total)),
+                        ("sync", sync),
+                    ],
+                );
+                println!("{}", line(format!("    {timing}")));
+            }
+        }
+    }
+}
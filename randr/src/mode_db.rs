@@ -1,17 +1,13 @@
 use std::{
-    cmp::Ordering,
-    collections::{HashMap, HashSet},
-    fmt::Display,
-    str::FromStr,
-    sync::{Arc, OnceLock},
+    cmp::Ordering, collections::HashMap, fmt::Display, ops::Range, str::FromStr, sync::OnceLock,
 };
 
 use anyhow::anyhow;
 use regex::Regex;
 
-use crate::dbus_api;
+use crate::dbus_api::{self, ModeId};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Resolution {
     pub width: u32,
     pub height: u32,
@@ -60,10 +56,10 @@ impl FromStr for Resolution {
 }
 
 /// Structure that coresponds to a single real mode with its frequency rounded
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct RoundedMode {
-    res: Resolution,
-    frequency: u32,
+    pub res: Resolution,
+    pub frequency: u32,
 }
 
 impl Display for RoundedMode {
@@ -72,195 +68,254 @@ impl Display for RoundedMode {
     }
 }
 
+impl FromStr for RoundedMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (res, frequency) = s.split_once('@').ok_or(anyhow!(
+            "wrong mode format, expected <WIDTH>x<HEIGHT>@<FPS>"
+        ))?;
+        let frequency = frequency
+            .parse()
+            .map_err(|_| anyhow!("could not parse mode frequency"))?;
+        Ok(RoundedMode {
+            res: res.parse()?,
+            frequency,
+        })
+    }
+}
+
+/// Handle of a [`RoundedMode`] interned in a [`ModeDb`]. Handles are ordered the same way as
+/// modes: from highest resolution and frequency to lowest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModeIdx(u32);
+
+/// Real modes grouped by resolution and rounded frequency.
+///
+/// Rounded modes are stored as parallel arrays sorted in descending order, so modes of the same
+/// resolution are contiguous and lookups return slices or iterators instead of allocating.
 pub struct ModeDb {
-    modes: Arc<[RoundedMode]>,
-    resolutions: Arc<[Resolution]>,
-    res_to_freqs: HashMap<Resolution, Arc<[u32]>>,
-    mode_to_id: HashMap<RoundedMode, u32>,
-    id_to_mode: HashMap<u32, RoundedMode>,
+    resolutions: Vec<Resolution>,
+    frequencies: Vec<u32>,
+    /// Real mode with frequency closest to rounded one, for every rounded mode
+    ids: Vec<ModeId>,
+    res_ranges: HashMap<Resolution, Range<usize>>,
+    id_to_idx: HashMap<ModeId, ModeIdx>,
 }
 
 impl ModeDb {
-    pub fn new(modes: &[dbus_api::Mode]) -> Self {
-        type ModeAndRoundedFreq<'a> = (Vec<&'a dbus_api::Mode>, HashSet<u32>);
-        let mut res_to_mode_and_freq: HashMap<Resolution, ModeAndRoundedFreq<'_>> = HashMap::new();
-
-        // Group every mode by resolution and find set of rounded frequencies for given resolution
-        for mode in modes {
-            let rounded_freq: u32 = mode.frequency.round() as u32;
-            res_to_mode_and_freq
-                .entry(Resolution {
-                    width: mode.width,
-                    height: mode.height,
-                })
-                .and_modify(|(res_m, res_f)| {
-                    res_m.push(mode);
-                    res_f.insert(rounded_freq);
-                })
-                .or_insert_with(|| {
-                    let mut freqs = HashSet::new();
-                    freqs.insert(rounded_freq);
-                    (vec![mode], freqs)
-                });
-        }
-
-        // Find mode that has the closest frequency to rounded one to link back to it;
-        let mut rounded_modes = vec![];
-        let mut resolutions = vec![];
-        let mut mode_to_id = HashMap::new();
-        let mut id_to_mode = HashMap::new();
-        let mut res_to_freqs = HashMap::new();
-        for (res, (modes, freqs)) in res_to_mode_and_freq {
-            resolutions.push(res.clone());
-
-            let mut freqs: Vec<_> = freqs.iter().cloned().collect();
-            freqs.sort_by(|l, r| r.cmp(l));
-            for &frequency in freqs.iter() {
+    pub fn new<'a>(modes: impl IntoIterator<Item = &'a dbus_api::Mode>) -> Self {
+        let modes = modes.into_iter();
+        let mut rounded: Vec<(RoundedMode, &dbus_api::Mode)> = modes
+            .map(|mode| {
                 let rounded_mode = RoundedMode {
-                    res: res.clone(),
-                    frequency,
+                    res: Resolution {
+                        width: mode.width,
+                        height: mode.height,
+                    },
+                    frequency: mode.frequency.round() as u32,
                 };
+                (rounded_mode, mode)
+            })
+            .collect();
+        // Descending order, and within one rounded mode the closest real mode first
+        rounded.sort_by(|(l, l_mode), (r, r_mode)| {
+            r.cmp(l).then_with(|| {
+                let l_diff = (l.frequency as f64 - l_mode.frequency).abs();
+                let r_diff = (r.frequency as f64 - r_mode.frequency).abs();
+                l_diff.total_cmp(&r_diff)
+            })
+        });
 
-                // Mode frequency needs to be within 1Hz to be considered "close"
-                let mut min_diff = 1f64;
-                let mut mode_id = 0u32;
-                // Searching for id of mode that will represent given rounded mode
-                for mode in &modes {
-                    let diff = (frequency as f64 - mode.frequency).abs();
-                    if diff < min_diff {
-                        min_diff = diff;
-                        mode_id = mode.id;
-                        // Additionally link every real mode to rounded one
-                        id_to_mode.insert(mode.id, rounded_mode.clone());
-                    }
-                }
-
-                rounded_modes.push(rounded_mode.clone());
-                mode_to_id.insert(rounded_mode, mode_id);
+        let mut db = ModeDb {
+            resolutions: Vec::new(),
+            frequencies: Vec::new(),
+            ids: Vec::new(),
+            res_ranges: HashMap::new(),
+            id_to_idx: HashMap::with_capacity(rounded.len()),
+        };
+        for (rounded_mode, mode) in rounded {
+            let is_new = db.resolutions.last() != Some(&rounded_mode.res)
+                || db.frequencies.last() != Some(&rounded_mode.frequency);
+            if is_new {
+                let idx = db.ids.len();
+                db.resolutions.push(rounded_mode.res);
+                db.frequencies.push(rounded_mode.frequency);
+                db.ids.push(mode.id);
+                db.res_ranges
+                    .entry(rounded_mode.res)
+                    .and_modify(|range| range.end = idx + 1)
+                    .or_insert(idx..idx + 1);
             }
-            res_to_freqs.insert(res.clone(), freqs.into());
-        }
-        rounded_modes.sort_by(|l, r| r.cmp(l));
-        resolutions.sort_by(|l, r| r.cmp(l));
-
-        ModeDb {
-            modes: rounded_modes.into(),
-            resolutions: resolutions.into(),
-            mode_to_id,
-            id_to_mode,
-            res_to_freqs,
+            // Link every real mode to rounded one
+            db.id_to_idx
+                .insert(mode.id, ModeIdx(db.ids.len() as u32 - 1));
         }
+        db
     }
 
-    pub fn get_modes(&self) -> Arc<[RoundedMode]> {
-        self.modes.clone()
+    /// Every rounded mode, from highest to lowest.
+    pub fn modes(&self) -> impl ExactSizeIterator<Item = ModeIdx> {
+        (0..self.ids.len() as u32).map(ModeIdx)
     }
 
-    pub fn get_resolutions(&self) -> Arc<[Resolution]> {
-        self.resolutions.clone()
+    pub fn mode(&self, idx: ModeIdx) -> RoundedMode {
+        RoundedMode {
+            res: self.resolutions[idx.0 as usize],
+            frequency: self.frequencies[idx.0 as usize],
+        }
     }
 
-    pub fn get_resolutions_with_frequencies(&self) -> Arc<[ResolutionFrequencies]> {
-        let mut result = vec![];
-        for res in self.get_resolutions().iter() {
-            let freqs = self
-                .get_res_frequencies(res)
-                .expect("Known resolutions should have matching frequencies");
-            result.push(ResolutionFrequencies {
-                res: res.clone(),
-                freqs,
-            });
-        }
-        result.into()
+    /// Every resolution, from highest to lowest.
+    pub fn resolutions(&self) -> impl Iterator<Item = Resolution> + '_ {
+        let mut last = None;
+        self.resolutions.iter().copied().filter(move |&res| {
+            let is_new = last != Some(res);
+            last = Some(res);
+            is_new
+        })
+    }
+
+    /// Rounded frequencies supported for `res`, from highest to lowest.
+    pub fn frequencies(&self, res: &Resolution) -> Option<&[u32]> {
+        self.res_ranges
+            .get(res)
+            .map(|range| &self.frequencies[range.clone()])
     }
 
-    pub fn get_res_frequencies(&self, res: &Resolution) -> Option<Arc<[u32]>> {
-        self.res_to_freqs.get(res).cloned()
+    pub fn find(&self, res: &Resolution, frequency: u32) -> Option<ModeIdx> {
+        let range = self.res_ranges.get(res)?;
+        self.frequencies[range.clone()]
+            .iter()
+            .position(|&f| f == frequency)
+            .map(|offset| ModeIdx((range.start + offset) as u32))
     }
 
     /// Returns an id of real Mode
-    pub fn get_id(&self, mode: &RoundedMode) -> u32 {
-        *self
-            .mode_to_id
-            .get(mode)
-            .expect("RoundedMode should be valid")
+    pub fn id(&self, idx: ModeIdx) -> ModeId {
+        self.ids[idx.0 as usize]
     }
 
-    pub fn get_mode(&self, res: Resolution, frequency: u32) -> Option<&RoundedMode> {
-        self.mode_to_id
-            .get(&RoundedMode { res, frequency })
-            .map(|id| {
-                self.id_to_mode
-                    .get(id)
-                    .expect("Id was found, should have coresponding mode")
-            })
+    /// Returns rounded mode given an id of real Mode
+    pub fn index_of(&self, mode_id: ModeId) -> Option<ModeIdx> {
+        self.id_to_idx.get(&mode_id).copied()
     }
 
-    /// Returns RoundedMode given an id of real Mode
-    pub fn get_mode_by_id(&self, mode_id: u32) -> Option<&RoundedMode> {
-        self.id_to_mode.get(&mode_id)
+    pub fn mode_by_id(&self, mode_id: ModeId) -> Option<RoundedMode> {
+        self.index_of(mode_id).map(|idx| self.mode(idx))
     }
 
-    pub fn get_modes_by_ids(&self, mode_ids: &[u32]) -> Arc<[RoundedMode]> {
-        let mut unique_modes = HashSet::new();
-        for &id in mode_ids {
-            if let Some(mode) = self.get_mode_by_id(id) {
-                unique_modes.insert(mode);
-            }
-        }
-        let unique_modes: Vec<_> = unique_modes.into_iter().cloned().collect();
-        unique_modes.into()
+    /// Unique rounded modes for real mode ids, from highest to lowest.
+    pub fn indices_of(&self, mode_ids: &[ModeId]) -> Vec<ModeIdx> {
+        let mut indices: Vec<_> = mode_ids
+            .iter()
+            .filter_map(|&id| self.index_of(id))
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct ResolutionFrequencies {
-    res: Resolution,
-    freqs: Arc<[u32]>,
+    /// Groups modes by resolution, `modes` should be sorted like ones returned by
+    /// [`ModeDb::indices_of`].
+    pub fn group_by_res<'a>(
+        &'a self,
+        modes: &'a [ModeIdx],
+    ) -> impl Iterator<Item = ResolutionFrequencies<'a>> + 'a {
+        modes
+            .chunk_by(|&l, &r| self.mode(l).res == self.mode(r).res)
+            .map(|chunk| ResolutionFrequencies {
+                db: self,
+                modes: chunk,
+            })
+    }
 }
 
-impl PartialEq for ResolutionFrequencies {
-    fn eq(&self, other: &Self) -> bool {
-        self.res.eq(&other.res)
-    }
+/// Resolution with rounded frequencies, borrowed from a [`ModeDb`].
+#[derive(Clone, Copy)]
+pub struct ResolutionFrequencies<'a> {
+    db: &'a ModeDb,
+    modes: &'a [ModeIdx],
 }
-impl Eq for ResolutionFrequencies {}
 
-impl PartialOrd for ResolutionFrequencies {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl ResolutionFrequencies<'_> {
+    pub fn res(&self) -> Resolution {
+        self.db.mode(self.modes[0]).res
     }
-}
-impl Ord for ResolutionFrequencies {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.res.cmp(&other.res)
+
+    pub fn frequencies(&self) -> impl Iterator<Item = u32> + '_ {
+        self.modes.iter().map(|&idx| self.db.mode(idx).frequency)
     }
 }
 
-impl Display for ResolutionFrequencies {
+impl Display for ResolutionFrequencies<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("{}, freqs: {:?}", self.res, self.freqs))
+        write!(f, "{}, freqs: ", self.res())?;
+        f.debug_list().entries(self.frequencies()).finish()
     }
 }
 
-pub fn group_modes_by_res(modes: &[RoundedMode]) -> Arc<[ResolutionFrequencies]> {
-    let mut res_freqs = HashMap::new();
-    for mode in modes {
-        res_freqs
-            .entry(mode.res.clone())
-            .and_modify(|f: &mut Vec<_>| f.push(mode.frequency))
-            .or_insert_with(|| vec![mode.frequency]);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode(id: u32, width: u32, height: u32, frequency: f64) -> dbus_api::Mode {
+        dbus_api::Mode {
+            id: ModeId(id),
+            width,
+            height,
+            frequency,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn modes_grouped_and_sorted() {
+        let modes = [
+            mode(0, 1280, 720, 59.94),
+            mode(1, 1920, 1080, 59.94),
+            mode(2, 1920, 1080, 60.0),
+            mode(3, 1920, 1080, 50.0),
+            mode(4, 2560, 1440, 144.0),
+        ];
+        let db = ModeDb::new(&modes);
+
+        let listed: Vec<_> = db.modes().map(|idx| db.mode(idx).to_string()).collect();
+        assert_eq!(
+            vec![
+                "2560x1440@144",
+                "1920x1080@60",
+                "1920x1080@50",
+                "1280x720@60"
+            ],
+            listed
+        );
+        let res: Resolution = "1920x1080".parse().unwrap();
+        assert_eq!(Some(&[60, 50][..]), db.frequencies(&res));
+        assert_eq!(3, db.resolutions().count());
+
+        // Both real modes map to one rounded mode, represented by the closest one
+        assert_eq!(db.index_of(ModeId(1)), db.index_of(ModeId(2)));
+        assert_eq!(ModeId(2), db.id(db.find(&res, 60).unwrap()));
+    }
+
+    #[test]
+    fn group_output_modes() {
+        let modes = [
+            mode(0, 1280, 720, 60.0),
+            mode(1, 1920, 1080, 60.0),
+            mode(2, 1920, 1080, 50.0),
+            mode(3, 2560, 1440, 144.0),
+        ];
+        let db = ModeDb::new(&modes);
+        let indices = db.indices_of(&[ModeId(0), ModeId(2), ModeId(1), ModeId(2)]);
+        let lines: Vec<_> = db
+            .group_by_res(&indices)
+            .map(|res_freqs| res_freqs.to_string())
+            .collect();
+        assert_eq!(
+            vec!["1920x1080, freqs: [60, 50]", "1280x720, freqs: [60]"],
+            lines
+        );
     }
-    let mut result: Vec<_> = res_freqs
-        .into_iter()
-        .map(|(res, mut freqs)| {
-            freqs.sort_by(|l, r| r.cmp(l));
-            ResolutionFrequencies {
-                res,
-                freqs: freqs.into(),
-            }
-        })
-        .collect();
-    result.sort_by(|l, r| r.cmp(l));
-    result.into()
 }
@@ -1,18 +1,17 @@
-use std::sync::Arc;
-
 use crate::{
-    dbus_api,
-    mode_db::{ModeDb, RoundedMode},
+    dbus_api::{self, OutputId},
+    mode_db::{ModeDb, ModeIdx},
 };
 
 pub struct Output {
-    id: u32,
-    possible_modes: Arc<[RoundedMode]>,
+    pub id: OutputId,
+    /// Interned in the [`ModeDb`] output was created with, sorted from highest to lowest
+    pub possible_modes: Vec<ModeIdx>,
 }
 
 impl Output {
     pub fn new(dbus_output: &dbus_api::Output, mode_db: &ModeDb) -> Self {
-        let possible_modes = mode_db.get_modes_by_ids(&dbus_output.mode_ids);
+        let possible_modes = mode_db.indices_of(&dbus_output.mode_ids);
         Output {
             id: dbus_output.id,
             possible_modes,
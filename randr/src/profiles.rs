@@ -0,0 +1,55 @@
+//! Named layouts saved in `$XDG_CONFIG_HOME/gnome-randr/profiles`, one file per profile in a
+//! format `diff` reads: a snapshot printed by `lock`, or GetResources JSON printed with `--json`.
+
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+
+use crate::{
+    apply_lock, cli::GlobalArgs, config, dbus_api::DisplayConfigApi, diff, history, lock::Snapshot,
+    status,
+};
+
+/// Directory with profiles, next to the configuration file.
+pub fn dir() -> Option<PathBuf> {
+    Some(config::default_path()?.with_file_name("profiles"))
+}
+
+/// Saved layout of profile `name`.
+pub fn load(name: &str) -> anyhow::Result<Snapshot> {
+    // Names are file names, not paths
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        return Err(anyhow!("invalid profile name {name:?}"));
+    }
+    let dir = dir().ok_or_else(|| anyhow!("profiles directory is unknown, HOME is not set"))?;
+    diff::load(&dir.join(name))
+}
+
+/// Applies profile `name` and records it as the current one.
+pub fn apply(api: &impl DisplayConfigApi, global: &GlobalArgs, name: &str) -> anyhow::Result<()> {
+    let snapshot = load(name)?;
+    history::record_current(api);
+    {
+        let _lock = apply_lock::acquire(global.lock_timeout)?;
+        let config = snapshot.restore(&api.get_resources()?)?;
+        api.apply_configuration(config)?;
+    }
+    history::record_current(api);
+    if let Some(dir) = status::state_dir() {
+        status::record_current_profile(&dir, Some(name))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_checked() {
+        for name in ["", "../config", ".hidden", "a/b"] {
+            let err = load(name).unwrap_err().to_string();
+            assert!(err.starts_with("invalid profile name"), "{err}");
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Progress of long operations, like fades, waits and daemon startup, reported on stderr with
+//! `--progress`, so GUIs wrapping gnome-randr could show feedback.
+//!
+//! Like warnings, the format is set globally instead of passing a reporter through every API.
+
+use std::{
+    io::Write,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use anyhow::anyhow;
+
+use crate::warnings::json_escape;
+
+/// Width of the bar, in characters.
+const BAR_WIDTH: usize = 20;
+
+/// 0 when progress is not reported, otherwise 1 + index of the format.
+static FORMAT: AtomicU8 = AtomicU8::new(0);
+
+/// How progress is printed, set with `--progress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A terminal progress bar, redrawn in place
+    Bar,
+    /// A JSON object per line, with `stage` and `percent` keys
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bar" => Ok(Format::Bar),
+            "json" => Ok(Format::Json),
+            _ => Err(anyhow!("unknown progress format {s}, expected bar or json")),
+        }
+    }
+}
+
+pub fn set_format(format: Option<Format>) {
+    let value = match format {
+        None => 0,
+        Some(Format::Bar) => 1,
+        Some(Format::Json) => 2,
+    };
+    FORMAT.store(value, Ordering::Relaxed);
+}
+
+/// Reports that `stage` is `percent` done, 100 finishes it. Nothing is printed unless a format
+/// is set with [`set_format`].
+pub fn report(stage: &str, percent: f64) {
+    let format = match FORMAT.load(Ordering::Relaxed) {
+        1 => Format::Bar,
+        2 => Format::Json,
+        _ => return,
+    };
+    let mut stderr = std::io::stderr().lock();
+    // Progress is best effort, a closed stderr is not worth failing over
+    let _ = stderr.write_all(format_progress(format, stage, percent).as_bytes());
+    let _ = stderr.flush();
+}
+
+fn format_progress(format: Format, stage: &str, percent: f64) -> String {
+    let percent = percent.clamp(0.0, 100.0).round() as usize;
+    match format {
+        Format::Bar => {
+            let filled = BAR_WIDTH * percent / 100;
+            let end = if percent == 100 { "\n" } else { "" };
+            format!(
+                "\r{stage} [{}{}] {percent:>3}%{end}",
+                "#".repeat(filled),
+                " ".repeat(BAR_WIDTH - filled)
+            )
+        }
+        Format::Json => format!(
+            "{{\"stage\":\"{}\",\"percent\":{percent}}}\n",
+            json_escape(stage)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_formatted() {
+        assert_eq!(
+            "\rfade [#####               ]  25%",
+            format_progress(Format::Bar, "fade", 25.0)
+        );
+        assert_eq!(
+            "\rfade [####################] 100%\n",
+            format_progress(Format::Bar, "fade", 100.0)
+        );
+        assert_eq!(
+            "{\"stage\":\"wait for service\",\"percent\":33}\n",
+            format_progress(Format::Json, "wait for service", 100.0 / 3.0)
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Recording of D-Bus calls and signals with `--record`, attached to bug reports and replayed
+//! by the fake service in integration tests.
+//!
+//! A recording has a JSON [`Entry`] per line. Serial numbers of monitors are anonymized, since
+//! they are not needed to reproduce a layout.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Context};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::warnings::{self, Kind};
+
+static RECORDER: Mutex<Option<File>> = Mutex::new(None);
+
+/// Replacement of serial numbers in recordings
+const ANONYMIZED: &str = "anonymized";
+
+/// A single recorded call or signal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Entry {
+    Call {
+        method: String,
+        args: Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reply: Option<Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Signal {
+        name: String,
+    },
+}
+
+/// Starts recording to `path`, replacing its contents.
+pub fn start(path: &Path) -> anyhow::Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("could not create {}", path.display()))?;
+    *RECORDER.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+/// Whether [`start`] was called, so values are only converted when they are recorded.
+pub fn enabled() -> bool {
+    RECORDER.lock().unwrap().is_some()
+}
+
+/// Records a method call with its reply or error.
+pub fn call<T: Serialize>(method: &str, args: impl Serialize, reply: &Result<T, dbus::Error>) {
+    if !enabled() {
+        return;
+    }
+    let (reply, error) = match reply {
+        Ok(reply) => (Some(to_value(reply)), None),
+        Err(err) => (
+            None,
+            Some(err.message().unwrap_or("unknown error").to_string()),
+        ),
+    };
+    write(&Entry::Call {
+        method: method.to_string(),
+        args: to_value(args),
+        reply,
+        error,
+    });
+}
+
+/// Records a received signal.
+pub fn signal(name: &str) {
+    if enabled() {
+        write(&Entry::Signal {
+            name: name.to_string(),
+        });
+    }
+}
+
+fn to_value(value: impl Serialize) -> Value {
+    let mut value = serde_json::to_value(value).expect("D-Bus values are representable in JSON");
+    anonymize(&mut value);
+    value
+}
+
+/// Entries are written as they happen, so recordings of `lock` and `watch` are useful even if
+/// they are killed. Write errors only stop recording.
+fn write(entry: &Entry) {
+    let mut recorder = RECORDER.lock().unwrap();
+    if let Some(file) = recorder.as_mut() {
+        let line = serde_json::to_string(entry).expect("Entries are representable in JSON");
+        if let Err(err) = writeln!(file, "{line}") {
+            warnings::warn(Kind::Record, format!("stopped recording: {err}"));
+            *recorder = None;
+        }
+    }
+}
+
+/// Replaces `serial` strings, and serial numbers in `edid` arrays.
+fn anonymize(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match (key.as_str(), value) {
+                    ("serial", Value::String(serial)) => *serial = ANONYMIZED.to_string(),
+                    ("edid", Value::Array(bytes)) => anonymize_edid(bytes),
+                    (_, value) => anonymize(value),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(anonymize),
+        _ => {}
+    }
+}
+
+/// Zeroes the serial number in the EDID header and text of serial number descriptors.
+fn anonymize_edid(bytes: &mut [Value]) {
+    let zero = |bytes: &mut [Value]| bytes.iter_mut().for_each(|byte| *byte = Value::from(0));
+    if bytes.len() < 128 {
+        return;
+    }
+    zero(&mut bytes[12..16]);
+    for offset in [54, 72, 90, 108] {
+        let descriptor = &mut bytes[offset..offset + 18];
+        let is_serial = descriptor[..4] == [0, 0, 0, 0xff].map(Value::from);
+        if is_serial {
+            zero(&mut descriptor[5..]);
+        }
+    }
+}
+
+/// Recording loaded from a file, for replaying.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    pub entries: Vec<Entry>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("could not open {}", path.display()))?;
+        let entries = BufReader::new(file)
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| {
+                serde_json::from_str(&line?).map_err(|err| anyhow!("line {}: {err}", idx + 1))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { entries })
+    }
+
+    fn calls<'a>(
+        &'a self,
+        method: &'a str,
+    ) -> impl Iterator<Item = (&'a Value, Option<&'a Value>)> {
+        self.entries.iter().filter_map(move |entry| match entry {
+            Entry::Call {
+                method: m,
+                args,
+                reply,
+                ..
+            } if m == method => Some((args, reply.as_ref())),
+            _ => None,
+        })
+    }
+
+    /// Successful replies of `method`, in order.
+    pub fn replies<T: DeserializeOwned>(&self, method: &str) -> anyhow::Result<Vec<T>> {
+        self.calls(method)
+            .filter_map(|(_, reply)| reply)
+            .map(|reply| Ok(T::deserialize(reply)?))
+            .collect()
+    }
+
+    /// Arguments `method` was called with, in order.
+    pub fn requests<T: DeserializeOwned>(&self, method: &str) -> anyhow::Result<Vec<T>> {
+        self.calls(method)
+            .map(|(args, _)| Ok(T::deserialize(args)?))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dbus_api::GetResourcesReturn, edid::tests::edid, layout::tests::resources};
+
+    #[test]
+    fn serials_anonymized() {
+        let mut resources = resources();
+        resources.outputs[0].props.serial = Some("ABC123".to_string());
+        let mut edid = edid();
+        edid[12..16].copy_from_slice(&[1, 2, 3, 4]);
+        edid[72..77].copy_from_slice(&[0, 0, 0, 0xff, 0]);
+        edid[77..90].copy_from_slice(b"SN0042\n      ");
+        resources.outputs[0].props.edid = Some(edid.clone());
+
+        let value = to_value(&resources);
+        let anonymized = GetResourcesReturn::deserialize(&value).unwrap();
+        let props = &anonymized.outputs[0].props;
+        assert_eq!(Some(ANONYMIZED), props.serial.as_deref());
+        let anonymized_edid = props.edid.as_ref().unwrap();
+        assert_eq!([0; 4], anonymized_edid[12..16]);
+        assert_eq!([0; 13], anonymized_edid[77..90]);
+        assert_eq!(edid[54..72], anonymized_edid[54..72]);
+        // Configuration serial is a number, not a monitor serial
+        assert_eq!(resources.serial, anonymized.serial);
+
+        let recording = Recording {
+            entries: vec![
+                Entry::Call {
+                    method: "GetResources".to_string(),
+                    args: Value::Null,
+                    reply: Some(value),
+                    error: None,
+                },
+                Entry::Signal {
+                    name: "MonitorsChanged".to_string(),
+                },
+            ],
+        };
+        let replies: Vec<GetResourcesReturn> = recording.replies("GetResources").unwrap();
+        assert_eq!(1, replies.len());
+        assert!(recording
+            .replies::<Value>("ApplyConfiguration")
+            .unwrap()
+            .is_empty());
+    }
+}
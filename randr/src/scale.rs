@@ -0,0 +1,369 @@
+//! Checks of output scales against a planned configuration, and changing scales with `--scale`.
+//!
+//! ApplyConfiguration can't change scales, so outputs keep their current ones and Mutter either
+//! rejects the change with a generic error or renders it poorly. `--scale` is applied afterwards
+//! with ApplyMonitorsConfig, together with `--layout-mode`.
+//!
+//! The two calls are separate modesets and are not atomic: if ApplyMonitorsConfig fails, the
+//! layout stays applied with old scales. Callers hold the apply lock across both, so at least
+//! another gnome-randr can't apply a configuration in between.
+
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+
+use crate::{
+    apply_lock,
+    cli::{GlobalArgs, OutputArgs},
+    dbus_api::{
+        ApplyConfigurationArgs, ApplyMonitorsConfigArgs, DisplayConfig, GetCurrentStateReturn,
+        GetResourcesReturn, LayoutMode, LogicalMonitorConfig, MonitorConfig,
+        MonitorsConfigProperties,
+    },
+    layout::Layout,
+    text_scale,
+    warnings::{self, Kind},
+};
+
+/// ApplyMonitorsConfig method applying configuration without storing it, like ApplyConfiguration
+/// is used
+const METHOD_TEMPORARY: u32 = 1;
+
+/// Problems with scales of outputs enabled by `config`, meant to be printed before applying it.
+pub fn warnings(
+    resources: &GetResourcesReturn,
+    state: &GetCurrentStateReturn,
+    config: &ApplyConfigurationArgs,
+) -> Vec<String> {
+    let current_scales = state.scales();
+    let mut warnings = Vec::new();
+    // Scale of every enabled output, sorted by connector for stable output
+    let mut scales = Vec::new();
+    for crtc in &config.crtcs {
+        let Some(mode) = crtc.mode_id.and_then(|id| resources.mode_by_id(id)) else {
+            continue;
+        };
+        for output in resources
+            .outputs
+            .iter()
+            .filter(|output| crtc.output_ids.contains(&output.id))
+        {
+            let connector = &output.connector_name;
+            let monitor_mode = state
+                .monitors
+                .iter()
+                .filter(|monitor| monitor.spec.connector == *connector)
+                .flat_map(|monitor| &monitor.modes)
+                .find(|monitor_mode| {
+                    (monitor_mode.width, monitor_mode.height) == (mode.width, mode.height)
+                        && (monitor_mode.refresh_rate - mode.frequency).abs() < 0.01
+                });
+            // Newly enabled outputs get preferred scale of their mode
+            let Some(scale) = current_scales
+                .get(connector)
+                .copied()
+                .or(monitor_mode.map(|monitor_mode| monitor_mode.preferred_scale))
+            else {
+                continue;
+            };
+            if let Some(monitor_mode) =
+                monitor_mode.filter(|monitor_mode| !supports(&monitor_mode.supported_scales, scale))
+            {
+                warnings.push(format!(
+                    "scale {scale} of {connector} is not supported by mode {}x{}@{:.2}, supported scales: {}",
+                    mode.width,
+                    mode.height,
+                    mode.frequency,
+                    list(&monitor_mode.supported_scales)
+                ));
+            }
+            scales.push((connector.as_str(), scale));
+        }
+    }
+    scales.sort_by_key(|&(connector, _)| connector);
+
+    let distinct: HashSet<_> = scales.iter().map(|(_, scale)| scale.to_bits()).collect();
+    if distinct.len() > 1 && state.props.global_scale_required == Some(true) {
+        warnings.push(format!(
+            "outputs use different scales ({}), but this session requires a single scale",
+            describe(&scales)
+        ));
+    } else if scales.iter().any(|(_, scale)| scale.fract() == 0.0)
+        && scales.iter().any(|(_, scale)| scale.fract() != 0.0)
+    {
+        warnings.push(format!(
+            "outputs mix integer and fractional scales ({}), windows moving between them may be blurry and cursor may change size",
+            describe(&scales)
+        ));
+    }
+    warnings
+}
+
+/// Configuration of current logical monitors, with scales of `scales` connectors changed and
+/// switched to `layout_mode` if it's given.
+///
+/// In the logical layout mode monitor sizes depend on scales, so monitors right of or below a
+/// rescaled one are moved to stay adjacent. Switching layout mode resizes monitors the same way.
+pub fn monitors_config(
+    state: &GetCurrentStateReturn,
+    scales: &[(&str, f64)],
+    layout_mode: Option<LayoutMode>,
+) -> anyhow::Result<ApplyMonitorsConfigArgs> {
+    let old_mode = state.layout_mode();
+    let new_mode = layout_mode.unwrap_or(old_mode);
+    let mut props = MonitorsConfigProperties::default();
+    if new_mode != old_mode {
+        if state.props.supports_changing_layout_mode != Some(true) {
+            return Err(anyhow!(
+                "this session can't change layout mode from {old_mode} to {new_mode}"
+            ));
+        }
+        props.layout_mode = Some(new_mode as u32);
+    }
+    let mut logical_monitors = Vec::new();
+    // Old and new size of every logical monitor, in layout units
+    let mut sizes = Vec::new();
+    for logical in &state.logical_monitors {
+        let mut config = LogicalMonitorConfig {
+            x: logical.x,
+            y: logical.y,
+            scale: logical.scale,
+            transform: logical.transform.into(),
+            primary: logical.primary,
+            monitors: Vec::new(),
+        };
+        let mut mode_size = (0, 0);
+        for spec in &logical.monitors {
+            let connector = &spec.connector;
+            let mode = state
+                .monitors
+                .iter()
+                .filter(|monitor| monitor.spec.connector == *connector)
+                .flat_map(|monitor| &monitor.modes)
+                .find(|mode| mode.props.is_current == Some(true))
+                .ok_or_else(|| anyhow!("{connector} has no current mode"))?;
+            if let Some(&(_, scale)) = scales.iter().find(|(name, _)| name == connector) {
+                if !supports(&mode.supported_scales, scale) {
+                    return Err(anyhow!(
+                        "scale {scale} is not supported by {connector} mode {}x{}@{:.2}, supported scales: {}",
+                        mode.width,
+                        mode.height,
+                        mode.refresh_rate,
+                        list(&mode.supported_scales)
+                    ));
+                }
+                config.scale = scale;
+            }
+            mode_size = (mode.width as i32, mode.height as i32);
+            config.monitors.push(MonitorConfig {
+                connector: connector.clone(),
+                mode_id: mode.id.clone(),
+                props: Default::default(),
+            });
+        }
+        // Odd transforms are rotated by 90 or 270 degrees
+        if config.transform % 2 == 1 {
+            mode_size = (mode_size.1, mode_size.0);
+        }
+        let size = |scale: f64, mode| match mode {
+            LayoutMode::Logical => (
+                (mode_size.0 as f64 / scale).round() as i32,
+                (mode_size.1 as f64 / scale).round() as i32,
+            ),
+            LayoutMode::Physical => mode_size,
+        };
+        sizes.push((size(logical.scale, old_mode), size(config.scale, new_mode)));
+        logical_monitors.push(config);
+    }
+
+    let moved: Vec<_> = logical_monitors
+        .iter()
+        .map(|monitor| {
+            let (mut x, mut y) = (monitor.x, monitor.y);
+            for (other, (old, new)) in state.logical_monitors.iter().zip(&sizes) {
+                if other.x + old.0 <= monitor.x {
+                    x += new.0 - old.0;
+                }
+                if other.y + old.1 <= monitor.y {
+                    y += new.1 - old.1;
+                }
+            }
+            (x, y)
+        })
+        .collect();
+    for (monitor, (x, y)) in logical_monitors.iter_mut().zip(moved) {
+        (monitor.x, monitor.y) = (x, y);
+    }
+
+    Ok(ApplyMonitorsConfigArgs {
+        serial: state.serial,
+        method: METHOD_TEMPORARY,
+        logical_monitors,
+        props,
+    })
+}
+
+/// Applies `--scale` of `outputs` and `--layout-mode`, after layout of outputs was applied.
+/// With `--sync-text-scale`, text scale compensates for a changed scale of the primary monitor.
+///
+/// Takes the apply lock, which should already be held since the layout was applied, otherwise
+/// scales could be applied over a configuration of another invocation.
+pub fn apply(
+    display_config: &DisplayConfig,
+    outputs: &[OutputArgs],
+    global: &GlobalArgs,
+) -> anyhow::Result<()> {
+    if outputs.iter().all(|output| output.scale.is_none()) && global.layout_mode.is_none() {
+        return Ok(());
+    }
+    let _lock = apply_lock::acquire(global.lock_timeout)?;
+    let resources = display_config.get_resources()?;
+    let layout = Layout::new(&resources);
+    let mut scales = Vec::new();
+    for output in outputs {
+        if let Some(scale) = output.scale {
+            scales.push((layout.output(&output.name)?.connector_name.as_str(), scale));
+        }
+    }
+    let state = display_config.get_current_state()?;
+    if scales.is_empty() && global.layout_mode == Some(state.layout_mode()) {
+        return Ok(());
+    }
+    let config = monitors_config(&state, &scales, global.layout_mode)?;
+    display_config.apply_monitors_config(config.clone())?;
+
+    let primary_scale = |monitors: &[_]| {
+        monitors
+            .iter()
+            .find(|monitor: &&LogicalMonitorConfig| monitor.primary)
+            .map(|monitor| monitor.scale)
+    };
+    let old = state
+        .logical_monitors
+        .iter()
+        .find(|monitor| monitor.primary)
+        .map(|monitor| monitor.scale);
+    match (old, primary_scale(&config.logical_monitors)) {
+        (Some(old), Some(new)) if global.sync_text_scale && old != new => {
+            // Scales are already applied, so text scale failures are only warnings
+            if let Err(err) = text_scale::sync(old, new) {
+                warnings::warn(Kind::Scale, format!("could not sync text scale: {err:#}"));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Supported scales are computed by Mutter, so they are compared with a tolerance.
+fn supports(supported: &[f64], scale: f64) -> bool {
+    supported
+        .iter()
+        .any(|supported| (supported - scale).abs() < 0.001)
+}
+
+fn list(scales: &[f64]) -> String {
+    scales
+        .iter()
+        .map(|scale| format!("{scale:.2}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe(scales: &[(&str, f64)]) -> String {
+    scales
+        .iter()
+        .map(|(connector, scale)| format!("{connector} {scale}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{
+        tests::{current_state, resources},
+        Layout,
+    };
+
+    #[test]
+    fn scales_changed() {
+        let mut state = current_state();
+        for monitor in &mut state.monitors {
+            monitor.modes[0].props.is_current = Some(true);
+        }
+        let config = monitors_config(&state, &[("HDMI-1", 2.0)], None).unwrap();
+        let positions: Vec<_> = config
+            .logical_monitors
+            .iter()
+            .map(|monitor| {
+                (
+                    monitor.monitors[0].connector.as_str(),
+                    monitor.x,
+                    monitor.scale,
+                )
+            })
+            .collect();
+        assert_eq!(vec![("HDMI-1", 0, 2.0), ("DP-1", 960, 2.0)], positions);
+        assert_eq!(
+            "1920x1080@60",
+            config.logical_monitors[0].monitors[0].mode_id
+        );
+
+        state.props.layout_mode = Some(LayoutMode::Physical as u32);
+        let config = monitors_config(&state, &[("HDMI-1", 2.0)], None).unwrap();
+        assert_eq!(1920, config.logical_monitors[1].x);
+        assert_eq!(None, config.props.layout_mode);
+
+        let err = monitors_config(&state, &[("DP-1", 1.1)], None).unwrap_err();
+        assert_eq!(
+            "scale 1.1 is not supported by DP-1 mode 1920x1080@60.00, supported scales: 1.00, 1.25, 1.50, 1.75, 2.00",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn layout_mode_switched() {
+        let mut state = current_state();
+        for monitor in &mut state.monitors {
+            monitor.modes[0].props.is_current = Some(true);
+        }
+        state.logical_monitors[0].scale = 2.0;
+        state.logical_monitors[1].x = 960;
+        let err = monitors_config(&state, &[], Some(LayoutMode::Physical)).unwrap_err();
+        assert_eq!(
+            "this session can't change layout mode from logical to physical",
+            err.to_string()
+        );
+
+        state.props.supports_changing_layout_mode = Some(true);
+        let config = monitors_config(&state, &[], Some(LayoutMode::Physical)).unwrap();
+        assert_eq!(Some(LayoutMode::Physical as u32), config.props.layout_mode);
+        // HDMI-1 takes its full mode width, DP-1 moves to stay adjacent
+        assert_eq!(1920, config.logical_monitors[1].x);
+    }
+
+    #[test]
+    fn scale_warnings() {
+        let resources = resources();
+        let config = Layout::new(&resources).into_config(false);
+        let mut state = current_state();
+        assert!(warnings(&resources, &state, &config).is_empty());
+
+        state.logical_monitors[0].scale = 1.5;
+        assert_eq!(
+            vec!["outputs mix integer and fractional scales (DP-1 2, HDMI-1 1.5), windows moving between them may be blurry and cursor may change size"],
+            warnings(&resources, &state, &config)
+        );
+
+        state.props.global_scale_required = Some(true);
+        state.monitors[1].modes[0].supported_scales = vec![1.0, 1.5];
+        assert_eq!(
+            vec![
+                "scale 2 of DP-1 is not supported by mode 1920x1080@60.00, supported scales: 1.00, 1.50",
+                "outputs use different scales (DP-1 2, HDMI-1 1.5), but this session requires a single scale",
+            ],
+            warnings(&resources, &state, &config)
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Reporting which named layout is active, for status bars and scripts.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+use dbus::{
+    arg::{RefArg, Variant},
+    blocking::Connection,
+};
+
+/// Directory for state that should persist between invocations, `$XDG_STATE_HOME/gnome-randr`.
+pub fn state_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(base.join("gnome-randr"))
+}
+
+/// Records name of applied layout in `current-profile` file inside of `dir`. Unnamed layouts
+/// remove the file, so a stale name isn't reported.
+pub fn record_current_profile(dir: &Path, name: Option<&str>) -> anyhow::Result<()> {
+    let path = dir.join("current-profile");
+    match name {
+        Some(name) => {
+            fs::create_dir_all(dir)
+                .and_then(|()| fs::write(&path, format!("{name}\n")))
+                .with_context(|| format!("could not write {}", path.display()))?;
+        }
+        None => match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(err).with_context(|| format!("could not remove {}", path.display()));
+            }
+            _ => {}
+        },
+    }
+    Ok(())
+}
+
+/// Name of the layout recorded by [`record_current_profile`], if there is one.
+pub fn current_profile(dir: &Path) -> Option<String> {
+    let name = fs::read_to_string(dir.join("current-profile")).ok()?;
+    Some(name.trim_end().to_string())
+}
+
+/// Shows a desktop notification about applied layout.
+pub fn notify(conn: &Connection, name: Option<&str>) -> Result<(), dbus::Error> {
+    let proxy = conn.with_proxy(
+        "org.freedesktop.Notifications",
+        "/org/freedesktop/Notifications",
+        Duration::from_millis(5000),
+    );
+    let body = match name {
+        Some(name) => format!("Applied layout {name}"),
+        None => "Applied display configuration".to_string(),
+    };
+    let hints: HashMap<&str, Variant<Box<dyn RefArg>>> = HashMap::new();
+    let (_id,): (u32,) = proxy.method_call(
+        "org.freedesktop.Notifications",
+        "Notify",
+        (
+            "gnome-randr",
+            0u32,
+            "video-display",
+            "Display configuration",
+            body,
+            Vec::<&str>::new(),
+            hints,
+            -1i32,
+        ),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_profile_recorded() {
+        let dir = std::env::temp_dir().join(format!("gnome-randr-status-{}", std::process::id()));
+        let path = dir.join("current-profile");
+
+        record_current_profile(&dir, Some("docked")).unwrap();
+        assert_eq!("docked\n", fs::read_to_string(&path).unwrap());
+        record_current_profile(&dir, None).unwrap();
+        assert!(!path.exists());
+        record_current_profile(&dir, None).unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
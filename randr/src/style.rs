@@ -0,0 +1,191 @@
+//! Colors of query output, enabled with `--color` and disabled by `NO_COLOR`.
+//!
+//! Colors come from a [`Theme`] of SGR codes, which could be changed with `GNOME_RANDR_COLORS`,
+//! like `current=1;32:disabled=2`.
+
+use std::{fmt::Display, io::IsTerminal, str::FromStr};
+
+use anyhow::anyhow;
+
+/// When to color output, set with `--color`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color when stdout is a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(anyhow!(
+                "unknown color choice {s}, expected auto, always or never"
+            )),
+        }
+    }
+}
+
+/// Parts of output that are styled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// Connector name of an output
+    Name,
+    /// Primary output marker
+    Primary,
+    /// Current mode and frequency
+    Current,
+    /// Outputs without a CRTC
+    Disabled,
+}
+
+/// SGR codes of every [`Style`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    pub name: String,
+    pub primary: String,
+    pub current: String,
+    pub disabled: String,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: "1".to_string(),
+            primary: "1;33".to_string(),
+            current: "1;32".to_string(),
+            disabled: "2".to_string(),
+        }
+    }
+}
+
+impl Theme {
+    /// Overrides codes with `style=code` entries separated by `:`.
+    pub fn with_overrides(mut self, overrides: &str) -> anyhow::Result<Self> {
+        for entry in overrides.split(':').filter(|entry| !entry.is_empty()) {
+            let (style, code) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected style=code, got {entry}"))?;
+            if !code.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                return Err(anyhow!("invalid SGR code {code} for {style}"));
+            }
+            let slot = match style {
+                "name" => &mut self.name,
+                "primary" => &mut self.primary,
+                "current" => &mut self.current,
+                "disabled" => &mut self.disabled,
+                _ => return Err(anyhow!("unknown style {style}")),
+            };
+            *slot = code.to_string();
+        }
+        Ok(self)
+    }
+
+    fn code(&self, style: Style) -> &str {
+        match style {
+            Style::Name => &self.name,
+            Style::Primary => &self.primary,
+            Style::Current => &self.current,
+            Style::Disabled => &self.disabled,
+        }
+    }
+}
+
+/// Styles text with a theme, or leaves it as is when colors are disabled.
+#[derive(Debug, Clone, Default)]
+pub struct Styler {
+    theme: Option<Theme>,
+}
+
+impl Styler {
+    pub fn new(theme: Option<Theme>) -> Self {
+        Self { theme }
+    }
+
+    /// Decides on colors from `choice`, stdout, `NO_COLOR` and `GNOME_RANDR_COLORS`.
+    pub fn from_env(choice: ColorChoice) -> anyhow::Result<Self> {
+        let enabled = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            // https://no-color.org: set and not empty disables colors
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+        };
+        if !enabled {
+            return Ok(Self::default());
+        }
+        let theme = match std::env::var("GNOME_RANDR_COLORS") {
+            Ok(overrides) => Theme::default()
+                .with_overrides(&overrides)
+                .map_err(|err| anyhow!("invalid GNOME_RANDR_COLORS: {err}"))?,
+            Err(_) => Theme::default(),
+        };
+        Ok(Self::new(Some(theme)))
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.theme.is_some()
+    }
+
+    pub fn paint<T: Display>(&self, style: Style, value: T) -> Painted<'_, T> {
+        Painted {
+            code: self.theme.as_ref().map(|theme| theme.code(style)),
+            value,
+        }
+    }
+}
+
+/// Value wrapped in escape codes when displayed, see [`Styler::paint`].
+pub struct Painted<'a, T> {
+    code: Option<&'a str>,
+    value: T,
+}
+
+impl<T: Display> Display for Painted<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "\x1b[{code}m{}\x1b[0m", self.value),
+            None => self.value.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_painted() {
+        let plain = Styler::default();
+        assert_eq!("DP-1", plain.paint(Style::Name, "DP-1").to_string());
+
+        let theme = Theme::default()
+            .with_overrides("current=4:disabled=90")
+            .unwrap();
+        let styler = Styler::new(Some(theme));
+        assert_eq!(
+            "\x1b[4m1920x1080@60\x1b[0m",
+            styler.paint(Style::Current, "1920x1080@60").to_string()
+        );
+        assert_eq!(
+            "\x1b[90mdisabled\x1b[0m",
+            styler.paint(Style::Disabled, "disabled").to_string()
+        );
+        assert_eq!(
+            "\x1b[1mDP-1\x1b[0m",
+            styler.paint(Style::Name, "DP-1").to_string()
+        );
+
+        assert!(Theme::default().with_overrides("current").is_err());
+        assert!(Theme::default().with_overrides("current=red").is_err());
+        assert!(Theme::default().with_overrides("selected=1").is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! "Did you mean" suggestions for mistyped connector names, options and commands.
+
+/// Edit distance between two strings, counting inserted, removed and replaced characters.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(a != b);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Closest candidate to a mistyped name, ignoring case.
+///
+/// Candidates further than a third of the name length, but at least 2 edits, are not suggested,
+/// since they are more likely to be a different name than a typo. Earlier candidates win ties.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let name = name.to_lowercase();
+    let max_distance = (name.chars().count() / 3).max(2);
+    candidates
+        .into_iter()
+        .map(|candidate| (levenshtein(&name, &candidate.to_lowercase()), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Appends a suggestion to an error message, if there is one.
+pub fn did_you_mean<'a>(
+    message: impl std::fmt::Display,
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+    prefix: &str,
+) -> String {
+    match closest(name, candidates) {
+        Some(candidate) => format!("{message}, did you mean {prefix}{candidate}?"),
+        None => message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance() {
+        assert_eq!(0, levenshtein("HDMI-1", "HDMI-1"));
+        assert_eq!(1, levenshtein("HDMI1", "HDMI-1"));
+        assert_eq!(1, levenshtein("DP-2", "DP-1"));
+        assert_eq!(3, levenshtein("kitten", "sitting"));
+        assert_eq!(4, levenshtein("", "eDP1"));
+    }
+
+    #[test]
+    fn closest_candidate() {
+        let outputs = ["HDMI-1", "HDMI-2", "DP-1", "eDP-1"];
+        assert_eq!(Some("HDMI-1"), closest("HMDI-1", outputs));
+        assert_eq!(Some("eDP-1"), closest("EDP1", outputs));
+        assert_eq!(Some("DP-1"), closest("DP-3", outputs));
+        assert_eq!(None, closest("VGA-1", outputs));
+
+        let options = ["resolution", "rotate", "reflect", "brightness"];
+        assert_eq!(Some("resolution"), closest("resoluton", options));
+        assert_eq!(Some("brightness"), closest("brigthness", options));
+        assert_eq!(None, closest("verbose", options));
+    }
+
+    #[test]
+    fn message() {
+        assert_eq!(
+            "output HDMI-3 not found, did you mean HDMI-1?",
+            did_you_mean("output HDMI-3 not found", "HDMI-3", ["HDMI-1"], "")
+        );
+        assert_eq!(
+            "unknown command dcotor, did you mean doctor?",
+            did_you_mean("unknown command dcotor", "dcotor", ["doctor", "diff"], "")
+        );
+        assert_eq!(
+            "output VGA-1 not found",
+            did_you_mean("output VGA-1 not found", "VGA-1", ["HDMI-1"], "")
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Format strings of `--format`, like `{name} {mode}@{hz}`, rendered once per output.
+//!
+//! Placeholders are `{name}`, `{mode}`, `{width}`, `{height}`, `{hz}`, `{x}`, `{y}`,
+//! `{rotation}`, `{primary}`, `{vendor}` and `{serial}`. Values that an output doesn't have, like
+//! the mode of a disabled output, are empty.
+
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
+use crate::{
+    dbus_api::{CrtController, GetResourcesReturn, Output},
+    mode_db::{ModeDb, RoundedMode},
+    suggest,
+};
+
+/// Format of `--query-current` lines if `--format` isn't given.
+pub const DEFAULT_FORMAT: &str = "{name} {mode}@{hz} +{x}+{y} {rotation} {primary}";
+
+/// Value of an output substituted for `{placeholder}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    /// Connector name
+    Name,
+    /// Resolution of the current mode, like `1920x1080`
+    Mode,
+    Width,
+    Height,
+    /// Rounded refresh rate of the current mode
+    Hz,
+    X,
+    Y,
+    /// `--rotate` name of the current transform
+    Rotation,
+    /// `primary` for the primary output, empty otherwise
+    Primary,
+    Vendor,
+    Serial,
+}
+
+impl Placeholder {
+    const NAMES: &'static [(&'static str, Placeholder)] = &[
+        ("name", Placeholder::Name),
+        ("mode", Placeholder::Mode),
+        ("width", Placeholder::Width),
+        ("height", Placeholder::Height),
+        ("hz", Placeholder::Hz),
+        ("x", Placeholder::X),
+        ("y", Placeholder::Y),
+        ("rotation", Placeholder::Rotation),
+        ("primary", Placeholder::Primary),
+        ("vendor", Placeholder::Vendor),
+        ("serial", Placeholder::Serial),
+    ];
+}
+
+impl FromStr for Placeholder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let names = || Placeholder::NAMES.iter().map(|&(name, _)| name);
+        Placeholder::NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|&(_, placeholder)| placeholder)
+            .ok_or_else(|| {
+                let message = format!(
+                    "unknown placeholder {{{s}}}, expected one of {}",
+                    names().collect::<Vec<_>>().join(", ")
+                );
+                anyhow!(suggest::did_you_mean(message, s, names(), ""))
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+/// Parsed format string, `{{` and `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(anyhow!("unclosed {{{name} in format")),
+                        }
+                    }
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Placeholder(name.parse()?));
+                }
+                '}' => return Err(anyhow!("unmatched }} in format, use }}}} for a brace")),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(Self { segments })
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        DEFAULT_FORMAT.parse().expect("default format is valid")
+    }
+}
+
+impl Template {
+    /// Renders a line for `output`, values of a disabled output are empty. Trailing whitespace
+    /// is trimmed, so optional values like `{primary}` could end a line.
+    pub fn render(
+        &self,
+        output: &Output,
+        crtc: Option<&CrtController>,
+        mode: Option<RoundedMode>,
+    ) -> String {
+        let mut line = String::new();
+        for segment in &self.segments {
+            let placeholder = match segment {
+                Segment::Text(text) => {
+                    line.push_str(text);
+                    continue;
+                }
+                Segment::Placeholder(placeholder) => placeholder,
+            };
+            let value = match placeholder {
+                Placeholder::Name => output.connector_name.clone(),
+                Placeholder::Mode => mode.map(|mode| mode.res.to_string()).unwrap_or_default(),
+                Placeholder::Width => mode
+                    .map(|mode| mode.res.width.to_string())
+                    .unwrap_or_default(),
+                Placeholder::Height => mode
+                    .map(|mode| mode.res.height.to_string())
+                    .unwrap_or_default(),
+                Placeholder::Hz => mode
+                    .map(|mode| mode.frequency.to_string())
+                    .unwrap_or_default(),
+                Placeholder::X => crtc.map(|crtc| crtc.x.to_string()).unwrap_or_default(),
+                Placeholder::Y => crtc.map(|crtc| crtc.y.to_string()).unwrap_or_default(),
+                Placeholder::Rotation => crtc
+                    .map(|crtc| crtc.transform.to_string())
+                    .unwrap_or_default(),
+                Placeholder::Primary => match output.props.primary {
+                    Some(true) => "primary".to_string(),
+                    _ => String::new(),
+                },
+                Placeholder::Vendor => output.props.vendor.clone().unwrap_or_default(),
+                Placeholder::Serial => output.props.serial.clone().unwrap_or_default(),
+            };
+            line.push_str(&value);
+        }
+        line.truncate(line.trim_end().len());
+        line
+    }
+}
+
+/// Lines of query with `--format`, one for every output, or only enabled ones for
+/// `--query-current`.
+pub fn lines(
+    resources: &GetResourcesReturn,
+    template: &Template,
+    enabled_only: bool,
+) -> Vec<String> {
+    let mode_db = ModeDb::new(&resources.modes);
+    resources
+        .outputs
+        .iter()
+        .filter_map(|output| {
+            let crtc = resources
+                .crtc_of(output)
+                .filter(|crtc| crtc.mode_id.is_some());
+            let mode = crtc
+                .and_then(|crtc| crtc.mode_id)
+                .and_then(|id| mode_db.mode_by_id(id));
+            match mode {
+                None if enabled_only => None,
+                _ => Some(template.render(output, crtc, mode)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::tests::resources;
+
+    #[test]
+    fn parsed() {
+        let template: Template = "{{{name}}}: {hz}".parse().unwrap();
+        assert_eq!(
+            vec![
+                Segment::Text("{".to_string()),
+                Segment::Placeholder(Placeholder::Name),
+                Segment::Text("}: ".to_string()),
+                Segment::Placeholder(Placeholder::Hz),
+            ],
+            template.segments
+        );
+
+        let err = |format: &str| format.parse::<Template>().unwrap_err().to_string();
+        assert!(err("{nmae}").contains("did you mean name?"));
+        assert!(err("{rate}").starts_with("unknown placeholder {rate}"));
+        assert!(err("{name").contains("unclosed"));
+        assert!(err("name}").contains("unmatched"));
+    }
+
+    #[test]
+    fn current_outputs() {
+        let resources = resources();
+        assert_eq!(
+            vec![
+                "HDMI-1 1920x1080@60 +0+0 normal primary",
+                "DP-1 1920x1080@60 +1920+0 normal"
+            ],
+            lines(&resources, &Template::default(), true)
+        );
+        let template = "{name}:{x},{y}".parse().unwrap();
+        assert_eq!(
+            vec!["HDMI-1:0,0", "DP-1:1920,0"],
+            lines(&resources, &template, true)
+        );
+    }
+
+    #[test]
+    fn all_outputs() {
+        let mut resources = resources();
+        resources.outputs[0].props.vendor = Some("DEL".to_string());
+        resources.outputs[0].props.serial = Some("ABC123".to_string());
+        let template = "{name} {width} {height} {vendor} {serial}".parse().unwrap();
+        assert_eq!(
+            vec!["HDMI-1 1920 1080 DEL ABC123", "DP-1 1920 1080", "HDMI-2"],
+            lines(&resources, &template, false)
+        );
+    }
+
+    #[test]
+    fn large_resources_listed() {
+        let resources = crate::test_fixtures::large_resources(48, 600);
+        let current = lines(&resources, &"{name} {x}".parse().unwrap(), true);
+        // DP-2 clones DP-1 and is listed at its position
+        assert_eq!(24, current.len());
+        assert_eq!(vec!["DP-1 0", "DP-2 0", "DP-3 7680"], current[..3]);
+        assert_eq!(48, lines(&resources, &Template::default(), false).len());
+    }
+}
@@ -0,0 +1,64 @@
+//! Resources for tests and benches, compiled with the `test-fixtures` feature.
+
+use crate::dbus_api::{
+    ConfigSerial, CrtController, CrtcId, GetResourcesReturn, Mode, ModeId, Output, OutputId,
+    OutputProperties, Transform,
+};
+
+/// Resources like an MST dock with virtual displays: `outputs` outputs named `DP-1`, `DP-2` and
+/// so on, each supporting all `modes` modes. The first half is enabled side by side in mode 0,
+/// 3840x2160@60, except DP-2 that clones DP-1, so CRTC 1 is unused.
+pub fn large_resources(outputs: u32, modes: u32) -> GetResourcesReturn {
+    let modes: Vec<_> = (0..modes)
+        .map(|id| Mode {
+            id: ModeId(id),
+            width: 3840 - (id / 5) * 16,
+            height: 2160 - (id / 5) * 9,
+            frequency: [60.0, 59.94, 50.0, 75.0, 144.0][id as usize % 5],
+            ..Default::default()
+        })
+        .collect();
+    let enabled = outputs / 2;
+    let crtc_of = |id| match id {
+        1 => Some(CrtcId(0)),
+        id if id < enabled => Some(CrtcId(id)),
+        _ => None,
+    };
+    GetResourcesReturn::new(
+        ConfigSerial(1),
+        (0..outputs)
+            .map(|id| CrtController {
+                id: CrtcId(id),
+                winsys_id: id.into(),
+                x: id as i32 * 3840,
+                y: 0,
+                width: 3840,
+                height: 2160,
+                mode_id: (id < enabled && id != 1).then_some(ModeId(0)),
+                transform: Transform::Normal,
+                transforms: vec![0, 1, 2, 3],
+            })
+            .collect(),
+        (0..outputs)
+            .map(|id| Output {
+                id: OutputId(id),
+                winsys_id: id.into(),
+                crtc_id: crtc_of(id),
+                possible_crtc_ids: (0..outputs).map(CrtcId).collect(),
+                connector_name: format!("DP-{}", id + 1),
+                mode_ids: modes.iter().map(|mode| mode.id).collect(),
+                clone_ids: (0..outputs)
+                    .filter(|&other| other != id)
+                    .map(OutputId)
+                    .collect(),
+                props: OutputProperties {
+                    primary: Some(id == 0),
+                    ..Default::default()
+                },
+            })
+            .collect(),
+        modes,
+        65536,
+        65536,
+    )
+}
@@ -0,0 +1,62 @@
+//! Keeping text size when the scale of the primary monitor changes, enabled with
+//! `--sync-text-scale`.
+//!
+//! Text scale is the `text-scaling-factor` GSettings key. GSettings has no D-Bus interface of
+//! its own, so `gsettings` is used, which writes through the dconf D-Bus service.
+
+use std::process::Command;
+
+use anyhow::{anyhow, Context};
+
+const SCHEMA: &str = "org.gnome.desktop.interface";
+const KEY: &str = "text-scaling-factor";
+
+/// Limits of `text-scaling-factor` in the GSettings schema
+const RANGE: (f64, f64) = (0.5, 3.0);
+
+/// Text scale keeping text the same size after the monitor scale changes from `old_scale` to
+/// `new_scale`, like 1.25 text scale at scale 1 becomes 1 at scale 1.25.
+pub fn compensated(text_scale: f64, old_scale: f64, new_scale: f64) -> f64 {
+    let scaled = text_scale * old_scale / new_scale;
+    // GNOME Settings uses two decimals, more only makes the value look odd
+    ((scaled * 100.0).round() / 100.0).clamp(RANGE.0, RANGE.1)
+}
+
+fn gsettings(args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("gsettings")
+        .args(args)
+        .output()
+        .context("could not run gsettings")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "gsettings {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Updates text scale after the primary monitor scale changed, returning the new text scale.
+pub fn sync(old_scale: f64, new_scale: f64) -> anyhow::Result<f64> {
+    let current: f64 = gsettings(&["get", SCHEMA, KEY])?
+        .parse()
+        .with_context(|| format!("invalid {KEY}"))?;
+    let text_scale = compensated(current, old_scale, new_scale);
+    gsettings(&["set", SCHEMA, KEY, &text_scale.to_string()])?;
+    Ok(text_scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_scale_compensated() {
+        assert_eq!(1.0, compensated(1.25, 1.0, 1.25));
+        assert_eq!(1.25, compensated(1.0, 1.25, 1.0));
+        assert_eq!(0.67, compensated(1.0, 1.0, 1.5));
+        assert_eq!(0.5, compensated(1.0, 1.0, 4.0));
+        assert_eq!(3.0, compensated(2.0, 4.0, 1.0));
+    }
+}
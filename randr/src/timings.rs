@@ -0,0 +1,93 @@
+//! Timing report for a single invocation, enabled with `--timings`.
+//!
+//! Measurements are collected globally, so D-Bus calls and layout resolution could be timed
+//! without passing a collector through every API.
+
+use std::{
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+static TIMINGS: Mutex<Option<Vec<(&'static str, Duration)>>> = Mutex::new(None);
+
+/// Starts collecting measurements.
+pub fn enable() {
+    TIMINGS.lock().unwrap().get_or_insert_with(Vec::new);
+}
+
+/// Runs `f`, recording time it took under `label` if timings are enabled.
+pub fn measure<T>(label: &'static str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let value = f();
+    if let Some(timings) = TIMINGS.lock().unwrap().as_mut() {
+        timings.push((label, start.elapsed()));
+    }
+    value
+}
+
+/// Report of everything measured so far, `None` if timings are not enabled.
+pub fn report(total: Duration) -> Option<String> {
+    TIMINGS
+        .lock()
+        .unwrap()
+        .as_deref()
+        .map(|timings| format_report(timings, total))
+}
+
+/// Sums measurements with the same label, keeping the order labels were first measured in.
+fn format_report(timings: &[(&'static str, Duration)], total: Duration) -> String {
+    let mut summed: Vec<(&str, Duration, usize)> = Vec::new();
+    for &(label, duration) in timings {
+        match summed.iter_mut().find(|(l, _, _)| *l == label) {
+            Some((_, sum, count)) => {
+                *sum += duration;
+                *count += 1;
+            }
+            None => summed.push((label, duration, 1)),
+        }
+    }
+
+    let mut report = String::from("timings:\n");
+    for (label, duration, count) in summed {
+        let label = match count {
+            1 => label.to_string(),
+            count => format!("{label} (x{count})"),
+        };
+        writeln!(report, "  {label:<24} {duration:>10.3?}").unwrap();
+    }
+    writeln!(report, "  {:<24} {total:>10.3?}", "total").unwrap();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_labels_summed() {
+        let ms = Duration::from_millis;
+        let report = format_report(
+            &[
+                ("GetResources", ms(10)),
+                ("ModeDb", ms(1)),
+                ("ModeDb", ms(2)),
+            ],
+            ms(20),
+        );
+        let lines: Vec<_> = report
+            .lines()
+            .map(str::split_whitespace)
+            .map(Iterator::collect::<Vec<_>>)
+            .collect();
+        assert_eq!(
+            vec![
+                vec!["timings:"],
+                vec!["GetResources", "10.000ms"],
+                vec!["ModeDb", "(x2)", "3.000ms"],
+                vec!["total", "20.000ms"],
+            ],
+            lines
+        );
+    }
+}
@@ -0,0 +1,361 @@
+//! Several changes committed together: layout, backlight, gamma and power saving.
+//!
+//! Only layout changes are atomic in DisplayConfig, so [`Transaction::commit`] orders calls to
+//! leave as little half-applied as possible:
+//!
+//! 1. Every output is looked up and the layout is checked before anything changes.
+//! 2. Displays are woken up from power saving.
+//! 3. Backlight is changed.
+//! 4. Layout is applied. If that fails, backlight and power saving are restored.
+//! 5. Gamma is set, after the layout, since Mutter resets gamma on every modeset. If that fails,
+//!    gamma, backlight and power saving are restored, the layout is kept.
+//! 6. Displays are put into power saving.
+
+use anyhow::anyhow;
+
+use crate::{
+    cli::{GlobalArgs, OutputArgs},
+    dbus_api::{ChangeBacklightArgs, ConfigSerial, CrtcGamma, CrtcId, DisplayConfigApi, OutputId},
+    gamma::GammaSettings,
+    layout::{self, Layout},
+};
+
+/// PowerSaveMode with displays on, other values are 1 for standby, 2 for suspend and 3 for off.
+pub const POWER_SAVE_ON: i32 = 0;
+
+/// Changes collected with `set_*` methods and applied with [`Transaction::commit`].
+///
+/// # Examples
+/// ```no_run
+/// use gnome_randr::{
+///     cli::OutputArgsBuilder, dbus_api::DisplayConfig, gamma::GammaSettings,
+///     transaction::Transaction,
+/// };
+///
+/// let conn = dbus::blocking::Connection::new_session()?;
+/// let display_config = DisplayConfig::new(&conn);
+/// let mut transaction = Transaction::new(&display_config);
+/// transaction
+///     .set_mode(OutputArgsBuilder::default().name("eDP-1").auto(true).build()?)
+///     .set_backlight("eDP-1", 60)
+///     .set_gamma("eDP-1", GammaSettings { brightness: 1.0, temperature: 4500 });
+/// transaction.commit()?;
+/// # anyhow::Ok(())
+/// ```
+pub struct Transaction<'a, A: DisplayConfigApi> {
+    api: &'a A,
+    global: Option<&'a GlobalArgs>,
+    outputs: Vec<OutputArgs>,
+    backlight: Vec<(String, u32)>,
+    gamma: Vec<(String, GammaSettings)>,
+    power_save: Option<i32>,
+}
+
+/// Previous values of changes that are already made, restored if a later step fails.
+struct Undo<'a, A: DisplayConfigApi> {
+    api: &'a A,
+    serial: ConfigSerial,
+    backlight: Vec<(OutputId, i32)>,
+    gamma: Vec<(CrtcId, CrtcGamma)>,
+    power_save: Option<i32>,
+}
+
+impl<A: DisplayConfigApi> Undo<'_, A> {
+    /// Restores everything in reverse order, trying every step even if some fail.
+    fn run(self) -> anyhow::Result<()> {
+        let mut failed = Vec::new();
+        for (crtc, gamma) in self.gamma.into_iter().rev() {
+            if let Err(err) = self.api.set_crtc_gamma(self.serial, crtc, gamma) {
+                failed.push(format!("gamma of CRTC {crtc}: {err}"));
+            }
+        }
+        for (output, value) in self.backlight.into_iter().rev() {
+            let args = ChangeBacklightArgs {
+                serial: self.serial,
+                output,
+                value,
+            };
+            if let Err(err) = self.api.change_backlight(args) {
+                failed.push(format!("backlight of output {output}: {err}"));
+            }
+        }
+        if let Some(mode) = self.power_save {
+            if let Err(err) = self.api.set_power_save_mode(mode) {
+                failed.push(format!("power saving: {err}"));
+            }
+        }
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow!("could not restore {}", failed.join(", "))),
+        }
+    }
+}
+
+impl<'a, A: DisplayConfigApi> Transaction<'a, A> {
+    pub fn new(api: &'a A) -> Self {
+        Self {
+            api,
+            global: None,
+            outputs: Vec::new(),
+            backlight: Vec::new(),
+            gamma: Vec::new(),
+            power_save: None,
+        }
+    }
+
+    /// Options like `--force` or `--placement` used for layout changes, defaults otherwise.
+    pub fn with_global(mut self, global: &'a GlobalArgs) -> Self {
+        self.global = Some(global);
+        self
+    }
+
+    /// Changes an output like an `--output` block: mode, position, rotation and so on.
+    pub fn set_mode(&mut self, args: OutputArgs) -> &mut Self {
+        self.outputs.push(args);
+        self
+    }
+
+    /// Sets backlight of `output` in percents.
+    pub fn set_backlight(&mut self, output: &str, percent: u32) -> &mut Self {
+        self.backlight.push((output.to_string(), percent));
+        self
+    }
+
+    /// Sets gamma ramps of `output`. Unlike `--brightness`, settings are not stored, so they are
+    /// lost on the next modeset.
+    pub fn set_gamma(&mut self, output: &str, settings: GammaSettings) -> &mut Self {
+        self.gamma.push((output.to_string(), settings));
+        self
+    }
+
+    /// Sets PowerSaveMode of all displays, see [`POWER_SAVE_ON`].
+    pub fn set_power_save(&mut self, mode: i32) -> &mut Self {
+        self.power_save = Some(mode);
+        self
+    }
+
+    /// Applies every change, skipping ones that wouldn't change anything.
+    pub fn commit(self) -> anyhow::Result<()> {
+        let default_global = GlobalArgs::default();
+        let global = self.global.unwrap_or(&default_global);
+        let resources = self.api.get_resources()?;
+
+        // Mistakes fail before anything is changed
+        let mut preview = Layout::new(&resources);
+        for args in &self.outputs {
+            preview.apply(args)?;
+        }
+        let mut backlight = Vec::new();
+        for (name, percent) in &self.backlight {
+            let output = preview.output(name)?;
+            let current = output
+                .props
+                .backlight
+                .filter(|&backlight| backlight >= 0)
+                .ok_or_else(|| anyhow!("output {name} doesn't support backlight"))?;
+            if current != i64::from(*percent) {
+                backlight.push((name, output.id, current as i32, *percent as i32));
+            }
+        }
+        for (name, _) in &self.gamma {
+            preview.output(name)?;
+        }
+        let power_save = match self.power_save {
+            Some(mode) => {
+                let current = self.api.power_save_mode()?;
+                (current != mode).then_some((current, mode))
+            }
+            None => None,
+        };
+
+        let mut undo = Undo {
+            api: self.api,
+            serial: resources.serial,
+            backlight: Vec::new(),
+            gamma: Vec::new(),
+            power_save: None,
+        };
+        // Changes of displays that are asleep might not take effect
+        if let Some((current, POWER_SAVE_ON)) = power_save {
+            self.api.set_power_save_mode(POWER_SAVE_ON)?;
+            undo.power_save = Some(current);
+        }
+        for (name, output, current, value) in backlight {
+            let args = ChangeBacklightArgs {
+                serial: resources.serial,
+                output,
+                value,
+            };
+            if let Err(err) = self.api.change_backlight(args) {
+                let err = anyhow!("could not change backlight of {name}: {err}");
+                return Err(rolled_back(err, undo));
+            }
+            undo.backlight.push((output, current));
+        }
+        if !self.outputs.is_empty() {
+            if let Err(err) = layout::apply_outputs(self.api, global, &self.outputs) {
+                return Err(rolled_back(err, undo));
+            }
+        }
+
+        if !self.gamma.is_empty() {
+            // Applied layout changes serial and could move outputs to other CRTCs
+            let resources = match self.outputs.is_empty() {
+                true => resources,
+                false => match self.api.get_resources() {
+                    Ok(resources) => resources,
+                    Err(err) => return Err(rolled_back(err.into(), undo)),
+                },
+            };
+            undo.serial = resources.serial;
+            let layout = Layout::new(&resources);
+            for (name, settings) in &self.gamma {
+                let set = layout
+                    .output(name)
+                    .and_then(|output| {
+                        output
+                            .crtc_id
+                            .ok_or_else(|| anyhow!("output {name} is disabled, gamma can't be set"))
+                    })
+                    .and_then(|crtc| {
+                        let previous = self.api.get_crtc_gamma(resources.serial, crtc)?;
+                        let ramp = settings.ramp(previous.len());
+                        self.api.set_crtc_gamma(resources.serial, crtc, ramp)?;
+                        Ok((crtc, previous))
+                    });
+                match set {
+                    Ok(previous) => undo.gamma.push(previous),
+                    Err(err) => {
+                        let err = anyhow!("could not set gamma of {name}: {err:#}");
+                        return Err(rolled_back(err, undo));
+                    }
+                }
+            }
+        }
+
+        if let Some((_, mode)) = power_save.filter(|&(_, mode)| mode != POWER_SAVE_ON) {
+            self.api.set_power_save_mode(mode)?;
+        }
+        Ok(())
+    }
+}
+
+/// `err` of a failed step, after changes made before it are restored.
+fn rolled_back<A: DisplayConfigApi>(err: anyhow::Error, undo: Undo<'_, A>) -> anyhow::Error {
+    match undo.run() {
+        Ok(()) => err,
+        Err(undo_err) => anyhow!("{err:#}, and {undo_err:#}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cli::OutputArgsBuilder, layout::tests::ScriptedApi};
+
+    fn hdmi1_50hz() -> OutputArgs {
+        OutputArgsBuilder::default()
+            .name("HDMI-1")
+            .framerate(50)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn committed_in_order() {
+        let api = ScriptedApi::default();
+        let mut transaction = Transaction::new(&api);
+        transaction
+            .set_gamma("HDMI-1", GammaSettings::default())
+            .set_power_save(POWER_SAVE_ON)
+            .set_backlight("HDMI-1", 80)
+            .set_mode(hdmi1_50hz());
+        transaction.commit().unwrap();
+        assert_eq!(
+            vec!["power 0", "backlight 0 80", "apply", "gamma 0 linear 256"],
+            api.calls()
+        );
+    }
+
+    #[test]
+    fn unchanged_values_skipped() {
+        let api = ScriptedApi::default();
+        let mut transaction = Transaction::new(&api);
+        transaction.set_power_save(3).set_backlight("HDMI-1", 50);
+        transaction.commit().unwrap();
+        assert!(api.calls().is_empty());
+    }
+
+    #[test]
+    fn rolled_back_on_failed_modeset() {
+        let api = ScriptedApi::failing("apply");
+        let mut transaction = Transaction::new(&api);
+        transaction
+            .set_power_save(POWER_SAVE_ON)
+            .set_backlight("HDMI-1", 80)
+            .set_gamma("HDMI-1", GammaSettings::default())
+            .set_mode(hdmi1_50hz());
+        let err = transaction.commit().unwrap_err();
+        assert!(format!("{err:#}").contains("apply failed"));
+        assert_eq!(
+            vec![
+                "power 0",
+                "backlight 0 80",
+                "apply",
+                "backlight 0 50",
+                "power 3"
+            ],
+            api.calls()
+        );
+    }
+
+    #[test]
+    fn mistakes_change_nothing() {
+        let api = ScriptedApi::default();
+        let mut transaction = Transaction::new(&api);
+        transaction.set_backlight("HDMI-1", 80).set_mode(
+            OutputArgsBuilder::default()
+                .name("HDMI-1")
+                .framerate(144)
+                .build()
+                .unwrap(),
+        );
+        assert!(transaction.commit().is_err());
+        let mut transaction = Transaction::new(&api);
+        transaction.set_backlight("HDMI-2", 80);
+        assert!(transaction
+            .commit()
+            .is_err_and(|err| err.to_string().contains("doesn't support backlight")));
+        let mut transaction = Transaction::new(&api);
+        transaction
+            .set_power_save(1)
+            .set_gamma("HDMI-3", GammaSettings::default());
+        assert!(transaction.commit().is_err());
+        assert!(api.calls().is_empty());
+    }
+
+    #[test]
+    fn rolled_back_on_failed_gamma() {
+        let api = ScriptedApi::failing("gamma 1");
+        let mut transaction = Transaction::new(&api);
+        transaction
+            .set_backlight("DP-1", 90)
+            .set_gamma("HDMI-1", GammaSettings::default())
+            .set_gamma("DP-1", GammaSettings::default());
+        let err = transaction.commit().unwrap_err();
+        assert_eq!(
+            "could not set gamma of DP-1: gamma 1 linear 257 failed",
+            err.to_string()
+        );
+        assert_eq!(
+            vec![
+                "backlight 1 90",
+                "gamma 0 linear 256",
+                "gamma 1 linear 257",
+                "gamma 0 linear 256",
+                "backlight 1 70"
+            ],
+            api.calls()
+        );
+    }
+}
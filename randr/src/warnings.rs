@@ -0,0 +1,206 @@
+//! Warnings and notes reported on stderr, so stdout only has query data. Notes tell about
+//! changes made on the user's behalf, like restoring a locked snapshot.
+//!
+//! Like timings, the format is set globally, so warnings could be reported from D-Bus calls and
+//! layout resolution without passing a reporter through every API.
+
+use std::{
+    fmt::{Display, Write},
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::anyhow;
+
+use crate::i18n;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// How warnings are printed, set with `--warnings`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `warning: <message>` lines
+    #[default]
+    Text,
+    /// A JSON object per line, with `kind`, `level` and `message` keys
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(anyhow!(
+                "unknown warnings format {s}, expected text or json"
+            )),
+        }
+    }
+}
+
+/// What a warning is about, so tools could filter warnings without matching messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Value in a D-Bus reply was replaced with a default
+    InvalidReply,
+    /// Scale is unsupported or inconsistent with other outputs
+    Scale,
+    /// Gamma settings were not remembered or re-applied
+    Gamma,
+    /// State file or notification was not updated after a change
+    Status,
+    /// Locked snapshot was restored or could not be
+    Lock,
+    /// Watch hook failed
+    Hook,
+    /// Recording with `--record` stopped
+    Record,
+    /// Windows were not moved back with `--preserve-windows`
+    Windows,
+    /// colord profiles could not be listed
+    Color,
+    /// Configuration was not reloaded
+    Config,
+    /// Power events are not reported
+    Power,
+    /// Change of an output affects outputs sharing its CRTC
+    Clone,
+    /// Layout was re-applied after resume
+    Resume,
+    /// Layout was changed because the lid closed or opened
+    Lid,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::InvalidReply => "invalid-reply",
+            Kind::Scale => "scale",
+            Kind::Gamma => "gamma",
+            Kind::Status => "status",
+            Kind::Lock => "lock",
+            Kind::Hook => "hook",
+            Kind::Record => "record",
+            Kind::Windows => "windows",
+            Kind::Color => "color",
+            Kind::Config => "config",
+            Kind::Power => "power",
+            Kind::Clone => "clone",
+            Kind::Resume => "resume",
+            Kind::Lid => "lid",
+        }
+    }
+}
+
+pub fn set_format(format: Format) {
+    JSON.store(format == Format::Json, Ordering::Relaxed);
+}
+
+/// Prints a warning to stderr in the format set with [`set_format`].
+pub fn warn(kind: Kind, message: impl Display) {
+    report(Level::Warning, kind, &message.to_string());
+}
+
+/// Prints a note to stderr in the format set with [`set_format`].
+pub fn note(kind: Kind, message: impl Display) {
+    report(Level::Note, kind, &message.to_string());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Warning,
+    Note,
+}
+
+impl Level {
+    fn as_str(self) -> &'static str {
+        match self {
+            Level::Warning => "warning",
+            Level::Note => "note",
+        }
+    }
+}
+
+fn report(level: Level, kind: Kind, message: &str) {
+    let format = match JSON.load(Ordering::Relaxed) {
+        true => Format::Json,
+        false => Format::Text,
+    };
+    eprintln!("{}", format_warning(format, level, kind, message));
+}
+
+fn format_warning(format: Format, level: Level, kind: Kind, message: &str) -> String {
+    match format {
+        Format::Text => i18n::global().message(level.as_str(), &[("message", message.to_string())]),
+        Format::Json => format!(
+            r#"{{"kind":"{}","level":"{}","message":"{}"}}"#,
+            kind.as_str(),
+            level.as_str(),
+            json_escape(message)
+        ),
+    }
+}
+
+pub(crate) fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_formatted() {
+        assert_eq!(
+            "warning: scale 1.25 is not supported",
+            format_warning(
+                Format::Text,
+                Level::Warning,
+                Kind::Scale,
+                "scale 1.25 is not supported"
+            )
+        );
+        assert_eq!(
+            r#"{"kind":"hook","level":"warning","message":"\"exit 1\" exited\nwith\u0007 \\"}"#,
+            format_warning(
+                Format::Json,
+                Level::Warning,
+                Kind::Hook,
+                "\"exit 1\" exited\nwith\x07 \\"
+            )
+        );
+        assert_eq!(
+            "note: restoring locked snapshot",
+            format_warning(
+                Format::Text,
+                Level::Note,
+                Kind::Lock,
+                "restoring locked snapshot"
+            )
+        );
+        assert_eq!(
+            r#"{"kind":"lock","level":"note","message":"restoring locked snapshot"}"#,
+            format_warning(
+                Format::Json,
+                Level::Note,
+                Kind::Lock,
+                "restoring locked snapshot"
+            )
+        );
+        assert_eq!(Format::Json, "json".parse().unwrap());
+        assert!("yaml".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,638 @@
+//! Reporting output changes as they happen, optionally running a user command for each of them.
+//!
+//! Configuration is reloaded on SIGHUP or when one of its files changes, so edited aliases are
+//! used by the next event without restarting.
+//!
+//! With a `[lid]` section in configuration, closing the lid turns the internal panel off and
+//! opening it restores the previous layout, see [`crate::lid`].
+
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use dbus::blocking::Connection;
+
+use crate::{
+    apply_lock,
+    cancel::Cancel,
+    cli::{GlobalArgs, WatchArgs},
+    config::Config,
+    daemon::Server,
+    dbus_api::{self, DisplayConfig, DisplayConfigApi, GetResourcesReturn},
+    debounce::Debounce,
+    gamma,
+    lid::{self, LidPolicy},
+    lock::{OutputState, Snapshot},
+    logind,
+    mode_db::RoundedMode,
+    profiles, progress, status,
+    warnings::{self, Kind},
+};
+
+/// What happened to an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Action {
+    Connected,
+    Disconnected,
+    Enabled,
+    Disabled,
+    /// Mode, position or transform of an enabled output changed
+    Changed,
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Action::Connected => "connected",
+            Action::Disconnected => "disconnected",
+            Action::Enabled => "enabled",
+            Action::Disabled => "disabled",
+            Action::Changed => "changed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub connector: String,
+    pub action: Action,
+    /// Mode after the event, `None` if output is disabled
+    pub mode: Option<RoundedMode>,
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.connector, self.action)?;
+        if let Some(mode) = self.mode {
+            write!(f, " {mode}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Power state change, not tied to an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PowerEvent {
+    /// PowerSaveMode of DisplayConfig changed, like monitors blanking
+    PowerSaveMode(i32),
+    /// System is about to suspend
+    Sleep,
+    /// System woke up
+    Resume,
+    /// Laptop lid closed, as reported by UPower
+    LidClosed,
+    /// Laptop lid opened
+    LidOpened,
+}
+
+impl PowerEvent {
+    /// Name of a PowerSaveMode value, as documented by DisplayConfig.
+    fn power_save_mode_name(mode: i32) -> &'static str {
+        match mode {
+            0 => "on",
+            1 => "standby",
+            2 => "suspend",
+            3 => "off",
+            _ => "unknown",
+        }
+    }
+
+    /// Environment variables describing the event for `--exec` commands, the connector is
+    /// empty.
+    pub fn env(&self) -> [(&'static str, String); 3] {
+        let (action, mode) = match self {
+            PowerEvent::PowerSaveMode(mode) => {
+                ("power-save-mode", Self::power_save_mode_name(*mode))
+            }
+            PowerEvent::Sleep => ("sleep", ""),
+            PowerEvent::Resume => ("resume", ""),
+            PowerEvent::LidClosed => ("lid-closed", ""),
+            PowerEvent::LidOpened => ("lid-opened", ""),
+        };
+        [
+            ("GNOME_RANDR_CONNECTOR", String::new()),
+            ("GNOME_RANDR_ACTION", action.to_string()),
+            ("GNOME_RANDR_POWER_SAVE_MODE", mode.to_string()),
+        ]
+    }
+}
+
+impl Display for PowerEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PowerEvent::PowerSaveMode(mode) => {
+                write!(f, "power-save-mode {}", Self::power_save_mode_name(*mode))
+            }
+            PowerEvent::Sleep => f.write_str("sleep"),
+            PowerEvent::Resume => f.write_str("resume"),
+            PowerEvent::LidClosed => f.write_str("lid-closed"),
+            PowerEvent::LidOpened => f.write_str("lid-opened"),
+        }
+    }
+}
+
+/// Power events queued while connections are processed. The system bus is optional, without it
+/// only PowerSaveMode changes are reported, and so is UPower, which reports the lid.
+struct PowerEvents {
+    queue: Arc<Mutex<Vec<PowerEvent>>>,
+    system: Option<Connection>,
+}
+
+impl PowerEvents {
+    fn subscribe(conn: &Connection) -> anyhow::Result<Self> {
+        let queue = Arc::new(Mutex::new(Vec::new()));
+        dbus_api::on_power_save_mode_changed(conn, {
+            let queue = queue.clone();
+            move |mode| queue.lock().unwrap().push(PowerEvent::PowerSaveMode(mode))
+        })?;
+        let system = Connection::new_system().and_then(|system| {
+            logind::on_prepare_for_sleep(&system, {
+                let queue = queue.clone();
+                move |sleeping| {
+                    let event = match sleeping {
+                        true => PowerEvent::Sleep,
+                        false => PowerEvent::Resume,
+                    };
+                    queue.lock().unwrap().push(event);
+                }
+            })?;
+            lid::on_lid_changed(&system, {
+                let queue = queue.clone();
+                move |closed| {
+                    let event = match closed {
+                        true => PowerEvent::LidClosed,
+                        false => PowerEvent::LidOpened,
+                    };
+                    queue.lock().unwrap().push(event);
+                }
+            })?;
+            Ok(system)
+        });
+        let system = system
+            .map_err(|err| {
+                let err = err.message().unwrap_or("unknown error");
+                warnings::warn(
+                    Kind::Power,
+                    format!("suspend and resume are not reported: {err}"),
+                )
+            })
+            .ok();
+        Ok(Self { queue, system })
+    }
+
+    /// Events received so far, the session bus is processed by the caller.
+    fn take(&self) -> Result<Vec<PowerEvent>, dbus::Error> {
+        if let Some(system) = &self.system {
+            system.process(Duration::ZERO)?;
+        }
+        Ok(std::mem::take(&mut *self.queue.lock().unwrap()))
+    }
+}
+
+impl Event {
+    /// Environment variables describing the event for `--exec` commands, alias is taken from
+    /// `config`.
+    pub fn env(&self, config: &Config) -> [(&'static str, String); 4] {
+        [
+            ("GNOME_RANDR_CONNECTOR", self.connector.clone()),
+            (
+                "GNOME_RANDR_ALIAS",
+                config
+                    .alias_of(&self.connector)
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            ("GNOME_RANDR_ACTION", self.action.to_string()),
+            (
+                "GNOME_RANDR_MODE",
+                self.mode.map(|mode| mode.to_string()).unwrap_or_default(),
+            ),
+        ]
+    }
+}
+
+/// Events that turn `old` resources into `new`, ordered by connector.
+pub fn events(old: &GetResourcesReturn, new: &GetResourcesReturn) -> Vec<Event> {
+    let (old_states, new_states) = (Snapshot::capture(old), Snapshot::capture(new));
+    let state = |snapshot: &'_ Snapshot, connector: &str| -> Option<OutputState> {
+        snapshot
+            .outputs
+            .iter()
+            .find(|state| state.connector == connector)
+            .cloned()
+    };
+    let connected = |resources: &GetResourcesReturn, connector: &str| {
+        resources
+            .outputs
+            .iter()
+            .any(|output| output.connector_name == connector)
+    };
+
+    let mut connectors: Vec<_> = old
+        .outputs
+        .iter()
+        .chain(&new.outputs)
+        .map(|output| output.connector_name.as_str())
+        .collect();
+    connectors.sort();
+    connectors.dedup();
+
+    let mut events = Vec::new();
+    for connector in connectors {
+        let (before, after) = (state(&old_states, connector), state(&new_states, connector));
+        let mut push = |action| {
+            events.push(Event {
+                connector: connector.to_string(),
+                action,
+                mode: after.as_ref().map(|state| state.mode),
+            })
+        };
+        match (connected(old, connector), connected(new, connector)) {
+            (false, true) => push(Action::Connected),
+            (true, false) => push(Action::Disconnected),
+            _ => {}
+        }
+        match (&before, &after) {
+            (None, Some(_)) => push(Action::Enabled),
+            (Some(_), None) => push(Action::Disabled),
+            (Some(before), Some(after)) if before != after => push(Action::Changed),
+            _ => {}
+        }
+    }
+    events
+}
+
+/// Configuration of a running watch, together with what's needed to notice it changed.
+struct WatchedConfig {
+    path: Option<PathBuf>,
+    config: Config,
+    /// Modification times of configuration files when they were read
+    modified: Vec<Option<SystemTime>>,
+    hangup: Arc<AtomicBool>,
+}
+
+impl WatchedConfig {
+    fn new(path: Option<&Path>) -> anyhow::Result<Self> {
+        let hangup = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::consts::SIGHUP, hangup.clone())?;
+        let mut watched = Self {
+            path: path.map(Path::to_path_buf),
+            config: Config::default(),
+            modified: Vec::new(),
+            hangup,
+        };
+        if let Some(path) = path {
+            watched.config = Config::load(path)?;
+            watched.modified = modification_times(&watched.config.files);
+        }
+        Ok(watched)
+    }
+
+    /// Reloads configuration if SIGHUP was received or its files changed. Broken configuration
+    /// is reported and the previous one is kept.
+    fn reload_if_changed(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let hangup = self.hangup.swap(false, Ordering::Relaxed);
+        if !hangup && modification_times(&self.config.files) == self.modified {
+            return;
+        }
+        match Config::load(path) {
+            Ok(config) => {
+                self.modified = modification_times(&config.files);
+                self.config = config;
+            }
+            Err(err) => {
+                // Not retried until the files change again
+                self.modified = modification_times(&self.config.files);
+                warnings::warn(
+                    Kind::Config,
+                    format!("keeping previous configuration: {err:#}"),
+                );
+            }
+        }
+    }
+}
+
+fn modification_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| fs::metadata(file).and_then(|meta| meta.modified()).ok())
+        .collect()
+}
+
+/// Prints every output event until cancelled, running `--exec` command for each of them.
+/// Configuration is read from `config_path` and reloaded while watching. Bursts of changes are
+/// reported together once they settle.
+pub fn watch(
+    conn: &Connection,
+    display_config: &DisplayConfig,
+    args: &WatchArgs,
+    global: &GlobalArgs,
+    config_path: Option<&Path>,
+    cancel: &Cancel,
+) -> anyhow::Result<()> {
+    let mut config = WatchedConfig::new(config_path)?;
+    let changed = Arc::new(AtomicBool::new(false));
+    dbus_api::on_monitors_changed(conn, {
+        let changed = changed.clone();
+        move || changed.store(true, Ordering::Relaxed)
+    })?;
+    let server = match args.serve {
+        true => Some(Server::start(conn)?),
+        false => None,
+    };
+    let power_events = PowerEvents::subscribe(conn)?;
+    let mut debounce = Debounce::from_args(global);
+    let mut resources = display_config.get_resources()?;
+    progress::report("startup", 100.0);
+    // Layout from before suspend, re-applied once changes after resume settle
+    let mut before_sleep: Option<Snapshot> = None;
+    let mut resumed = false;
+    // Layout from before the lid closed, restored once it opens
+    let mut before_lid: Option<Snapshot> = None;
+    while !cancel.is_cancelled() {
+        conn.process(debounce.poll_interval())?;
+        config.reload_if_changed();
+        if let Some(server) = &server {
+            server.handle(conn, display_config, global)?;
+        }
+        for event in power_events.take()? {
+            println!("{event}");
+            if let Some(cmd) = &args.exec {
+                run_hook(cmd, event.env());
+            }
+            match event {
+                PowerEvent::Sleep if args.reapply_on_resume => {
+                    before_sleep = Some(Snapshot::capture(&resources));
+                }
+                PowerEvent::Resume if args.reapply_on_resume => {
+                    resumed = true;
+                    // Monitors might not come back at all, so resume counts as a change
+                    debounce.changed(Instant::now());
+                }
+                PowerEvent::LidClosed | PowerEvent::LidOpened => {
+                    let Some(policy) = &config.config.lid else {
+                        continue;
+                    };
+                    let policy = LidPolicy {
+                        internal: (policy.internal.as_deref())
+                            .map(|name| config.config.resolve(name).to_string()),
+                        ..policy.clone()
+                    };
+                    let result = match event {
+                        PowerEvent::LidClosed => {
+                            lid_closed(display_config, global, &policy, &mut before_lid)
+                        }
+                        _ => lid_opened(display_config, global, &policy, before_lid.take()),
+                    };
+                    if let Err(err) = result {
+                        warnings::warn(
+                            Kind::Power,
+                            format!("could not apply lid policy on {event}: {err:#}"),
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+        if changed.swap(false, Ordering::Relaxed) {
+            debounce.changed(Instant::now());
+        }
+        if !debounce.ready(Instant::now()) {
+            continue;
+        }
+        let new = display_config.get_resources()?;
+        for event in events(&resources, &new) {
+            println!("{event}");
+            if let Some(cmd) = &args.exec {
+                run_hook(cmd, event.env(&config.config));
+            }
+        }
+        resources = new;
+        if std::mem::take(&mut resumed) {
+            let profile = status::state_dir().and_then(|dir| status::current_profile(&dir));
+            let reapplied =
+                reapply_after_resume(display_config, global, profile, before_sleep.take());
+            if let Err(err) = reapplied {
+                warnings::warn(
+                    Kind::Power,
+                    format!("could not re-apply layout after resume: {err:#}"),
+                );
+            }
+        }
+        if args.reapply_gamma || args.reapply_on_resume {
+            if let Err(err) = gamma::reapply(display_config, &[]) {
+                warnings::warn(Kind::Gamma, format!("could not re-apply gamma: {err:#}"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Re-applies `profile`, which is the current one, or `before_sleep` layout if no profile is
+/// current. Docks sometimes bring monitors back with a different mode.
+fn reapply_after_resume(
+    api: &impl DisplayConfigApi,
+    global: &GlobalArgs,
+    profile: Option<String>,
+    before_sleep: Option<Snapshot>,
+) -> anyhow::Result<()> {
+    if let Some(name) = profile {
+        warnings::note(
+            Kind::Resume,
+            format!("re-applying profile {name} after resume"),
+        );
+        return profiles::apply(api, global, &name);
+    }
+    let Some(snapshot) = before_sleep else {
+        return Ok(());
+    };
+    let _lock = apply_lock::acquire(global.lock_timeout)?;
+    let resources = api.get_resources()?;
+    if snapshot.deviates(&resources) {
+        warnings::note(Kind::Resume, "re-applying layout from before suspend");
+        api.apply_configuration(snapshot.restore(&resources)?)?;
+    }
+    Ok(())
+}
+
+/// Applies `closed` profile of `policy`, or turns the internal panel off while other outputs stay
+/// enabled. Current layout is saved in `before_lid` to be restored when the lid opens.
+fn lid_closed(
+    display_config: &DisplayConfig,
+    global: &GlobalArgs,
+    policy: &LidPolicy,
+    before_lid: &mut Option<Snapshot>,
+) -> anyhow::Result<()> {
+    if let Some(name) = &policy.closed {
+        *before_lid = Some(Snapshot::capture(&display_config.get_resources()?));
+        warnings::note(
+            Kind::Lid,
+            format!("applying profile {name} while the lid is closed"),
+        );
+        return profiles::apply(display_config, global, name);
+    }
+    let _lock = apply_lock::acquire(global.lock_timeout)?;
+    let resources = display_config.get_resources()?;
+    if let Some(config) = policy.closed_config(&resources)? {
+        *before_lid = Some(Snapshot::capture(&resources));
+        warnings::note(
+            Kind::Lid,
+            "turning the internal panel off while the lid is closed",
+        );
+        display_config.apply_configuration(config)?;
+    }
+    Ok(())
+}
+
+/// Applies `opened` profile of `policy`, or restores `before_lid` layout.
+fn lid_opened(
+    display_config: &DisplayConfig,
+    global: &GlobalArgs,
+    policy: &LidPolicy,
+    before_lid: Option<Snapshot>,
+) -> anyhow::Result<()> {
+    if let Some(name) = &policy.opened {
+        warnings::note(
+            Kind::Lid,
+            format!("applying profile {name} after the lid opened"),
+        );
+        return profiles::apply(display_config, global, name);
+    }
+    let Some(snapshot) = before_lid else {
+        return Ok(());
+    };
+    let _lock = apply_lock::acquire(global.lock_timeout)?;
+    let resources = display_config.get_resources()?;
+    if snapshot.deviates(&resources) {
+        warnings::note(Kind::Lid, "restoring layout from before the lid closed");
+        display_config.apply_configuration(snapshot.restore(&resources)?)?;
+    }
+    Ok(())
+}
+
+/// Hook failures are reported, but don't stop watching.
+fn run_hook<const N: usize>(cmd: &str, env: [(&'static str, String); N]) {
+    let status = process::Command::new("sh")
+        .args(["-c", cmd])
+        .envs(env)
+        .status();
+    match status {
+        Ok(status) if !status.success() => {
+            warnings::warn(Kind::Hook, format!("{cmd:?} exited with {status}"))
+        }
+        Err(err) => warnings::warn(Kind::Hook, format!("could not run {cmd:?}: {err}")),
+        Ok(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dbus_api::{CrtcId, ModeId},
+        layout::tests::{resources, ScriptedApi},
+    };
+
+    #[test]
+    fn output_events() {
+        let old = resources();
+        let mut new = resources();
+        // HDMI-2 enabled, DP-1 unplugged, HDMI-1 switched to 50Hz
+        new.crtcs[2].mode_id = Some(ModeId(2));
+        new.outputs[2].crtc_id = Some(CrtcId(2));
+        new.outputs.remove(1);
+        new.crtcs[1].mode_id = None;
+        new.crtcs[0].mode_id = Some(ModeId(1));
+
+        let described: Vec<_> = events(&old, &new).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            vec![
+                "DP-1 disconnected",
+                "DP-1 disabled",
+                "HDMI-1 changed 1920x1080@50",
+                "HDMI-2 enabled 1280x720@60",
+            ],
+            described
+        );
+        assert!(events(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn power_events_described() {
+        assert_eq!(
+            "power-save-mode off",
+            PowerEvent::PowerSaveMode(3).to_string()
+        );
+        assert_eq!("resume", PowerEvent::Resume.to_string());
+        assert_eq!("lid-closed", PowerEvent::LidClosed.to_string());
+        let env = PowerEvent::PowerSaveMode(1).env();
+        assert_eq!(
+            ("GNOME_RANDR_ACTION", "power-save-mode".to_string()),
+            env[1]
+        );
+        assert_eq!(
+            ("GNOME_RANDR_POWER_SAVE_MODE", "standby".to_string()),
+            env[2]
+        );
+    }
+
+    #[test]
+    fn config_reloaded() {
+        let path = std::env::temp_dir().join(format!("gnome-randr-watch-{}", process::id()));
+        fs::write(&path, "[aliases]\nTV = HDMI-1\n").unwrap();
+        let mut watched = WatchedConfig::new(Some(&path)).unwrap();
+        assert_eq!(Some("TV"), watched.config.alias_of("HDMI-1"));
+
+        fs::write(&path, "[aliases]\nProjector = HDMI-1\n").unwrap();
+        watched.hangup.store(true, Ordering::Relaxed);
+        watched.reload_if_changed();
+        assert_eq!(Some("Projector"), watched.config.alias_of("HDMI-1"));
+
+        fs::write(&path, "[aliases]\nbroken\n").unwrap();
+        watched.hangup.store(true, Ordering::Relaxed);
+        watched.reload_if_changed();
+        assert_eq!(Some("Projector"), watched.config.alias_of("HDMI-1"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn deviating_layout_restored_after_resume() {
+        let api = ScriptedApi::default();
+        let mut before_sleep = api.get_resources().unwrap();
+        before_sleep.crtcs[1].mode_id = None;
+        before_sleep.outputs[1].crtc_id = None;
+        let snapshot = Snapshot::capture(&before_sleep);
+
+        reapply_after_resume(&api, &GlobalArgs::default(), None, Some(snapshot)).unwrap();
+        assert_eq!(vec!["apply"], api.calls());
+    }
+
+    #[test]
+    fn matching_layout_kept_after_resume() {
+        let api = ScriptedApi::default();
+        let snapshot = Snapshot::capture(&api.get_resources().unwrap());
+
+        reapply_after_resume(&api, &GlobalArgs::default(), None, Some(snapshot)).unwrap();
+        assert!(api.calls().is_empty());
+    }
+
+    #[test]
+    fn nothing_reapplied_without_snapshot() {
+        let api = ScriptedApi::default();
+        reapply_after_resume(&api, &GlobalArgs::default(), None, None).unwrap();
+        assert!(api.calls().is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+//! Moving windows back to the monitors they were on before a layout change, with
+//! `--preserve-windows`.
+//!
+//! Windows are only reachable through GNOME Shell's `Eval`, which recent GNOME versions only
+//! allow in unsafe mode or with an extension providing it, so failures are reported as warnings.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::{anyhow, Context};
+use dbus::blocking::Connection;
+use serde::Deserialize;
+
+/// Connector of the monitor every window was on, by window ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowPlacement {
+    pub monitors: BTreeMap<u64, String>,
+}
+
+#[derive(Deserialize)]
+struct Captured {
+    /// Monitor index of every connector, -1 for disabled ones
+    monitors: BTreeMap<String, i32>,
+    /// Window ID and monitor index pairs
+    windows: Vec<(u64, i32)>,
+}
+
+fn eval(conn: &Connection, script: &str) -> anyhow::Result<String> {
+    let proxy = conn.with_proxy(
+        "org.gnome.Shell",
+        "/org/gnome/Shell",
+        Duration::from_millis(5000),
+    );
+    let (success, result): (bool, String) = proxy
+        .method_call("org.gnome.Shell", "Eval", (script,))
+        .context("could not call GNOME Shell Eval")?;
+    match success {
+        true => Ok(result),
+        // Result is empty if Eval is disabled, which is the default outside of unsafe mode
+        false if result.is_empty() => Err(anyhow!(
+            "GNOME Shell Eval is disabled, enable unsafe mode in Looking Glass to preserve windows"
+        )),
+        false => Err(anyhow!("GNOME Shell Eval failed: {result}")),
+    }
+}
+
+fn capture_script(connectors: &[String]) -> String {
+    let connectors = serde_json::to_string(connectors).expect("Strings are representable in JSON");
+    format!(
+        "const manager = global.backend.get_monitor_manager(); \
+         JSON.stringify({{\
+         monitors: Object.fromEntries({connectors}.map(c => [c, manager.get_monitor_for_connector(c)])), \
+         windows: global.display.list_all_windows().map(w => [w.get_id(), w.get_monitor()])}})"
+    )
+}
+
+fn parse_capture(result: &str) -> anyhow::Result<WindowPlacement> {
+    // Eval returns JSON.stringify result as a JSON string
+    let json: String = serde_json::from_str(result).unwrap_or_else(|_| result.to_string());
+    let captured: Captured =
+        serde_json::from_str(&json).context("unexpected reply of GNOME Shell Eval")?;
+    let connectors: BTreeMap<_, _> = captured
+        .monitors
+        .into_iter()
+        .filter(|&(_, index)| index >= 0)
+        .map(|(connector, index)| (index, connector))
+        .collect();
+    let monitors = captured
+        .windows
+        .into_iter()
+        .filter_map(|(id, index)| Some((id, connectors.get(&index)?.clone())))
+        .collect();
+    Ok(WindowPlacement { monitors })
+}
+
+fn restore_script(placement: &WindowPlacement) -> String {
+    let monitors =
+        serde_json::to_string(&placement.monitors).expect("Strings are representable in JSON");
+    format!(
+        "const manager = global.backend.get_monitor_manager(); \
+         const monitors = {monitors}; \
+         let moved = 0; \
+         for (const w of global.display.list_all_windows()) {{ \
+         const connector = monitors[w.get_id()]; \
+         const index = connector === undefined ? -1 : manager.get_monitor_for_connector(connector); \
+         if (index >= 0 && index !== w.get_monitor()) {{ w.move_to_monitor(index); moved++; }} \
+         }} \
+         moved"
+    )
+}
+
+/// Records which monitor every window is on, `connectors` are every connector of resources.
+pub fn capture(conn: &Connection, connectors: &[String]) -> anyhow::Result<WindowPlacement> {
+    parse_capture(&eval(conn, &capture_script(connectors))?)
+}
+
+/// Moves windows back to monitors of their connectors, if they are still enabled. Returns the
+/// number of moved windows.
+pub fn restore(conn: &Connection, placement: &WindowPlacement) -> anyhow::Result<u32> {
+    let moved = eval(conn, &restore_script(placement))?;
+    moved
+        .parse()
+        .with_context(|| format!("unexpected reply of GNOME Shell Eval: {moved}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placement_captured() {
+        let script = capture_script(&["HDMI-1".to_string(), "DP-1".to_string()]);
+        assert!(script.contains(r#"["HDMI-1","DP-1"].map("#));
+
+        let reply = r#""{\"monitors\":{\"HDMI-1\":0,\"DP-1\":1,\"HDMI-2\":-1},\"windows\":[[3,1],[5,0],[8,2]]}""#;
+        let placement = parse_capture(reply).unwrap();
+        assert_eq!(
+            BTreeMap::from([(3, "DP-1".to_string()), (5, "HDMI-1".to_string())]),
+            placement.monitors
+        );
+        assert!(
+            restore_script(&placement).contains(r#"const monitors = {"3":"DP-1","5":"HDMI-1"};"#)
+        );
+        assert!(parse_capture("undefined").is_err());
+    }
+}
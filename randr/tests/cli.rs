@@ -0,0 +1,513 @@
+mod fake;
+
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use fake::{resources, Bus, DisplayConfig};
+use gnome_randr::test_fixtures::large_resources;
+use gnome_randr::{
+    daemon,
+    dbus_api::{ApplyConfigurationArgs, ConfigSerial, CrtcId, ModeId, OutputId},
+    record::Recording,
+};
+
+macro_rules! bus_or_skip {
+    () => {
+        match Bus::start() {
+            Some(bus) => bus,
+            None => {
+                eprintln!("dbus-daemon is not available, skipping");
+                return;
+            }
+        }
+    };
+}
+
+#[test]
+fn query_lists_outputs() {
+    let bus = bus_or_skip!();
+    let _service = DisplayConfig::serve(&bus, vec![resources()]);
+
+    let output = bus.gnome_randr().output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("serial: 7\n"));
+    assert!(stdout.contains("HDMI-1"));
+    assert!(stdout.contains("HDMI-2"));
+    assert!(stdout.contains("    disabled\n"));
+    assert!(stdout.contains("    current: 1920x1080@60\n    transforms: normal\n"));
+}
+
+#[test]
+fn current_outputs_listed() {
+    let bus = bus_or_skip!();
+    let _service = DisplayConfig::serve(&bus, vec![resources()]);
+
+    let output = bus.gnome_randr().arg("--query-current").output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "HDMI-1 1920x1080@60 +0+0 normal primary\nDP-1 1920x1080@60 +1920+0 normal\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = bus
+        .gnome_randr()
+        .args(["--query-current", "--format", "{name} {mode}@{hz}"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "HDMI-1 1920x1080@60\nDP-1 1920x1080@60\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = bus
+        .gnome_randr()
+        .args(["--format", "{name}={width}x{height}"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "HDMI-1=1920x1080\nDP-1=1920x1080\nHDMI-2=x\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn large_resources_queried() {
+    let bus = bus_or_skip!();
+    let service = DisplayConfig::serve(&bus, vec![large_resources(48, 600)]);
+
+    let output = bus.gnome_randr().arg("--verbose").output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("DP-48 "));
+    assert!(stdout.contains("    current: 3840x2160@60\n"));
+
+    let output = bus.gnome_randr().arg("--query-current").output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(24, stdout.lines().count());
+    assert!(stdout.ends_with("DP-24 3840x2160@60 +88320+0 normal\n"));
+
+    let output = bus
+        .gnome_randr()
+        .args(["--output", "DP-48", "--mode", "3840x2160", "--rate", "60"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let applied = service.applied();
+    let crtc = applied[0].crtcs.last().unwrap();
+    assert_eq!(
+        (vec![OutputId(47)], 24 * 3840),
+        (crtc.output_ids.clone(), crtc.x)
+    );
+}
+
+#[test]
+fn verbose_query_lists_winsys_ids() {
+    let bus = bus_or_skip!();
+    let _service = DisplayConfig::serve(&bus, vec![resources()]);
+
+    let output = bus.gnome_randr().arg("--verbose").output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(
+        "    index: %1\n    output id: 1, winsys id: 1, crtc id: 1, crtc winsys id: 1\n"
+    ));
+    assert!(stdout.contains("    output id: 2, winsys id: 2\n"));
+    assert!(stdout.contains(
+        "    1920x1080@60.00: 133.32 MHz pixel clock, 2000x1111 total (estimated), unknown sync\n"
+    ));
+}
+
+#[test]
+fn colored_query() {
+    let bus = bus_or_skip!();
+    let _service = DisplayConfig::serve(&bus, vec![resources()]);
+
+    let output = bus
+        .gnome_randr()
+        .args(["--color", "always"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\x1b[1mHDMI-1\x1b[0m"));
+    assert!(stdout.contains("\x1b[2m    disabled\x1b[0m\n"));
+
+    let output = bus.gnome_randr().env("NO_COLOR", "1").output().unwrap();
+    assert!(!String::from_utf8(output.stdout).unwrap().contains('\x1b'));
+}
+
+#[test]
+fn json_replayed_as_fixture() {
+    let bus = bus_or_skip!();
+    let service = DisplayConfig::serve(&bus, vec![resources()]);
+
+    let recorded = bus.gnome_randr().arg("--json").output().unwrap();
+    assert!(recorded.status.success());
+    let queried = bus.gnome_randr().output().unwrap();
+    drop(service);
+
+    fs::create_dir_all(&bus.state_home).unwrap();
+    let fixture = bus.state_home.join("fixture.json");
+    fs::write(&fixture, recorded.stdout).unwrap();
+    let replayed = bus
+        .gnome_randr()
+        .arg("--fixture")
+        .arg(&fixture)
+        .env_remove("DBUS_SESSION_BUS_ADDRESS")
+        .output()
+        .unwrap();
+    assert!(replayed.status.success());
+    assert_eq!(queried.stdout, replayed.stdout);
+
+    let applied = bus
+        .gnome_randr()
+        .arg("--fixture")
+        .arg(&fixture)
+        .args(["--output", "HDMI-2", "--auto"])
+        .output()
+        .unwrap();
+    assert!(applied.status.success());
+    let stdout = String::from_utf8(applied.stdout).unwrap();
+    assert!(stdout.contains("\"output_ids\": [\n        2\n"));
+
+    let layout_mode = bus
+        .gnome_randr()
+        .arg("--fixture")
+        .arg(&fixture)
+        .args(["--layout-mode", "physical"])
+        .output()
+        .unwrap();
+    assert!(!layout_mode.status.success());
+    let stderr = String::from_utf8(layout_mode.stderr).unwrap();
+    assert!(stderr.contains("--layout-mode is not supported with --fixture"));
+}
+
+#[test]
+fn diff_against_saved_layout() {
+    let bus = bus_or_skip!();
+    let _service = DisplayConfig::serve(&bus, vec![resources()]);
+    fs::create_dir_all(&bus.state_home).unwrap();
+
+    let saved = bus.state_home.join("saved.json");
+    let recorded = bus.gnome_randr().arg("--json").output().unwrap();
+    fs::write(&saved, recorded.stdout).unwrap();
+    let same = bus.gnome_randr().arg("diff").arg(&saved).output().unwrap();
+    assert!(same.status.success());
+    assert!(same.stdout.is_empty());
+
+    let snapshot = bus.state_home.join("snapshot");
+    fs::write(&snapshot, "HDMI-1 1280x720@60 0x0 transform=0 primary\n").unwrap();
+    let differs = bus
+        .gnome_randr()
+        .arg("diff")
+        .arg(&snapshot)
+        .output()
+        .unwrap();
+    assert!(!differs.status.success());
+    let stdout = String::from_utf8(differs.stdout).unwrap();
+    assert!(stdout.contains("HDMI-1 mode: expected 1280x720@60"));
+}
+
+#[test]
+fn history_listed_and_restored() {
+    let bus = bus_or_skip!();
+    let service = DisplayConfig::serve(&bus, vec![resources()]);
+    let history = bus.state_home.join("gnome-randr/history");
+    fs::create_dir_all(&history).unwrap();
+    fs::write(
+        history.join("1000"),
+        "DP-1 1920x1080@60 1920x0 transform=0\nHDMI-1 1920x1080@60 0x0 transform=0 primary\n",
+    )
+    .unwrap();
+    fs::write(
+        history.join("2000"),
+        "HDMI-1 1280x720@60 0x0 transform=0 primary\n",
+    )
+    .unwrap();
+
+    let output = bus.gnome_randr().arg("history").output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "0: 1970-01-01 00:00:02 UTC\n    HDMI-1 1280x720@60 0x0 transform=0 primary\n\
+         1: 1970-01-01 00:00:01 UTC (current)\n    DP-1 1920x1080@60 1920x0 transform=0\n\
+         \x20   HDMI-1 1920x1080@60 0x0 transform=0 primary\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = bus
+        .gnome_randr()
+        .args(["history", "diff", "1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "changes from 1 (1970-01-01 00:00:01 UTC) to 0 (1970-01-01 00:00:02 UTC):\n\
+         \x20   DP-1 removed, was DP-1 1920x1080@60 1920x0 transform=0\n\
+         \x20   HDMI-1 mode: 1920x1080@60 -> 1280x720@60\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = bus
+        .gnome_randr()
+        .args(["history", "restore", "0"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let applied = &service.applied()[0];
+    let hdmi1 = applied.crtcs.iter().find(|crtc| crtc.id == CrtcId(0));
+    assert_eq!(Some(ModeId(2)), hdmi1.unwrap().mode_id);
+    assert!(applied
+        .crtcs
+        .iter()
+        .all(|crtc| crtc.output_ids != [OutputId(1)]));
+    // The fake service doesn't change, so its configuration is recorded as the newest one
+    assert_eq!(3, fs::read_dir(&history).unwrap().count());
+}
+
+#[test]
+fn profile_applied_over_dbus() {
+    let bus = bus_or_skip!();
+    let service = DisplayConfig::serve(&bus, vec![resources()]);
+    let profiles = bus.state_home.join("gnome-randr/profiles");
+    fs::create_dir_all(&profiles).unwrap();
+    fs::write(
+        profiles.join("docked"),
+        "DP-1 1920x1080@60 0x0 transform=0 primary\n",
+    )
+    .unwrap();
+
+    let mut watch = bus
+        .gnome_randr()
+        .args(["watch", "--serve"])
+        .spawn()
+        .unwrap();
+    let conn = bus.connect();
+    let proxy = conn.with_proxy(daemon::SERVICE, daemon::PATH, Duration::from_secs(5));
+    let dbus = conn.with_proxy(
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        Duration::from_secs(5),
+    );
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let (owned,): (bool,) = dbus
+            .method_call("org.freedesktop.DBus", "NameHasOwner", (daemon::SERVICE,))
+            .unwrap();
+        if owned {
+            break;
+        }
+        assert!(Instant::now() < deadline, "watch should own its name");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    let applied: Result<(), _> = proxy.method_call(daemon::INTERFACE, "ApplyProfile", ("docked",));
+    let missing: Result<(), _> =
+        proxy.method_call(daemon::INTERFACE, "ApplyProfile", ("undocked",));
+    let current: Result<(String,), _> = proxy.method_call(daemon::INTERFACE, "CurrentProfile", ());
+    watch.kill().unwrap();
+    watch.wait().unwrap();
+
+    applied.unwrap();
+    assert!(missing.is_err());
+    assert_eq!("docked", current.unwrap().0);
+    let applied = service.applied();
+    assert_eq!(1, applied.len());
+    assert_eq!(1, applied[0].crtcs.len());
+}
+
+#[test]
+fn recording_replayed() {
+    let bus = bus_or_skip!();
+    fs::create_dir_all(&bus.state_home).unwrap();
+    let path = bus.state_home.join("session.jsonl");
+    let apply = |cmd: &mut std::process::Command| {
+        cmd.args(["--output", "HDMI-2", "--auto"]).output().unwrap()
+    };
+
+    let service = DisplayConfig::serve(&bus, vec![resources()]);
+    let output = apply(bus.gnome_randr().arg("--record").arg(&path));
+    assert!(output.status.success());
+    let recorded_applied = service.applied();
+    drop(service);
+
+    let recording = Recording::load(&path).unwrap();
+    let requests: Vec<ApplyConfigurationArgs> = recording.requests("ApplyConfiguration").unwrap();
+    assert_eq!(format!("{recorded_applied:?}"), format!("{requests:?}"));
+
+    let service = DisplayConfig::replay(&bus, &recording);
+    assert!(apply(&mut bus.gnome_randr()).status.success());
+    assert_eq!(format!("{requests:?}"), format!("{:?}", service.applied()));
+}
+
+#[test]
+fn apply_enables_output() {
+    let bus = bus_or_skip!();
+    let service = DisplayConfig::serve(&bus, vec![resources()]);
+
+    let status = bus
+        .gnome_randr()
+        .args(["--output", "HDMI-2", "--auto"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let applied = service.applied();
+    assert_eq!(1, applied.len());
+    assert_eq!(ConfigSerial(7), applied[0].serial);
+    let crtc = applied[0]
+        .crtcs
+        .iter()
+        .find(|crtc| crtc.output_ids == [OutputId(2)])
+        .unwrap();
+    assert_eq!((Some(ModeId(0)), 3840, 0), (crtc.mode_id, crtc.x, crtc.y));
+}
+
+#[test]
+fn named_layout_recorded() {
+    let bus = bus_or_skip!();
+    let _service = DisplayConfig::serve(&bus, vec![resources()]);
+
+    let output = bus
+        .gnome_randr()
+        .args(["--name", "docked", "--output", "HDMI-2", "--auto"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "applied layout docked\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let current = bus.state_home.join("gnome-randr/current-profile");
+    assert_eq!("docked\n", std::fs::read_to_string(&current).unwrap());
+
+    let status = bus
+        .gnome_randr()
+        .args(["--output", "HDMI-2", "--off"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(!current.exists());
+}
+
+#[test]
+fn cloned_outputs_marked() {
+    let bus = bus_or_skip!();
+    let mut cloned = resources();
+    cloned.outputs[1].crtc_id = Some(CrtcId(0));
+    cloned.crtcs[1].mode_id = None;
+    let service = DisplayConfig::serve(&bus, vec![cloned]);
+
+    let output = bus.gnome_randr().output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("DP-1 [cloned with HDMI-1] "));
+
+    let output = bus
+        .gnome_randr()
+        .args(["--output", "DP-1", "--off"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("DP-1 shares its CRTC with HDMI-1, turning it off changes the shared CRTC"));
+    assert_eq!(vec![OutputId(0)], service.applied()[0].crtcs[0].output_ids);
+}
+
+#[test]
+fn serial_mismatch_applies_nothing() {
+    let bus = bus_or_skip!();
+    let mut changed = resources();
+    changed.serial = ConfigSerial(8);
+    let service = DisplayConfig::serve(&bus, vec![changed]);
+
+    let output = bus
+        .gnome_randr()
+        .args(["--expect-serial", "7", "--output", "HDMI-1", "--off"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("serial mismatch"));
+    assert!(service.applied().is_empty());
+}
+
+#[test]
+fn missing_service_exit_code() {
+    let bus = bus_or_skip!();
+
+    let output = bus.gnome_randr().output().unwrap();
+    assert_eq!(Some(69), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not available"));
+}
+
+#[test]
+fn doctor_reports_missing_service() {
+    let bus = bus_or_skip!();
+
+    let output = bus.gnome_randr().arg("doctor").output().unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("ok    session bus: connected\n"));
+    assert!(stdout.contains("FAIL  DisplayConfig service"));
+    assert!(stdout.contains("      fix: "));
+}
+
+#[test]
+fn doctor_checks_service() {
+    let bus = bus_or_skip!();
+    let _service = DisplayConfig::serve(&bus, vec![resources()]);
+
+    let output = bus.gnome_randr().arg("doctor").output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("ok    GetResources: serial 7, 3 outputs, 2 enabled\n"));
+    assert!(stdout.contains("0 failed"));
+}
+
+#[test]
+fn timings_reported() {
+    let bus = bus_or_skip!();
+    let _service = DisplayConfig::serve(&bus, vec![resources()]);
+
+    let output = bus
+        .gnome_randr()
+        .args(["--timings", "--output", "HDMI-2", "--auto"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    for label in [
+        "timings:",
+        "GetResources",
+        "layout",
+        "ApplyConfiguration",
+        "total",
+    ] {
+        assert!(stderr.contains(label), "{label} missing from {stderr}");
+    }
+}
+
+#[test]
+fn shift_moves_outputs() {
+    let bus = bus_or_skip!();
+    let service = DisplayConfig::serve(&bus, vec![resources()]);
+
+    let status = bus
+        .gnome_randr()
+        .args(["shift", "--outputs", "DP-1", "--by", "+0x540"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let applied = service.applied();
+    let positions: Vec<_> = applied[0]
+        .crtcs
+        .iter()
+        .map(|crtc| (crtc.x, crtc.y))
+        .collect();
+    assert_eq!(vec![(0, 0), (1920, 540)], positions);
+}
@@ -0,0 +1,243 @@
+//! Fake `org.gnome.Mutter.DisplayConfig` service on a private bus, so CLI could be tested end to
+//! end without a GNOME session.
+
+use std::{
+    ffi::CString,
+    fs,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use dbus::{
+    arg::{AppendAll, IterAppend},
+    blocking::Connection,
+    channel::{Channel, MatchingReceiver, Sender},
+    message::MatchRule,
+    strings::ErrorName,
+    Message,
+};
+use gnome_randr::{
+    dbus_api::{
+        ApplyConfigurationArgs, ConfigSerial, CrtController, CrtcId, GetResourcesReturn, Mode,
+        ModeId, Output, OutputId, OutputProperties, Transform, DISPLAY_CONFIG_SERVICE,
+    },
+    record::Recording,
+};
+
+/// Private bus daemon, killed on drop.
+pub struct Bus {
+    daemon: Child,
+    pub address: String,
+    /// `XDG_STATE_HOME` and `XDG_CONFIG_HOME` of spawned commands, removed on drop
+    pub state_home: PathBuf,
+}
+
+impl Bus {
+    /// Starts `dbus-daemon`, `None` if it's not installed.
+    pub fn start() -> Option<Self> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take()?)
+            .read_line(&mut address)
+            .ok()?;
+        let state_home = std::env::temp_dir().join(format!(
+            "gnome-randr-state-{}-{}",
+            std::process::id(),
+            daemon.id()
+        ));
+        Some(Self {
+            daemon,
+            address: address.trim().to_string(),
+            state_home,
+        })
+    }
+
+    /// Command running gnome-randr against this bus.
+    pub fn gnome_randr(&self) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_gnome-randr"));
+        cmd.env("DBUS_SESSION_BUS_ADDRESS", &self.address)
+            .env("RUST_BACKTRACE", "0")
+            .env("XDG_STATE_HOME", &self.state_home)
+            .env("XDG_CONFIG_HOME", &self.state_home);
+        cmd
+    }
+
+    pub fn connect(&self) -> Connection {
+        let mut channel = Channel::open_private(&self.address).expect("Bus should be running");
+        channel.register().expect("Bus should accept connection");
+        Connection::from(channel)
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+        let _ = fs::remove_dir_all(&self.state_home);
+    }
+}
+
+/// DisplayConfig object answering GetResources with scripted responses and recording every
+/// ApplyConfiguration call, stopped on drop.
+pub struct DisplayConfig {
+    applied: Arc<Mutex<Vec<ApplyConfigurationArgs>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DisplayConfig {
+    /// Serves `responses` in order, repeating the last one once others are used up.
+    pub fn serve(bus: &Bus, responses: Vec<GetResourcesReturn>) -> Self {
+        assert!(!responses.is_empty(), "At least one response is required");
+        let applied = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let conn = bus.connect();
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let thread = {
+            let applied = applied.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                conn.request_name(DISPLAY_CONFIG_SERVICE, false, true, true)
+                    .expect("Name should be free on a private bus");
+                let mut responses = responses.into_iter().peekable();
+                let mut current = responses.next().expect("Checked above");
+                conn.start_receive(
+                    MatchRule::new_method_call(),
+                    Box::new(move |msg, conn| {
+                        let reply = match msg.member().as_deref() {
+                            Some("GetResources") => {
+                                let mut reply = msg.method_return();
+                                current.clone().append(&mut IterAppend::new(&mut reply));
+                                if let Some(next) = responses.next() {
+                                    current = next;
+                                }
+                                reply
+                            }
+                            Some("ApplyConfiguration") => match msg.read_all() {
+                                Ok(args) => {
+                                    applied.lock().unwrap().push(args);
+                                    msg.method_return()
+                                }
+                                Err(err) => error(&msg, "InvalidArgs", &err.to_string()),
+                            },
+                            _ => error(&msg, "UnknownMethod", "Not implemented by fake service"),
+                        };
+                        let _ = conn.send(reply);
+                        true
+                    }),
+                );
+                ready_tx.send(()).unwrap();
+                while !stop.load(Ordering::Relaxed) {
+                    conn.process(Duration::from_millis(20)).unwrap();
+                }
+            })
+        };
+        ready_rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("Fake service should start");
+
+        Self {
+            applied,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Serves GetResources replies of a `--record` recording, for regression tests of bug
+    /// reports.
+    pub fn replay(bus: &Bus, recording: &Recording) -> Self {
+        let responses = recording
+            .replies("GetResources")
+            .expect("Recorded GetResources replies should be valid");
+        Self::serve(bus, responses)
+    }
+
+    /// Every ApplyConfiguration call received so far.
+    pub fn applied(&self) -> Vec<ApplyConfigurationArgs> {
+        self.applied.lock().unwrap().clone()
+    }
+}
+
+impl Drop for DisplayConfig {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn error(msg: &Message, name: &str, text: &str) -> Message {
+    let name = ErrorName::new(format!("org.freedesktop.DBus.Error.{name}"))
+        .expect("Error name should be valid");
+    msg.error(&name, &CString::new(text).expect("Error text has no nul"))
+}
+
+/// Three outputs, HDMI-1 and DP-1 are enabled side by side, HDMI-2 is disabled.
+pub fn resources() -> GetResourcesReturn {
+    let mode = |id, width, height, frequency| Mode {
+        id: ModeId(id),
+        width,
+        height,
+        frequency,
+        ..Default::default()
+    };
+    let crtc = |id, mode_id: Option<u32>, x| CrtController {
+        id: CrtcId(id),
+        winsys_id: id.into(),
+        x,
+        y: 0,
+        width: 1920,
+        height: 1080,
+        mode_id: mode_id.map(ModeId),
+        transform: Transform::Normal,
+        transforms: vec![0],
+    };
+    let output = |id, crtc_id: Option<u32>, name: &str, primary| {
+        let mut props = OutputProperties::default();
+        props.primary = Some(primary);
+        Output {
+            id: OutputId(id),
+            winsys_id: id.into(),
+            crtc_id: crtc_id.map(CrtcId),
+            possible_crtc_ids: vec![CrtcId(0), CrtcId(1), CrtcId(2)],
+            connector_name: name.to_string(),
+            mode_ids: vec![ModeId(0), ModeId(1), ModeId(2)],
+            clone_ids: vec![],
+            props,
+        }
+    };
+    GetResourcesReturn::new(
+        ConfigSerial(7),
+        vec![
+            crtc(0, Some(0), 0),
+            crtc(1, Some(0), 1920),
+            crtc(2, None, 0),
+        ],
+        vec![
+            output(0, Some(0), "HDMI-1", true),
+            output(1, Some(1), "DP-1", false),
+            output(2, None, "HDMI-2", false),
+        ],
+        vec![
+            mode(0, 1920, 1080, 60.0),
+            mode(1, 1920, 1080, 50.0),
+            mode(2, 1280, 720, 59.94),
+        ],
+        8192,
+        8192,
+    )
+}